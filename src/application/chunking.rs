@@ -0,0 +1,595 @@
+use std::ops::Range;
+use tiktoken_rs::cl100k_base_singleton;
+use crate::domain::{
+    models::{ChunkingOptions, ChunkingStrategy, PAGE_BREAK},
+    errors::AppError
+};
+
+// 1500 caracteres ~= 300-400 tokens (Sweet spot para embeddings)
+const DEFAULT_CHUNK_SIZE: usize = 1500;
+const DEFAULT_CHUNK_OVERLAP: usize = 200;
+// Equivalentes para la estrategia por tokens
+const DEFAULT_TOKEN_CHUNK_SIZE: usize = 400;
+const DEFAULT_TOKEN_CHUNK_OVERLAP: usize = 50;
+
+/// Fragmento de texto junto a la página donde comienza.
+pub struct TextChunk {
+    pub content: String,
+    pub page: Option<u32>,
+}
+
+/// Estrategia de troceado del texto transmutado.
+pub trait Chunker: Send + Sync {
+    fn chunk(&self, text: &str) -> Vec<TextChunk>;
+}
+
+/// Completa las opciones con la configuración por defecto y resuelve `auto` según el tipo MIME.
+/// El resultado es estable: se guarda con el trabajo para que una reanudación trocee igual.
+pub fn resolve_options(
+    requested: &ChunkingOptions,
+    defaults: &ChunkingOptions,
+    mime_type: Option<&str>
+) -> Result<ChunkingOptions, AppError> {
+    let strategy = match requested.strategy.or(defaults.strategy).unwrap_or_default() {
+        ChunkingStrategy::Auto => auto_strategy(mime_type),
+        other => other,
+    };
+
+    // El tamaño por defecto del servidor solo vale si está en la misma unidad
+    let same_unit = (defaults.strategy == Some(ChunkingStrategy::Tokens)) == (strategy == ChunkingStrategy::Tokens);
+    let (default_size, default_overlap) = match strategy {
+        ChunkingStrategy::Tokens => (DEFAULT_TOKEN_CHUNK_SIZE, DEFAULT_TOKEN_CHUNK_OVERLAP),
+        _ => (DEFAULT_CHUNK_SIZE, DEFAULT_CHUNK_OVERLAP),
+    };
+    let size = requested.chunk_size
+        .or(defaults.chunk_size.filter(|_| same_unit))
+        .unwrap_or(default_size);
+    let overlap = requested.chunk_overlap
+        .or(defaults.chunk_overlap.filter(|_| same_unit))
+        .unwrap_or(default_overlap.min(size / 2));
+
+    if size == 0 {
+        return Err(AppError::ValidationError("chunk_size must be greater than 0".to_string()));
+    }
+    if overlap >= size {
+        return Err(AppError::ValidationError(format!(
+            "chunk_overlap ({}) must be smaller than chunk_size ({})", overlap, size
+        )));
+    }
+
+    Ok(ChunkingOptions { strategy: Some(strategy), chunk_size: Some(size), chunk_overlap: Some(overlap) })
+}
+
+/// Lee `CHUNK_STRATEGY`, `CHUNK_SIZE` y `CHUNK_OVERLAP` (todos opcionales).
+pub fn options_from_env() -> Result<ChunkingOptions, AppError> {
+    let number = |name: &str| -> Result<Option<usize>, AppError> {
+        std::env::var(name).ok()
+            .map(|v| v.trim().parse::<usize>()
+                .map_err(|_| AppError::ConfigError(format!("{} must be a number", name))))
+            .transpose()
+    };
+    let strategy = std::env::var("CHUNK_STRATEGY").ok()
+        .map(|v| v.parse::<ChunkingStrategy>().map_err(AppError::ConfigError))
+        .transpose()?;
+
+    let options = ChunkingOptions {
+        strategy,
+        chunk_size: number("CHUNK_SIZE")?,
+        chunk_overlap: number("CHUNK_OVERLAP")?,
+    };
+    // Validación temprana de la combinación configurada
+    resolve_options(&ChunkingOptions::default(), &options, None)?;
+    Ok(options)
+}
+
+fn auto_strategy(mime_type: Option<&str>) -> ChunkingStrategy {
+    match mime_type.unwrap_or("text/plain") {
+        "text/csv"
+        | "application/vnd.ms-excel"
+        | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => ChunkingStrategy::Table,
+        "text/markdown"
+        | "text/html"
+        | "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => ChunkingStrategy::Paragraph,
+        _ => ChunkingStrategy::Sliding,
+    }
+}
+
+/// Construye el chunker de unas opciones ya resueltas (ver `resolve_options`).
+pub fn build_chunker(options: &ChunkingOptions) -> Box<dyn Chunker> {
+    let strategy = options.strategy.unwrap_or(ChunkingStrategy::Sliding);
+    let tokens = strategy == ChunkingStrategy::Tokens;
+    let size = options.chunk_size
+        .unwrap_or(if tokens { DEFAULT_TOKEN_CHUNK_SIZE } else { DEFAULT_CHUNK_SIZE })
+        .max(1);
+    let overlap = options.chunk_overlap
+        .unwrap_or(if tokens { DEFAULT_TOKEN_CHUNK_OVERLAP } else { DEFAULT_CHUNK_OVERLAP })
+        .min(size - 1);
+
+    match strategy {
+        ChunkingStrategy::Auto | ChunkingStrategy::Sliding => Box::new(SlidingWindowChunker { size, overlap }),
+        ChunkingStrategy::Sentence => Box::new(SentenceChunker { size, overlap }),
+        ChunkingStrategy::Paragraph => Box::new(ParagraphChunker { size, overlap }),
+        ChunkingStrategy::Tokens => Box::new(TokenChunker { size, overlap }),
+        ChunkingStrategy::Table => Box::new(TableChunker { size, overlap }),
+    }
+}
+
+// --- Utilidades comunes ---
+
+/// Posiciones de los saltos de página para atribuir cada chunk a su página.
+struct Pages(Vec<usize>);
+
+impl Pages {
+    fn new(text: &str) -> Self {
+        Self(text.match_indices(PAGE_BREAK).map(|(i, _)| i).collect())
+    }
+
+    /// Página (1-based) del byte `offset`; `None` si el texto no tiene paginación.
+    fn at(&self, offset: usize) -> Option<u32> {
+        (!self.0.is_empty()).then(|| 1 + self.0.partition_point(|&b| b < offset) as u32)
+    }
+}
+
+fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// Ventana deslizante sobre `range`: cortes de hasta `size` caracteres sin partir palabras
+/// y retrocediendo `overlap` caracteres entre ventanas.
+fn window_spans(text: &str, range: Range<usize>, size: usize, overlap: usize) -> Vec<Range<usize>> {
+    let base = range.start;
+    let slice = &text[range];
+    let chars: Vec<(usize, char)> = slice.char_indices().collect();
+    let byte_at = |i: usize| base + chars.get(i).map(|&(b, _)| b).unwrap_or(slice.len());
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+
+    while start < chars.len() {
+        let end = std::cmp::min(start + size, chars.len());
+
+        // Ajuste para no cortar palabras (buscar espacio hacia atrás)
+        let mut actual_end = end;
+        if actual_end < chars.len() {
+            while actual_end > start && !chars[actual_end].1.is_whitespace() {
+                actual_end -= 1;
+            }
+        }
+        if actual_end == start { actual_end = end; } // Fallback si la palabra es gigante
+
+        spans.push(byte_at(start)..byte_at(actual_end));
+        if actual_end >= chars.len() {
+            break;
+        }
+
+        // Avanzar restando el overlap para mantener contexto
+        let mut next = start + std::cmp::max(1, (actual_end - start).saturating_sub(overlap));
+        // Sin empezar a mitad de palabra ni con espacios
+        if !chars[next - 1].1.is_whitespace() {
+            while next < actual_end && !chars[next].1.is_whitespace() { next += 1; }
+        }
+        while next < chars.len() && chars[next].1.is_whitespace() { next += 1; }
+        start = next;
+    }
+    spans
+}
+
+/// Recorta los espacios de un tramo; `None` si queda vacío.
+fn trimmed(text: &str, range: Range<usize>) -> Option<Range<usize>> {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.start + slice.trim_end().len();
+    (start < end).then_some(start..end)
+}
+
+/// Frases de `range`: terminan en `.`, `!`, `?` o `…` seguidos de espacio, o en una línea en blanco.
+fn sentence_spans(text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let base = range.start;
+    let slice = &text[range.clone()];
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut chars = slice.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, n)| n);
+        let end_of_sentence = matches!(c, '.' | '!' | '?' | '…') && next.is_none_or(char::is_whitespace);
+        let blank_line = c == '\n' && matches!(next, Some('\n') | Some(PAGE_BREAK));
+
+        if end_of_sentence || blank_line {
+            let end = i + c.len_utf8();
+            spans.extend(trimmed(text, base + start..base + end));
+            start = end;
+        }
+    }
+    spans.extend(trimmed(text, base + start..range.end));
+    spans
+}
+
+/// Divide en frases (y, si aún no caben, en ventanas) los tramos que superan `size` caracteres.
+fn split_oversized(text: &str, spans: Vec<Range<usize>>, size: usize) -> Vec<Range<usize>> {
+    spans.into_iter().flat_map(|span| {
+        if char_len(&text[span.clone()]) <= size {
+            return vec![span];
+        }
+        sentence_spans(text, span).into_iter().flat_map(|sentence| {
+            if char_len(&text[sentence.clone()]) <= size {
+                vec![sentence]
+            } else {
+                window_spans(text, sentence, size, 0)
+            }
+        }).collect()
+    }).collect()
+}
+
+/// Agrupa tramos consecutivos en chunks de hasta `size` (según `measure`), repitiendo al inicio
+/// de cada chunk los últimos tramos del anterior que quepan en `overlap`. Cada chunk es el texto
+/// original entre su primer y su último tramo, precedido de `prefix`.
+fn pack(
+    text: &str,
+    spans: &[Range<usize>],
+    size: usize,
+    overlap: usize,
+    prefix: &str,
+    measure: &dyn Fn(&str) -> usize,
+    pages: &Pages
+) -> Vec<TextChunk> {
+    let mut out = Vec::new();
+    let emit = |current: &[(Range<usize>, usize)], out: &mut Vec<TextChunk>| {
+        if let (Some(first), Some(last)) = (current.first(), current.last()) {
+            out.push(TextChunk {
+                content: format!("{}{}", prefix, &text[first.0.start..last.0.end]),
+                page: pages.at(first.0.start),
+            });
+        }
+    };
+
+    let mut current: Vec<(Range<usize>, usize)> = Vec::new();
+    let mut used = 0;
+
+    for span in spans {
+        let weight = measure(&text[span.clone()]);
+
+        if !current.is_empty() && used + weight > size {
+            emit(&current, &mut out);
+
+            // Solape: arrastrar los tramos finales que quepan (si dejan sitio al nuevo)
+            let mut carried = 0;
+            let keep = current.iter().rev()
+                .take_while(|(_, w)| { carried += w; carried <= overlap })
+                .count();
+            current.drain(..current.len() - keep);
+            used = current.iter().map(|(_, w)| w).sum();
+            if used + weight > size {
+                current.clear();
+                used = 0;
+            }
+        }
+
+        current.push((span.clone(), weight));
+        used += weight;
+    }
+    emit(&current, &mut out);
+    out
+}
+
+// --- Estrategias ---
+
+/// Ventana deslizante de caracteres que no parte palabras.
+pub struct SlidingWindowChunker {
+    pub size: usize,
+    pub overlap: usize,
+}
+
+impl Chunker for SlidingWindowChunker {
+    fn chunk(&self, text: &str) -> Vec<TextChunk> {
+        let pages = Pages::new(text);
+        window_spans(text, 0..text.len(), self.size, self.overlap)
+            .into_iter()
+            .map(|span| TextChunk { page: pages.at(span.start), content: text[span].to_string() })
+            .collect()
+    }
+}
+
+/// Agrupa frases completas hasta `size` caracteres.
+pub struct SentenceChunker {
+    pub size: usize,
+    pub overlap: usize,
+}
+
+impl Chunker for SentenceChunker {
+    fn chunk(&self, text: &str) -> Vec<TextChunk> {
+        let pages = Pages::new(text);
+        let spans = split_oversized(text, sentence_spans(text, 0..text.len()), self.size);
+        pack(text, &spans, self.size, self.overlap, "", &char_len, &pages)
+    }
+}
+
+/// Agrupa párrafos sin cruzar secciones. Los encabezados (`#` de Markdown, que la
+/// transmutación también genera para los estilos de título de DOCX y para HTML) se
+/// anteponen a cada chunk de su sección para conservar el contexto.
+pub struct ParagraphChunker {
+    pub size: usize,
+    pub overlap: usize,
+}
+
+impl ParagraphChunker {
+    /// Nivel de un encabezado Markdown (`# Título` → 1).
+    fn heading_level(line: &str) -> Option<usize> {
+        let line = line.trim_start();
+        let level = line.chars().take_while(|&c| c == '#').count();
+        ((1..=6).contains(&level) && line[level..].starts_with(' ')).then_some(level)
+    }
+}
+
+impl Chunker for ParagraphChunker {
+    fn chunk(&self, text: &str) -> Vec<TextChunk> {
+        let pages = Pages::new(text);
+        let mut out = Vec::new();
+
+        let mut headings: Vec<(usize, String)> = Vec::new();
+        let mut paragraphs: Vec<Range<usize>> = Vec::new();
+        let mut paragraph_start: Option<usize> = None;
+        let mut offset = 0;
+
+        let flush = |headings: &[(usize, String)], paragraphs: &mut Vec<Range<usize>>, out: &mut Vec<TextChunk>| {
+            if paragraphs.is_empty() {
+                return;
+            }
+            let mut prefix: String = headings.iter().map(|(_, h)| format!("{}\n", h)).collect();
+            if !prefix.is_empty() {
+                prefix.push('\n');
+            }
+            // El contexto de encabezados no puede comerse todo el chunk
+            let budget = self.size.saturating_sub(char_len(&prefix)).max(self.size / 2);
+            let spans = split_oversized(text, std::mem::take(paragraphs), budget);
+            out.extend(pack(text, &spans, budget, self.overlap, &prefix, &char_len, &pages));
+        };
+
+        for line in text.split_inclusive('\n') {
+            let line_range = offset..offset + line.len();
+            offset += line.len();
+
+            if let Some(level) = Self::heading_level(line) {
+                paragraphs.extend(paragraph_start.take().and_then(|s| trimmed(text, s..line_range.start)));
+                flush(&headings, &mut paragraphs, &mut out);
+                headings.retain(|(l, _)| *l < level);
+                headings.push((level, line.trim().to_string()));
+            } else if line.trim().is_empty() {
+                paragraphs.extend(paragraph_start.take().and_then(|s| trimmed(text, s..line_range.start)));
+            } else if paragraph_start.is_none() {
+                paragraph_start = Some(line_range.start);
+            }
+        }
+        paragraphs.extend(paragraph_start.take().and_then(|s| trimmed(text, s..text.len())));
+        flush(&headings, &mut paragraphs, &mut out);
+        out
+    }
+}
+
+/// Ventana medida en tokens (codificación cl100k) que respeta palabras completas.
+pub struct TokenChunker {
+    pub size: usize,
+    pub overlap: usize,
+}
+
+impl Chunker for TokenChunker {
+    fn chunk(&self, text: &str) -> Vec<TextChunk> {
+        let pages = Pages::new(text);
+        let bpe = cl100k_base_singleton();
+
+        // Cada palabra se mide con su espacio previo, como la ve el tokenizador en contexto
+        let words: Vec<Range<usize>> = text.split_whitespace()
+            .map(|w| {
+                let start = w.as_ptr() as usize - text.as_ptr() as usize;
+                start..start + w.len()
+            })
+            .collect();
+        let measure = |w: &str| bpe.encode_ordinary(&format!(" {}", w)).len();
+
+        pack(text, &words, self.size, self.overlap, "", &measure, &pages)
+    }
+}
+
+/// Mantiene las filas de tabla intactas. Cada hoja (`--- HOJA: ... ---` de XLSX) o el CSV
+/// completo es una tabla cuya cabecera (título y primera fila) se repite en todos sus chunks.
+pub struct TableChunker {
+    pub size: usize,
+    pub overlap: usize,
+}
+
+impl TableChunker {
+    fn is_sheet_marker(line: &str) -> bool {
+        let line = line.trim();
+        line.starts_with("--- ") && line.ends_with(" ---") && line.len() > 8
+    }
+}
+
+impl Chunker for TableChunker {
+    fn chunk(&self, text: &str) -> Vec<TextChunk> {
+        let pages = Pages::new(text);
+        let mut out = Vec::new();
+
+        let mut header = String::new();
+        let mut expect_header_row = true;
+        let mut rows: Vec<Range<usize>> = Vec::new();
+        let mut offset = 0;
+
+        let flush = |header: &str, rows: &mut Vec<Range<usize>>, out: &mut Vec<TextChunk>| {
+            if rows.is_empty() {
+                return;
+            }
+            let budget = self.size.saturating_sub(char_len(header)).max(self.size / 2);
+            out.extend(pack(text, rows, budget, self.overlap, header, &char_len, &pages));
+            rows.clear();
+        };
+
+        for line in text.split_inclusive('\n') {
+            let line_range = offset..offset + line.len();
+            offset += line.len();
+            let Some(row) = trimmed(text, line_range) else { continue };
+            let content = &text[row.clone()];
+
+            if Self::is_sheet_marker(content) {
+                flush(&header, &mut rows, &mut out);
+                header = format!("{}\n", content);
+                expect_header_row = true;
+            } else if expect_header_row {
+                header.push_str(content);
+                header.push('\n');
+                expect_header_row = false;
+            } else if content == "---" && rows.is_empty() {
+                // Separador de cabecera del CSV
+                header.push_str("---\n");
+            } else {
+                rows.push(row);
+            }
+        }
+        flush(&header, &mut rows, &mut out);
+
+        // Tabla sin filas de datos (solo cabecera): se conserva tal cual
+        if out.is_empty() && !header.trim().is_empty() {
+            out.push(TextChunk { content: header.trim_end().to_string(), page: pages.at(0) });
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(chunks: Vec<TextChunk>) -> Vec<String> {
+        chunks.into_iter().map(|c| c.content).collect()
+    }
+
+    fn options(strategy: Option<ChunkingStrategy>, size: Option<usize>, overlap: Option<usize>) -> ChunkingOptions {
+        ChunkingOptions { strategy, chunk_size: size, chunk_overlap: overlap }
+    }
+
+    #[test]
+    fn sliding_window_overlaps_without_splitting_words() {
+        let text = "alfa beta gamma delta épsilon zeta eta theta iota kappa";
+        let words: Vec<&str> = text.split(' ').collect();
+        let chunks = contents(SlidingWindowChunker { size: 20, overlap: 8 }.chunk(text));
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(char_len(chunk) <= 20, "chunk demasiado largo: {:?}", chunk);
+            assert!(chunk.split(' ').all(|w| words.contains(&w)), "palabra partida en {:?}", chunk);
+        }
+        // Cada ventana repite el final de la anterior (el solape supera la palabra más larga)
+        for pair in chunks.windows(2) {
+            let last_word = pair[0].rsplit(' ').next().unwrap();
+            assert!(pair[1].split(' ').any(|w| w == last_word), "sin solape entre {:?} y {:?}", pair[0], pair[1]);
+        }
+        assert!(chunks[0].starts_with("alfa"));
+        assert!(chunks.last().unwrap().ends_with("kappa"));
+    }
+
+    #[test]
+    fn sliding_window_without_whitespace_terminates() {
+        let text = "x".repeat(100);
+        let chunks = contents(SlidingWindowChunker { size: 10, overlap: 9 }.chunk(&text));
+
+        assert_eq!(chunks.len(), 10);
+        assert!(chunks.iter().all(|c| c.len() == 10));
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[test]
+    fn sentences_are_packed_carrying_the_overlap() {
+        let text = "Uno dos tres. Cuatro cinco seis. Siete ocho nueve. Diez once doce.";
+        let chunks = contents(SentenceChunker { size: 40, overlap: 20 }.chunk(text));
+
+        assert_eq!(chunks, vec![
+            "Uno dos tres. Cuatro cinco seis.",
+            "Cuatro cinco seis. Siete ocho nueve.",
+            "Siete ocho nueve. Diez once doce.",
+        ]);
+    }
+
+    #[test]
+    fn paragraphs_are_packed_carrying_the_overlap() {
+        let text = "Primer párrafo corto.\n\nSegundo párrafo corto.\n\nTercero.";
+        let chunks = contents(ParagraphChunker { size: 50, overlap: 25 }.chunk(text));
+
+        assert_eq!(chunks, vec![
+            "Primer párrafo corto.\n\nSegundo párrafo corto.",
+            "Segundo párrafo corto.\n\nTercero.",
+        ]);
+    }
+
+    #[test]
+    fn paragraph_chunks_carry_their_headings() {
+        let text = "# Guía\n\nPrimer párrafo.\n\n## Detalle\n\nSegundo párrafo.\n\n# Anexo\n\nTercer párrafo.";
+        let chunks = contents(ParagraphChunker { size: 200, overlap: 0 }.chunk(text));
+
+        assert_eq!(chunks, vec![
+            "# Guía\n\nPrimer párrafo.",
+            "# Guía\n## Detalle\n\nSegundo párrafo.",
+            "# Anexo\n\nTercer párrafo.",
+        ]);
+    }
+
+    #[test]
+    fn table_chunks_repeat_the_header_and_keep_rows_whole() {
+        let rows = ["manzana,10", "pera,20", "naranja,30", "uva,40", "melocotón,50"];
+        let header = "--- HOJA: Ventas ---\nproducto,unidades\n";
+        let text = format!("{}{}\n", header, rows.join("\n"));
+        let chunks = contents(TableChunker { size: 40, overlap: 0 }.chunk(&text));
+
+        assert!(chunks.len() > 1);
+        let mut seen = Vec::new();
+        for chunk in &chunks {
+            let body = chunk.strip_prefix(header).unwrap_or_else(|| panic!("sin cabecera: {:?}", chunk));
+            for line in body.lines() {
+                assert!(rows.contains(&line), "fila partida: {:?}", line);
+                seen.push(line);
+            }
+        }
+        assert_eq!(seen, rows);
+    }
+
+    #[test]
+    fn pages_are_resolved_at_page_breaks() {
+        let text = "a\u{000C}b\u{000C}c";
+        let pages = Pages::new(text);
+
+        assert_eq!(pages.at(0), Some(1));
+        // El salto pertenece a la página que cierra
+        assert_eq!(pages.at(1), Some(1));
+        assert_eq!(pages.at(2), Some(2));
+        assert_eq!(pages.at(3), Some(2));
+        assert_eq!(pages.at(4), Some(3));
+        assert_eq!(Pages::new("sin paginar").at(0), None);
+
+        let chunks = SentenceChunker { size: 5, overlap: 0 }.chunk("Uno.\u{000C}Dos.\u{000C}Tres.");
+        assert_eq!(chunks.iter().map(|c| c.page).collect::<Vec<_>>(), vec![Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn resolve_options_validates_and_picks_the_strategy_by_mime_type() {
+        let defaults = ChunkingOptions::default();
+        let strategy = |mime: Option<&str>| resolve_options(&defaults, &defaults, mime).unwrap().strategy;
+
+        assert_eq!(strategy(Some("text/csv")), Some(ChunkingStrategy::Table));
+        assert_eq!(strategy(Some("text/markdown")), Some(ChunkingStrategy::Paragraph));
+        assert_eq!(strategy(Some("application/pdf")), Some(ChunkingStrategy::Sliding));
+        assert_eq!(strategy(None), Some(ChunkingStrategy::Sliding));
+
+        // Una estrategia explícita gana al tipo MIME
+        let sentence = options(Some(ChunkingStrategy::Sentence), None, None);
+        assert_eq!(resolve_options(&sentence, &defaults, Some("text/csv")).unwrap().strategy, Some(ChunkingStrategy::Sentence));
+
+        assert!(resolve_options(&options(None, Some(100), Some(100)), &defaults, None).is_err());
+        assert!(resolve_options(&options(None, Some(100), Some(150)), &defaults, None).is_err());
+        assert!(resolve_options(&options(None, Some(0), Some(0)), &defaults, None).is_err());
+
+        // Los tamaños por defecto del servidor en caracteres no se aplican a tokens
+        let server = options(None, Some(1000), Some(100));
+        let tokens = resolve_options(&options(Some(ChunkingStrategy::Tokens), None, None), &server, None).unwrap();
+        assert_eq!((tokens.chunk_size, tokens.chunk_overlap), (Some(DEFAULT_TOKEN_CHUNK_SIZE), Some(DEFAULT_TOKEN_CHUNK_OVERLAP)));
+        let chars = resolve_options(&defaults, &server, None).unwrap();
+        assert_eq!((chars.chunk_size, chars.chunk_overlap), (Some(1000), Some(100)));
+    }
+}
@@ -0,0 +1,816 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use futures::stream::{self, StreamExt};
+use tokio::sync::RwLock;
+use crate::domain::models::estimate_tokens;
+use crate::domain::{
+    ports::{CommunityRepository, AIService},
+    models::{Community, CommunityIndexStatus, EntityNode, EntityLink},
+    errors::AppError
+};
+
+// Niveles de la jerarquía (cada uno agrupa las comunidades del anterior)
+const MAX_LEVELS: usize = 3;
+// Las comunidades de una sola entidad no se resumen
+const MIN_COMMUNITY_SIZE: usize = 2;
+// Comunidades resumidas por nivel (las más grandes) y resúmenes simultáneos
+const MAX_SUMMARIES_PER_LEVEL: usize = 100;
+const SUMMARY_CONCURRENCY: usize = 4;
+// Fracción de resúmenes fallidos a partir de la cual se aborta y se conservan las comunidades previas
+const MAX_SUMMARY_FAILURE_RATIO: f64 = 0.5;
+// Entidades guardadas por comunidad y caracteres de entrada del prompt de resumen
+const STORED_MEMBERS: usize = 50;
+const SUMMARY_INPUT_CHARS: usize = 8000;
+// Modo global: tokens de resúmenes por llamada "map", llamadas simultáneas y tokens del "reduce"
+const MAP_BATCH_TOKENS: usize = 3000;
+const MAP_CONCURRENCY: usize = 4;
+const REDUCE_TOKENS: usize = 4000;
+const DEFAULT_COMMUNITY_LEVEL: usize = 1;
+// Louvain: pasadas máximas de movimientos locales y ganancia mínima para mover un nodo
+const LOUVAIN_MAX_PASSES: usize = 20;
+const LOUVAIN_MIN_GAIN: f64 = 1e-9;
+
+/// Grafo no dirigido con pesos; los bucles guardan el peso interno de los nodos agregados.
+struct WeightedGraph {
+    adjacency: Vec<HashMap<usize, f64>>,
+    loops: Vec<f64>,
+}
+
+impl WeightedGraph {
+    fn new(n: usize, edges: &[(usize, usize, f64)]) -> Self {
+        let mut graph = Self { adjacency: vec![HashMap::new(); n], loops: vec![0.0; n] };
+        for &(a, b, w) in edges {
+            graph.add(a, b, w);
+        }
+        graph
+    }
+
+    fn add(&mut self, a: usize, b: usize, w: f64) {
+        if a == b {
+            self.loops[a] += w;
+        } else {
+            *self.adjacency[a].entry(b).or_default() += w;
+            *self.adjacency[b].entry(a).or_default() += w;
+        }
+    }
+
+    fn degree(&self, node: usize) -> f64 {
+        self.adjacency[node].values().sum::<f64>() + 2.0 * self.loops[node]
+    }
+
+    /// Un nodo por comunidad; las aristas internas pasan a ser bucles.
+    fn aggregate(&self, assignment: &[usize], communities: usize) -> Self {
+        let mut graph = Self { adjacency: vec![HashMap::new(); communities], loops: vec![0.0; communities] };
+        for (node, neighbours) in self.adjacency.iter().enumerate() {
+            graph.loops[assignment[node]] += self.loops[node];
+            for (&other, &w) in neighbours {
+                // Cada arista aparece en las dos listas de adyacencia
+                if node < other {
+                    graph.add(assignment[node], assignment[other], w);
+                }
+            }
+        }
+        graph
+    }
+}
+
+/// Fase de movimientos locales de Louvain: cada nodo pasa a la comunidad vecina que más aumenta
+/// la modularidad, hasta que ninguno se mueve. Devuelve la comunidad de cada nodo (0..k) y k.
+/// El recorrido es en orden de índice, así que el resultado es determinista.
+fn louvain_level(graph: &WeightedGraph) -> (Vec<usize>, usize) {
+    let n = graph.adjacency.len();
+    let degrees: Vec<f64> = (0..n).map(|i| graph.degree(i)).collect();
+    let total: f64 = degrees.iter().sum();
+    let mut community: Vec<usize> = (0..n).collect();
+    if total <= 0.0 {
+        return (community, n);
+    }
+    let mut community_degree = degrees.clone();
+
+    for _ in 0..LOUVAIN_MAX_PASSES {
+        let mut moved = false;
+        for node in 0..n {
+            let current = community[node];
+            community_degree[current] -= degrees[node];
+
+            let mut links: HashMap<usize, f64> = HashMap::new();
+            for (&other, &w) in &graph.adjacency[node] {
+                *links.entry(community[other]).or_default() += w;
+            }
+            // En orden de comunidad para que los empates se resuelvan siempre igual
+            let mut candidates: Vec<(usize, f64)> = links.into_iter().collect();
+            candidates.sort_by_key(|&(c, _)| c);
+
+            let gain = |c: usize, w: f64| w - community_degree[c] * degrees[node] / total;
+            let mut best = current;
+            let mut best_gain = gain(current, candidates.iter().find(|&&(c, _)| c == current).map_or(0.0, |&(_, w)| w));
+            for &(candidate, w) in &candidates {
+                let candidate_gain = gain(candidate, w);
+                if candidate_gain > best_gain + LOUVAIN_MIN_GAIN {
+                    best = candidate;
+                    best_gain = candidate_gain;
+                }
+            }
+
+            community_degree[best] += degrees[node];
+            if best != current {
+                community[node] = best;
+                moved = true;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+
+    // Renumeración compacta en orden de aparición
+    let mut ids: HashMap<usize, usize> = HashMap::new();
+    for c in community.iter_mut() {
+        let next = ids.len();
+        *c = *ids.entry(*c).or_insert(next);
+    }
+    (community, ids.len())
+}
+
+/// Jerarquía de comunidades por Louvain: `levels[l][nodo]` es la comunidad del nodo en el nivel `l`.
+/// Se detiene al llegar a `MAX_LEVELS` o cuando un nivel ya no agrupa nada.
+fn detect_hierarchy(n: usize, edges: &[(usize, usize, f64)]) -> Vec<Vec<usize>> {
+    let mut graph = WeightedGraph::new(n, edges);
+    let mut mapping: Vec<usize> = (0..n).collect();
+    let mut levels = Vec::new();
+
+    while levels.len() < MAX_LEVELS {
+        let nodes = graph.adjacency.len();
+        let (assignment, communities) = louvain_level(&graph);
+        if communities == nodes {
+            break;
+        }
+        mapping = mapping.iter().map(|&c| assignment[c]).collect();
+        levels.push(mapping.clone());
+        if communities == 1 {
+            break;
+        }
+        graph = graph.aggregate(&assignment, communities);
+    }
+    levels
+}
+
+/// Una comunidad detectada, antes de resumirla.
+struct Detected {
+    id: String,
+    level: usize,
+    /// Índices de las entidades, las más conectadas primero
+    members: Vec<usize>,
+    parent_id: Option<String>,
+    /// Subcomunidades del nivel anterior
+    children: Vec<String>,
+}
+
+/// Resumen intermedio del modo global: puntos clave de un lote de comunidades y cuánto ayudan.
+struct PartialAnswer {
+    score: u32,
+    points: String,
+}
+
+/// Contexto del modo global, listo para el prompt de la respuesta final.
+pub struct GlobalContext {
+    pub level: usize,
+    pub communities: usize,
+    /// Puntos clave de las respuestas parciales, los más útiles primero
+    pub points: String,
+}
+
+/// Detección de comunidades sobre el grafo de entidades (Louvain), resúmenes por nivel con el
+/// LLM y respuestas globales por map-reduce sobre esos resúmenes.
+pub struct CommunityService {
+    repo: Arc<dyn CommunityRepository>,
+    ai: Arc<RwLock<dyn AIService>>,
+    default_level: usize,
+    status: Mutex<CommunityIndexStatus>,
+}
+
+impl CommunityService {
+    pub fn new(repo: Arc<dyn CommunityRepository>, ai: Arc<RwLock<dyn AIService>>, default_level: usize) -> Self {
+        Self { repo, ai, default_level, status: Mutex::new(CommunityIndexStatus::default()) }
+    }
+
+    /// Lee `CHAT_COMMUNITY_LEVEL` (nivel por defecto del modo global).
+    pub fn default_level_from_env() -> Result<usize, AppError> {
+        match std::env::var("CHAT_COMMUNITY_LEVEL") {
+            Ok(value) => value.trim().parse::<usize>()
+                .map_err(|_| AppError::ConfigError("CHAT_COMMUNITY_LEVEL must be a number".to_string())),
+            Err(_) => Ok(DEFAULT_COMMUNITY_LEVEL),
+        }
+    }
+
+    pub fn status(&self) -> CommunityIndexStatus {
+        self.status.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    pub async fn list(&self, level: Option<usize>) -> Result<Vec<Community>, AppError> {
+        self.repo.list_communities(level).await
+    }
+
+    /// Lanza la reconstrucción en segundo plano (una sola a la vez).
+    pub fn start_rebuild(self: &Arc<Self>) -> Result<CommunityIndexStatus, AppError> {
+        {
+            let mut status = self.status.lock()
+                .map_err(|_| AppError::ConfigError("Community status lock poisoned".to_string()))?;
+            if status.building {
+                return Err(AppError::ValidationError("A community rebuild is already running".to_string()));
+            }
+            status.building = true;
+            status.last_error = None;
+        }
+
+        let service = self.clone();
+        tokio::spawn(async move {
+            // La reconstrucción va en su propia tarea: si entra en pánico, el error llega aquí y
+            // `building` vuelve a false en lugar de bloquear las reconstrucciones siguientes
+            let rebuild = service.clone();
+            let result = match tokio::spawn(async move { rebuild.rebuild().await }).await {
+                Ok(result) => result,
+                Err(e) => Err(AppError::InternalError(format!("Community rebuild task failed: {}", e))),
+            };
+            if let Ok(mut status) = service.status.lock() {
+                status.building = false;
+                match result {
+                    Ok((levels, communities)) => {
+                        tracing::info!("🏘️ Communities rebuilt: {} communities in {} levels", communities, levels);
+                        status.levels = levels;
+                        status.communities = communities;
+                        status.built_at = Some(now_secs());
+                    },
+                    Err(e) => {
+                        tracing::error!("❌ Community rebuild failed: {}", e);
+                        status.last_error = Some(e.to_string());
+                    },
+                }
+            }
+        });
+        Ok(self.status())
+    }
+
+    /// Detecta la jerarquía, resume de abajo arriba (cada nivel a partir de los resúmenes del
+    /// anterior) y sustituye las comunidades guardadas. Devuelve (niveles, comunidades). Si fallan
+    /// demasiados resúmenes (p. ej. con el proveedor caído) aborta sin tocar las comunidades previas.
+    async fn rebuild(&self) -> Result<(usize, usize), AppError> {
+        let (nodes, links) = self.repo.load_entity_graph().await?;
+        let index: HashMap<&str, usize> = nodes.iter().enumerate().map(|(i, n)| (n.name.as_str(), i)).collect();
+        let edges: Vec<(usize, usize, f64)> = links.iter()
+            .filter_map(|l| Some((*index.get(l.source.as_str())?, *index.get(l.target.as_str())?, l.weight)))
+            .collect();
+
+        let hierarchy = detect_hierarchy(nodes.len(), &edges);
+        let detected = group_communities(&hierarchy, &nodes, &edges);
+        let mut summaries: HashMap<String, Community> = HashMap::new();
+        let (mut attempted, mut failed) = (0, 0);
+        for level in 0..hierarchy.len() {
+            let mut pending: Vec<usize> = (0..detected.len()).filter(|&i| detected[i].level == level).collect();
+            pending.sort_by(|&a, &b| detected[b].members.len().cmp(&detected[a].members.len()));
+            pending.truncate(MAX_SUMMARIES_PER_LEVEL);
+
+            let prompts: Vec<(usize, String)> = pending.into_iter()
+                .map(|i| (i, self.summary_prompt(&detected[i], &nodes, &links, &index, &summaries)))
+                .collect();
+            let ai = self.ai.clone();
+            let responses: Vec<(usize, Result<String, AppError>)> = stream::iter(prompts)
+                .map(|(i, prompt)| {
+                    let ai = ai.clone();
+                    async move { (i, ai.read().await.generate_text(&prompt).await) }
+                })
+                .buffer_unordered(SUMMARY_CONCURRENCY)
+                .collect()
+                .await;
+            attempted += responses.len();
+            for (i, response) in responses {
+                match response {
+                    Ok(text) => {
+                        let community = to_community(&detected[i], &nodes, &text);
+                        summaries.insert(community.id.clone(), community);
+                    },
+                    Err(e) => {
+                        tracing::warn!("⚠️ Could not summarize community {}: {}", detected[i].id, e);
+                        failed += 1;
+                    },
+                }
+            }
+            // Se comprueba por nivel para no seguir llamando al LLM si ya no hay vuelta atrás
+            check_summary_failures(failed, attempted)?;
+        }
+
+        let mut communities: Vec<Community> = summaries.into_values().collect();
+        unlink_missing_parents(&mut communities);
+        communities.sort_by(|a, b| a.level.cmp(&b.level).then(b.size.cmp(&a.size)));
+        self.repo.replace_communities(&communities).await?;
+        Ok((hierarchy.len(), communities.len()))
+    }
+
+    fn summary_prompt(
+        &self,
+        detected: &Detected,
+        nodes: &[EntityNode],
+        links: &[EntityLink],
+        index: &HashMap<&str, usize>,
+        summaries: &HashMap<String, Community>
+    ) -> String {
+        let mut content = String::new();
+        let children: Vec<&Community> = detected.children.iter().filter_map(|id| summaries.get(id)).collect();
+
+        if children.is_empty() {
+            content.push_str("ENTIDADES:\n");
+            for &member in &detected.members {
+                let node = &nodes[member];
+                content.push_str(&format!(
+                    "- {} ({}): {}\n", node.name, node.category, node.description.as_deref().unwrap_or("sin descripción")
+                ));
+            }
+            content.push_str("\nRELACIONES:\n");
+            let members: HashSet<usize> = detected.members.iter().copied().collect();
+            for link in links {
+                let inside = |name: &str| index.get(name).is_some_and(|i| members.contains(i));
+                if inside(&link.source) && inside(&link.target) {
+                    content.push_str(&format!("- {} -[{}]-> {}", link.source, link.relation, link.target));
+                    if let Some(description) = &link.description {
+                        content.push_str(&format!(": {}", description));
+                    }
+                    content.push('\n');
+                }
+            }
+        } else {
+            content.push_str("SUBCOMUNIDADES:\n");
+            for child in children {
+                content.push_str(&format!("## {}\n{}\n\n", child.title, child.summary));
+            }
+            let names: Vec<&str> = detected.members.iter().take(STORED_MEMBERS).map(|&m| nodes[m].name.as_str()).collect();
+            content.push_str(&format!("ENTIDADES PRINCIPALES: {}\n", names.join(", ")));
+        }
+        let content: String = content.chars().take(SUMMARY_INPUT_CHARS).collect();
+
+        format!(
+            "Eres un analista que resume un grafo de conocimiento. A continuación tienes una comunidad de \
+             entidades muy relacionadas entre sí.\n\
+             Escribe en la primera línea un título breve (máximo 8 palabras) y, a continuación, un resumen \
+             de 3 a 6 frases con los temas que trata, las entidades clave y cómo se relacionan. \
+             Usa solo la información dada.\n\n{}",
+            content
+        )
+    }
+
+    /// Map-reduce sobre los resúmenes del nivel pedido (acotado al más alto disponible): cada lote
+    /// produce puntos clave con una puntuación de utilidad y se conservan los mejores que caben.
+    pub async fn global_context(&self, question: &str, level: Option<usize>) -> Result<GlobalContext, AppError> {
+        let all = self.repo.list_communities(None).await?;
+        let Some(max_level) = all.iter().map(|c| c.level).max() else {
+            return Err(AppError::ValidationError(
+                "No communities yet: run POST /api/communities/rebuild before using the global mode".to_string()
+            ));
+        };
+        let level = level.unwrap_or(self.default_level).min(max_level);
+        let communities: Vec<&Community> = all.iter().filter(|c| c.level == level).collect();
+
+        // Lotes de resúmenes que caben en el presupuesto de cada llamada
+        let mut batches: Vec<String> = Vec::new();
+        let mut current = String::new();
+        for community in &communities {
+            let text = format!("## {}\n{}\n\n", community.title, community.summary);
+            if !current.is_empty() && estimate_tokens(&current) + estimate_tokens(&text) > MAP_BATCH_TOKENS {
+                batches.push(std::mem::take(&mut current));
+            }
+            current.push_str(&text);
+        }
+        if !current.is_empty() {
+            batches.push(current);
+        }
+
+        let partials: Vec<PartialAnswer> = stream::iter(batches)
+            .map(|batch| async move {
+                let prompt = format!(
+                    "Con los RESÚMENES DE COMUNIDADES de un grafo de conocimiento, enumera los puntos clave que \
+                     ayudan a responder la pregunta, usando solo esa información.\n\
+                     La primera línea debe ser `PUNTUACIÓN: n`, donde n (0-100) indica cuánto ayudan estos \
+                     resúmenes a responder; si no ayudan, responde solo `PUNTUACIÓN: 0`.\n\n\
+                     PREGUNTA: {}\n\nRESÚMENES DE COMUNIDADES:\n{}",
+                    question, batch
+                );
+                match self.ai.read().await.generate_text(&prompt).await {
+                    Ok(text) => {
+                        let partial = parse_partial(&text);
+                        if partial.is_none() {
+                            tracing::warn!("⚠️ Global map step without a score, batch dropped: {:?}",
+                                text.trim().lines().next().unwrap_or_default());
+                        }
+                        partial
+                    },
+                    Err(e) => {
+                        tracing::warn!("⚠️ Global map step failed: {}", e);
+                        None
+                    },
+                }
+            })
+            .buffer_unordered(MAP_CONCURRENCY)
+            .filter_map(|partial| async move { partial })
+            .collect()
+            .await;
+
+        let mut partials: Vec<PartialAnswer> = partials.into_iter().filter(|p| p.score > 0).collect();
+        partials.sort_by_key(|p| std::cmp::Reverse(p.score));
+
+        let mut points = String::new();
+        for (i, partial) in partials.iter().enumerate() {
+            let text = format!("ANÁLISIS {} (utilidad {}/100):\n{}\n\n", i + 1, partial.score, partial.points);
+            if i > 0 && estimate_tokens(&points) + estimate_tokens(&text) > REDUCE_TOKENS {
+                break;
+            }
+            points.push_str(&text);
+        }
+
+        Ok(GlobalContext { level, communities: communities.len(), points })
+    }
+}
+
+/// Error si fallaron más de `MAX_SUMMARY_FAILURE_RATIO` de los resúmenes intentados (todos, en
+/// particular): un índice a medias o vacío es peor que conservar el anterior.
+fn check_summary_failures(failed: usize, attempted: usize) -> Result<(), AppError> {
+    if failed > 0 && failed as f64 > attempted as f64 * MAX_SUMMARY_FAILURE_RATIO {
+        return Err(AppError::AIError(format!(
+            "{} of {} community summaries failed; the previous communities were kept", failed, attempted
+        )));
+    }
+    Ok(())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Agrupa las entidades por comunidad en cada nivel (descartando las de menos de
+/// `MIN_COMMUNITY_SIZE`) con sus miembros ordenados por grado ponderado.
+fn group_communities(hierarchy: &[Vec<usize>], nodes: &[EntityNode], edges: &[(usize, usize, f64)]) -> Vec<Detected> {
+    let mut degree = vec![0.0f64; nodes.len()];
+    for &(a, b, w) in edges {
+        degree[a] += w;
+        degree[b] += w;
+    }
+    let id = |level: usize, community: usize| format!("c{}-{}", level, community);
+
+    let mut detected = Vec::new();
+    for (level, assignment) in hierarchy.iter().enumerate() {
+        let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (node, &community) in assignment.iter().enumerate() {
+            members.entry(community).or_default().push(node);
+        }
+        for (community, mut list) in members {
+            if list.len() < MIN_COMMUNITY_SIZE {
+                continue;
+            }
+            list.sort_by(|&a, &b| degree[b].total_cmp(&degree[a]).then(nodes[a].name.cmp(&nodes[b].name)));
+            let parent_id = hierarchy.get(level + 1).map(|parents| id(level + 1, parents[list[0]]));
+            let children: Vec<String> = match level {
+                0 => Vec::new(),
+                _ => {
+                    let mut children: Vec<usize> = list.iter().map(|&m| hierarchy[level - 1][m]).collect();
+                    children.sort_unstable();
+                    children.dedup();
+                    children.into_iter().map(|c| id(level - 1, c)).collect()
+                },
+            };
+            detected.push(Detected { id: id(level, community), level, members: list, parent_id, children });
+        }
+    }
+    detected
+}
+
+/// Título (primera línea, sin adornos de Markdown) y resumen (el resto) de la respuesta del LLM.
+fn to_community(detected: &Detected, nodes: &[EntityNode], text: &str) -> Community {
+    let mut lines = text.trim().lines();
+    let first = lines.next().unwrap_or_default();
+    let title = first.trim().trim_start_matches('#').trim().trim_matches('*').trim();
+    let title = title.strip_prefix("Título:").unwrap_or(title).trim();
+    let summary = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+
+    Community {
+        id: detected.id.clone(),
+        level: detected.level,
+        title: title.to_string(),
+        summary: if summary.is_empty() { text.trim().to_string() } else { summary },
+        size: detected.members.len(),
+        members: detected.members.iter().take(STORED_MEMBERS).map(|&m| nodes[m].name.clone()).collect(),
+        parent_id: detected.parent_id.clone(),
+    }
+}
+
+/// `PUNTUACIÓN: n` en la primera línea y los puntos clave en el resto. Tolera adornos de
+/// Markdown (`**PUNTUACIÓN: 80**`) y la forma `n/100`.
+fn parse_partial(text: &str) -> Option<PartialAnswer> {
+    let mut lines = text.trim().lines();
+    let header: String = lines.next()?.chars().filter(|c| !matches!(c, '*' | '_' | '`' | '#')).collect();
+    let (_, value) = header.split_once(':')?;
+    let digits: String = value.trim().chars().take_while(char::is_ascii_digit).collect();
+    let score: u32 = digits.parse().ok()?;
+    Some(PartialAnswer { score: score.min(100), points: lines.collect::<Vec<_>>().join("\n").trim().to_string() })
+}
+
+/// Quita el `parent_id` de las comunidades cuyo padre no se resumió (y por tanto no se guarda).
+fn unlink_missing_parents(communities: &mut [Community]) {
+    let stored: HashSet<String> = communities.iter().map(|c| c.id.clone()).collect();
+    for community in communities.iter_mut() {
+        if community.parent_id.as_ref().is_some_and(|parent| !stored.contains(parent)) {
+            community.parent_id = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use secrecy::SecretString;
+    use crate::domain::{
+        models::{AIConfig, AIProvider, InferenceResult, KnowledgeExtraction, MergeAdjudication},
+        ontology::Ontology,
+        ports::ChatStream,
+    };
+
+    /// Aristas de una clique completa entre los nodos `from..to`.
+    fn clique(from: usize, to: usize) -> Vec<(usize, usize, f64)> {
+        (from..to).flat_map(|a| (a + 1..to).map(move |b| (a, b, 1.0))).collect()
+    }
+
+    fn nodes(n: usize) -> Vec<EntityNode> {
+        (0..n).map(|i| EntityNode { name: format!("E{}", i), category: "Concept".to_string(), description: None }).collect()
+    }
+
+    fn detected(id: &str, parent_id: Option<&str>) -> Detected {
+        Detected { id: id.to_string(), level: 0, members: vec![0, 1], parent_id: parent_id.map(str::to_string), children: Vec::new() }
+    }
+
+    /// Cuatro cliques de 4 nodos: A-B y C-D unidas por seis aristas cada par, y un único puente A-C.
+    fn two_pairs_of_cliques() -> Vec<(usize, usize, f64)> {
+        let mut edges: Vec<_> = [0, 4, 8, 12].iter().flat_map(|&s| clique(s, s + 4)).collect();
+        for (a, b) in [(0, 4), (8, 12)] {
+            edges.extend((0..4).map(|i| (a + i, b + i, 1.0)));
+            edges.extend([(a, b + 1, 1.0), (a + 2, b + 3, 1.0)]);
+        }
+        edges.push((3, 11, 1.0));
+        edges
+    }
+
+    #[test]
+    fn two_cliques_joined_by_a_bridge_split_in_two() {
+        let mut edges = clique(0, 4);
+        edges.extend(clique(4, 8));
+        edges.push((3, 4, 1.0));
+
+        let (assignment, communities) = louvain_level(&WeightedGraph::new(8, &edges));
+        assert_eq!(communities, 2);
+        assert!(assignment[..4].iter().all(|&c| c == assignment[0]), "primera clique junta: {:?}", assignment);
+        assert!(assignment[4..].iter().all(|&c| c == assignment[4]), "segunda clique junta: {:?}", assignment);
+        assert_ne!(assignment[0], assignment[4]);
+    }
+
+    #[test]
+    fn aggregation_preserves_the_total_degree() {
+        let edges = two_pairs_of_cliques();
+        let graph = WeightedGraph::new(16, &edges);
+        let (assignment, communities) = louvain_level(&graph);
+        let aggregated = graph.aggregate(&assignment, communities);
+
+        let total = |g: &WeightedGraph| (0..g.adjacency.len()).map(|i| g.degree(i)).sum::<f64>();
+        assert_eq!(aggregated.adjacency.len(), communities);
+        assert!((total(&graph) - total(&aggregated)).abs() < 1e-9, "{} != {}", total(&graph), total(&aggregated));
+        assert!((total(&graph) - 2.0 * edges.len() as f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hierarchy_stops_when_nothing_merges() {
+        assert!(detect_hierarchy(5, &[]).is_empty(), "sin aristas no hay nada que agrupar");
+
+        let mut edges = clique(0, 4);
+        edges.extend(clique(4, 8));
+        edges.push((3, 4, 1.0));
+        let levels = detect_hierarchy(8, &edges);
+        assert_eq!(levels.len(), 1, "las dos cliques no se fusionan en un segundo nivel: {:?}", levels);
+    }
+
+    #[test]
+    fn hierarchy_builds_coarser_levels() {
+        let levels = detect_hierarchy(16, &two_pairs_of_cliques());
+        assert!(levels.len() >= 2, "{:?}", levels);
+        let count = |level: &Vec<usize>| level.iter().collect::<HashSet<_>>().len();
+        assert_eq!(count(&levels[0]), 4);
+        assert_eq!(count(&levels[1]), 2);
+        assert!(levels.windows(2).all(|w| count(&w[1]) < count(&w[0])));
+    }
+
+    #[test]
+    fn parents_and_children_are_consistent_across_levels() {
+        let edges = two_pairs_of_cliques();
+        let hierarchy = detect_hierarchy(16, &edges);
+        let detected = group_communities(&hierarchy, &nodes(16), &edges);
+        let by_id: HashMap<&str, &Detected> = detected.iter().map(|d| (d.id.as_str(), d)).collect();
+
+        for community in &detected {
+            assert_eq!(community.parent_id.is_some(), community.level + 1 < hierarchy.len(), "{}", community.id);
+            if let Some(parent_id) = &community.parent_id {
+                let parent = by_id[parent_id.as_str()];
+                assert_eq!(parent.level, community.level + 1);
+                assert!(parent.children.contains(&community.id), "{} no figura entre los hijos de {}", community.id, parent.id);
+                assert!(community.members.iter().all(|m| parent.members.contains(m)), "{} no está contenida en {}", community.id, parent.id);
+            }
+            for child in &community.children {
+                assert_eq!(by_id[child.as_str()].parent_id.as_deref(), Some(community.id.as_str()));
+            }
+            if community.level == 0 {
+                assert!(community.children.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn members_are_sorted_by_weighted_degree() {
+        let edges = vec![(0, 1, 1.0), (1, 2, 5.0), (2, 0, 1.0)];
+        let detected = group_communities(&[vec![0, 0, 0]], &nodes(3), &edges);
+        assert_eq!(detected.len(), 1);
+        assert_eq!(detected[0].members, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn parents_without_summary_are_unlinked() {
+        let nodes = nodes(2);
+        let mut communities = vec![
+            to_community(&detected("c0-0", Some("c1-0")), &nodes, "A\nresumen"),
+            to_community(&detected("c0-1", Some("c1-1")), &nodes, "B\nresumen"),
+            to_community(&detected("c1-0", None), &nodes, "C\nresumen"),
+        ];
+        unlink_missing_parents(&mut communities);
+        let parents: Vec<Option<&str>> = communities.iter().map(|c| c.parent_id.as_deref()).collect();
+        assert_eq!(parents, vec![Some("c1-0"), None, None]);
+    }
+
+    #[test]
+    fn community_title_drops_markdown() {
+        let nodes = nodes(2);
+        for text in ["## **Título: Energía solar**\nResumen.", "Título: Energía solar\nResumen.", "**Energía solar**\n\nResumen."] {
+            let community = to_community(&detected("c0-0", None), &nodes, text);
+            assert_eq!(community.title, "Energía solar", "{:?}", text);
+            assert_eq!(community.summary, "Resumen.");
+            assert_eq!(community.members, vec!["E0", "E1"]);
+        }
+        let untitled = to_community(&detected("c0-0", None), &nodes, "Solo una línea");
+        assert_eq!(untitled.summary, "Solo una línea");
+    }
+
+    #[test]
+    fn partial_answers_accept_common_score_formats() {
+        for header in ["PUNTUACIÓN: 80", "**PUNTUACIÓN: 80**", "Puntuación: 80/100", "PUNTUACIÓN: `80`", "## Puntuación: 80 / 100"] {
+            let partial = parse_partial(&format!("{}\n- punto clave", header)).unwrap_or_else(|| panic!("{:?}", header));
+            assert_eq!(partial.score, 80, "{:?}", header);
+            assert_eq!(partial.points, "- punto clave");
+        }
+        assert_eq!(parse_partial("PUNTUACIÓN: 250\n- x").map(|p| p.score), Some(100));
+        assert!(parse_partial("Sin puntuación\n- x").is_none());
+        assert!(parse_partial("PUNTUACIÓN: alta\n- x").is_none());
+        assert!(parse_partial("").is_none());
+    }
+
+    /// Repositorio en memoria con un grafo de 16 entidades y una comunidad ya guardada.
+    struct MemoryRepo {
+        communities: Mutex<Vec<Community>>,
+        panic_on_load: bool,
+    }
+
+    impl MemoryRepo {
+        fn new(panic_on_load: bool) -> Self {
+            let previous = to_community(&detected("c0-0", None), &nodes(2), "Anterior\nresumen");
+            Self { communities: Mutex::new(vec![previous]), panic_on_load }
+        }
+    }
+
+    #[async_trait]
+    impl CommunityRepository for MemoryRepo {
+        async fn load_entity_graph(&self) -> Result<(Vec<EntityNode>, Vec<EntityLink>), AppError> {
+            assert!(!self.panic_on_load, "fallo simulado al cargar el grafo");
+            let nodes = nodes(16);
+            let links = two_pairs_of_cliques().into_iter()
+                .map(|(a, b, weight)| EntityLink {
+                    source: nodes[a].name.clone(),
+                    target: nodes[b].name.clone(),
+                    relation: "RELATED_TO".to_string(),
+                    description: None,
+                    weight,
+                })
+                .collect();
+            Ok((nodes, links))
+        }
+        async fn replace_communities(&self, communities: &[Community]) -> Result<(), AppError> {
+            *self.communities.lock().unwrap() = communities.to_vec();
+            Ok(())
+        }
+        async fn list_communities(&self, _level: Option<usize>) -> Result<Vec<Community>, AppError> {
+            Ok(self.communities.lock().unwrap().clone())
+        }
+    }
+
+    /// LLM que responde siempre lo mismo; `None` simula el proveedor caído.
+    struct FixedAI(Option<String>);
+
+    #[async_trait]
+    impl AIService for FixedAI {
+        async fn extract_knowledge(&self, _text: &str, _ontology: &Ontology) -> Result<KnowledgeExtraction, AppError> {
+            unimplemented!()
+        }
+        async fn generate_embedding(&self, _text: &str) -> Result<Vec<f32>, AppError> {
+            unimplemented!()
+        }
+        async fn generate_embeddings(&self, _texts: &[&str]) -> Result<Vec<Vec<f32>>, AppError> {
+            unimplemented!()
+        }
+        fn embedding_batch_size(&self) -> usize {
+            1
+        }
+        fn update_config(&mut self, _config: AIConfig) -> Result<(), AppError> {
+            Ok(())
+        }
+        fn get_config(&self) -> AIConfig {
+            AIConfig {
+                provider: AIProvider::Ollama,
+                model_name: "test".to_string(),
+                embedding_model: "test".to_string(),
+                api_key: SecretString::new("".into()),
+                embedding_dim: 3,
+                base_url: None,
+            }
+        }
+        async fn generate_inference(&self, _prompt: &str) -> Result<InferenceResult, AppError> {
+            unimplemented!()
+        }
+        async fn generate_text(&self, _prompt: &str) -> Result<String, AppError> {
+            self.0.clone().ok_or_else(|| AppError::AITransientError { message: "503".to_string(), retry_after: None })
+        }
+        async fn adjudicate_merge(&self, _prompt: &str) -> Result<MergeAdjudication, AppError> {
+            unimplemented!()
+        }
+        async fn stream_chat(&self, _preamble: &str, _message: &str) -> Result<ChatStream, AppError> {
+            unimplemented!()
+        }
+    }
+
+    fn service(repo: Arc<MemoryRepo>, reply: Option<&str>) -> Arc<CommunityService> {
+        let ai: Arc<RwLock<dyn AIService>> = Arc::new(RwLock::new(FixedAI(reply.map(str::to_string))));
+        Arc::new(CommunityService::new(repo, ai, DEFAULT_COMMUNITY_LEVEL))
+    }
+
+    /// Lanza la reconstrucción y espera a que termine.
+    async fn rebuild_in_background(service: &Arc<CommunityService>) -> CommunityIndexStatus {
+        service.start_rebuild().expect("no hay otra reconstrucción en curso");
+        for _ in 0..200 {
+            let status = service.status();
+            if !status.building {
+                return status;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("la reconstrucción no terminó");
+    }
+
+    #[test]
+    fn too_many_failed_summaries_abort_the_rebuild() {
+        assert!(check_summary_failures(0, 0).is_ok(), "un grafo sin comunidades no es un fallo");
+        assert!(check_summary_failures(0, 10).is_ok());
+        assert!(check_summary_failures(5, 10).is_ok(), "la mitad aún se acepta");
+        assert!(check_summary_failures(6, 10).is_err());
+        assert!(check_summary_failures(3, 3).is_err());
+    }
+
+    #[tokio::test]
+    async fn a_provider_outage_keeps_the_previous_communities() {
+        let repo = Arc::new(MemoryRepo::new(false));
+        let status = rebuild_in_background(&service(repo.clone(), None)).await;
+
+        assert!(status.last_error.as_deref().is_some_and(|e| e.contains("summaries failed")), "{:?}", status);
+        assert!(status.built_at.is_none(), "no se informa de una reconstrucción completada");
+        let kept = repo.communities.lock().unwrap().clone();
+        assert_eq!(kept.iter().map(|c| c.title.as_str()).collect::<Vec<_>>(), vec!["Anterior"]);
+    }
+
+    #[tokio::test]
+    async fn a_successful_rebuild_replaces_the_communities() {
+        let repo = Arc::new(MemoryRepo::new(false));
+        let status = rebuild_in_background(&service(repo.clone(), Some("Título\nResumen."))).await;
+
+        assert!(status.last_error.is_none(), "{:?}", status);
+        assert_eq!(status.communities, 6, "4 cliques y 2 pares en el segundo nivel");
+        assert_eq!(repo.communities.lock().unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn a_panicking_rebuild_releases_the_building_flag() {
+        let service = service(Arc::new(MemoryRepo::new(true)), Some("Título\nResumen."));
+        let status = rebuild_in_background(&service).await;
+
+        assert!(status.last_error.as_deref().is_some_and(|e| e.contains("task failed")), "{:?}", status);
+        assert!(service.start_rebuild().is_ok(), "se puede volver a lanzar");
+    }
+}
@@ -0,0 +1,371 @@
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use crate::domain::{
+    ports::{ConversationRepository, AIService},
+    models::{estimate_tokens, Conversation, ConversationSummary, ConversationTurn},
+    errors::AppError
+};
+
+// Presupuesto por defecto (tokens estimados) del historial incluido en el prompt
+const DEFAULT_HISTORY_TOKENS: usize = 1500;
+const TITLE_MAX_CHARS: usize = 60;
+
+fn turn_text(turn: &ConversationTurn) -> String {
+    format!("Usuario: {}\nAsistente: {}", turn.question, turn.answer)
+}
+
+/// Historial ya preparado para una pregunta nueva.
+pub struct ConversationContext {
+    pub id: Uuid,
+    /// Título con el que se crea la conversación si aún no existe
+    pub title: String,
+    /// Resumen de lo antiguo más los últimos turnos, listo para el prompt (vacío si no hay historial)
+    pub history: String,
+    /// Pregunta reescrita para que se entienda sin el historial (la que se embebe)
+    pub standalone_question: Option<String>,
+}
+
+impl ConversationContext {
+    pub fn search_query<'a>(&'a self, question: &'a str) -> &'a str {
+        self.standalone_question.as_deref().unwrap_or(question)
+    }
+}
+
+/// Conversaciones del chat: historial con presupuesto de tokens (lo que no cabe se resume con
+/// el LLM) y reescritura de las preguntas de seguimiento en preguntas autónomas.
+pub struct ConversationService {
+    repo: Arc<dyn ConversationRepository>,
+    ai: Arc<RwLock<dyn AIService>>,
+    history_tokens: usize,
+}
+
+impl ConversationService {
+    pub fn new(repo: Arc<dyn ConversationRepository>, ai: Arc<RwLock<dyn AIService>>, history_tokens: usize) -> Self {
+        Self { repo, ai, history_tokens }
+    }
+
+    /// Lee `CHAT_HISTORY_TOKENS` (presupuesto del historial en el prompt).
+    pub fn history_tokens_from_env() -> Result<usize, AppError> {
+        match std::env::var("CHAT_HISTORY_TOKENS") {
+            Ok(value) => value.trim().parse::<usize>()
+                .map_err(|_| AppError::ConfigError("CHAT_HISTORY_TOKENS must be a number".to_string())),
+            Err(_) => Ok(DEFAULT_HISTORY_TOKENS),
+        }
+    }
+
+    /// Prepara el historial de `id` (o una conversación nueva) para responder a `question`.
+    pub async fn open(&self, id: Option<Uuid>, question: &str) -> Result<ConversationContext, AppError> {
+        let title: String = question.trim().chars().take(TITLE_MAX_CHARS).collect();
+        let Some(id) = id else {
+            return Ok(ConversationContext { id: Uuid::new_v4(), title, history: String::new(), standalone_question: None });
+        };
+        let conversation = self.repo.get_conversation(id).await?
+            .ok_or_else(|| AppError::NotFoundError(format!("Conversation {}", id)))?;
+
+        let history = self.history(id, &conversation).await?;
+        let standalone_question = if history.is_empty() { None } else { self.rewrite(&history, question).await };
+
+        Ok(ConversationContext { id, title, history, standalone_question })
+    }
+
+    /// Turnos recientes que caben en el presupuesto; los anteriores se resumen (y el resumen se guarda).
+    async fn history(&self, id: Uuid, conversation: &Conversation) -> Result<String, AppError> {
+        let mut summary = conversation.summary.clone();
+        let pending = &conversation.turns[conversation.summarized_turns.min(conversation.turns.len())..];
+
+        let mut budget = self.history_tokens.saturating_sub(summary.as_deref().map(estimate_tokens).unwrap_or(0));
+        let mut keep_from = pending.len();
+        for (i, turn) in pending.iter().enumerate().rev() {
+            let cost = estimate_tokens(&turn_text(turn));
+            if cost > budget {
+                break;
+            }
+            budget -= cost;
+            keep_from = i;
+        }
+
+        let overflow = &pending[..keep_from];
+        let mut omitted = 0;
+        if !overflow.is_empty() {
+            let turns: Vec<String> = overflow.iter().map(turn_text).collect();
+            let prompt = format!(
+                "Resume esta conversación en un párrafo breve, en su mismo idioma, conservando los nombres, \
+                 datos y decisiones que puedan ser necesarios para entender preguntas posteriores. \
+                 Devuelve solo el resumen.\n\nRESUMEN PREVIO:\n{}\n\nTURNOS:\n{}",
+                summary.as_deref().unwrap_or("(ninguno)"),
+                turns.join("\n\n")
+            );
+            match self.ai.read().await.generate_text(&prompt).await {
+                Ok(text) if !text.is_empty() => {
+                    let summarized_turns = conversation.summarized_turns + keep_from;
+                    self.repo.set_conversation_summary(id, &text, summarized_turns).await?;
+                    summary = Some(text);
+                },
+                // Sin resumen, lo que no cabe se deja fuera, pero el prompt avisa de que falta
+                Ok(_) => {
+                    tracing::warn!("⚠️ Empty summary for conversation {}", id);
+                    omitted = overflow.len();
+                },
+                Err(e) => {
+                    tracing::warn!("⚠️ Could not summarize conversation {}: {}", id, e);
+                    omitted = overflow.len();
+                },
+            }
+        }
+
+        let mut history = String::new();
+        if let Some(summary) = summary {
+            history.push_str(&format!("Resumen de lo anterior: {}\n\n", summary));
+        }
+        if omitted > 0 {
+            history.push_str(&format!("[{} turnos anteriores omitidos: no caben en el historial]\n\n", omitted));
+        }
+        for turn in &pending[keep_from..] {
+            history.push_str(&turn_text(turn));
+            history.push_str("\n\n");
+        }
+        Ok(history.trim_end().to_string())
+    }
+
+    /// Reescribe una pregunta de seguimiento ("¿y quién la financió?") como pregunta autónoma.
+    /// Si el LLM falla se usa la pregunta original.
+    async fn rewrite(&self, history: &str, question: &str) -> Option<String> {
+        let prompt = format!(
+            "Dado el historial de una conversación y una pregunta de seguimiento, reescribe la pregunta \
+             para que se entienda sin el historial (sustituye pronombres y referencias por los nombres \
+             concretos). Mantén el idioma. Si ya es autónoma, devuélvela igual. Devuelve solo la pregunta.\n\n\
+             HISTORIAL:\n{}\n\nPREGUNTA: {}",
+            history, question
+        );
+        match self.ai.read().await.generate_text(&prompt).await {
+            Ok(text) if !text.is_empty() => Some(text),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!("⚠️ Could not rewrite follow-up question: {}", e);
+                None
+            },
+        }
+    }
+
+    pub async fn record_turn(&self, context: &ConversationContext, turn: ConversationTurn) -> Result<(), AppError> {
+        self.repo.append_turn(context.id, &context.title, &turn).await
+    }
+
+    pub async fn list(&self, limit: usize) -> Result<Vec<ConversationSummary>, AppError> {
+        self.repo.list_conversations(limit).await
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Conversation, AppError> {
+        self.repo.get_conversation(id).await?
+            .ok_or_else(|| AppError::NotFoundError(format!("Conversation {}", id)))
+    }
+
+    pub async fn rename(&self, id: Uuid, title: &str) -> Result<ConversationSummary, AppError> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err(AppError::ValidationError("Conversation title cannot be empty".to_string()));
+        }
+        self.repo.rename_conversation(id, title).await?
+            .ok_or_else(|| AppError::NotFoundError(format!("Conversation {}", id)))
+    }
+
+    pub async fn delete(&self, id: Uuid) -> Result<(), AppError> {
+        if self.repo.delete_conversation(id).await? {
+            Ok(())
+        } else {
+            Err(AppError::NotFoundError(format!("Conversation {}", id)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use async_trait::async_trait;
+    use secrecy::SecretString;
+    use crate::domain::{
+        models::{AIConfig, AIProvider, InferenceResult, KnowledgeExtraction, MergeAdjudication},
+        ontology::Ontology,
+        ports::ChatStream,
+    };
+
+    /// Repositorio en memoria que solo registra los resúmenes guardados.
+    #[derive(Default)]
+    struct RecordingRepo {
+        summaries: Mutex<Vec<(String, usize)>>,
+    }
+
+    #[async_trait]
+    impl ConversationRepository for RecordingRepo {
+        async fn list_conversations(&self, _limit: usize) -> Result<Vec<ConversationSummary>, AppError> {
+            unimplemented!()
+        }
+        async fn get_conversation(&self, _id: Uuid) -> Result<Option<Conversation>, AppError> {
+            unimplemented!()
+        }
+        async fn append_turn(&self, _id: Uuid, _title: &str, _turn: &ConversationTurn) -> Result<(), AppError> {
+            unimplemented!()
+        }
+        async fn set_conversation_summary(&self, _id: Uuid, summary: &str, summarized_turns: usize) -> Result<(), AppError> {
+            self.summaries.lock().unwrap().push((summary.to_string(), summarized_turns));
+            Ok(())
+        }
+        async fn rename_conversation(&self, _id: Uuid, _title: &str) -> Result<Option<ConversationSummary>, AppError> {
+            unimplemented!()
+        }
+        async fn delete_conversation(&self, _id: Uuid) -> Result<bool, AppError> {
+            unimplemented!()
+        }
+    }
+
+    /// LLM que responde siempre lo mismo (`None` = proveedor caído) y guarda los prompts recibidos.
+    struct ScriptedAI {
+        reply: Option<String>,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl AIService for ScriptedAI {
+        async fn extract_knowledge(&self, _text: &str, _ontology: &Ontology) -> Result<KnowledgeExtraction, AppError> {
+            unimplemented!()
+        }
+        async fn generate_embedding(&self, _text: &str) -> Result<Vec<f32>, AppError> {
+            unimplemented!()
+        }
+        async fn generate_embeddings(&self, _texts: &[&str]) -> Result<Vec<Vec<f32>>, AppError> {
+            unimplemented!()
+        }
+        fn embedding_batch_size(&self) -> usize {
+            1
+        }
+        fn update_config(&mut self, _config: AIConfig) -> Result<(), AppError> {
+            Ok(())
+        }
+        fn get_config(&self) -> AIConfig {
+            AIConfig {
+                provider: AIProvider::Ollama,
+                model_name: "test".to_string(),
+                embedding_model: "test".to_string(),
+                api_key: SecretString::new("".into()),
+                embedding_dim: 3,
+                base_url: None,
+            }
+        }
+        async fn generate_inference(&self, _prompt: &str) -> Result<InferenceResult, AppError> {
+            unimplemented!()
+        }
+        async fn generate_text(&self, prompt: &str) -> Result<String, AppError> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            self.reply.clone().ok_or_else(|| AppError::AITransientError { message: "503".to_string(), retry_after: None })
+        }
+        async fn adjudicate_merge(&self, _prompt: &str) -> Result<MergeAdjudication, AppError> {
+            unimplemented!()
+        }
+        async fn stream_chat(&self, _preamble: &str, _message: &str) -> Result<ChatStream, AppError> {
+            unimplemented!()
+        }
+    }
+
+    fn service(reply: Option<&str>, history_tokens: usize) -> (ConversationService, Arc<RecordingRepo>, Arc<RwLock<ScriptedAI>>) {
+        let repo = Arc::new(RecordingRepo::default());
+        let ai = Arc::new(RwLock::new(ScriptedAI { reply: reply.map(str::to_string), prompts: Mutex::new(Vec::new()) }));
+        (ConversationService::new(repo.clone(), ai.clone(), history_tokens), repo, ai)
+    }
+
+    fn turn(n: usize) -> ConversationTurn {
+        ConversationTurn {
+            question: format!("pregunta {}", n),
+            standalone_question: None,
+            // ~25 tokens por turno con el texto de la pregunta
+            answer: format!("respuesta {} {}", n, "x".repeat(60)),
+            sources: Vec::new(),
+            created_at: String::new(),
+        }
+    }
+
+    fn conversation(turns: usize, summary: Option<&str>, summarized_turns: usize) -> Conversation {
+        Conversation {
+            id: Uuid::nil().to_string(),
+            title: "test".to_string(),
+            summary: summary.map(str::to_string),
+            summarized_turns,
+            turns: (0..turns).map(turn).collect(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    fn turn_cost() -> usize {
+        estimate_tokens(&turn_text(&turn(0)))
+    }
+
+    #[tokio::test]
+    async fn a_history_within_budget_is_kept_verbatim() {
+        let (service, repo, ai) = service(Some("resumen"), 1000);
+
+        let history = service.history(Uuid::nil(), &conversation(3, None, 0)).await.unwrap();
+
+        assert!(ai.read().await.prompts.lock().unwrap().is_empty(), "no hace falta resumir");
+        assert!(repo.summaries.lock().unwrap().is_empty());
+        let positions: Vec<usize> = (0..3).map(|n| history.find(&format!("pregunta {}", n)).expect("turno incluido")).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "los turnos conservan su orden");
+    }
+
+    #[tokio::test]
+    async fn turns_over_budget_are_summarized_and_the_summary_is_saved() {
+        // Caben los dos últimos turnos
+        let (service, repo, ai) = service(Some("hablaron de energía solar"), turn_cost() * 2 + 1);
+
+        let history = service.history(Uuid::nil(), &conversation(5, None, 0)).await.unwrap();
+
+        let prompts = ai.read().await.prompts.lock().unwrap().clone();
+        assert_eq!(prompts.len(), 1);
+        assert!(prompts[0].contains("pregunta 0") && prompts[0].contains("pregunta 2"), "resume los turnos que no caben");
+        assert!(!prompts[0].contains("pregunta 3"), "los turnos que caben no se resumen");
+
+        assert_eq!(repo.summaries.lock().unwrap().as_slice(), &[("hablaron de energía solar".to_string(), 3)]);
+        assert!(history.starts_with("Resumen de lo anterior: hablaron de energía solar"), "{}", history);
+        assert!(history.contains("pregunta 3") && history.contains("pregunta 4"));
+        assert!(!history.contains("pregunta 2"));
+    }
+
+    #[tokio::test]
+    async fn an_existing_summary_uses_budget_and_covers_its_turns() {
+        let summary = "y".repeat(4 * turn_cost());
+        // El resumen previo ocupa casi todo: solo cabe el último turno
+        let budget = estimate_tokens(&summary) + turn_cost() + 1;
+        let (service, repo, ai) = service(Some("resumen nuevo"), budget);
+
+        let history = service.history(Uuid::nil(), &conversation(6, Some(&summary), 3)).await.unwrap();
+
+        let prompts = ai.read().await.prompts.lock().unwrap().clone();
+        assert_eq!(prompts.len(), 1);
+        assert!(prompts[0].contains(&summary), "el resumen previo se amplía, no se pierde");
+        assert!(!prompts[0].contains("pregunta 2"), "los turnos ya resumidos no se repiten");
+        assert_eq!(repo.summaries.lock().unwrap().as_slice(), &[("resumen nuevo".to_string(), 5)]);
+        assert!(history.contains("pregunta 5") && !history.contains("pregunta 4"));
+    }
+
+    #[tokio::test]
+    async fn a_failed_summary_leaves_a_marker_instead_of_silently_dropping_turns() {
+        let (service, repo, _ai) = service(None, turn_cost() * 2 + 1);
+
+        let history = service.history(Uuid::nil(), &conversation(5, None, 0)).await.unwrap();
+
+        assert!(repo.summaries.lock().unwrap().is_empty(), "sin resumen no se marca nada como resumido");
+        assert!(history.starts_with("[3 turnos anteriores omitidos"), "{}", history);
+        assert!(history.contains("pregunta 3") && history.contains("pregunta 4"));
+    }
+
+    #[tokio::test]
+    async fn an_empty_summary_also_leaves_a_marker() {
+        let (service, repo, _ai) = service(Some(""), turn_cost() + 1);
+
+        let history = service.history(Uuid::nil(), &conversation(3, None, 0)).await.unwrap();
+
+        assert!(repo.summaries.lock().unwrap().is_empty());
+        assert!(history.starts_with("[2 turnos anteriores omitidos"), "{}", history);
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 use crate::domain::models::AIConfig;
 
 #[derive(Deserialize, ToSchema)]
@@ -10,6 +10,20 @@ pub struct AdminConfigPayload {
 
 #[derive(Serialize, ToSchema)]
 pub struct IngestionResponse {
+    /// ID del trabajo de ingesta
     pub id: String,
     pub status: String,
-}
\ No newline at end of file
+    /// Documento que recibirá los chunks
+    pub document_id: String,
+}
+#[derive(Deserialize, IntoParams)]
+pub struct QuarantineQuery {
+    /// Máximo de entradas a devolver (por defecto 100, tope 1000)
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct CommunityQuery {
+    /// Nivel de la jerarquía (0 = comunidades más pequeñas); sin él, todos
+    pub level: Option<usize>,
+}
@@ -1,112 +1,633 @@
 use uuid::Uuid;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, Semaphore};
+use futures::stream::{self, StreamExt};
+use sha2::{Digest, Sha256};
+use crate::application::chunking::{build_chunker, TextChunk};
+use crate::application::jobs::JobHandle;
 use crate::domain::{
     ports::{KGRepository, AIService},
-    // models::IngestionRequest, // Comentado para evitar warning
+    models::{
+        IngestionRequest, IngestionEvent, ChunkFailure, ChunkStage, KnowledgeExtraction, NewDocument, ChunkRecord, DocumentInfo,
+        EntityEmbedding, StaleEntity
+    },
+    ontology::Ontology,
     errors::AppError
 };
 
-// Reducir drásticamente para mejorar la precisión vectorial
-// 1500 caracteres ~= 300-400 tokens (Sweet spot para embeddings)
-const CHUNK_SIZE: usize = 1500; 
-const CHUNK_OVERLAP: usize = 200;
+// Concurrencia por defecto: chunks en vuelo y llamadas simultáneas al proveedor
+const DEFAULT_CHUNKS_IN_FLIGHT: usize = 4;
+const DEFAULT_EMBED_CONCURRENCY: usize = 4;
+const DEFAULT_EXTRACT_CONCURRENCY: usize = 2;
+// Entidades por lote al recalcular embeddings de entidades
+const ENTITY_REFRESH_BATCH: usize = 64;
+// Descripción acumulada a partir de la cual se resume con el LLM antes de embeberla
+const ENTITY_DESCRIPTION_MAX_CHARS: usize = 1200;
+
+/// Normaliza espacios (incluidos saltos de página) para que el hash no dependa del formato de origen.
+fn normalize_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// SHA-256 (hex) del texto normalizado; base de la deduplicación de documentos y chunks.
+fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(normalize_text(text).as_bytes()))
+}
+
+/// Prompt para resumir la descripción acumulada de una entidad, o `None` si aún cabe en el límite.
+fn summary_prompt(entity: &StaleEntity) -> Option<String> {
+    if entity.description.chars().count() <= ENTITY_DESCRIPTION_MAX_CHARS {
+        return None;
+    }
+    Some(format!(
+        "Resume en un máximo de 3 frases, en el mismo idioma, qué es \"{}\" ({}) \
+         a partir de estas descripciones. Devuelve solo el resumen.\n\n{}",
+        entity.name, entity.category, entity.description
+    ))
+}
+
+/// Asigna a cada entidad del lote su vector. Si el proveedor devuelve otro número de vectores
+/// no se puede saber a quién corresponde cada uno, así que se descarta el lote entero.
+fn attach_embeddings(mut updates: Vec<EntityEmbedding>, embeddings: Vec<Vec<f32>>) -> Option<Vec<EntityEmbedding>> {
+    if embeddings.len() != updates.len() {
+        return None;
+    }
+    for (update, embedding) in updates.iter_mut().zip(embeddings) {
+        update.embedding = embedding;
+    }
+    Some(updates)
+}
+
+/// Límites de concurrencia de la ingesta. Los semáforos se comparten entre todos los trabajos,
+/// de modo que el proveedor nunca recibe más de `embed`/`extract` llamadas simultáneas.
+#[derive(Clone)]
+pub struct IngestionLimits {
+    chunks_in_flight: usize,
+    embed: Arc<Semaphore>,
+    extract: Arc<Semaphore>,
+}
+
+impl IngestionLimits {
+    pub fn new(chunks_in_flight: usize, embed: usize, extract: usize) -> Self {
+        Self {
+            chunks_in_flight: chunks_in_flight.max(1),
+            embed: Arc::new(Semaphore::new(embed.max(1))),
+            extract: Arc::new(Semaphore::new(extract.max(1))),
+        }
+    }
+
+    /// Lee `INGEST_CHUNK_CONCURRENCY`, `INGEST_EMBED_CONCURRENCY` e `INGEST_EXTRACT_CONCURRENCY`.
+    pub fn from_env() -> Result<Self, AppError> {
+        let read = |name: &str, default: usize| -> Result<usize, AppError> {
+            match std::env::var(name) {
+                Ok(value) => value.trim().parse::<usize>()
+                    .map_err(|_| AppError::ConfigError(format!("{} must be a number", name))),
+                Err(_) => Ok(default),
+            }
+        };
+        Ok(Self::new(
+            read("INGEST_CHUNK_CONCURRENCY", DEFAULT_CHUNKS_IN_FLIGHT)?,
+            read("INGEST_EMBED_CONCURRENCY", DEFAULT_EMBED_CONCURRENCY)?,
+            read("INGEST_EXTRACT_CONCURRENCY", DEFAULT_EXTRACT_CONCURRENCY)?,
+        ))
+    }
+}
+
+/// Estado de un chunk a lo largo del pipeline concurrente. `Pending` y `Embedded` son etapas
+/// intermedias; al consumidor solo llegan los estados finales.
+enum ChunkOutcome {
+    Cancelled,
+    /// Hay un chunk idéntico ya procesado: el consumidor lo copia (sin IA) en su turno
+    Reusable { record: ChunkRecord },
+    Pending { record: ChunkRecord },
+    Embedded { record: ChunkRecord, embedding: Vec<f32> },
+    Failed { stage: ChunkStage, reason: String, transient: bool },
+    Analyzed {
+        record: ChunkRecord,
+        embedding: Vec<f32>,
+        extraction: Result<KnowledgeExtraction, AppError>,
+    },
+}
+
+/// Resultado de preparar el nodo `Document` en la primera ejecución de un trabajo.
+enum PreparedDocument {
+    /// No hay nada que procesar; el id es el del documento vigente
+    Skip(Uuid),
+    /// Procesar los chunks sobre este documento
+    Process(Uuid),
+}
 
 pub struct IngestionService {
     repo: Arc<dyn KGRepository>,
     ai: Arc<RwLock<dyn AIService>>,
+    limits: IngestionLimits,
+    /// Ontología vigente al lanzar el trabajo (fija durante toda la ejecución)
+    ontology: Arc<Ontology>,
 }
 
 impl IngestionService {
-    pub fn new(repo: Arc<dyn KGRepository>, ai: Arc<RwLock<dyn AIService>>) -> Self {
-        Self { repo, ai }
-    }
-
-    /// Función auxiliar para dividir texto preservando palabras completas
-    // En split_text_into_chunks:
-    // Implementar lógica de ventana deslizante (sliding window)
-    fn split_text_into_chunks(&self, text: &str) -> Vec<String> {
-        let mut chunks = Vec::new();
-        let chars: Vec<char> = text.chars().collect();
-        let mut start = 0;
-
-        while start < chars.len() {
-            let end = std::cmp::min(start + CHUNK_SIZE, chars.len());
-        
-            // Ajuste para no cortar palabras (buscar espacio hacia atrás)
-            let mut actual_end = end;
-            if actual_end < chars.len() {
-                while actual_end > start && !chars[actual_end].is_whitespace() {
-                    actual_end -= 1;
-                }
+    pub fn new(
+        repo: Arc<dyn KGRepository>,
+        ai: Arc<RwLock<dyn AIService>>,
+        limits: IngestionLimits,
+        ontology: Arc<Ontology>
+    ) -> Self {
+        Self { repo, ai, limits, ontology }
+    }
+
+    /// Ejecuta (o reanuda) un trabajo de ingesta. El progreso y los contadores se vuelcan en `job`,
+    /// que persiste un checkpoint tras cada chunk para poder continuar después de un reinicio.
+    pub async fn run_job(&self, request: IngestionRequest, job: &JobHandle) -> Result<Uuid, AppError> {
+        let snapshot = job.snapshot().await;
+        let document_id = Uuid::parse_str(&snapshot.document_id)
+            .map_err(|e| AppError::ValidationError(format!("Invalid document id: {}", e)))?;
+
+        // Las opciones llegan resueltas desde el gestor de trabajos: reanudar trocea igual
+        let chunks = build_chunker(&request.chunking).chunk(&request.content);
+        let force = request.force;
+
+        let document_id = if snapshot.total_chunks == 0 {
+            // Primera ejecución: deduplicar y preparar el nodo Document
+            let document_id = match self.prepare_document(document_id, snapshot.replace_existing, request, chunks.len(), job).await? {
+                PreparedDocument::Skip(existing_id) => return Ok(existing_id),
+                PreparedDocument::Process(document_id) => document_id,
+            };
+            job.emit(IngestionEvent::DocumentChunked { total: chunks.len() }).await;
+            job.update(|j| j.total_chunks = chunks.len()).await?;
+            document_id
+        } else {
+            // Reanudación: descartar lo que el chunk interrumpido llegara a escribir
+            let purge = self.repo.purge_document_chunks(document_id, snapshot.next_chunk).await?;
+            job.emit(IngestionEvent::Resumed {
+                from_chunk: snapshot.next_chunk + 1,
+                total: snapshot.total_chunks,
+                discarded: purge.chunks_deleted,
+            }).await;
+            document_id
+        };
+
+        self.process_chunks(document_id, chunks, snapshot.next_chunk, force, job).await?;
+        if !job.is_cancelled() {
+            self.refresh_entity_profiles(job).await?;
+        }
+
+        Ok(document_id)
+    }
+
+    /// Deduplica y registra (o actualiza) el nodo Document. Hasta que `process_chunks` termina,
+    /// los `pending` chunks cuentan como fallidos: si el trabajo se interrumpe, volver a subir el
+    /// documento lo reintenta en lugar de darlo por ingerido (`Unchanged`/`AlreadyIngested`).
+    async fn prepare_document(
+        &self,
+        document_id: Uuid,
+        replace_existing: bool,
+        request: IngestionRequest,
+        pending: usize,
+        job: &JobHandle
+    ) -> Result<PreparedDocument, AppError> {
+        let hash = content_hash(&request.content);
+        let force = request.force;
+
+        let previous = if replace_existing {
+            let existing = self.repo.get_document(document_id).await?
+                .ok_or_else(|| AppError::NotFoundError(format!("Document {}", document_id)))?;
+
+            // Sin cambios y sin fallos pendientes: nada que hacer
+            if !force && existing.content_hash == hash && existing.failed_chunks == 0 {
+                job.emit(IngestionEvent::Unchanged { document_id: existing.id }).await;
+                return Ok(PreparedDocument::Skip(document_id));
             }
-            if actual_end == start { actual_end = end; } // Fallback si la palabra es gigante
+            Some(existing)
+        } else if force {
+            None
+        } else {
+            // Deduplicación: el mismo contenido ya ingerido no vuelve a pasar por la IA
+            match self.repo.find_document_by_hash(&hash).await? {
+                Some(existing) if existing.id != document_id.to_string() => {
+                    let existing_id = Uuid::parse_str(&existing.id)
+                        .map_err(|e| AppError::DatabaseError(format!("Invalid document id: {}", e)))?;
 
-            let chunk_str: String = chars[start..actual_end].iter().collect();
-            chunks.push(chunk_str);
+                    if existing.failed_chunks == 0 {
+                        job.update(|j| j.document_id = existing.id.clone()).await?;
+                        job.emit(IngestionEvent::AlreadyIngested { document_id: existing.id, filename: existing.filename }).await;
+                        return Ok(PreparedDocument::Skip(existing_id));
+                    }
 
-            // Avanzar restando el overlap para mantener contexto
-            start +=  std::cmp::max(1, (actual_end - start).saturating_sub(CHUNK_OVERLAP));
+                    // La ingesta anterior quedó incompleta: se rehace sobre el mismo documento
+                    job.update(|j| {
+                        j.document_id = existing.id.clone();
+                        j.replace_existing = true;
+                    }).await?;
+                    job.emit(IngestionEvent::RetryingIncomplete {
+                        document_id: existing.id.clone(),
+                        chunks_failed: existing.failed_chunks,
+                    }).await;
+                    Some(existing)
+                },
+                _ => None,
+            }
+        };
+
+        let document_id = match &previous {
+            Some(existing) => {
+                let existing_id = Uuid::parse_str(&existing.id)
+                    .map_err(|e| AppError::DatabaseError(format!("Invalid document id: {}", e)))?;
+
+                // Marcado antes de purgar: la versión anterior deja de estar completa
+                self.repo.set_document_failed_chunks(existing_id, pending).await?;
+
+                // Sustitución: limpiar la versión anterior
+                let purge = self.repo.purge_document_chunks(existing_id, 0).await?;
+                job.emit(IngestionEvent::PreviousVersionPurged {
+                    chunks_deleted: purge.chunks_deleted,
+                    relations_deleted: purge.relations_deleted,
+                    entities_deleted: purge.entities_deleted,
+                }).await;
+                existing_id
+            },
+            None => document_id,
+        };
+
+        let is_new = previous.is_none();
+        let document = Self::build_document(document_id, request, previous);
+        self.repo.save_document(&document).await?;
+        if is_new {
+            self.repo.set_document_failed_chunks(document_id, pending).await?;
         }
-        chunks
+        Ok(PreparedDocument::Process(document_id))
     }
 
-    pub async fn ingest_with_progress(
-        &self, 
-        content: String,
-        progress_tx: tokio::sync::mpsc::Sender<String>
-    ) -> Result<Uuid, AppError> {
-        
-        // 1. Dividir el contenido en trozos (Chunks)
-        let chunks = self.split_text_into_chunks(&content);
+    fn build_document(id: Uuid, request: IngestionRequest, previous: Option<DocumentInfo>) -> NewDocument {
+        let (prev_metadata, prev_uploader) = match previous {
+            Some(doc) => (doc.metadata, Some(doc.uploaded_by)),
+            None => (serde_json::Value::Null, None),
+        };
+
+        NewDocument {
+            id,
+            filename: request.filename.unwrap_or_else(|| "Texto Plano".to_string()),
+            mime_type: request.mime_type.unwrap_or_else(|| "text/plain".to_string()),
+            content_hash: content_hash(&request.content),
+            uploaded_by: request.uploaded_by.or(prev_uploader).unwrap_or_else(|| "anonymous".to_string()),
+            metadata: if request.metadata.is_null() { prev_metadata } else { request.metadata },
+        }
+    }
+
+    /// Procesa los chunks en un pipeline: preparación (deduplicación), embeddings por lotes del
+    /// tamaño que marque el proveedor y extracción con `chunks_in_flight` en vuelo. Las llamadas
+    /// a la IA corren en paralelo (acotadas por los semáforos de embedding y extracción), pero los
+    /// resultados se consumen en orden: la escritura en el grafo, los eventos de progreso y el
+    /// checkpoint `next_chunk` avanzan chunk a chunk, y cada etapa solo pide trabajo nuevo a la
+    /// anterior cuando tiene hueco.
+    async fn process_chunks(
+        &self,
+        document_id: Uuid,
+        chunks: Vec<TextChunk>,
+        start_at: usize,
+        force: bool,
+        job: &JobHandle
+    ) -> Result<(), AppError> {
+
         let total_chunks = chunks.len();
-        let doc_group_id = Uuid::new_v4(); // ID para agrupar (opcional en lógica futura)
+        let in_flight = self.limits.chunks_in_flight;
+        let batch_size = self.ai.read().await.embedding_batch_size().max(1);
 
-        let _ = progress_tx.send(format!("🔪 Documento largo detectado. Dividido en {} fragmentos.", total_chunks)).await;
+        // Saltando los ya completados en una ejecución anterior
+        let mut outcomes = stream::iter(chunks.into_iter().enumerate().skip(start_at))
+            .map(|(index, chunk)| self.prepare_chunk(document_id, index, chunk, force, job))
+            .buffered(in_flight)
+            .chunks(batch_size)
+            .map(|batch| self.embed_batch(batch, total_chunks, job))
+            .buffered(2)
+            .flat_map(stream::iter)
+            .map(|prepared| self.extract_chunk(prepared))
+            .buffered(in_flight);
 
-        // 2. Procesar cada chunk
-        for (index, chunk_text) in chunks.iter().enumerate() {
+        while let Some(outcome) = outcomes.next().await {
+            let (index, outcome) = outcome?;
             let current_step = index + 1;
-            let chunk_id = Uuid::new_v4();
-
-            // A. Vectorizar
-            let _ = progress_tx.send(format!("🧠 [{}/{}] Generando Embeddings...", current_step, total_chunks)).await;
-            
-            // Obtenemos lock para IA
-            let ai_guard = self.ai.read().await;
-            
-            // Manejo de error específico de Embeddings para no detener todo el proceso si uno falla
-            let embedding = match ai_guard.generate_embedding(chunk_text).await {
-                Ok(emb) => emb,
-                Err(e) => {
-                    let _ = progress_tx.send(format!("⚠️ Error embedding chunk {}: {}. Saltando...", current_step, e)).await;
-                    continue; 
+
+            match outcome {
+                // Cancelado: lo que quede en vuelo se descarta y se reanuda desde `next_chunk`
+                ChunkOutcome::Cancelled => return Ok(()),
+
+                // Chunk idéntico ya procesado (en este u otro documento): reutilizamos embedding y entidades
+                ChunkOutcome::Reusable { record } => {
+                    if self.repo.reuse_chunk_by_hash(&record).await? {
+                        job.emit(IngestionEvent::ChunkReused { chunk: current_step, total: total_chunks }).await;
+                        job.update(|j| {
+                            j.chunks_reused += 1;
+                            j.chunks_done += 1;
+                            j.next_chunk = index + 1;
+                        }).await?;
+                    } else {
+                        // El original desapareció (p. ej. se borró su documento) mientras el chunk esperaba turno
+                        let reason = "reusable chunk no longer available".to_string();
+                        Self::record_failure(job, index, total_chunks, ChunkStage::Embedding, reason, true).await?;
+                    }
+                },
+
+                ChunkOutcome::Failed { stage, reason, transient } => {
+                    Self::record_failure(job, index, total_chunks, stage, reason, transient).await?;
+                },
+
+                ChunkOutcome::Analyzed { mut record, embedding, extraction } => {
+                    // Guardar Chunk
+                    record.extraction_failed = extraction.is_err();
+                    self.repo.save_chunk(&record, embedding).await?;
+                    job.emit(IngestionEvent::EmbeddingDone { chunk: current_step, total: total_chunks }).await;
+
+                    match extraction {
+                        Ok(extraction) => {
+                            // Sinónimos a nombres canónicos; lo que no encaje se descarta o va a cuarentena
+                            let conformed = self.ontology.conform(extraction);
+                            let off_ontology = conformed.off_ontology.len();
+                            let quarantined = self.ontology.quarantined(conformed.off_ontology);
+
+                            let rejected_types = conformed.rejected_relation_types;
+                            if !rejected_types.is_empty() {
+                                job.emit(IngestionEvent::RelationTypesRejected {
+                                    chunk: current_step,
+                                    total: total_chunks,
+                                    types: rejected_types.clone(),
+                                }).await;
+                            }
+
+                            let count = conformed.extraction.entities.len();
+                            job.emit(IngestionEvent::EntitiesExtracted { chunk: current_step, total: total_chunks, count }).await;
+                            let created = self.repo.save_graph(record.id, conformed.extraction, quarantined).await?;
+                            job.update(|j| {
+                                j.chunks_done += 1;
+                                j.entities_created += created;
+                                j.off_ontology += off_ontology;
+                                for t in rejected_types {
+                                    if !j.rejected_relation_types.contains(&t) {
+                                        j.rejected_relation_types.push(t);
+                                    }
+                                }
+                                j.next_chunk = index + 1;
+                            }).await?;
+                        },
+                        Err(e) => {
+                            // No detenemos el proceso: el chunk queda guardado (recuperable por vector) sin entidades
+                            let transient = matches!(e, AppError::AITransientError { .. });
+                            let reason = format!("extraction failed: {}", e);
+                            Self::record_failure(job, index, total_chunks, ChunkStage::Extraction, reason, transient).await?;
+                        }
+                    }
+                },
+
+                // No debería ocurrir: el trabajo falla (y se persiste) en lugar de quedarse en `Running`
+                ChunkOutcome::Pending { .. } | ChunkOutcome::Embedded { .. } => {
+                    return Err(AppError::InternalError(format!("chunk {} left the pipeline unfinished", current_step)));
+                },
+            }
+        }
+
+        // Los fallos quedan en el documento: volver a subirlo los reintenta en lugar de deduplicarlo
+        let failed = job.snapshot().await.failed_chunks.len();
+        self.repo.set_document_failed_chunks(document_id, failed).await?;
+
+        Ok(())
+    }
+
+    /// Recalcula el embedding de las entidades nuevas o cuya descripción creció, resumiendo antes
+    /// las descripciones demasiado largas. Un fallo del proveedor no falla el trabajo: las entidades
+    /// siguen marcadas y se retoman en la siguiente ingesta.
+    async fn refresh_entity_profiles(&self, job: &JobHandle) -> Result<(), AppError> {
+        let mut refreshed = 0;
+
+        loop {
+            let stale = self.repo.list_stale_entities(ENTITY_REFRESH_BATCH).await?;
+            if stale.is_empty() {
+                break;
+            }
+
+            let mut updates = Vec::with_capacity(stale.len());
+            let mut texts = Vec::with_capacity(stale.len());
+            for mut entity in stale {
+                let mut summary = None;
+                if let Some(prompt) = summary_prompt(&entity) {
+                    let result = {
+                        let _permit = self.limits.extract.acquire().await
+                            .map_err(|_| AppError::AIError("Extraction limiter closed".to_string()))?;
+                        self.ai.read().await.generate_text(&prompt).await
+                    };
+                    match result {
+                        Ok(text) if !text.is_empty() => {
+                            entity.description = text.clone();
+                            summary = Some(text);
+                        },
+                        Ok(_) => {},
+                        Err(e) => {
+                            tracing::warn!("⚠️ Entity description summary failed, keeping the full text: {}", e);
+                        },
+                    }
                 }
-            };
+                texts.push(entity.embedding_text());
+                updates.push(EntityEmbedding { name: entity.name, description: summary, embedding: Vec::new() });
+            }
 
-            // B. Guardar Chunk
-            // let _ = progress_tx.send(format!("💾 [{}/{}] Guardando datos...", current_step, total_chunks)).await;
-            self.repo.save_chunk(chunk_id, chunk_text, embedding).await?;
-
-            // C. Extracción Simbólica (LLM)
-            let _ = progress_tx.send(format!("🕵️ [{}/{}] Extrayendo conocimiento...", current_step, total_chunks)).await;
-            
-            match ai_guard.extract_knowledge(chunk_text).await {
-                Ok(extraction) => {
-                    let count = extraction.entities.len();
-                    let _ = progress_tx.send(format!("🕸️ [{}/{}] Conectando {} entidades al grafo...", current_step, total_chunks, count)).await;
-                    self.repo.save_graph(chunk_id, extraction).await?;
+            let embeddings = {
+                let _permit = self.limits.embed.acquire().await
+                    .map_err(|_| AppError::AIError("Embedding limiter closed".to_string()))?;
+                let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+                self.ai.read().await.generate_embeddings(&refs).await
+            };
+            let updates = match embeddings {
+                Ok(embeddings) => match attach_embeddings(updates, embeddings) {
+                    Some(updates) => updates,
+                    None => {
+                        tracing::warn!("⚠️ Entity embedding batch returned an unexpected number of vectors");
+                        break;
+                    },
                 },
                 Err(e) => {
-                    let _ = progress_tx.send(format!("⚠️ Error extrayendo entidades en parte {}: {}", current_step, e)).await;
-                    // No detenemos el proceso, solo avisamos
-                }
+                    tracing::warn!("⚠️ Entity embeddings not refreshed: {}", e);
+                    break;
+                },
             };
+
+            refreshed += updates.len();
+            self.repo.update_entity_embeddings(updates).await?;
+        }
+
+        if refreshed > 0 {
+            job.emit(IngestionEvent::EntityProfilesUpdated { count: refreshed }).await;
+        }
+        Ok(())
+    }
+
+    /// Registra un chunk fallido en el resultado del trabajo (sin detener la ingesta).
+    async fn record_failure(
+        job: &JobHandle,
+        index: usize,
+        total_chunks: usize,
+        stage: ChunkStage,
+        reason: String,
+        transient: bool
+    ) -> Result<(), AppError> {
+        let chunk = index + 1;
+        job.update(|j| {
+            j.chunks_failed += 1;
+            j.errors.push(format!("Chunk {}: {}", chunk, reason));
+            j.failed_chunks.push(ChunkFailure { chunk, stage, reason: reason.clone(), transient });
+            j.next_chunk = index + 1;
+        }).await?;
+        job.emit(IngestionEvent::ChunkFailed { chunk, total: total_chunks, reason }).await;
+        Ok(())
+    }
+
+    /// Etapa 1: construye el registro del chunk y comprueba si existe uno idéntico que reutilizar
+    /// (solo lectura: la copia la escribe el consumidor, en orden).
+    async fn prepare_chunk(
+        &self,
+        document_id: Uuid,
+        index: usize,
+        chunk: TextChunk,
+        force: bool,
+        job: &JobHandle
+    ) -> Result<(usize, ChunkOutcome), AppError> {
+        if job.is_cancelled() {
+            return Ok((index, ChunkOutcome::Cancelled));
+        }
+
+        let record = ChunkRecord {
+            id: Uuid::new_v4(),
+            document_id,
+            order: index,
+            page: chunk.page,
+            content_hash: content_hash(&chunk.content),
+            content: chunk.content,
+            extraction_failed: false,
+        };
+
+        if !force && self.repo.has_reusable_chunk(&record.content_hash).await? {
+            return Ok((index, ChunkOutcome::Reusable { record }));
+        }
+        Ok((index, ChunkOutcome::Pending { record }))
+    }
+
+    /// Etapa 2: vectoriza todos los chunks pendientes del lote en una sola petición.
+    async fn embed_batch(
+        &self,
+        batch: Vec<Result<(usize, ChunkOutcome), AppError>>,
+        total_chunks: usize,
+        job: &JobHandle
+    ) -> Vec<Result<(usize, ChunkOutcome), AppError>> {
+        let mut texts = Vec::new();
+        for (index, outcome) in batch.iter().flatten() {
+            if let ChunkOutcome::Pending { record } = outcome {
+                job.emit(IngestionEvent::ChunkStarted { chunk: index + 1, total: total_chunks }).await;
+                texts.push(record.content.as_str());
+            }
+        }
+        if texts.is_empty() {
+            return batch;
         }
+        if job.is_cancelled() {
+            return batch.into_iter().map(|item| item.map(|(index, outcome)| match outcome {
+                ChunkOutcome::Pending { .. } => (index, ChunkOutcome::Cancelled),
+                other => (index, other),
+            })).collect();
+        }
+
+        let embeddings = match self.limits.embed.acquire().await {
+            Ok(_permit) => self.ai.read().await.generate_embeddings(&texts).await,
+            Err(_) => Err(AppError::AIError("Embedding limiter closed".to_string())),
+        };
 
-        let _ = progress_tx.send("✅ ¡Todo el documento ha sido procesado!".to_string()).await;
+        // Un fallo de embeddings marca los chunks del lote como fallidos sin detener el proceso
+        let mut embeddings = match embeddings {
+            Ok(vectors) => Ok(vectors.into_iter()),
+            Err(e) => Err((format!("embedding failed: {}", e), matches!(e, AppError::AITransientError { .. }))),
+        };
 
-        // Retornamos el ID del último chunk procesado (o uno nuevo genérico)
-        Ok(doc_group_id)
+        batch.into_iter().map(|item| {
+            let (index, outcome) = item?;
+            let outcome = match (outcome, &mut embeddings) {
+                (ChunkOutcome::Pending { record }, Ok(vectors)) => match vectors.next() {
+                    Some(embedding) => ChunkOutcome::Embedded { record, embedding },
+                    None => ChunkOutcome::Failed {
+                        stage: ChunkStage::Embedding,
+                        reason: "embedding failed: missing vector".to_string(),
+                        transient: false,
+                    },
+                },
+                (ChunkOutcome::Pending { .. }, Err((reason, transient))) => ChunkOutcome::Failed {
+                    stage: ChunkStage::Embedding,
+                    reason: reason.clone(),
+                    transient: *transient,
+                },
+                (other, _) => other,
+            };
+            Ok((index, outcome))
+        }).collect()
     }
-}
\ No newline at end of file
+
+    /// Etapa 3: extracción simbólica (LLM) de un chunk ya vectorizado.
+    async fn extract_chunk(
+        &self,
+        prepared: Result<(usize, ChunkOutcome), AppError>
+    ) -> Result<(usize, ChunkOutcome), AppError> {
+        let (index, outcome) = prepared?;
+        let ChunkOutcome::Embedded { record, embedding } = outcome else {
+            return Ok((index, outcome));
+        };
+
+        let extraction = {
+            let _permit = self.limits.extract.acquire().await
+                .map_err(|_| AppError::AIError("Extraction limiter closed".to_string()))?;
+            self.ai.read().await.extract_knowledge(&record.content, &self.ontology).await
+        };
+
+        Ok((index, ChunkOutcome::Analyzed { record, embedding, extraction }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stale(description: &str) -> StaleEntity {
+        StaleEntity { name: "Energía Solar".to_string(), category: "Concept".to_string(), description: description.to_string() }
+    }
+
+    fn update(name: &str) -> EntityEmbedding {
+        EntityEmbedding { name: name.to_string(), description: None, embedding: Vec::new() }
+    }
+
+    #[test]
+    fn only_descriptions_over_the_limit_are_summarized() {
+        assert!(summary_prompt(&stale("")).is_none(), "sin descripción no hay nada que resumir");
+        assert!(summary_prompt(&stale(&"a".repeat(ENTITY_DESCRIPTION_MAX_CHARS))).is_none(), "el límite es inclusivo");
+
+        let prompt = summary_prompt(&stale(&"a".repeat(ENTITY_DESCRIPTION_MAX_CHARS + 1)))
+            .expect("por encima del límite hay que resumir");
+        assert!(prompt.contains("\"Energía Solar\" (Concept)"), "el prompt nombra la entidad: {}", prompt);
+    }
+
+    #[test]
+    fn the_summary_threshold_counts_characters_not_bytes() {
+        // 'ñ' ocupa dos bytes: una descripción en el límite no debe resumirse por su tamaño en bytes
+        assert!(summary_prompt(&stale(&"ñ".repeat(ENTITY_DESCRIPTION_MAX_CHARS))).is_none());
+        assert!(summary_prompt(&stale(&"ñ".repeat(ENTITY_DESCRIPTION_MAX_CHARS + 1))).is_some());
+    }
+
+    #[test]
+    fn embeddings_are_attached_in_batch_order() {
+        let updates = attach_embeddings(vec![update("a"), update("b")], vec![vec![1.0], vec![2.0]])
+            .expect("mismo número de vectores que de entidades");
+        assert_eq!(updates[0].name, "a");
+        assert_eq!(updates[0].embedding, vec![1.0]);
+        assert_eq!(updates[1].name, "b");
+        assert_eq!(updates[1].embedding, vec![2.0]);
+    }
+
+    #[test]
+    fn a_batch_with_a_different_number_of_vectors_is_discarded() {
+        assert!(attach_embeddings(vec![update("a"), update("b")], vec![vec![1.0]]).is_none(), "faltan vectores");
+        assert!(attach_embeddings(vec![update("a")], vec![vec![1.0], vec![2.0]]).is_none(), "sobran vectores");
+        assert_eq!(attach_embeddings(Vec::new(), Vec::new()).map(|u| u.len()), Some(0));
+    }
+}
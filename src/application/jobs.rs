@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{Mutex, RwLock, broadcast};
+use uuid::Uuid;
+use crate::application::chunking::resolve_options;
+use crate::application::ingestion::{IngestionService, IngestionLimits};
+use crate::domain::{
+    ports::{KGRepository, AIService, JobRepository},
+    models::{IngestionJob, IngestionRequest, JobStatus, IngestionEvent, IngestionProgress, ChunkingOptions},
+    ontology::Ontology,
+    errors::AppError
+};
+
+// Mensajes de progreso que se conservan en el estado del trabajo
+const MAX_JOB_MESSAGES: usize = 50;
+// Eventos en vuelo por suscriptor SSE antes de que los más antiguos se descarten
+const EVENT_BUFFER: usize = 256;
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Vista viva de un trabajo en ejecución: estado en memoria, persistencia, difusión de eventos
+/// y señal de cancelación.
+pub struct JobHandle {
+    state: Mutex<IngestionJob>,
+    store: Arc<dyn JobRepository>,
+    events: broadcast::Sender<IngestionProgress>,
+    cancelled: AtomicBool,
+}
+
+impl JobHandle {
+    fn new(job: IngestionJob, store: Arc<dyn JobRepository>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        Self { state: Mutex::new(job), store, events, cancelled: AtomicBool::new(false) }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub async fn snapshot(&self) -> IngestionJob {
+        self.state.lock().await.clone()
+    }
+
+    /// Registra el mensaje legible del evento y lo difunde a los suscriptores (no persiste por sí solo).
+    pub async fn emit(&self, event: IngestionEvent) {
+        let progress = {
+            let mut job = self.state.lock().await;
+            Self::record(&mut job, event)
+        };
+
+        // Sin suscriptores el envío falla; no es un error
+        let _ = self.events.send(progress);
+    }
+
+    /// Cierra el trabajo: fija el estado final, lo persiste y solo entonces emite el evento terminal,
+    /// de modo que quien lo reciba ya pueda consultar el estado definitivo.
+    async fn finish(&self, status: JobStatus, event: IngestionEvent, error: Option<String>) -> Result<(), AppError> {
+        let (snapshot, progress) = {
+            let mut job = self.state.lock().await;
+            job.status = status;
+            job.errors.extend(error);
+            job.updated_at = now_secs();
+            let progress = Self::record(&mut job, event);
+            (job.clone(), progress)
+        };
+
+        let persisted = self.store.update_job(&snapshot).await;
+        let _ = self.events.send(progress);
+        persisted
+    }
+
+    fn record(job: &mut IngestionJob, event: IngestionEvent) -> IngestionProgress {
+        let message = event.message();
+        tracing::debug!("[job {}] {}", job.id, message);
+
+        job.messages.push(message.clone());
+        if job.messages.len() > MAX_JOB_MESSAGES {
+            let overflow = job.messages.len() - MAX_JOB_MESSAGES;
+            job.messages.drain(..overflow);
+        }
+        IngestionProgress { job_id: job.id.clone(), event, message }
+    }
+
+    /// Suscripción a los eventos futuros junto con el estado actual (suscribir antes de leer
+    /// el estado garantiza que no se pierde ningún evento intermedio).
+    pub async fn subscribe(&self) -> (IngestionJob, broadcast::Receiver<IngestionProgress>) {
+        let rx = self.events.subscribe();
+        (self.snapshot().await, rx)
+    }
+
+    /// Modifica el estado y lo persiste (checkpoint).
+    pub async fn update(&self, f: impl FnOnce(&mut IngestionJob)) -> Result<(), AppError> {
+        let snapshot = {
+            let mut job = self.state.lock().await;
+            f(&mut job);
+            job.updated_at = now_secs();
+            job.clone()
+        };
+        self.store.update_job(&snapshot).await
+    }
+}
+
+/// Orquesta los trabajos de ingesta: los lanza en segundo plano, permite consultarlos
+/// y cancelarlos, y reanuda al arrancar los que quedaron a medias.
+pub struct IngestionJobManager {
+    repo: Arc<dyn KGRepository>,
+    ai: Arc<RwLock<dyn AIService>>,
+    store: Arc<dyn JobRepository>,
+    limits: IngestionLimits,
+    chunking: ChunkingOptions,
+    ontology: Arc<RwLock<Ontology>>,
+    running: std::sync::Mutex<HashMap<Uuid, Arc<JobHandle>>>,
+}
+
+impl IngestionJobManager {
+    pub fn new(
+        repo: Arc<dyn KGRepository>,
+        ai: Arc<RwLock<dyn AIService>>,
+        store: Arc<dyn JobRepository>,
+        limits: IngestionLimits,
+        chunking: ChunkingOptions,
+        ontology: Arc<RwLock<Ontology>>
+    ) -> Self {
+        Self { repo, ai, store, limits, chunking, ontology, running: std::sync::Mutex::new(HashMap::new()) }
+    }
+
+    /// Registra y lanza un trabajo. `replace` indica el documento cuyo contenido se sustituye (PUT).
+    pub async fn submit(self: &Arc<Self>, mut request: IngestionRequest, replace: Option<Uuid>) -> Result<IngestionJob, AppError> {
+        // Fijar el troceado antes de persistir la petición: una reanudación debe trocear igual
+        request.chunking = resolve_options(&request.chunking, &self.chunking, request.mime_type.as_deref())?;
+
+        let now = now_secs();
+        let job = IngestionJob {
+            id: Uuid::new_v4().to_string(),
+            document_id: replace.unwrap_or_else(Uuid::new_v4).to_string(),
+            replace_existing: replace.is_some(),
+            filename: request.filename.clone(),
+            status: JobStatus::Queued,
+            total_chunks: 0,
+            next_chunk: 0,
+            chunks_done: 0,
+            chunks_failed: 0,
+            chunks_reused: 0,
+            entities_created: 0,
+            off_ontology: 0,
+            rejected_relation_types: Vec::new(),
+            errors: Vec::new(),
+            failed_chunks: Vec::new(),
+            messages: Vec::new(),
+            resumed: 0,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.store.create_job(&job, &request).await?;
+        self.spawn(job.clone(), request);
+        Ok(job)
+    }
+
+    /// Reanuda los trabajos pendientes tras un reinicio del servidor.
+    pub async fn resume_pending(self: &Arc<Self>) -> Result<usize, AppError> {
+        let pending = self.store.list_resumable_jobs().await?;
+        let count = pending.len();
+
+        for (mut job, request) in pending {
+            tracing::info!("🔁 Resuming ingestion job {} from chunk {}", job.id, job.next_chunk);
+            job.resumed += 1;
+            self.spawn(job, request);
+        }
+        Ok(count)
+    }
+
+    /// Devuelve el handle vivo si el trabajo se está ejecutando en este proceso.
+    pub fn live(&self, id: Uuid) -> Option<Arc<JobHandle>> {
+        self.running.lock().expect("job registry poisoned").get(&id).cloned()
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Option<IngestionJob>, AppError> {
+        match self.live(id) {
+            Some(handle) => Ok(Some(handle.snapshot().await)),
+            None => self.store.get_job(id).await,
+        }
+    }
+
+    /// Solicita la cancelación; el trabajo se detiene al terminar el chunk en curso.
+    pub async fn cancel(&self, id: Uuid) -> Result<IngestionJob, AppError> {
+        match self.live(id) {
+            Some(handle) => {
+                handle.cancel();
+                handle.emit(IngestionEvent::CancelRequested).await;
+                Ok(handle.snapshot().await)
+            },
+            None => {
+                let job = self.store.get_job(id).await?
+                    .ok_or_else(|| AppError::NotFoundError(format!("Job {}", id)))?;
+                Err(AppError::ValidationError(format!("Job {} is not running (status: {:?})", id, job.status)))
+            }
+        }
+    }
+
+    fn spawn(self: &Arc<Self>, job: IngestionJob, request: IngestionRequest) {
+        let Ok(job_id) = Uuid::parse_str(&job.id) else {
+            tracing::error!("❌ Invalid job id {}", job.id);
+            return;
+        };
+
+        let handle = Arc::new(JobHandle::new(job, self.store.clone()));
+        self.running.lock().expect("job registry poisoned").insert(job_id, handle.clone());
+
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let ontology = Arc::new(manager.ontology.read().await.clone());
+            let service = IngestionService::new(manager.repo.clone(), manager.ai.clone(), manager.limits.clone(), ontology);
+
+            if let Err(e) = handle.update(|j| j.status = JobStatus::Running).await {
+                tracing::warn!("⚠️ Could not persist job {} state: {}", job_id, e);
+            }
+
+            let result = service.run_job(request, &handle).await;
+
+            let (status, event, error) = match result {
+                Ok(_) if handle.is_cancelled() => (JobStatus::Cancelled, IngestionEvent::Cancelled, None),
+                Ok(document_id) => {
+                    let chunks_failed = handle.snapshot().await.chunks_failed;
+                    (JobStatus::Completed, IngestionEvent::Completed { document_id: document_id.to_string(), chunks_failed }, None)
+                },
+                Err(e) => (JobStatus::Failed, IngestionEvent::Fatal { error: e.to_string() }, Some(e.to_string())),
+            };
+
+            if let Err(e) = handle.finish(status, event, error).await {
+                tracing::error!("❌ Could not persist final state of job {}: {}", job_id, e);
+            }
+
+            manager.running.lock().expect("job registry poisoned").remove(&job_id);
+        });
+    }
+}
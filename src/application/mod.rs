@@ -1,3 +1,10 @@
+pub mod chunking;
+pub mod communities;
+pub mod conversation;
 pub mod dtos;
 pub mod ingestion;
+pub mod jobs;
+pub mod ontology;
+pub mod resolution;
+pub mod retrieval;
 pub mod reasoning; // <-- NUEVO
\ No newline at end of file
@@ -0,0 +1,104 @@
+use std::path::Path;
+use crate::domain::{ports::KGRepository, ontology::Ontology, errors::AppError};
+
+/// Lee una ontología en YAML (`.yaml`/`.yml`) o JSON y la valida.
+pub fn load_file(path: &Path) -> Result<Ontology, AppError> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| AppError::ConfigError(format!("Cannot read ontology {}: {}", path.display(), e)))?;
+
+    let is_yaml = path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+    let ontology: Ontology = if is_yaml {
+        serde_yaml::from_str(&raw).map_err(|e| AppError::ConfigError(format!("Invalid ontology {}: {}", path.display(), e)))?
+    } else {
+        serde_json::from_str(&raw).map_err(|e| AppError::ConfigError(format!("Invalid ontology {}: {}", path.display(), e)))?
+    };
+
+    ontology.validate()?;
+    Ok(ontology)
+}
+
+/// Ontología de arranque: la guardada por API tiene prioridad sobre el fichero `ONTOLOGY_PATH`;
+/// sin ninguna de las dos, la extracción es libre.
+pub async fn load_initial(repo: &dyn KGRepository) -> Result<Ontology, AppError> {
+    match repo.load_ontology().await {
+        Ok(Some(ontology)) => {
+            tracing::info!("📚 Using ontology defined via API ({} categories, {} relation types)",
+                ontology.categories.len(), ontology.relation_types.len());
+            return Ok(ontology);
+        },
+        Ok(None) => {},
+        Err(e) => tracing::warn!("⚠️ Could not load stored ontology: {}", e),
+    }
+
+    match std::env::var("ONTOLOGY_PATH").ok().filter(|p| !p.trim().is_empty()) {
+        Some(path) => {
+            let ontology = load_file(Path::new(path.trim()))?;
+            tracing::info!("📚 Loaded ontology from {} ({} categories, {} relation types)",
+                path, ontology.categories.len(), ontology.relation_types.len());
+            Ok(ontology)
+        },
+        None => Ok(Ontology::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ontology::OffOntologyPolicy;
+
+    /// Fichero temporal con este contenido; se borra al soltarse.
+    struct TempFile(std::path::PathBuf);
+
+    impl TempFile {
+        fn new(extension: &str, content: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("ontology-{}.{}", uuid::Uuid::new_v4(), extension));
+            std::fs::write(&path, content).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn the_example_ontology_loads() {
+        let ontology = load_file(&Path::new(env!("CARGO_MANIFEST_DIR")).join("ontology.example.yaml")).unwrap();
+        assert_eq!(ontology.off_ontology, OffOntologyPolicy::Quarantine);
+        assert_eq!(ontology.resolve_category("empresa").as_deref(), Some("Organization"));
+        assert!(ontology.relation_type_names().contains(&"WORKS_FOR"));
+    }
+
+    #[test]
+    fn yaml_and_json_are_read_by_extension() {
+        let yaml = TempFile::new("YML", "off_ontology: reject\ncategories:\n  - name: Person\n    synonyms: [Persona]\n");
+        let ontology = load_file(&yaml.0).unwrap();
+        assert_eq!(ontology.off_ontology, OffOntologyPolicy::Reject);
+        assert_eq!(ontology.category_names(), vec!["Person"]);
+
+        let json = TempFile::new("json", r#"{"relation_types": [{"name": "USES"}]}"#);
+        let ontology = load_file(&json.0).unwrap();
+        assert_eq!(ontology.relation_type_names(), vec!["USES"]);
+        assert!(ontology.categories.is_empty());
+
+        // YAML con extensión .json no se interpreta como YAML
+        let misnamed = TempFile::new("json", "categories: []\n");
+        assert!(matches!(load_file(&misnamed.0), Err(AppError::ConfigError(_))));
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let missing = std::env::temp_dir().join(format!("ontology-{}.yaml", uuid::Uuid::new_v4()));
+        assert!(matches!(load_file(&missing), Err(AppError::ConfigError(e)) if e.contains("Cannot read")));
+
+        let malformed = TempFile::new("yaml", "categories: [\n");
+        assert!(matches!(load_file(&malformed.0), Err(AppError::ConfigError(e)) if e.contains("Invalid ontology")));
+
+        let unknown_category = TempFile::new("yaml", "categories:\n  - name: Person\nrelation_types:\n  - name: LOCATED_IN\n    range: [Location]\n");
+        assert!(matches!(load_file(&unknown_category.0), Err(AppError::ValidationError(_))));
+    }
+}
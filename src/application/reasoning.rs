@@ -2,7 +2,8 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use crate::domain::{
     ports::{KGRepository, AIService},
-    models::InferredRelation,
+    models::{InferredRelation, ReasoningResult, RejectedRelation},
+    ontology::sanitize_relation_type,
     errors::AppError
 };
 
@@ -16,7 +17,7 @@ impl ReasoningService {
         Self { repo, ai }
     }
 
-    pub async fn infer_new_knowledge(&self) -> Result<Vec<InferredRelation>, AppError> {
+    pub async fn infer_new_knowledge(&self) -> Result<ReasoningResult, AppError> {
         // 1. Obtener contexto más amplio
         let graph_context = self.repo.get_graph_context_for_reasoning(500).await?;
 
@@ -60,11 +61,29 @@ impl ReasoningService {
         // Usamos generate_inference que ya maneja la limpieza de JSON
         let response_json = ai_guard.generate_inference(&prompt).await?;
         
-        // 4. Guardar en Base de Datos
-        if !response_json.new_relations.is_empty() {
-            self.repo.save_inferred_relations(response_json.new_relations.clone()).await?;
+        // 4. Solo se guardan los tipos de relación que son etiquetas seguras
+        let mut new_relations = Vec::new();
+        let mut rejected = Vec::new();
+        for rel in response_json.new_relations {
+            match sanitize_relation_type(&rel.relation) {
+                Ok(relation) => new_relations.push(InferredRelation { relation, ..rel }),
+                Err(reason) => rejected.push(RejectedRelation {
+                    source: rel.source,
+                    target: rel.target,
+                    relation: rel.relation,
+                    reason,
+                }),
+            }
+        }
+        if !rejected.is_empty() {
+            tracing::warn!("🚫 {} inferred relations rejected for their relation type", rejected.len());
+        }
+
+        // 5. Guardar en Base de Datos
+        if !new_relations.is_empty() {
+            self.repo.save_inferred_relations(new_relations.clone()).await?;
         }
 
-        Ok(response_json.new_relations)
+        Ok(ReasoningResult { new_relations, rejected })
     }
 }
\ No newline at end of file
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use futures::stream::{self, StreamExt};
+use tokio::sync::RwLock;
+use crate::domain::{
+    ports::{KGRepository, AIService},
+    models::{EntityProfile, MatchReason, MergeCandidate, ResolutionRequest, StaleEntity},
+    errors::AppError
+};
+
+const DEFAULT_SIMILARITY_THRESHOLD: f32 = 0.9;
+const DEFAULT_MAX_ENTITIES: usize = 1000;
+// Tope de `max_entities`: la comparación es cuadrática en el número de entidades
+const MAX_ENTITIES_LIMIT: usize = 5000;
+const DEFAULT_MAX_CANDIDATES: usize = 100;
+// Llamadas simultáneas al LLM durante la adjudicación
+const ADJUDICATION_CONCURRENCY: usize = 4;
+
+// Sufijos societarios que no distinguen entidades ("OpenAI Inc." = "OpenAI")
+const NAME_SUFFIXES: &[&str] = &[
+    "inc", "incorporated", "corp", "corporation", "co", "company", "ltd", "limited", "llc", "plc",
+    "gmbh", "ag", "sa", "sl", "sas", "srl", "bv", "nv",
+];
+// Artículo inicial que tampoco distingue ("The Guardian" = "Guardian")
+const LEADING_ARTICLE: &str = "the";
+
+/// Clave de comparación de nombres: minúsculas, sin puntuación ni espacios, sin el artículo
+/// inicial y sin los sufijos societarios del final. "OpenAI", "Open AI" y "OpenAI Inc." comparten
+/// clave; "Co-op Bank" o "SA Power" conservan sus tokens porque no están al final.
+pub fn normalize_name(name: &str) -> String {
+    // Sin puntos, las siglas con puntos ("S.A.", "S.L.") son un solo token
+    let lowered = name.to_lowercase().replace('.', "");
+    let tokens: Vec<&str> = lowered
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    let mut significant = tokens.as_slice();
+    if let [LEADING_ARTICLE, rest @ ..] = significant {
+        significant = rest;
+    }
+    while let [rest @ .., last] = significant {
+        if !NAME_SUFFIXES.contains(last) {
+            break;
+        }
+        significant = rest;
+    }
+    // Un nombre compuesto solo de "sufijos" (p. ej. "The Company") se compara tal cual
+    if significant.is_empty() { tokens.concat() } else { significant.concat() }
+}
+
+fn normalized(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+/// Busca entidades duplicadas: mismo nombre normalizado, alias cruzados o perfiles
+/// (nombre, categoría, alias y vecinos) con embeddings muy parecidos; opcionalmente
+/// el LLM confirma cada candidato.
+pub struct EntityResolutionService {
+    repo: Arc<dyn KGRepository>,
+    ai: Arc<RwLock<dyn AIService>>,
+}
+
+impl EntityResolutionService {
+    pub fn new(repo: Arc<dyn KGRepository>, ai: Arc<RwLock<dyn AIService>>) -> Self {
+        Self { repo, ai }
+    }
+
+    pub async fn find_candidates(&self, request: &ResolutionRequest) -> Result<Vec<MergeCandidate>, AppError> {
+        let threshold = request.similarity_threshold.unwrap_or(DEFAULT_SIMILARITY_THRESHOLD);
+        let max_entities = request.max_entities.unwrap_or(DEFAULT_MAX_ENTITIES).clamp(2, MAX_ENTITIES_LIMIT);
+        let max_candidates = request.max_candidates.unwrap_or(DEFAULT_MAX_CANDIDATES);
+
+        let profiles = self.repo.list_entity_profiles(max_entities).await?;
+        if profiles.len() < 2 {
+            return Ok(Vec::new());
+        }
+
+        // (i, j) con i < j -> (puntuación, motivos)
+        let mut pairs: HashMap<(usize, usize), (f32, Vec<MatchReason>)> = HashMap::new();
+        let mut add = |a: usize, b: usize, score: f32, reason: MatchReason| {
+            let entry = pairs.entry((a.min(b), a.max(b))).or_insert((0.0, Vec::new()));
+            entry.0 = entry.0.max(score);
+            if !entry.1.contains(&reason) {
+                entry.1.push(reason);
+            }
+        };
+
+        // 1. Nombres normalizados y alias
+        let mut by_key: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, profile) in profiles.iter().enumerate() {
+            by_key.entry(normalize_name(&profile.name)).or_default().push(i);
+        }
+        for group in by_key.values() {
+            for (n, &a) in group.iter().enumerate() {
+                for &b in &group[n + 1..] {
+                    add(a, b, 1.0, MatchReason::NormalizedName);
+                }
+            }
+        }
+        for (i, profile) in profiles.iter().enumerate() {
+            for alias in &profile.aliases {
+                for &j in by_key.get(&normalize_name(alias)).into_iter().flatten() {
+                    if i != j {
+                        add(i, j, 1.0, MatchReason::Alias);
+                    }
+                }
+            }
+        }
+
+        // 2. Similitud de perfiles dentro de la misma categoría
+        let embeddings = self.profile_embeddings(&profiles).await?;
+        for i in 0..profiles.len() {
+            for j in i + 1..profiles.len() {
+                if !profiles[i].category.eq_ignore_ascii_case(&profiles[j].category)
+                    || embeddings[i].len() != embeddings[j].len() {
+                    continue;
+                }
+                let similarity: f32 = embeddings[i].iter().zip(&embeddings[j]).map(|(a, b)| a * b).sum();
+                if similarity >= threshold {
+                    add(i, j, similarity, MatchReason::Embedding);
+                }
+            }
+        }
+
+        // 3. Candidatos: se conserva la entidad más mencionada
+        let mut candidates: Vec<MergeCandidate> = pairs.into_iter()
+            .map(|((a, b), (score, reasons))| {
+                let (keep, merge) = if profiles[b].mentions > profiles[a].mentions { (b, a) } else { (a, b) };
+                MergeCandidate {
+                    keep: profiles[keep].name.clone(),
+                    keep_category: profiles[keep].category.clone(),
+                    merge: profiles[merge].name.clone(),
+                    merge_category: profiles[merge].category.clone(),
+                    score,
+                    reasons,
+                    adjudication: None,
+                }
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.keep.cmp(&b.keep)));
+        candidates.truncate(max_candidates);
+
+        if request.adjudicate {
+            let profiles_by_name: HashMap<&str, &EntityProfile> = profiles.iter().map(|p| (p.name.as_str(), p)).collect();
+            candidates = self.adjudicate(candidates, &profiles_by_name).await;
+            // Los descartados por el LLM, al final
+            candidates.sort_by_key(|c| c.adjudication.as_ref().is_some_and(|a| !a.same_entity));
+        }
+
+        Ok(candidates)
+    }
+
+    /// Embeddings normalizados de los perfiles: los guardados en el grafo y, solo para las
+    /// entidades que aún no tienen, uno calculado con el mismo texto que usa la ingesta.
+    async fn profile_embeddings(&self, profiles: &[EntityProfile]) -> Result<Vec<Vec<f32>>, AppError> {
+        let missing: Vec<usize> = (0..profiles.len()).filter(|&i| profiles[i].embedding.is_none()).collect();
+        let mut computed = Vec::new();
+        if !missing.is_empty() {
+            let texts: Vec<String> = missing.iter()
+                .map(|&i| StaleEntity {
+                    name: profiles[i].name.clone(),
+                    category: profiles[i].category.clone(),
+                    description: profiles[i].description.clone().unwrap_or_default(),
+                }.embedding_text())
+                .collect();
+            let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+            computed = self.ai.read().await.generate_embeddings(&refs).await?;
+            if computed.len() != missing.len() {
+                return Err(AppError::AIError("Embedding batch returned an unexpected number of vectors".to_string()));
+            }
+        }
+
+        let mut computed = computed.into_iter();
+        Ok(profiles.iter()
+            .map(|p| normalized(p.embedding.clone().or_else(|| computed.next()).unwrap_or_default()))
+            .collect())
+    }
+
+    async fn adjudicate(
+        &self,
+        candidates: Vec<MergeCandidate>,
+        profiles: &HashMap<&str, &EntityProfile>
+    ) -> Vec<MergeCandidate> {
+        stream::iter(candidates)
+            .map(|mut candidate| async move {
+                let describe = |name: &str| profiles.get(name).map(|p| p.describe()).unwrap_or_else(|| name.to_string());
+                let prompt = format!(
+                    r#"Eres un experto en resolución de entidades para un grafo de conocimiento.
+                    ¿Se refieren estas dos entidades al mismo objeto del mundo real?
+
+                    A: {}
+                    B: {}
+
+                    Responde SOLO con JSON estricto:
+                    {{ "same_entity": true|false, "confidence": 0.0-1.0, "reasoning": "explicación breve" }}
+                    "#,
+                    describe(&candidate.keep),
+                    describe(&candidate.merge)
+                );
+
+                match self.ai.read().await.adjudicate_merge(&prompt).await {
+                    Ok(verdict) => candidate.adjudication = Some(verdict),
+                    Err(e) => tracing::warn!("⚠️ Could not adjudicate '{}' / '{}': {}", candidate.keep, candidate.merge, e),
+                }
+                candidate
+            })
+            .buffered(ADJUDICATION_CONCURRENCY)
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_ignore_case_punctuation_and_spacing() {
+        assert_eq!(normalize_name("OpenAI"), "openai");
+        assert_eq!(normalize_name("Open AI"), "openai");
+        assert_eq!(normalize_name("open-ai."), "openai");
+        assert_eq!(normalize_name("  Ñandú  S.A. "), "ñandú");
+    }
+
+    #[test]
+    fn only_trailing_suffixes_and_a_leading_article_are_dropped() {
+        assert_eq!(normalize_name("OpenAI Inc."), "openai");
+        assert_eq!(normalize_name("Acme Holdings Co. Ltd"), "acmeholdings");
+        assert_eq!(normalize_name("The Guardian"), "guardian");
+        // En medio o al principio son parte del nombre
+        assert_eq!(normalize_name("Co-op Bank"), "coopbank");
+        assert_eq!(normalize_name("SA Power Networks"), "sapowernetworks");
+        assert_eq!(normalize_name("Bank of the West"), "bankofthewest");
+        assert_ne!(normalize_name("Coca Cola"), normalize_name("Cola"));
+    }
+
+    #[test]
+    fn names_made_only_of_suffixes_are_kept_whole() {
+        assert_eq!(normalize_name("The Company"), "thecompany");
+        assert_eq!(normalize_name("Co"), "co");
+        assert_eq!(normalize_name("..."), "");
+    }
+
+    #[test]
+    fn vectors_are_normalized_to_unit_length() {
+        assert_eq!(normalized(vec![3.0, 4.0]), vec![0.6, 0.8]);
+        assert_eq!(normalized(vec![0.0, 0.0]), vec![0.0, 0.0]);
+    }
+}
@@ -0,0 +1,723 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::domain::{
+    ports::{KGRepository, Reranker},
+    models::{estimate_tokens, ChatRequest, RetrievalFilters, RetrievalMode, RetrievalOptions, GraphFact, HybridContext, RelevantEntity},
+    errors::AppError
+};
+
+// Fragmentos recuperados por defecto y máximo que puede pedir una petición
+const DEFAULT_TOP_K: usize = 5;
+const DEFAULT_MAX_TOP_K: usize = 20;
+// Similitud mínima por defecto (0 = sin corte). El índice vectorial devuelve el coseno normalizado a [0, 1]
+const DEFAULT_MIN_SCORE: f32 = 0.0;
+// Candidatos por fragmento final que se piden cuando hay reranking
+const DEFAULT_RERANK_OVERFETCH: usize = 3;
+// Presupuesto por defecto (tokens estimados) de los fragmentos en el prompt
+const DEFAULT_CONTEXT_TOKENS: usize = 3000;
+// Constante k de reciprocal rank fusion (la habitual en la literatura)
+const RRF_K: f32 = 60.0;
+// Expansión por el grafo: saltos y hechos por defecto y sus máximos
+const DEFAULT_GRAPH_HOPS: usize = 1;
+const MAX_GRAPH_HOPS: usize = 3;
+const DEFAULT_MAX_FACTS: usize = 15;
+const MAX_FACTS: usize = 100;
+// Relaciones candidatas que se puntúan antes de quedarse con las `max_facts` mejores
+const GRAPH_CANDIDATES_PER_FACT: usize = 20;
+// Fragmentos vecinos (evidencia de los hechos elegidos) que se añaden como fuentes, como mucho
+const GRAPH_CHUNKS: usize = 3;
+// Personalized PageRank
+const PPR_DAMPING: f32 = 0.85;
+const PPR_ITERATIONS: usize = 30;
+
+/// Valores por defecto y límites de la recuperación del chat; cada petición puede ajustarlos.
+#[derive(Debug, Clone, Copy)]
+pub struct RetrievalSettings {
+    mode: RetrievalMode,
+    top_k: usize,
+    max_top_k: usize,
+    min_score: f32,
+    hops: usize,
+    max_facts: usize,
+    rerank_overfetch: usize,
+    context_tokens: usize,
+}
+
+impl RetrievalSettings {
+    /// Lee `CHAT_RETRIEVAL_MODE`, `CHAT_TOP_K`, `CHAT_MAX_TOP_K`, `CHAT_MIN_SCORE`, `CHAT_GRAPH_HOPS`,
+    /// `CHAT_MAX_FACTS`, `RERANK_OVERFETCH` y `CHAT_CONTEXT_TOKENS`.
+    pub fn from_env() -> Result<Self, AppError> {
+        let read = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let number = |name: &str, default: usize| -> Result<usize, AppError> {
+            match read(name) {
+                Some(value) => value.trim().parse::<usize>()
+                    .map_err(|_| AppError::ConfigError(format!("{} must be a number", name))),
+                None => Ok(default),
+            }
+        };
+
+        let mode = read("CHAT_RETRIEVAL_MODE")
+            .map(|v| v.parse::<RetrievalMode>().map_err(AppError::ConfigError))
+            .transpose()?
+            .unwrap_or_default();
+        let max_top_k = number("CHAT_MAX_TOP_K", DEFAULT_MAX_TOP_K)?.max(1);
+        let top_k = number("CHAT_TOP_K", DEFAULT_TOP_K)?.clamp(1, max_top_k);
+        let min_score = match read("CHAT_MIN_SCORE") {
+            Some(value) => value.trim().parse::<f32>().ok()
+                .filter(|s| (0.0..=1.0).contains(s))
+                .ok_or_else(|| AppError::ConfigError("CHAT_MIN_SCORE must be a number between 0 and 1".to_string()))?,
+            None => DEFAULT_MIN_SCORE,
+        };
+
+        let hops = number("CHAT_GRAPH_HOPS", DEFAULT_GRAPH_HOPS)?;
+        if hops > MAX_GRAPH_HOPS {
+            return Err(AppError::ConfigError(format!("CHAT_GRAPH_HOPS must be at most {}", MAX_GRAPH_HOPS)));
+        }
+        let max_facts = number("CHAT_MAX_FACTS", DEFAULT_MAX_FACTS)?.min(MAX_FACTS);
+
+        let rerank_overfetch = number("RERANK_OVERFETCH", DEFAULT_RERANK_OVERFETCH)?.max(1);
+        let context_tokens = number("CHAT_CONTEXT_TOKENS", DEFAULT_CONTEXT_TOKENS)?;
+
+        Ok(Self { mode, top_k, max_top_k, min_score, hops, max_facts, rerank_overfetch, context_tokens })
+    }
+
+    /// Opciones efectivas de una petición: lo que pida (validado) o estos valores por defecto.
+    /// El reranking solo se activa si hay un reranker configurado (`can_rerank`).
+    fn resolve(&self, request: &ChatRequest, can_rerank: bool) -> Result<RetrievalOptions, AppError> {
+        let top_k = request.top_k.unwrap_or(self.top_k);
+        if top_k == 0 || top_k > self.max_top_k {
+            return Err(AppError::ValidationError(format!("top_k must be between 1 and {}", self.max_top_k)));
+        }
+        let min_score = request.min_score.unwrap_or(self.min_score);
+        if !(0.0..=1.0).contains(&min_score) {
+            return Err(AppError::ValidationError("min_score must be between 0 and 1".to_string()));
+        }
+        let hops = request.hops.unwrap_or(self.hops);
+        if hops > MAX_GRAPH_HOPS {
+            return Err(AppError::ValidationError(format!("hops must be at most {}", MAX_GRAPH_HOPS)));
+        }
+        let max_facts = request.max_facts.unwrap_or(self.max_facts);
+        if max_facts > MAX_FACTS {
+            return Err(AppError::ValidationError(format!("max_facts must be at most {}", MAX_FACTS)));
+        }
+        let mode = request.mode.unwrap_or(self.mode);
+        let filters = request.filters.clone().unwrap_or_default();
+        validate_filters(&filters)?;
+        if mode == RetrievalMode::Global && !filters.is_empty() {
+            return Err(AppError::ValidationError("filters are not supported in global mode".to_string()));
+        }
+        Ok(RetrievalOptions {
+            mode,
+            top_k,
+            min_score,
+            hops,
+            max_facts,
+            rerank: can_rerank && request.rerank.unwrap_or(true),
+            context_tokens: self.context_tokens,
+            filters,
+        })
+    }
+}
+
+/// Recuperación del chat: búsqueda de fragmentos (vectorial, texto completo o ambas), reranking
+/// opcional, recorte al presupuesto de tokens y expansión por el grafo.
+pub struct RetrievalService {
+    repo: Arc<dyn KGRepository>,
+    reranker: Option<Arc<dyn Reranker>>,
+    settings: RetrievalSettings,
+}
+
+impl RetrievalService {
+    pub fn new(repo: Arc<dyn KGRepository>, reranker: Option<Arc<dyn Reranker>>, settings: RetrievalSettings) -> Self {
+        Self { repo, reranker, settings }
+    }
+
+    /// Opciones efectivas de una petición: lo que pida (validado) o los valores por defecto.
+    pub fn resolve(&self, request: &ChatRequest) -> Result<RetrievalOptions, AppError> {
+        self.settings.resolve(request, self.reranker.is_some())
+    }
+
+    /// Fragmentos para la pregunta: con reranking se piden `RERANK_OVERFETCH` veces más candidatos,
+    /// se reordenan y se conservan los `top_k` mejores; al final se recorta al presupuesto de tokens.
+    pub async fn retrieve(&self, embedding: Vec<f32>, text: &str, options: &RetrievalOptions) -> Result<Vec<HybridContext>, AppError> {
+        let mut contexts = match (&self.reranker, options.rerank) {
+            (Some(reranker), true) => {
+                let fetch = RetrievalOptions { top_k: options.top_k * self.settings.rerank_overfetch, ..options.clone() };
+                let candidates = self.search(embedding, text, &fetch).await?;
+                rerank(reranker.as_ref(), text, candidates).await
+            },
+            _ => self.search(embedding, text, options).await?,
+        };
+        contexts.truncate(options.top_k);
+        Ok(trim_to_budget(contexts, options.context_tokens))
+    }
+
+    /// Búsqueda según el modo: vectorial, texto completo o ambas fusionadas.
+    async fn search(&self, embedding: Vec<f32>, text: &str, options: &RetrievalOptions) -> Result<Vec<HybridContext>, AppError> {
+        match options.mode {
+            RetrievalMode::Vector => self.repo.find_hybrid_context(embedding, options).await,
+            RetrievalMode::Keyword => self.repo.find_keyword_context(text, options).await,
+            RetrievalMode::Hybrid => {
+                let (vector, keyword) = tokio::try_join!(
+                    self.repo.find_hybrid_context(embedding, options),
+                    self.repo.find_keyword_context(text, options)
+                )?;
+                Ok(fuse_rrf(vector, keyword, options.top_k))
+            },
+            // Se responde desde los resúmenes de comunidades, no desde fragmentos
+            RetrievalMode::Global => Ok(Vec::new()),
+        }
+    }
+
+    /// Vecindario a `hops` saltos de las entidades de los fragmentos y de las entidades similares,
+    /// ordenado por personalized PageRank, y los fragmentos vecinos que mejor lo respaldan.
+    pub async fn expand_graph(
+        &self,
+        contexts: &[HybridContext],
+        entities: &[RelevantEntity],
+        options: &RetrievalOptions
+    ) -> Result<GraphExpansion, AppError> {
+        if options.hops == 0 || options.max_facts == 0 {
+            return Ok(GraphExpansion::default());
+        }
+        let seeds = seed_weights(contexts, entities);
+        let names: Vec<String> = seeds.keys().cloned().collect();
+        let candidates = self.repo
+            .find_graph_neighborhood(&names, options.hops, options.max_facts * GRAPH_CANDIDATES_PER_FACT, &options.filters)
+            .await?;
+        let facts = rank_facts(candidates, &seeds, options.max_facts);
+
+        let ranked = rank_graph_chunks(&facts, contexts, GRAPH_CHUNKS);
+        if ranked.is_empty() {
+            return Ok(GraphExpansion { facts, chunks: Vec::new() });
+        }
+        let ids: Vec<String> = ranked.iter().map(|(id, _)| id.clone()).collect();
+        let mut found = self.repo.get_chunks(&ids).await?;
+
+        // En el orden de su puntuación y sin pasarse de lo que dejan libre los fragmentos recuperados
+        let mut available = options.context_tokens.saturating_sub(contexts.iter().map(|c| estimate_tokens(&c.content)).sum());
+        let mut chunks = Vec::new();
+        for (id, score) in ranked {
+            let Some(position) = found.iter().position(|c| c.chunk_id == id) else { continue };
+            let mut chunk = found.swap_remove(position);
+            let cost = estimate_tokens(&chunk.content);
+            if cost > available {
+                continue;
+            }
+            available -= cost;
+            chunk.score = score.min(1.0);
+            chunk.graph_score = Some(score);
+            chunks.push(chunk);
+        }
+        Ok(GraphExpansion { facts, chunks })
+    }
+}
+
+/// Resultado de la expansión por el grafo.
+#[derive(Debug, Default)]
+pub struct GraphExpansion {
+    /// Los `max_facts` hechos mejor puntuados
+    pub facts: Vec<GraphFact>,
+    /// Fragmentos que respaldan esos hechos y no estaban entre los recuperados
+    pub chunks: Vec<HybridContext>,
+}
+
+/// Reordena por la puntuación del reranker, cuya versión acotada (`rerank_relevance`) pasa a ser la
+/// relevancia. Si el reranker falla se conserva el orden de la búsqueda: el reranking mejora la
+/// respuesta pero no es imprescindible.
+async fn rerank(reranker: &dyn Reranker, query: &str, mut contexts: Vec<HybridContext>) -> Vec<HybridContext> {
+    if contexts.len() < 2 {
+        return contexts;
+    }
+    let passages: Vec<&str> = contexts.iter().map(|c| c.content.as_str()).collect();
+    match reranker.score(query, &passages).await {
+        Ok(scores) => {
+            for ((ctx, &score), relevance) in contexts.iter_mut().zip(&scores).zip(rerank_relevance(&scores)) {
+                ctx.rerank_score = Some(score);
+                ctx.score = relevance;
+            }
+            // Se ordena por la puntuación original: la sigmoide iguala los logits altos
+            let key = |ctx: &HybridContext| ctx.rerank_score.unwrap_or(f32::NEG_INFINITY);
+            contexts.sort_by(|a, b| key(b).total_cmp(&key(a)));
+        },
+        Err(e) => tracing::warn!("⚠️ Reranking failed, keeping retrieval order: {}", e),
+    }
+    contexts
+}
+
+/// Relevancia (0.0 - 1.0) de las puntuaciones de un reranker. Si todas están entre 0 y 1 ya son
+/// probabilidades; si no, son logits (llama.cpp, TEI...) y se pasan por una sigmoide.
+fn rerank_relevance(scores: &[f32]) -> Vec<f32> {
+    if scores.iter().all(|s| (0.0..=1.0).contains(s)) {
+        scores.to_vec()
+    } else {
+        scores.iter().map(|s| 1.0 / (1.0 + (-s).exp())).collect()
+    }
+}
+
+/// Fechas `YYYY-MM-DD` y rango no invertido: se comparan en Cypher con `date()`.
+fn validate_filters(filters: &RetrievalFilters) -> Result<(), AppError> {
+    for date in [&filters.uploaded_from, &filters.uploaded_to].into_iter().flatten() {
+        if !is_iso_date(date) {
+            return Err(AppError::ValidationError(format!("invalid date '{}': expected YYYY-MM-DD", date)));
+        }
+    }
+    if let (Some(from), Some(to)) = (&filters.uploaded_from, &filters.uploaded_to) {
+        if from > to {
+            return Err(AppError::ValidationError("uploaded_from must not be after uploaded_to".to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn is_iso_date(text: &str) -> bool {
+    let number = |s: &str, len: usize| {
+        if s.len() == len && s.bytes().all(|b| b.is_ascii_digit()) { s.parse::<u32>().ok() } else { None }
+    };
+    let parts: Vec<&str> = text.split('-').collect();
+    let [Some(year), Some(month), Some(day)] = [
+        parts.first().and_then(|s| number(s, 4)),
+        parts.get(1).and_then(|s| number(s, 2)),
+        parts.get(2).and_then(|s| number(s, 2)),
+    ] else {
+        return false;
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return false,
+    };
+    parts.len() == 3 && (1..=days).contains(&day)
+}
+
+/// Conserva fragmentos en orden mientras quepan en el presupuesto (al menos el primero).
+fn trim_to_budget(contexts: Vec<HybridContext>, budget: usize) -> Vec<HybridContext> {
+    let mut used = 0;
+    contexts.into_iter()
+        .enumerate()
+        .take_while(|(i, ctx)| {
+            used += estimate_tokens(&ctx.content);
+            *i == 0 || used <= budget
+        })
+        .map(|(_, ctx)| ctx)
+        .collect()
+}
+
+/// Reciprocal rank fusion: cada lista aporta `1 / (k + posición)` a los fragmentos que contiene.
+/// La relevancia resultante se normaliza con el máximo posible (primero en ambas listas), y cada
+/// fragmento conserva las puntuaciones originales de las búsquedas donde apareció.
+fn fuse_rrf(vector: Vec<HybridContext>, keyword: Vec<HybridContext>, top_k: usize) -> Vec<HybridContext> {
+    let mut fused: Vec<(HybridContext, f32)> = Vec::new();
+    for list in [vector, keyword] {
+        for (rank, ctx) in list.into_iter().enumerate() {
+            let contribution = 1.0 / (RRF_K + rank as f32 + 1.0);
+            match fused.iter_mut().find(|(c, _)| c.chunk_id == ctx.chunk_id) {
+                Some((existing, score)) => {
+                    *score += contribution;
+                    existing.vector_score = existing.vector_score.or(ctx.vector_score);
+                    existing.keyword_score = existing.keyword_score.or(ctx.keyword_score);
+                },
+                None => fused.push((ctx, contribution)),
+            }
+        }
+    }
+
+    let best = 2.0 / (RRF_K + 1.0);
+    fused.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    fused.into_iter()
+        .take(top_k)
+        .map(|(mut ctx, score)| {
+            ctx.score = score / best;
+            ctx
+        })
+        .collect()
+}
+
+/// Peso de cada entidad como punto de partida de la expansión: la similitud de los fragmentos
+/// que la mencionan más la de su propio perfil.
+fn seed_weights(contexts: &[HybridContext], entities: &[RelevantEntity]) -> HashMap<String, f32> {
+    let mut seeds: HashMap<String, f32> = HashMap::new();
+    for ctx in contexts {
+        for name in &ctx.connected_entities {
+            *seeds.entry(name.clone()).or_default() += ctx.score;
+        }
+    }
+    for entity in entities {
+        *seeds.entry(entity.name.clone()).or_default() += entity.score;
+    }
+    seeds
+}
+
+/// Ordena las relaciones del vecindario por personalized PageRank: el paseo aleatorio vuelve a las
+/// entidades semilla (con su peso) y las aristas pesan por número de menciones. Cada hecho puntúa
+/// la suma del rango de sus extremos (ponderada por la confianza) y se conservan los `max_facts` mejores.
+fn rank_facts(mut facts: Vec<GraphFact>, seeds: &HashMap<String, f32>, max_facts: usize) -> Vec<GraphFact> {
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for fact in &facts {
+        for name in [fact.source.as_str(), fact.target.as_str()] {
+            let next = index.len();
+            index.entry(name).or_insert(next);
+        }
+    }
+    let n = index.len();
+
+    let mut personalization = vec![0.0f32; n];
+    for (name, weight) in seeds {
+        if let Some(&i) = index.get(name.as_str()) {
+            personalization[i] += weight.max(0.0);
+        }
+    }
+    let total: f32 = personalization.iter().sum();
+    if n == 0 || total <= 0.0 {
+        return Vec::new();
+    }
+    personalization.iter_mut().for_each(|p| *p /= total);
+
+    // Grafo no dirigido con el peso de cada arista
+    let mut edges: Vec<(usize, usize, f32)> = Vec::with_capacity(facts.len());
+    let mut degree = vec![0.0f32; n];
+    for fact in &facts {
+        let (a, b) = (index[fact.source.as_str()], index[fact.target.as_str()]);
+        let w = fact.mention_count.max(1) as f32;
+        edges.push((a, b, w));
+        degree[a] += w;
+        degree[b] += w;
+    }
+
+    let mut rank = personalization.clone();
+    for _ in 0..PPR_ITERATIONS {
+        let mut next: Vec<f32> = personalization.iter().map(|p| (1.0 - PPR_DAMPING) * p).collect();
+        for &(a, b, w) in &edges {
+            next[b] += PPR_DAMPING * rank[a] * w / degree[a];
+            next[a] += PPR_DAMPING * rank[b] * w / degree[b];
+        }
+        rank = next;
+    }
+
+    for (fact, &(a, b, _)) in facts.iter_mut().zip(&edges) {
+        fact.score = (rank[a] + rank[b]) * fact.confidence.unwrap_or(1.0);
+    }
+    facts.sort_by(|x, y| y.score.total_cmp(&x.score).then(y.mention_count.cmp(&x.mention_count)));
+    facts.truncate(max_facts);
+    facts
+}
+
+/// Fragmentos vecinos: los que respaldan los hechos elegidos sin estar ya entre `contexts`,
+/// puntuados con la suma de la puntuación de esos hechos (los `limit` mejores).
+fn rank_graph_chunks(facts: &[GraphFact], contexts: &[HybridContext], limit: usize) -> Vec<(String, f32)> {
+    let mut scores: HashMap<&str, f32> = HashMap::new();
+    for fact in facts {
+        for chunk_id in &fact.evidence {
+            if contexts.iter().all(|c| &c.chunk_id != chunk_id) {
+                *scores.entry(chunk_id.as_str()).or_insert(0.0) += fact.score;
+            }
+        }
+    }
+    let mut ranked: Vec<(String, f32)> = scores.into_iter().map(|(id, score)| (id.to_string(), score)).collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(chunk_id: &str, score: f32, entities: &[&str]) -> HybridContext {
+        HybridContext {
+            chunk_id: chunk_id.to_string(),
+            content: String::new(),
+            document_id: None,
+            filename: None,
+            page: None,
+            connected_entities: entities.iter().map(|e| e.to_string()).collect(),
+            score,
+            vector_score: None,
+            keyword_score: None,
+            rerank_score: None,
+            graph_score: None,
+        }
+    }
+
+    fn fact(source: &str, target: &str, mention_count: i64) -> GraphFact {
+        GraphFact {
+            source: source.to_string(),
+            relation: "RELATED_TO".to_string(),
+            target: target.to_string(),
+            description: None,
+            mention_count,
+            confidence: None,
+            evidence: Vec::new(),
+            score: 0.0,
+        }
+    }
+
+    fn pairs(facts: &[GraphFact]) -> Vec<(&str, &str)> {
+        facts.iter().map(|f| (f.source.as_str(), f.target.as_str())).collect()
+    }
+
+    #[test]
+    fn seed_weights_add_chunk_and_entity_similarity() {
+        let contexts = [context("c1", 0.9, &["Solar", "Red"]), context("c2", 0.5, &["Solar"])];
+        let entities = [RelevantEntity { name: "Red".to_string(), category: "Concept".to_string(), description: None, score: 0.3 }];
+        let seeds = seed_weights(&contexts, &entities);
+
+        assert_eq!(seeds.len(), 2);
+        assert!((seeds["Solar"] - 1.4).abs() < 1e-6);
+        assert!((seeds["Red"] - 1.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pagerank_prefers_facts_next_to_heavy_seeds() {
+        // Dos estrellas iguales: la de la semilla con más peso debe ganar
+        let facts = vec![
+            fact("Ligera", "L1", 1),
+            fact("Ligera", "L2", 1),
+            fact("Pesada", "P1", 1),
+            fact("Pesada", "P2", 1),
+            fact("L2", "Lejos", 1),
+        ];
+        let seeds = HashMap::from([("Pesada".to_string(), 0.9), ("Ligera".to_string(), 0.1)]);
+        let ranked = rank_facts(facts, &seeds, 3);
+
+        assert_eq!(ranked.len(), 3);
+        assert_eq!(ranked[0].source, "Pesada");
+        assert_eq!(ranked[1].source, "Pesada");
+        assert!(ranked.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(!pairs(&ranked).contains(&("L2", "Lejos")));
+    }
+
+    #[test]
+    fn pagerank_weighs_mentions_and_confidence() {
+        let mut doubtful = fact("Semilla", "B", 1);
+        doubtful.confidence = Some(0.2);
+        let facts = vec![fact("Semilla", "A", 1), doubtful, fact("A", "C", 10), fact("B", "D", 1)];
+        let seeds = HashMap::from([("Semilla".to_string(), 1.0)]);
+        let ranked = rank_facts(facts, &seeds, 10);
+
+        let position = |pair: (&str, &str)| pairs(&ranked).iter().position(|p| *p == pair).unwrap();
+        assert!(position(("Semilla", "A")) < position(("Semilla", "B")));
+        assert!(position(("A", "C")) < position(("B", "D")));
+    }
+
+    #[test]
+    fn rrf_normalizes_and_keeps_the_original_scores() {
+        let mut vector = vec![context("ambos", 0.9, &[]), context("solo-vector", 0.8, &[])];
+        vector.iter_mut().for_each(|c| c.vector_score = Some(c.score));
+        let mut keyword = vec![context("ambos", 1.0, &[]), context("solo-texto", 0.4, &[])];
+        keyword.iter_mut().for_each(|c| c.keyword_score = Some(c.score));
+
+        let fused = fuse_rrf(vector, keyword, 10);
+        assert_eq!(fused.iter().map(|c| c.chunk_id.as_str()).collect::<Vec<_>>(), vec!["ambos", "solo-vector", "solo-texto"]);
+        // Primero en ambas listas: relevancia máxima
+        assert!((fused[0].score - 1.0).abs() < 1e-6);
+        assert_eq!((fused[0].vector_score, fused[0].keyword_score), (Some(0.9), Some(1.0)));
+        // En una sola lista, segundo: la mitad del máximo con su rango
+        assert!((fused[1].score - (RRF_K + 1.0) / (2.0 * (RRF_K + 2.0))).abs() < 1e-6);
+        assert_eq!((fused[1].vector_score, fused[1].keyword_score), (Some(0.8), None));
+        assert_eq!((fused[2].vector_score, fused[2].keyword_score), (None, Some(0.4)));
+
+        assert_eq!(fuse_rrf(vec![context("a", 1.0, &[])], vec![context("b", 1.0, &[])], 1).len(), 1);
+    }
+
+    #[test]
+    fn budget_trimming_always_keeps_the_first_chunk() {
+        let chunk = |id: &str, chars: usize| HybridContext { content: "x".repeat(chars), ..context(id, 1.0, &[]) };
+        let ids = |contexts: Vec<HybridContext>| contexts.into_iter().map(|c| c.chunk_id).collect::<Vec<_>>();
+
+        // ~100 tokens cada uno
+        let contexts = || vec![chunk("a", 400), chunk("b", 400), chunk("c", 400)];
+        assert_eq!(ids(trim_to_budget(contexts(), 250)), vec!["a", "b"]);
+        assert_eq!(ids(trim_to_budget(contexts(), 10_000)), vec!["a", "b", "c"]);
+        assert_eq!(ids(trim_to_budget(contexts(), 0)), vec!["a"]);
+        // Se corta en el primero que no cabe aunque los siguientes sí cupieran
+        assert_eq!(ids(trim_to_budget(vec![chunk("a", 40), chunk("b", 4000), chunk("c", 40)], 100)), vec!["a"]);
+        assert!(trim_to_budget(Vec::new(), 100).is_empty());
+    }
+
+    #[test]
+    fn iso_dates_check_the_calendar() {
+        assert!(is_iso_date("2024-02-29"), "2024 es bisiesto");
+        assert!(is_iso_date("2000-02-29"), "2000 es bisiesto");
+        assert!(is_iso_date("2025-12-31"));
+        for invalid in ["2023-02-29", "1900-02-29", "2025-13-01", "2025-00-10", "2025-04-31", "2025-01-00", "2025-1-01", "2025-01-01-01", "25-01-01", "2025/01/01", ""] {
+            assert!(!is_iso_date(invalid), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn filters_reject_invalid_dates_and_inverted_ranges() {
+        let range = |from: &str, to: &str| RetrievalFilters {
+            uploaded_from: Some(from.to_string()),
+            uploaded_to: Some(to.to_string()),
+            ..Default::default()
+        };
+        assert!(validate_filters(&RetrievalFilters::default()).is_ok());
+        assert!(validate_filters(&range("2024-02-29", "2024-02-29")).is_ok(), "un solo día es un rango válido");
+        assert!(matches!(validate_filters(&range("2025-03-01", "2025-02-01")), Err(AppError::ValidationError(_))));
+        assert!(matches!(validate_filters(&range("2025-13-01", "2025-12-31")), Err(AppError::ValidationError(_))));
+        let only_to = RetrievalFilters { uploaded_to: Some("2023-02-29".to_string()), ..Default::default() };
+        assert!(matches!(validate_filters(&only_to), Err(AppError::ValidationError(_))));
+    }
+
+    #[test]
+    fn pagerank_without_seeds_in_the_neighbourhood_returns_nothing() {
+        let seeds = HashMap::from([("Otra".to_string(), 1.0)]);
+        assert!(rank_facts(vec![fact("A", "B", 1)], &seeds, 10).is_empty());
+        assert!(rank_facts(Vec::new(), &seeds, 10).is_empty());
+    }
+
+    fn settings() -> RetrievalSettings {
+        RetrievalSettings {
+            mode: RetrievalMode::Hybrid,
+            top_k: 5,
+            max_top_k: 20,
+            min_score: 0.3,
+            hops: 1,
+            max_facts: 15,
+            rerank_overfetch: 3,
+            context_tokens: 3000,
+        }
+    }
+
+    fn request(fields: serde_json::Value) -> ChatRequest {
+        let mut body = serde_json::json!({ "message": "¿qué es la energía solar?" });
+        body.as_object_mut().unwrap().extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(body).expect("petición de chat válida")
+    }
+
+    fn rejects(fields: serde_json::Value) -> bool {
+        matches!(settings().resolve(&request(fields), true), Err(AppError::ValidationError(_)))
+    }
+
+    #[test]
+    fn resolve_fills_in_the_configured_defaults() {
+        let options = settings().resolve(&request(serde_json::json!({})), false).unwrap();
+
+        assert_eq!(options.mode, RetrievalMode::Hybrid);
+        assert_eq!((options.top_k, options.hops, options.max_facts, options.context_tokens), (5, 1, 15, 3000));
+        assert!((options.min_score - 0.3).abs() < 1e-6);
+        assert!(!options.rerank, "sin reranker configurado no se reordena");
+        assert!(options.filters.is_empty());
+    }
+
+    #[test]
+    fn resolve_accepts_the_bounds_inclusive() {
+        let fields = serde_json::json!({ "top_k": 20, "min_score": 1.0, "hops": MAX_GRAPH_HOPS, "max_facts": MAX_FACTS });
+        let options = settings().resolve(&request(fields), true).unwrap();
+        assert_eq!((options.top_k, options.hops, options.max_facts), (20, MAX_GRAPH_HOPS, MAX_FACTS));
+
+        let fields = serde_json::json!({ "top_k": 1, "min_score": 0.0, "hops": 0, "max_facts": 0 });
+        let options = settings().resolve(&request(fields), true).unwrap();
+        assert_eq!((options.top_k, options.hops, options.max_facts), (1, 0, 0));
+        assert_eq!(options.min_score, 0.0);
+    }
+
+    #[test]
+    fn resolve_rejects_values_out_of_bounds() {
+        assert!(rejects(serde_json::json!({ "top_k": 0 })), "top_k 0");
+        assert!(rejects(serde_json::json!({ "top_k": 21 })), "top_k por encima de max_top_k");
+        assert!(rejects(serde_json::json!({ "min_score": -0.1 })), "min_score negativo");
+        assert!(rejects(serde_json::json!({ "min_score": 1.5 })), "min_score por encima de 1");
+        assert!(rejects(serde_json::json!({ "hops": MAX_GRAPH_HOPS + 1 })), "demasiados saltos");
+        assert!(rejects(serde_json::json!({ "max_facts": MAX_FACTS + 1 })), "demasiados hechos");
+        assert!(rejects(serde_json::json!({ "filters": { "uploaded_from": "2025-13-01" } })), "fecha inválida");
+    }
+
+    #[test]
+    fn resolve_rejects_filters_in_global_mode() {
+        let filters = serde_json::json!({ "categories": ["Technology"] });
+        assert!(rejects(serde_json::json!({ "mode": "global", "filters": filters.clone() })));
+        assert!(settings().resolve(&request(serde_json::json!({ "mode": "global" })), true).is_ok());
+        assert!(settings().resolve(&request(serde_json::json!({ "filters": filters })), true).is_ok());
+    }
+
+    #[test]
+    fn resolve_reranks_only_when_configured_and_not_declined() {
+        let resolve = |fields: serde_json::Value, can_rerank| settings().resolve(&request(fields), can_rerank).unwrap().rerank;
+
+        assert!(resolve(serde_json::json!({}), true));
+        assert!(!resolve(serde_json::json!({ "rerank": false }), true));
+        assert!(!resolve(serde_json::json!({ "rerank": true }), false));
+    }
+
+    #[test]
+    fn neighbouring_chunks_add_up_the_score_of_the_facts_they_support() {
+        let scored = |score: f32, evidence: &[&str]| GraphFact {
+            score,
+            evidence: evidence.iter().map(|e| e.to_string()).collect(),
+            ..fact("A", "B", 1)
+        };
+        let facts = [scored(0.5, &["c1", "vecino"]), scored(0.3, &["otro", "vecino"]), scored(0.4, &["lejano"])];
+        let contexts = [context("c1", 0.9, &["A"])];
+
+        let ranked = rank_graph_chunks(&facts, &contexts, 10);
+        let ids: Vec<&str> = ranked.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["vecino", "lejano", "otro"], "los ya recuperados no se repiten");
+        assert!((ranked[0].1 - 0.8).abs() < 1e-6);
+
+        assert_eq!(rank_graph_chunks(&facts, &contexts, 1).len(), 1);
+        assert!(rank_graph_chunks(&[], &contexts, 10).is_empty());
+    }
+
+    /// Reranker con puntuaciones fijas; `None` simula que el servicio falla.
+    struct FixedReranker(Option<Vec<f32>>);
+
+    #[async_trait::async_trait]
+    impl Reranker for FixedReranker {
+        async fn score(&self, _query: &str, _passages: &[&str]) -> Result<Vec<f32>, AppError> {
+            self.0.clone().ok_or_else(|| AppError::AIError("reranker caído".to_string()))
+        }
+    }
+
+    fn candidates() -> Vec<HybridContext> {
+        ["c1", "c2", "c3", "c4"].iter().enumerate().map(|(i, id)| context(id, 0.9 - i as f32 * 0.1, &[])).collect()
+    }
+
+    fn ids(contexts: &[HybridContext]) -> Vec<&str> {
+        contexts.iter().map(|c| c.chunk_id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn rerank_orders_by_probability_and_keeps_it_as_relevance() {
+        let reranker = FixedReranker(Some(vec![0.1, 0.7, 0.4, 0.9]));
+        let reranked = rerank(&reranker, "pregunta", candidates()).await;
+
+        assert_eq!(ids(&reranked), vec!["c4", "c2", "c3", "c1"]);
+        assert_eq!(reranked[0].score, 0.9);
+        assert_eq!(reranked[0].rerank_score, Some(0.9));
+    }
+
+    #[tokio::test]
+    async fn rerank_orders_logits_by_their_raw_value() {
+        // Logits como los de llama.cpp: negativos y por encima de 1, que la sigmoide acerca a 0 y a 1
+        let reranker = FixedReranker(Some(vec![-6.0, 25.0, -2.0, 18.0]));
+        let reranked = rerank(&reranker, "pregunta", candidates()).await;
+
+        assert_eq!(ids(&reranked), vec!["c2", "c4", "c3", "c1"]);
+        assert_eq!(reranked[0].rerank_score, Some(25.0), "se expone la puntuación original");
+        assert!(reranked.iter().all(|c| (0.0..=1.0).contains(&c.score)));
+        assert!(reranked[2].score > reranked[3].score, "los logits negativos no se aplastan a 0");
+    }
+
+    #[tokio::test]
+    async fn a_failed_reranker_keeps_the_retrieval_order() {
+        let reranked = rerank(&FixedReranker(None), "pregunta", candidates()).await;
+
+        assert_eq!(ids(&reranked), vec!["c1", "c2", "c3", "c4"]);
+        assert!(reranked.iter().all(|c| c.rerank_score.is_none()));
+        assert_eq!(reranked[0].score, 0.9, "la relevancia sigue siendo la de la búsqueda");
+    }
+
+    #[test]
+    fn rerank_relevance_only_squashes_logits() {
+        assert_eq!(rerank_relevance(&[0.0, 0.5, 1.0]), vec![0.0, 0.5, 1.0]);
+
+        let logits = rerank_relevance(&[-2.0, 0.0, 2.0]);
+        assert!((logits[1] - 0.5).abs() < 1e-6);
+        assert!(logits[0] > 0.0 && logits[0] < logits[1] && logits[1] < logits[2] && logits[2] < 1.0);
+    }
+}
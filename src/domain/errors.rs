@@ -1,27 +1,44 @@
-use axum::{http::StatusCode, response::{IntoResponse, Response}, Json};
+use axum::{http::{header, StatusCode}, response::{IntoResponse, Response}, Json};
 use serde_json::json;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum AppError {
     #[error("Database error: {0}")]
     DatabaseError(String),
     #[error("AI Provider error: {0}")]
     AIError(String),
+    /// Fallo transitorio del proveedor (429, 5xx, red, circuito abierto): se puede reintentar
+    #[error("AI Provider temporarily unavailable: {message}")]
+    AITransientError { message: String, retry_after: Option<Duration> },
     #[error("Configuration error: {0}")]
     ConfigError(String),
     #[error("Validation error: {0}")]
     ValidationError(String),
     #[error("Parsing error: {0}")]
     ParseError(String),
+    #[error("Not found: {0}")]
+    NotFoundError(String),
     #[error("Admin operation requires force flag")]
     SafetyGuardError,
+    /// Invariante interno roto (un fallo del código, no de los datos ni del proveedor)
+    #[error("Internal invariant violated: {0}")]
+    InternalError(String),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if let AppError::AITransientError { retry_after, .. } = &self {
+            let retry_after = retry_after.map(|d| d.as_secs().max(1)).unwrap_or(1).to_string();
+            let body = Json(json!({ "error": self.to_string() }));
+            return (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, retry_after)], body).into_response();
+        }
+
         let (status, error_message) = match self {
             AppError::ValidationError(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            AppError::NotFoundError(_) => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::SafetyGuardError => (StatusCode::FORBIDDEN, self.to_string()),
             AppError::ConfigError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
             _ => (StatusCode::INTERNAL_SERVER_ERROR, format!("Internal Error: {}", self)),
@@ -1,3 +1,4 @@
 pub mod models;
+pub mod ontology;
 pub mod ports;
 pub mod errors;
\ No newline at end of file
@@ -3,6 +3,13 @@ use serde::{Deserialize, Serialize};
 use secrecy::SecretString;
 use utoipa::ToSchema;
 use validator::Validate;
+use uuid::Uuid;
+
+/// Tokens aproximados de un texto (~4 caracteres por token en inglés/español), para
+/// presupuestos de prompt y de cuota sin depender del tokenizador del proveedor.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len() / 4 + 1
+}
 
 // --- CONFIGURACIÓN (Sin cambios significativos) ---
 
@@ -36,22 +43,43 @@ pub struct AIConfig {
 
 // --- GRAFO BÁSICO (Sin cambios) ---
 
+// Los alias aceptan los nombres de campo alternativos que devuelven algunos modelos
+
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct GraphEntity {
+    #[serde(alias = "entity", alias = "label")]
     pub name: String,
+    #[serde(default, alias = "type")]
     pub category: String, 
+    /// Qué es la entidad según el fragmento (se acumula entre fragmentos)
+    #[serde(default)]
+    pub description: String,
+    /// Otros nombres con los que aparece en el fragmento
+    #[serde(default)]
+    pub aliases: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct GraphRelation {
+    #[serde(alias = "from", alias = "head", alias = "subject")]
     pub source: String,
+    #[serde(alias = "to", alias = "tail", alias = "object")]
     pub target: String,
+    #[serde(alias = "type", alias = "relation", alias = "predicate")]
     pub relation_type: String, 
+    /// Frase breve que justifica la relación según el texto
+    #[serde(default)]
+    pub description: String,
+    /// Confianza de la extracción (0.0 - 1.0), si el modelo la da
+    #[serde(default)]
+    pub confidence: Option<f32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct KnowledgeExtraction {
+    #[serde(default, alias = "nodes")]
     pub entities: Vec<GraphEntity>,
+    #[serde(default, alias = "edges", alias = "relationships")]
     pub relations: Vec<GraphRelation>,
 }
 
@@ -59,7 +87,311 @@ pub struct KnowledgeExtraction {
 pub struct IngestionRequest {
     #[validate(length(min = 10))]
     pub content: String,
+    #[serde(default)]
     pub metadata: serde_json::Value,
+    /// Nombre del archivo original (None para texto pegado directamente)
+    #[serde(default)]
+    pub filename: Option<String>,
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    #[serde(default)]
+    pub uploaded_by: Option<String>,
+    /// Reprocesa aunque el contenido (o alguno de sus chunks) ya esté ingerido
+    #[serde(default)]
+    pub force: bool,
+    /// Estrategia de troceado; lo que no se indique se toma de la configuración del servidor
+    #[serde(default)]
+    pub chunking: ChunkingOptions,
+}
+
+// --- TROCEADO (CHUNKING) ---
+
+/// Estrategia para dividir el texto en chunks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkingStrategy {
+    /// Elige según el formato: `table` para hojas de cálculo/CSV, `paragraph` para
+    /// Markdown/DOCX/HTML y `sliding` para el resto
+    #[default]
+    Auto,
+    /// Ventana deslizante de caracteres que respeta palabras completas
+    Sliding,
+    /// Agrupa frases completas
+    Sentence,
+    /// Agrupa párrafos dentro de cada sección (encabezados Markdown/DOCX), que se anteponen al chunk
+    Paragraph,
+    /// Ventana medida en tokens (cl100k) en lugar de caracteres
+    Tokens,
+    /// Filas de tabla completas, repitiendo la cabecera de la hoja en cada chunk
+    Table,
+}
+
+impl std::str::FromStr for ChunkingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "sliding" => Ok(Self::Sliding),
+            "sentence" => Ok(Self::Sentence),
+            "paragraph" => Ok(Self::Paragraph),
+            "tokens" => Ok(Self::Tokens),
+            "table" => Ok(Self::Table),
+            other => Err(format!("Unknown chunking strategy '{}'", other)),
+        }
+    }
+}
+
+/// Parámetros de troceado. El tamaño y el solape se miden en caracteres, salvo con
+/// la estrategia `tokens`, que los mide en tokens.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ChunkingOptions {
+    #[serde(default)]
+    pub strategy: Option<ChunkingStrategy>,
+    #[serde(default)]
+    pub chunk_size: Option<usize>,
+    #[serde(default)]
+    pub chunk_overlap: Option<usize>,
+}
+
+// --- DOCUMENTOS Y PROCEDENCIA ---
+
+/// Separador de página (form feed) que los extractores insertan entre páginas
+/// para que la ingesta pueda atribuir cada chunk a su página de origen.
+pub const PAGE_BREAK: char = '\u{000C}';
+
+/// Datos necesarios para registrar un nodo `Document` antes de procesar sus chunks.
+#[derive(Debug, Clone)]
+pub struct NewDocument {
+    pub id: Uuid,
+    pub filename: String,
+    pub mime_type: String,
+    pub content_hash: String,
+    pub uploaded_by: String,
+    pub metadata: serde_json::Value,
+}
+
+/// Fragmento de un documento listo para persistir junto a su embedding.
+#[derive(Debug, Clone)]
+pub struct ChunkRecord {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    /// Posición del chunk dentro del documento (0-based)
+    pub order: usize,
+    /// Página de origen (1-based), si el formato la conserva
+    pub page: Option<u32>,
+    pub content: String,
+    /// SHA-256 (hex) del texto normalizado del chunk
+    pub content_hash: String,
+    /// La extracción de entidades falló: el chunk no se reutiliza en la deduplicación
+    pub extraction_failed: bool,
+}
+
+/// Documento fuente tal y como se expone en la API.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DocumentInfo {
+    pub id: String,
+    pub filename: String,
+    pub mime_type: String,
+    /// SHA-256 (hex) del texto extraído, normalizado
+    pub content_hash: String,
+    pub uploaded_by: String,
+    /// Fecha de subida (ISO 8601)
+    pub uploaded_at: String,
+    pub metadata: serde_json::Value,
+    pub chunk_count: usize,
+    /// Chunks que la última ingesta no pudo procesar por completo
+    pub failed_chunks: usize,
+}
+
+/// Vista de inspección de un chunk perteneciente a un documento.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct DocumentChunkInfo {
+    pub id: String,
+    pub order: usize,
+    pub page: Option<u32>,
+    pub short_content: String,
+    /// Entidades mencionadas en este chunk
+    pub entities: Vec<String>,
+}
+
+/// Resultado de eliminar los chunks de un documento y recolectar la basura del grafo.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct DocumentPurgeResult {
+    pub document_id: String,
+    pub chunks_deleted: usize,
+    /// Relaciones que ya no respalda ningún chunk restante
+    pub relations_deleted: usize,
+    /// Entidades que ya no menciona ningún chunk restante
+    pub entities_deleted: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DocumentDetail {
+    pub document: DocumentInfo,
+    pub chunks: Vec<DocumentChunkInfo>,
+}
+
+// --- TRABAJOS DE INGESTA EN SEGUNDO PLANO ---
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+/// Estado persistido de un trabajo de ingesta.
+/// `next_chunk` actúa como checkpoint: tras un reinicio se reanuda desde ese chunk.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct IngestionJob {
+    pub id: String,
+    /// Documento destino (se asigna al crear el trabajo para que la reanudación sea idempotente)
+    pub document_id: String,
+    /// `true` si el trabajo sustituye el contenido de un documento existente (PUT)
+    pub replace_existing: bool,
+    pub filename: Option<String>,
+    pub status: JobStatus,
+    pub total_chunks: usize,
+    /// Índice del siguiente chunk a procesar
+    pub next_chunk: usize,
+    pub chunks_done: usize,
+    pub chunks_failed: usize,
+    /// Chunks deduplicados (embedding y entidades reutilizados)
+    pub chunks_reused: usize,
+    pub entities_created: usize,
+    /// Entidades y relaciones fuera de la ontología (descartadas o en cuarentena)
+    #[serde(default)]
+    pub off_ontology: usize,
+    /// Tipos de relación rechazados por no ser etiquetas seguras (sin repetir)
+    #[serde(default)]
+    pub rejected_relation_types: Vec<String>,
+    pub errors: Vec<String>,
+    /// Detalle de los chunks que no se pudieron procesar por completo
+    #[serde(default)]
+    pub failed_chunks: Vec<ChunkFailure>,
+    /// Últimos mensajes de progreso (legibles)
+    pub messages: Vec<String>,
+    /// Número de veces que se ha reanudado tras un reinicio
+    pub resumed: usize,
+    /// Marcas de tiempo (segundos UNIX)
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Fase del procesamiento de un chunk en la que se produjo un fallo.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkStage {
+    /// Sin embedding: el chunk no se guardó
+    Embedding,
+    /// Guardado y recuperable por vector, pero sin entidades
+    Extraction,
+}
+
+/// Chunk que no se pudo procesar por completo tras agotar los reintentos.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct ChunkFailure {
+    /// Posición 1-based, como en los eventos de progreso
+    pub chunk: usize,
+    pub stage: ChunkStage,
+    pub reason: String,
+    /// Fallo transitorio del proveedor: volver a subir el documento lo reintentará
+    pub transient: bool,
+}
+
+/// Evento tipado de progreso de una ingesta (se emite como evento SSE).
+/// `chunk` es 1-based para coincidir con los mensajes legibles.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+#[serde(tag = "type")]
+pub enum IngestionEvent {
+    DocumentChunked { total: usize },
+    AlreadyIngested { document_id: String, filename: String },
+    Unchanged { document_id: String },
+    PreviousVersionPurged { chunks_deleted: usize, relations_deleted: usize, entities_deleted: usize },
+    Resumed { from_chunk: usize, total: usize, discarded: usize },
+    ChunkStarted { chunk: usize, total: usize },
+    ChunkReused { chunk: usize, total: usize },
+    EmbeddingDone { chunk: usize, total: usize },
+    EntitiesExtracted { chunk: usize, total: usize, count: usize },
+    RelationTypesRejected { chunk: usize, total: usize, types: Vec<String> },
+    /// Descripciones resumidas y embeddings de entidades recalculados al final de la ingesta
+    EntityProfilesUpdated { count: usize },
+    ChunkFailed { chunk: usize, total: usize, reason: String },
+    CancelRequested,
+    Cancelled,
+    Completed { document_id: String, chunks_failed: usize },
+    /// Se vuelve a subir un documento cuya ingesta anterior dejó chunks sin procesar
+    RetryingIncomplete { document_id: String, chunks_failed: usize },
+    Fatal { error: String },
+}
+
+impl IngestionEvent {
+    /// Mensaje legible equivalente (el que se muestra en el dashboard).
+    pub fn message(&self) -> String {
+        match self {
+            Self::DocumentChunked { total } => format!("🔪 Documento largo detectado. Dividido en {} fragmentos.", total),
+            Self::AlreadyIngested { document_id, filename } => {
+                format!("♻️ Contenido ya ingerido como '{}' ({}). Usa 'force' para reprocesarlo.", filename, document_id)
+            },
+            Self::Unchanged { .. } => "♻️ El contenido no ha cambiado; no hay nada que reprocesar.".to_string(),
+            Self::PreviousVersionPurged { chunks_deleted, relations_deleted, entities_deleted } => format!(
+                "🧹 Versión anterior eliminada: {} fragmentos, {} relaciones y {} entidades huérfanas.",
+                chunks_deleted, relations_deleted, entities_deleted
+            ),
+            Self::Resumed { from_chunk, total, discarded } => format!(
+                "🔁 Reanudando desde el fragmento {}/{} ({} fragmentos incompletos descartados).",
+                from_chunk, total, discarded
+            ),
+            Self::ChunkStarted { chunk, total } => format!("🧠 [{}/{}] Generando Embeddings...", chunk, total),
+            Self::ChunkReused { chunk, total } => {
+                format!("♻️ [{}/{}] Fragmento ya ingerido, reutilizando embeddings y entidades.", chunk, total)
+            },
+            Self::EmbeddingDone { chunk, total } => format!("🕵️ [{}/{}] Extrayendo conocimiento...", chunk, total),
+            Self::EntitiesExtracted { chunk, total, count } => {
+                format!("🕸️ [{}/{}] Conectando {} entidades al grafo...", chunk, total, count)
+            },
+            Self::RelationTypesRejected { chunk, total, types } => {
+                format!("🚫 [{}/{}] Tipos de relación rechazados: {}", chunk, total, types.join(", "))
+            },
+            Self::EntityProfilesUpdated { count } => format!("🧬 {} entidades actualizadas (descripción y embedding).", count),
+            Self::ChunkFailed { chunk, total, reason } => format!("⚠️ [{}/{}] Fragmento fallido: {}", chunk, total, reason),
+            Self::CancelRequested => "⛔ Cancelación solicitada...".to_string(),
+            Self::Cancelled => "⛔ Trabajo cancelado.".to_string(),
+            Self::Completed { chunks_failed: 0, .. } => "✅ ¡Todo el documento ha sido procesado!".to_string(),
+            Self::Completed { chunks_failed, .. } => format!(
+                "⚠️ Documento procesado con {} fragmentos fallidos (ver 'failed_chunks'); vuelve a subirlo para reintentarlos.",
+                chunks_failed
+            ),
+            Self::RetryingIncomplete { document_id, chunks_failed } => format!(
+                "🔁 La ingesta anterior de este contenido ({}) dejó {} fragmentos fallidos: se reprocesa.",
+                document_id, chunks_failed
+            ),
+            Self::Fatal { error } => format!("❌ Error Crítico: {}", error),
+        }
+    }
+
+    /// Eventos tras los cuales el trabajo ya no emitirá nada más.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Cancelled | Self::Completed { .. } | Self::Fatal { .. })
+    }
+}
+
+/// Evento tal y como viaja por SSE: el evento tipado más su mensaje legible.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct IngestionProgress {
+    pub job_id: String,
+    #[serde(flatten)]
+    pub event: IngestionEvent,
+    pub message: String,
 }
 
 // --- VISUALIZACIÓN (Sin cambios) ---
@@ -69,6 +401,10 @@ pub struct VisNode {
     pub id: String,
     pub label: String,
     pub group: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -76,6 +412,19 @@ pub struct VisEdge {
     pub from: String,
     pub to: String,
     pub label: String,
+    /// Chunks que respaldan la relación
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub evidence: Vec<String>,
+    /// Veces que la extracción ha afirmado la relación
+    pub mention_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -86,42 +435,324 @@ pub struct GraphDataResponse {
 
 // --- CHAT RAG AVANZADO (MODIFICADO) ---
 
+/// Cómo se buscan los fragmentos de una pregunta.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrievalMode {
+    /// Solo similitud de embeddings
+    Vector,
+    /// Solo texto completo (BM25) sobre el contenido de los chunks y los nombres de entidades
+    Keyword,
+    /// Ambas listas fusionadas por reciprocal rank fusion
+    #[default]
+    Hybrid,
+    /// Preguntas sobre todo el corpus: map-reduce sobre los resúmenes de comunidades
+    Global,
+}
+
+impl std::str::FromStr for RetrievalMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "vector" => Ok(Self::Vector),
+            "keyword" => Ok(Self::Keyword),
+            "hybrid" => Ok(Self::Hybrid),
+            "global" => Ok(Self::Global),
+            other => Err(format!("Unknown retrieval mode '{}'", other)),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ChatRequest {
     pub message: String,
+    /// Conversación a continuar; sin ella se abre una nueva
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+    /// Búsqueda de fragmentos (por defecto `CHAT_RETRIEVAL_MODE`)
+    #[serde(default)]
+    pub mode: Option<RetrievalMode>,
+    /// Fragmentos a recuperar (por defecto `CHAT_TOP_K`)
+    #[serde(default)]
+    pub top_k: Option<usize>,
+    /// Relevancia mínima (0.0 - 1.0) de un fragmento en su búsqueda para usarse como fuente: similitud
+    /// vectorial o BM25 saturado, ver `SourceReference::relevance` (por defecto `CHAT_MIN_SCORE`)
+    #[serde(default)]
+    pub min_score: Option<f32>,
+    /// Saltos de expansión por el grafo desde las entidades de las fuentes; 0 la desactiva (por defecto `CHAT_GRAPH_HOPS`)
+    #[serde(default)]
+    pub hops: Option<usize>,
+    /// Máximo de hechos (triples) del grafo en el prompt (por defecto `CHAT_MAX_FACTS`)
+    #[serde(default)]
+    pub max_facts: Option<usize>,
+    /// Reordenar los fragmentos con el reranker configurado (`RERANKER`); por defecto sí, si hay uno
+    #[serde(default)]
+    pub rerank: Option<bool>,
+    /// Nivel de comunidades del modo `global` (0 = las más pequeñas; por defecto `CHAT_COMMUNITY_LEVEL`)
+    #[serde(default)]
+    pub community_level: Option<usize>,
+    /// Acota las fuentes a una parte del corpus (no aplica al modo `global`)
+    #[serde(default)]
+    pub filters: Option<RetrievalFilters>,
+}
+
+/// Filtros de fragmentos de una pregunta. Los criterios se combinan entre sí (todos deben
+/// cumplirse); dentro de una lista basta con que coincida un valor.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct RetrievalFilters {
+    /// Solo fragmentos de estos documentos
+    #[serde(default)]
+    pub document_ids: Vec<Uuid>,
+    /// Solo fragmentos que mencionan alguna entidad de estas categorías
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Pares clave/valor que deben estar en los metadatos del documento; si el valor guardado
+    /// es una lista, basta con que lo contenga (ej: `{"tags": "2025"}`)
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    /// Documentos subidos desde esta fecha (`YYYY-MM-DD`, incluida)
+    #[serde(default)]
+    pub uploaded_from: Option<String>,
+    /// Documentos subidos hasta esta fecha (`YYYY-MM-DD`, incluida)
+    #[serde(default)]
+    pub uploaded_to: Option<String>,
+}
+
+impl RetrievalFilters {
+    pub fn is_empty(&self) -> bool {
+        self.document_ids.is_empty()
+            && self.categories.is_empty()
+            && self.metadata.is_empty()
+            && self.uploaded_from.is_none()
+            && self.uploaded_to.is_none()
+    }
 }
 
 /// Referencia a una fuente documental específica.
 /// Se usa para crear citas interactivas [1] que iluminan el grafo.
-#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
 pub struct SourceReference {
     /// Índice visual para la cita (ej: 1, 2, 3)
     pub index: usize,
     /// ID interno del chunk
     pub chunk_id: String,
+    /// Documento de origen del chunk
+    pub document_id: Option<String>,
+    /// Nombre del archivo de origen
+    pub filename: Option<String>,
+    /// Página de origen (1-based), si se conoce
+    pub page: Option<u32>,
     /// Fragmento de texto para mostrar en tooltip/panel
     pub short_content: String,
-    /// Puntuación de relevancia (0.0 - 1.0)
+    /// Relevancia (0.0 - 1.0): la del reranker si se usó; si no, similitud vectorial, BM25 saturado
+    /// (`bm25 / (bm25 + 5)`) o fusión de ambas según el modo. Los fragmentos añadidos por la expansión
+    /// del grafo llevan su puntuación en él
     pub relevance: f32,
+    /// Similitud vectorial, si el fragmento salió de la búsqueda vectorial
+    #[serde(default)]
+    pub vector_score: Option<f32>,
+    /// Puntuación BM25, si el fragmento salió de la búsqueda de texto completo
+    #[serde(default)]
+    pub keyword_score: Option<f32>,
+    /// Puntuación original del reranker (probabilidad o logit, según el modelo), si se usó
+    #[serde(default)]
+    pub rerank_score: Option<f32>,
+    /// Suma de la puntuación (personalized PageRank) de los hechos del grafo que respalda, si el
+    /// fragmento no salió de la búsqueda sino de la expansión por el grafo
+    #[serde(default)]
+    pub graph_score: Option<f32>,
     /// Conceptos (nodos) del grafo presentes en este fragmento.
     /// Clave para la interactividad Visual <-> Texto.
     pub concepts: Vec<String>,
 }
 
+/// Entidad del grafo recuperada por similitud con la pregunta.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct RelevantEntity {
+    pub name: String,
+    pub category: String,
+    pub description: Option<String>,
+    /// Similitud coseno con la pregunta
+    pub score: f32,
+}
+
+/// Relación del vecindario de las fuentes incluida como hecho en el prompt.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct GraphFact {
+    pub source: String,
+    pub relation: String,
+    pub target: String,
+    pub description: Option<String>,
+    pub mention_count: i64,
+    pub confidence: Option<f32>,
+    /// Chunks que respaldan la relación
+    pub evidence: Vec<String>,
+    /// Relevancia respecto a las fuentes (personalized PageRank de sus extremos)
+    pub score: f32,
+}
+
 /// Respuesta estructurada del chat.
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ChatResponse {
+    pub conversation_id: Uuid,
     /// Texto generado por el LLM (Markdown)
     pub response: String,
     /// Lista de fuentes utilizadas para generar la respuesta
     pub sources: Vec<SourceReference>,
+    /// Entidades cuya descripción se incluyó en el contexto
+    #[serde(default)]
+    pub entities: Vec<RelevantEntity>,
+    /// Hechos del grafo incluidos en el contexto
+    #[serde(default)]
+    pub facts: Vec<GraphFact>,
+}
+
+/// Evento de `/api/chat/stream` (el nombre del evento SSE es `type`): primero `Sources`,
+/// después un `Token` por fragmento de texto y, al final, `Usage` (o `Error` si el LLM falla).
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type")]
+pub enum ChatStreamEvent {
+    Sources { conversation_id: Uuid, sources: Vec<SourceReference>, entities: Vec<RelevantEntity>, facts: Vec<GraphFact> },
+    Token { text: String },
+    Usage { input_tokens: u64, output_tokens: u64, total_tokens: u64 },
+    Error { message: String },
+}
+
+/// Fragmento de una respuesta de chat en streaming del puerto `AIService`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatChunk {
+    Text(String),
+    /// Último elemento: tokens consumidos por la respuesta completa.
+    Done(TokenUsage),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+}
+
+// --- CONVERSACIONES ---
+
+/// Pregunta y respuesta dentro de una conversación.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct ConversationTurn {
+    pub question: String,
+    /// Pregunta reescrita sin depender del historial (la que se usó para recuperar contexto)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub standalone_question: Option<String>,
+    pub answer: String,
+    pub sources: Vec<SourceReference>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    /// Resumen de los turnos más antiguos, que ya no caben en el historial del prompt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Turnos (desde el primero) incluidos en `summary`
+    pub summarized_turns: usize,
+    pub turns: Vec<ConversationTurn>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Conversación en el listado (sin turnos).
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub turn_count: usize,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RenameConversationRequest {
+    pub title: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct HybridContext {
     pub chunk_id: String,
     pub content: String,
+    pub document_id: Option<String>,
+    pub filename: Option<String>,
+    pub page: Option<u32>,
     pub connected_entities: Vec<String>, 
+    /// Relevancia usada para ordenar (0.0 - 1.0); ver `SourceReference::relevance`
+    pub score: f32,
+    pub vector_score: Option<f32>,
+    pub keyword_score: Option<f32>,
+    pub rerank_score: Option<f32>,
+    pub graph_score: Option<f32>,
+}
+
+/// Parámetros de la recuperación de fragmentos para una pregunta.
+#[derive(Debug, Clone)]
+pub struct RetrievalOptions {
+    pub mode: RetrievalMode,
+    pub top_k: usize,
+    pub min_score: f32,
+    /// Saltos de la expansión por el grafo (0 = sin expansión)
+    pub hops: usize,
+    pub max_facts: usize,
+    pub rerank: bool,
+    /// Presupuesto (tokens estimados) del contenido de los fragmentos en el prompt
+    pub context_tokens: usize,
+    pub filters: RetrievalFilters,
+}
+
+// --- COMUNIDADES ---
+
+/// Grupo de entidades densamente conectadas, con su resumen. El nivel 0 es el más fino; cada
+/// comunidad de nivel `n + 1` agrupa varias de nivel `n` (`parent_id`).
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct Community {
+    pub id: String,
+    pub level: usize,
+    pub title: String,
+    pub summary: String,
+    /// Entidades de la comunidad (incluidas las de sus subcomunidades)
+    pub size: usize,
+    /// Las entidades más conectadas de la comunidad
+    pub members: Vec<String>,
+    pub parent_id: Option<String>,
+}
+
+/// Entidad del grafo completo sobre el que se detectan comunidades.
+#[derive(Debug, Clone)]
+pub struct EntityNode {
+    pub name: String,
+    pub category: String,
+    pub description: Option<String>,
+}
+
+/// Relación entre dos entidades, con su número de menciones como peso.
+#[derive(Debug, Clone)]
+pub struct EntityLink {
+    pub source: String,
+    pub target: String,
+    pub relation: String,
+    pub description: Option<String>,
+    pub weight: f64,
+}
+
+/// Estado de la detección de comunidades.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Default)]
+pub struct CommunityIndexStatus {
+    pub building: bool,
+    /// Fin de la última reconstrucción completada (segundos UNIX)
+    pub built_at: Option<u64>,
+    pub levels: usize,
+    pub communities: usize,
+    pub last_error: Option<String>,
 }
 
 // --- RAZONAMIENTO E INFERENCIA ---
@@ -137,4 +768,151 @@ pub struct InferredRelation {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct InferenceResult {
     pub new_relations: Vec<InferredRelation>,
+}
+
+/// Relación propuesta por el LLM que no se guardó por tener un tipo no admitido.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct RejectedRelation {
+    pub source: String,
+    pub target: String,
+    pub relation: String,
+    pub reason: String,
+}
+
+/// Resultado de una ronda de razonamiento.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct ReasoningResult {
+    /// Relaciones inferidas y guardadas (con el tipo ya normalizado)
+    pub new_relations: Vec<InferredRelation>,
+    pub rejected: Vec<RejectedRelation>,
+}
+
+// --- RESOLUCIÓN DE ENTIDADES ---
+
+/// Resumen de una entidad para compararla con otras (texto embebible y para el LLM).
+#[derive(Debug, Clone)]
+pub struct EntityProfile {
+    pub name: String,
+    pub category: String,
+    pub description: Option<String>,
+    pub aliases: Vec<String>,
+    /// Algunas entidades vecinas (contexto para desambiguar)
+    pub neighbors: Vec<String>,
+    /// Chunks que la mencionan
+    pub mentions: usize,
+    /// Embedding guardado de la entidad (`None` si aún no se ha calculado)
+    pub embedding: Option<Vec<f32>>,
+}
+
+impl EntityProfile {
+    pub fn describe(&self) -> String {
+        let mut text = format!("{} ({})", self.name, self.category);
+        if let Some(description) = self.description.as_deref().filter(|d| !d.is_empty()) {
+            text.push_str(&format!(": {}", description));
+        }
+        if !self.aliases.is_empty() {
+            text.push_str(&format!(". Also known as: {}", self.aliases.join(", ")));
+        }
+        if !self.neighbors.is_empty() {
+            text.push_str(&format!(". Related to: {}", self.neighbors.join(", ")));
+        }
+        text
+    }
+}
+
+/// Entidad cuya descripción cambió desde que se calculó su embedding.
+#[derive(Debug, Clone)]
+pub struct StaleEntity {
+    pub name: String,
+    pub category: String,
+    pub description: String,
+}
+
+impl StaleEntity {
+    /// Texto que se embebe para buscar la entidad por similitud.
+    pub fn embedding_text(&self) -> String {
+        if self.description.is_empty() {
+            format!("{} ({})", self.name, self.category)
+        } else {
+            format!("{} ({}): {}", self.name, self.category, self.description)
+        }
+    }
+}
+
+/// Embedding recalculado de una entidad; `description` sustituye a la acumulada si se resumió.
+#[derive(Debug, Clone)]
+pub struct EntityEmbedding {
+    pub name: String,
+    pub description: Option<String>,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize, ToSchema, Default)]
+pub struct ResolutionRequest {
+    /// Similitud coseno mínima entre perfiles de entidades de la misma categoría (por defecto 0.9)
+    pub similarity_threshold: Option<f32>,
+    /// Entidades a comparar, las más mencionadas primero (por defecto 1000, como mucho 5000)
+    pub max_entities: Option<usize>,
+    /// Máximo de candidatos devueltos (por defecto 100)
+    pub max_candidates: Option<usize>,
+    /// Pedir al LLM que confirme cada candidato
+    #[serde(default)]
+    pub adjudicate: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchReason {
+    /// Mismo nombre normalizado (mayúsculas, puntuación, sufijos societarios)
+    NormalizedName,
+    /// El nombre de una coincide con un alias de la otra
+    Alias,
+    /// Perfiles semánticamente muy parecidos
+    Embedding,
+}
+
+/// Veredicto del LLM sobre si dos entidades son la misma.
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone)]
+pub struct MergeAdjudication {
+    pub same_entity: bool,
+    #[serde(default)]
+    pub confidence: f32,
+    #[serde(default)]
+    pub reasoning: String,
+}
+
+/// Par de entidades que probablemente son la misma. `keep` es la más mencionada.
+#[derive(Debug, Serialize, ToSchema, Clone)]
+pub struct MergeCandidate {
+    pub keep: String,
+    pub keep_category: String,
+    pub merge: String,
+    pub merge_category: String,
+    /// 1.0 para coincidencias de nombre; similitud coseno en el resto
+    pub score: f32,
+    pub reasons: Vec<MatchReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adjudication: Option<MergeAdjudication>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MergeEntitiesRequest {
+    /// Entidad que se conserva
+    pub keep: String,
+    /// Entidad que se absorbe (su nombre pasa a `aliases` de `keep`)
+    pub merge: String,
+}
+
+/// Fusión registrada; guarda lo necesario para deshacerla.
+#[derive(Debug, Serialize, ToSchema, Clone)]
+pub struct EntityMergeRecord {
+    pub id: String,
+    pub kept: String,
+    pub merged: String,
+    /// Relaciones de la entidad absorbida trasladadas a la conservada
+    pub relations_moved: usize,
+    /// Chunks que pasan a mencionar a la conservada
+    pub mentions_moved: usize,
+    pub created_at: String,
+    pub undone: bool,
 }
\ No newline at end of file
@@ -0,0 +1,555 @@
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::domain::{
+    models::{GraphEntity, GraphRelation, KnowledgeExtraction},
+    errors::AppError
+};
+
+/// Clave de comparación: `Person`, `PERSON` y ` person ` son la misma categoría;
+/// `works for` y `WORKS_FOR` el mismo tipo de relación.
+fn key(name: &str) -> String {
+    name.trim().to_lowercase().replace([' ', '-'], "_")
+}
+
+// Etiquetas estructurales del grafo: una relación extraída no puede hacerse pasar por ellas
+const RESERVED_RELATION_TYPES: &[&str] = &["MENTIONS", "HAS_CHUNK", "QUARANTINED"];
+// Prefijo de las relaciones inferidas por IA (las protege de la recolección de basura)
+const RESERVED_RELATION_PREFIX: &str = "INFERRED_";
+const MAX_RELATION_TYPE_LEN: usize = 64;
+
+/// Convierte un tipo de relación producido por el LLM en una etiqueta segura para Cypher:
+/// espacios y guiones pasan a `_` y todo a mayúsculas (`works for` -> `WORKS_FOR`). Solo se
+/// admiten letras, dígitos y `_`, empezando por letra; cualquier otro carácter (comillas
+/// invertidas, llaves, paréntesis, flechas...) rechaza el tipo en lugar de "arreglarlo".
+pub fn sanitize_relation_type(raw: &str) -> Result<String, String> {
+    let label = raw.split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+        .to_uppercase();
+
+    if label.is_empty() {
+        return Err("empty relation type".to_string());
+    }
+    if label.chars().count() > MAX_RELATION_TYPE_LEN {
+        return Err(format!("relation type longer than {} characters", MAX_RELATION_TYPE_LEN));
+    }
+    if let Some(c) = label.chars().find(|c| !(c.is_alphanumeric() || *c == '_')) {
+        return Err(format!("relation type contains forbidden character {:?}", c));
+    }
+    if !label.starts_with(char::is_alphabetic) {
+        return Err("relation type must start with a letter".to_string());
+    }
+    if RESERVED_RELATION_TYPES.contains(&label.as_str()) || label.starts_with(RESERVED_RELATION_PREFIX) {
+        return Err(format!("relation type '{}' is reserved", label));
+    }
+    Ok(label)
+}
+
+/// Categoría de entidad admitida.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CategoryDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Nombres alternativos que se reescriben a `name` (p. ej. `Persona` -> `Person`)
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+}
+
+/// Tipo de relación admitido, con las categorías permitidas en cada extremo.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RelationTypeDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Categorías admitidas como origen (vacío = cualquiera)
+    #[serde(default)]
+    pub domain: Vec<String>,
+    /// Categorías admitidas como destino (vacío = cualquiera)
+    #[serde(default)]
+    pub range: Vec<String>,
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+}
+
+/// Qué hacer con lo que la extracción devuelve fuera de la ontología.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OffOntologyPolicy {
+    /// Se descarta
+    Reject,
+    /// Se guarda aparte (`QuarantinedTriple`) para revisarlo
+    #[default]
+    Quarantine,
+}
+
+/// Vocabulario admitido en el grafo. Una sección vacía no restringe nada: la ontología
+/// por defecto (sin categorías ni relaciones) equivale a la extracción libre.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Ontology {
+    #[serde(default)]
+    pub categories: Vec<CategoryDefinition>,
+    #[serde(default)]
+    pub relation_types: Vec<RelationTypeDefinition>,
+    #[serde(default)]
+    pub off_ontology: OffOntologyPolicy,
+}
+
+/// Entidad o relación extraída que no encaja en la ontología.
+/// Las entidades aisladas se registran sin `relation_type` ni `target`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct QuarantinedTriple {
+    pub source: String,
+    pub source_category: Option<String>,
+    pub relation_type: Option<String>,
+    pub target: Option<String>,
+    pub target_category: Option<String>,
+    pub reason: String,
+    /// Chunk del que procede (solo al listar la cuarentena)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_id: Option<String>,
+}
+
+/// Resultado de ajustar una extracción a la ontología.
+pub struct ConformedExtraction {
+    pub extraction: KnowledgeExtraction,
+    pub off_ontology: Vec<QuarantinedTriple>,
+    /// Tipos de relación descartados por no ser etiquetas seguras (también van en `off_ontology`)
+    pub rejected_relation_types: Vec<String>,
+}
+
+impl Ontology {
+    /// Sin categorías ni tipos de relación: no hay nada que imponer.
+    pub fn is_open(&self) -> bool {
+        self.categories.is_empty() && self.relation_types.is_empty()
+    }
+
+    /// Comprueba que no haya nombres vacíos o repetidos (incluidos sinónimos) y que
+    /// domain/range solo usen categorías declaradas.
+    pub fn validate(&self) -> Result<(), AppError> {
+        let check_names = |section: &str, names: Vec<&String>| -> Result<(), AppError> {
+            let mut seen = HashSet::new();
+            for name in names {
+                if name.trim().is_empty() {
+                    return Err(AppError::ValidationError(format!("Empty name in ontology {}", section)));
+                }
+                if !seen.insert(key(name)) {
+                    return Err(AppError::ValidationError(format!("Duplicate name or synonym '{}' in ontology {}", name, section)));
+                }
+            }
+            Ok(())
+        };
+
+        check_names("categories", self.categories.iter()
+            .flat_map(|c| std::iter::once(&c.name).chain(&c.synonyms)).collect())?;
+        check_names("relation types", self.relation_types.iter()
+            .flat_map(|r| std::iter::once(&r.name).chain(&r.synonyms)).collect())?;
+
+        for rel in &self.relation_types {
+            sanitize_relation_type(&rel.name)
+                .map_err(|e| AppError::ValidationError(format!("Invalid relation type '{}': {}", rel.name, e)))?;
+            for category in rel.domain.iter().chain(&rel.range) {
+                if self.resolve_category(category).is_none() {
+                    return Err(AppError::ValidationError(format!(
+                        "Relation type '{}' references unknown category '{}'", rel.name, category
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Nombre canónico de la categoría (o `None` si está fuera de la ontología).
+    pub fn resolve_category(&self, category: &str) -> Option<String> {
+        if self.categories.is_empty() {
+            return Some(category.trim().to_string());
+        }
+        let wanted = key(category);
+        self.categories.iter()
+            .find(|c| key(&c.name) == wanted || c.synonyms.iter().any(|s| key(s) == wanted))
+            .map(|c| c.name.clone())
+    }
+
+    fn resolve_relation(&self, relation_type: &str) -> Option<&RelationTypeDefinition> {
+        let wanted = key(relation_type);
+        self.relation_types.iter()
+            .find(|r| key(&r.name) == wanted || r.synonyms.iter().any(|s| key(s) == wanted))
+    }
+
+    /// Reescribe sinónimos a sus nombres canónicos y separa lo que queda fuera de la ontología
+    /// (categorías desconocidas, tipos de relación desconocidos o que violan domain/range).
+    /// Los tipos de relación se normalizan con `sanitize_relation_type` aunque la ontología sea abierta.
+    pub fn conform(&self, extraction: KnowledgeExtraction) -> ConformedExtraction {
+        let mut off_ontology = Vec::new();
+        let mut rejected_relation_types = Vec::new();
+        let mut entities = Vec::with_capacity(extraction.entities.len());
+        // nombre -> categoría canónica, o `Err` con la categoría original si está fuera de la ontología
+        let mut categories: HashMap<String, Result<String, String>> = HashMap::new();
+
+        for entity in extraction.entities {
+            match self.resolve_category(&entity.category) {
+                Some(category) => {
+                    categories.insert(entity.name.clone(), Ok(category.clone()));
+                    entities.push(GraphEntity { category, ..entity });
+                },
+                None => {
+                    categories.insert(entity.name.clone(), Err(entity.category.clone()));
+                    off_ontology.push(QuarantinedTriple {
+                        reason: format!("category '{}' is not in the ontology", entity.category),
+                        source: entity.name,
+                        source_category: Some(entity.category),
+                        relation_type: None,
+                        target: None,
+                        target_category: None,
+                        chunk_id: None,
+                    });
+                },
+            }
+        }
+
+        let mut relations = Vec::with_capacity(extraction.relations.len());
+        for rel in extraction.relations {
+            let source_category = categories.get(&rel.source).cloned();
+            let target_category = categories.get(&rel.target).cloned();
+
+            let verdict = match (&source_category, &target_category) {
+                (Some(Ok(source_cat)), Some(Ok(target_cat))) => self.check_relation(&rel.relation_type, source_cat, target_cat)
+                    .and_then(|relation_type| sanitize_relation_type(&relation_type).inspect_err(|_| {
+                        if !rejected_relation_types.contains(&rel.relation_type) {
+                            rejected_relation_types.push(rel.relation_type.clone());
+                        }
+                    })),
+                (Some(Ok(_)), _) => Err(format!("target '{}' is outside the ontology", rel.target)),
+                _ => Err(format!("source '{}' is outside the ontology", rel.source)),
+            };
+            let flatten = |category: Option<Result<String, String>>| category.map(|c| c.unwrap_or_else(|raw| raw));
+
+            match verdict {
+                Ok(relation_type) => relations.push(GraphRelation { relation_type, ..rel }),
+                Err(reason) => off_ontology.push(QuarantinedTriple {
+                    source: rel.source,
+                    source_category: flatten(source_category),
+                    relation_type: Some(rel.relation_type),
+                    target: Some(rel.target),
+                    target_category: flatten(target_category),
+                    reason,
+                    chunk_id: None,
+                }),
+            }
+        }
+
+        ConformedExtraction { extraction: KnowledgeExtraction { entities, relations }, off_ontology, rejected_relation_types }
+    }
+
+    /// Tipo canónico si la relación y sus extremos encajan; si no, el motivo del rechazo.
+    fn check_relation(&self, relation_type: &str, source_category: &str, target_category: &str) -> Result<String, String> {
+        if self.relation_types.is_empty() {
+            return Ok(relation_type.to_string());
+        }
+        let definition = self.resolve_relation(relation_type)
+            .ok_or_else(|| format!("relation type '{}' is not in the ontology", relation_type))?;
+
+        let allows = |allowed: &[String], category: &str| allowed.is_empty() || allowed.iter().any(|a| key(a) == key(category));
+        if !allows(&definition.domain, source_category) {
+            return Err(format!("'{}' does not accept {} as source", definition.name, source_category));
+        }
+        if !allows(&definition.range, target_category) {
+            return Err(format!("'{}' does not accept {} as target", definition.name, target_category));
+        }
+        Ok(definition.name.clone())
+    }
+
+    /// Instrucciones para el prompt de extracción (`None` si la ontología no restringe nada).
+    pub fn prompt_guidelines(&self) -> Option<String> {
+        if self.is_open() {
+            return None;
+        }
+
+        let mut prompt = String::from("Use ONLY the following vocabulary.");
+        if !self.categories.is_empty() {
+            prompt.push_str("\nAllowed entity categories:");
+            for c in &self.categories {
+                prompt.push_str(&format!("\n- {}", c.name));
+                if let Some(description) = &c.description {
+                    prompt.push_str(&format!(": {}", description));
+                }
+            }
+        }
+        if !self.relation_types.is_empty() {
+            prompt.push_str("\nAllowed relation types (source category -> target category):");
+            let any = || "any".to_string();
+            for r in &self.relation_types {
+                let domain = if r.domain.is_empty() { any() } else { r.domain.join("|") };
+                let range = if r.range.is_empty() { any() } else { r.range.join("|") };
+                prompt.push_str(&format!("\n- {} ({} -> {})", r.name, domain, range));
+                if let Some(description) = &r.description {
+                    prompt.push_str(&format!(": {}", description));
+                }
+            }
+        }
+        prompt.push_str("\nOmit entities and relations that do not fit this vocabulary.");
+        Some(prompt)
+    }
+
+    /// Lo que se guarda en cuarentena según `off_ontology`: todo, o nada con `Reject`.
+    pub fn quarantined(&self, off_ontology: Vec<QuarantinedTriple>) -> Vec<QuarantinedTriple> {
+        match self.off_ontology {
+            OffOntologyPolicy::Quarantine => off_ontology,
+            OffOntologyPolicy::Reject => Vec::new(),
+        }
+    }
+
+    pub fn category_names(&self) -> Vec<&str> {
+        self.categories.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    pub fn relation_type_names(&self) -> Vec<&str> {
+        self.relation_types.iter().map(|r| r.name.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relation(relation_type: &str) -> GraphRelation {
+        GraphRelation {
+            source: "Alice".to_string(),
+            target: "Acme".to_string(),
+            relation_type: relation_type.to_string(),
+            description: String::new(),
+            confidence: None,
+        }
+    }
+
+    fn extraction(relation_types: &[&str]) -> KnowledgeExtraction {
+        let entity = |name: &str, category: &str| GraphEntity {
+            name: name.to_string(),
+            category: category.to_string(),
+            description: String::new(),
+            aliases: Vec::new(),
+        };
+        KnowledgeExtraction {
+            entities: vec![entity("Alice", "Person"), entity("Acme", "Organization")],
+            relations: relation_types.iter().map(|t| relation(t)).collect(),
+        }
+    }
+
+    #[test]
+    fn normalizes_plain_relation_types() {
+        assert_eq!(sanitize_relation_type("works for").unwrap(), "WORKS_FOR");
+        assert_eq!(sanitize_relation_type("  part-of ").unwrap(), "PART_OF");
+        assert_eq!(sanitize_relation_type("located\tin").unwrap(), "LOCATED_IN");
+        assert_eq!(sanitize_relation_type("situado en").unwrap(), "SITUADO_EN");
+        assert_eq!(sanitize_relation_type("fundó").unwrap(), "FUNDÓ");
+        assert_eq!(sanitize_relation_type("HAS_VERSION_2").unwrap(), "HAS_VERSION_2");
+    }
+
+    #[test]
+    fn rejects_cypher_injection_attempts() {
+        let hostile = [
+            "WORKS_FOR`]->(b) DETACH DELETE b //",
+            "KNOWS}]->() MATCH (n) DETACH DELETE n //",
+            "`",
+            "A`B",
+            "REL {weight: 1}",
+            "REL]->(x)<-[:OTHER",
+            "REL) WITH a MATCH (n",
+            "REL;DROP",
+            "REL'",
+            "REL\"",
+            "REL\\u0060",
+            "REL\nMATCH (n) DELETE n",
+            "REL\0",
+            "REL:OTHER",
+            "REL|OTHER",
+            "$param",
+            "REL/*comment*/",
+        ];
+        for raw in hostile {
+            assert!(sanitize_relation_type(raw).is_err(), "accepted hostile relation type {:?}", raw);
+        }
+    }
+
+    #[test]
+    fn rejects_empty_oversized_and_reserved_types() {
+        assert!(sanitize_relation_type("").is_err());
+        assert!(sanitize_relation_type("  - ").is_err());
+        assert!(sanitize_relation_type("_HIDDEN").is_err());
+        assert!(sanitize_relation_type("2ND_PLACE").is_err());
+        assert!(sanitize_relation_type(&"A".repeat(MAX_RELATION_TYPE_LEN + 1)).is_err());
+        assert!(sanitize_relation_type("mentions").is_err());
+        assert!(sanitize_relation_type("HAS_CHUNK").is_err());
+        assert!(sanitize_relation_type("inferred same as").is_err());
+    }
+
+    #[test]
+    fn conform_reports_rejected_relation_types_with_an_open_ontology() {
+        let conformed = Ontology::default().conform(extraction(&["works for", "OWNS`]->(b) DELETE b //"]));
+
+        assert_eq!(conformed.extraction.relations.len(), 1);
+        assert_eq!(conformed.extraction.relations[0].relation_type, "WORKS_FOR");
+        assert_eq!(conformed.rejected_relation_types, vec!["OWNS`]->(b) DELETE b //".to_string()]);
+        assert_eq!(conformed.off_ontology.len(), 1);
+        assert_eq!(conformed.off_ontology[0].relation_type.as_deref(), Some("OWNS`]->(b) DELETE b //"));
+    }
+
+    #[test]
+    fn conform_rejects_hostile_types_outside_a_closed_ontology() {
+        let ontology = Ontology {
+            relation_types: vec![RelationTypeDefinition {
+                name: "WORKS_FOR".to_string(),
+                description: None,
+                domain: Vec::new(),
+                range: Vec::new(),
+                synonyms: vec!["employed by".to_string()],
+            }],
+            ..Ontology::default()
+        };
+        let conformed = ontology.conform(extraction(&["employed by", "WORKS_FOR`]->()"]));
+
+        assert_eq!(conformed.extraction.relations.len(), 1);
+        assert_eq!(conformed.extraction.relations[0].relation_type, "WORKS_FOR");
+        assert_eq!(conformed.off_ontology.len(), 1);
+    }
+
+    #[test]
+    fn ontology_with_unsafe_relation_names_is_invalid() {
+        let ontology = Ontology {
+            relation_types: vec![RelationTypeDefinition {
+                name: "WORKS`FOR".to_string(),
+                description: None,
+                domain: Vec::new(),
+                range: Vec::new(),
+                synonyms: Vec::new(),
+            }],
+            ..Ontology::default()
+        };
+        assert!(ontology.validate().is_err());
+    }
+
+    /// Personas y organizaciones (con sinónimos) y `WORKS_FOR` de persona a organización.
+    fn closed_ontology(off_ontology: OffOntologyPolicy) -> Ontology {
+        let category = |name: &str, synonyms: &[&str]| CategoryDefinition {
+            name: name.to_string(),
+            description: None,
+            synonyms: synonyms.iter().map(|s| s.to_string()).collect(),
+        };
+        Ontology {
+            categories: vec![category("Person", &["Persona"]), category("Organization", &["Empresa", "Company"])],
+            relation_types: vec![RelationTypeDefinition {
+                name: "WORKS_FOR".to_string(),
+                description: None,
+                domain: vec!["Person".to_string()],
+                range: vec!["organization".to_string()],
+                synonyms: vec!["trabaja para".to_string()],
+            }],
+            off_ontology,
+        }
+    }
+
+    fn entity(name: &str, category: &str) -> GraphEntity {
+        GraphEntity { name: name.to_string(), category: category.to_string(), description: String::new(), aliases: Vec::new() }
+    }
+
+    fn between(source: &str, target: &str, relation_type: &str) -> GraphRelation {
+        GraphRelation { source: source.to_string(), target: target.to_string(), ..relation(relation_type) }
+    }
+
+    #[test]
+    fn conform_rewrites_synonyms_to_canonical_names() {
+        let conformed = closed_ontology(OffOntologyPolicy::Quarantine).conform(KnowledgeExtraction {
+            entities: vec![entity("Alice", " persona "), entity("Acme", "EMPRESA")],
+            relations: vec![between("Alice", "Acme", "Trabaja-para")],
+        });
+
+        let categories: Vec<&str> = conformed.extraction.entities.iter().map(|e| e.category.as_str()).collect();
+        assert_eq!(categories, vec!["Person", "Organization"]);
+        assert_eq!(conformed.extraction.relations[0].relation_type, "WORKS_FOR");
+        assert!(conformed.off_ontology.is_empty());
+        assert!(conformed.rejected_relation_types.is_empty());
+    }
+
+    #[test]
+    fn conform_enforces_domain_and_range() {
+        let conformed = closed_ontology(OffOntologyPolicy::Quarantine).conform(KnowledgeExtraction {
+            entities: vec![entity("Alice", "Person"), entity("Bob", "Person"), entity("Acme", "Organization")],
+            relations: vec![
+                between("Acme", "Alice", "WORKS_FOR"),
+                between("Alice", "Bob", "WORKS_FOR"),
+                between("Alice", "Acme", "OWNS"),
+                between("Alice", "Acme", "WORKS_FOR"),
+            ],
+        });
+
+        assert_eq!(conformed.extraction.relations.len(), 1);
+        assert_eq!(conformed.extraction.relations[0].source, "Alice");
+        let reasons: Vec<&str> = conformed.off_ontology.iter().map(|t| t.reason.as_str()).collect();
+        assert_eq!(reasons, vec![
+            "'WORKS_FOR' does not accept Organization as source",
+            "'WORKS_FOR' does not accept Person as target",
+            "relation type 'OWNS' is not in the ontology",
+        ]);
+        // Un tipo desconocido no es un tipo inseguro
+        assert!(conformed.rejected_relation_types.is_empty());
+    }
+
+    #[test]
+    fn conform_sets_aside_unknown_categories_and_their_relations() {
+        let conformed = closed_ontology(OffOntologyPolicy::Quarantine).conform(KnowledgeExtraction {
+            entities: vec![entity("Alice", "Person"), entity("Madrid", "City")],
+            relations: vec![between("Alice", "Madrid", "WORKS_FOR"), between("Madrid", "Alice", "WORKS_FOR")],
+        });
+
+        assert_eq!(conformed.extraction.entities.len(), 1);
+        assert!(conformed.extraction.relations.is_empty());
+        let madrid = &conformed.off_ontology[0];
+        assert_eq!((madrid.source.as_str(), madrid.source_category.as_deref()), ("Madrid", Some("City")));
+        assert!(madrid.relation_type.is_none() && madrid.target.is_none());
+        assert_eq!(conformed.off_ontology[1].reason, "target 'Madrid' is outside the ontology");
+        assert_eq!(conformed.off_ontology[1].target_category.as_deref(), Some("City"));
+        assert_eq!(conformed.off_ontology[2].reason, "source 'Madrid' is outside the ontology");
+    }
+
+    #[test]
+    fn reject_policy_quarantines_nothing() {
+        let off_ontology = || closed_ontology(OffOntologyPolicy::Quarantine).conform(KnowledgeExtraction {
+            entities: vec![entity("Madrid", "City")],
+            relations: Vec::new(),
+        }).off_ontology;
+
+        assert_eq!(closed_ontology(OffOntologyPolicy::Quarantine).quarantined(off_ontology()).len(), 1);
+        assert!(closed_ontology(OffOntologyPolicy::Reject).quarantined(off_ontology()).is_empty());
+        assert_eq!(OffOntologyPolicy::default(), OffOntologyPolicy::Quarantine);
+    }
+
+    #[test]
+    fn validate_detects_duplicates_and_unknown_categories() {
+        assert!(closed_ontology(OffOntologyPolicy::Reject).validate().is_ok());
+        assert!(Ontology::default().validate().is_ok());
+
+        let mut duplicated = closed_ontology(OffOntologyPolicy::Reject);
+        duplicated.categories[1].synonyms.push("PERSON".to_string());
+        assert!(matches!(duplicated.validate(), Err(AppError::ValidationError(e)) if e.contains("Duplicate")));
+
+        let mut duplicated_relation = closed_ontology(OffOntologyPolicy::Reject);
+        duplicated_relation.relation_types.push(RelationTypeDefinition {
+            name: "Works-For".to_string(),
+            description: None,
+            domain: Vec::new(),
+            range: Vec::new(),
+            synonyms: Vec::new(),
+        });
+        assert!(matches!(duplicated_relation.validate(), Err(AppError::ValidationError(e)) if e.contains("Duplicate")));
+
+        let mut unknown = closed_ontology(OffOntologyPolicy::Reject);
+        unknown.relation_types[0].range.push("Location".to_string());
+        assert!(matches!(unknown.validate(), Err(AppError::ValidationError(e)) if e.contains("unknown category 'Location'")));
+
+        let mut empty = closed_ontology(OffOntologyPolicy::Reject);
+        empty.categories[0].synonyms.push("  ".to_string());
+        assert!(matches!(empty.validate(), Err(AppError::ValidationError(e)) if e.contains("Empty name")));
+    }
+}
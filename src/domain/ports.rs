@@ -1,33 +1,158 @@
 use async_trait::async_trait;
-use crate::domain::models::{AIConfig, KnowledgeExtraction, GraphDataResponse, HybridContext, InferredRelation, InferenceResult};
+use futures::stream::BoxStream;
+use crate::domain::models::{
+    AIConfig, KnowledgeExtraction, GraphDataResponse, HybridContext, RetrievalOptions, RetrievalFilters, InferredRelation, InferenceResult,
+    NewDocument, ChunkRecord, DocumentInfo, DocumentChunkInfo, DocumentPurgeResult,
+    IngestionJob, IngestionRequest, EntityProfile, EntityMergeRecord, MergeAdjudication,
+    StaleEntity, EntityEmbedding, ChatChunk, RelevantEntity, GraphFact, Community, EntityNode, EntityLink, Conversation, ConversationSummary, ConversationTurn
+};
+use crate::domain::ontology::{Ontology, QuarantinedTriple};
 use crate::domain::errors::AppError;
 use uuid::Uuid;
 
 #[async_trait]
 pub trait KGRepository: Send + Sync {
-    async fn save_chunk(&self, id: Uuid, content: &str, embedding: Vec<f32>) -> Result<(), AppError>;
-    async fn save_graph(&self, chunk_id: Uuid, data: KnowledgeExtraction) -> Result<(), AppError>;
+    async fn save_document(&self, doc: &NewDocument) -> Result<(), AppError>;
+    async fn save_chunk(&self, chunk: &ChunkRecord, embedding: Vec<f32>) -> Result<(), AppError>;
+    /// Si ya existe un chunk con el mismo `content_hash`, crea `chunk` copiando su embedding y sus
+    /// `MENTIONS` (sin llamar a la IA). Devuelve `false` si no había nada que reutilizar.
+    async fn reuse_chunk_by_hash(&self, chunk: &ChunkRecord) -> Result<bool, AppError>;
+    /// ¿Hay un chunk reutilizable (con embedding y extracción correcta) con este `content_hash`?
+    async fn has_reusable_chunk(&self, content_hash: &str) -> Result<bool, AppError>;
+    /// Persiste la extracción (y lo puesto en cuarentena por la ontología, ligado al chunk)
+    /// y devuelve cuántas entidades nuevas se crearon.
+    async fn save_graph(&self, chunk_id: Uuid, data: KnowledgeExtraction, quarantined: Vec<QuarantinedTriple>) -> Result<usize, AppError>;
     async fn reset_database(&self) -> Result<(), AppError>;
     async fn create_indexes(&self, dim: usize) -> Result<(), AppError>;
     
     async fn get_full_graph(&self) -> Result<GraphDataResponse, AppError>;
-    async fn find_hybrid_context(&self, embedding: Vec<f32>, limit: usize) -> Result<Vec<HybridContext>, AppError>;
+    async fn find_hybrid_context(&self, embedding: Vec<f32>, options: &RetrievalOptions) -> Result<Vec<HybridContext>, AppError>;
+    /// Fragmentos por texto completo (BM25) sobre su contenido y los nombres de las entidades que mencionan.
+    async fn find_keyword_context(&self, text: &str, options: &RetrievalOptions) -> Result<Vec<HybridContext>, AppError>;
     
+    /// Entidades más parecidas al embedding (índice vectorial de entidades).
+    async fn find_similar_entities(&self, embedding: Vec<f32>, limit: usize) -> Result<Vec<RelevantEntity>, AppError>;
+
+    /// Relaciones entre las entidades a `hops` saltos o menos de `seeds` (sin puntuar, como mucho `limit`).
+    /// Con `filters`, solo las respaldadas por algún fragmento que los cumple, y esa evidencia.
+    async fn find_graph_neighborhood(&self, seeds: &[String], hops: usize, limit: usize, filters: &RetrievalFilters) -> Result<Vec<GraphFact>, AppError>;
+    /// Fragmentos por id, sin puntuar y en cualquier orden (los que respaldan los hechos del grafo).
+    async fn get_chunks(&self, ids: &[String]) -> Result<Vec<HybridContext>, AppError>;
+
     // --- MÉTODO NUEVO DE VECINDARIO ---
     async fn get_concept_neighborhood(&self, concept_name: &str) -> Result<GraphDataResponse, AppError>;
 
+    // --- Documentos y procedencia ---
+    async fn list_documents(&self) -> Result<Vec<DocumentInfo>, AppError>;
+    async fn get_document(&self, id: Uuid) -> Result<Option<DocumentInfo>, AppError>;
+    async fn find_document_by_hash(&self, content_hash: &str) -> Result<Option<DocumentInfo>, AppError>;
+    async fn get_document_chunks(&self, id: Uuid) -> Result<Vec<DocumentChunkInfo>, AppError>;
+    /// Elimina los chunks del documento con `order >= from_order` (conservando el nodo `Document`)
+    /// y las entidades/relaciones que queden huérfanas.
+    async fn purge_document_chunks(&self, id: Uuid, from_order: usize) -> Result<DocumentPurgeResult, AppError>;
+    /// Registra cuántos chunks no pudo procesar por completo la última ingesta del documento.
+    async fn set_document_failed_chunks(&self, id: Uuid, failed_chunks: usize) -> Result<(), AppError>;
+    /// Como `purge_document_chunks`, pero además elimina el nodo `Document`.
+    async fn delete_document(&self, id: Uuid) -> Result<DocumentPurgeResult, AppError>;
+
+    // --- Ontología ---
+    /// Ontología definida por API (tiene prioridad sobre el fichero `ONTOLOGY_PATH`).
+    async fn load_ontology(&self) -> Result<Option<Ontology>, AppError>;
+    async fn save_ontology(&self, ontology: &Ontology) -> Result<(), AppError>;
+    /// Triples fuera de la ontología pendientes de revisión, los más recientes primero.
+    async fn list_quarantined_triples(&self, limit: usize) -> Result<Vec<QuarantinedTriple>, AppError>;
+
+    // --- Perfil de entidades ---
+    /// Entidades sin embedding (incluidas las anteriores al perfilado) o con descripción ampliada
+    /// desde su último embedding.
+    async fn list_stale_entities(&self, limit: usize) -> Result<Vec<StaleEntity>, AppError>;
+    /// Guarda los embeddings (y las descripciones resumidas) y marca las entidades como al día.
+    async fn update_entity_embeddings(&self, updates: Vec<EntityEmbedding>) -> Result<(), AppError>;
+
+    // --- Resolución de entidades ---
+    /// Perfiles de las `limit` entidades más mencionadas.
+    async fn list_entity_profiles(&self, limit: usize) -> Result<Vec<EntityProfile>, AppError>;
+    /// Absorbe `merge` en `keep`: traslada relaciones y `MENTIONS`, añade el nombre a `keep.aliases`
+    /// y registra la fusión para poder deshacerla.
+    async fn merge_entities(&self, keep: &str, merge: &str) -> Result<EntityMergeRecord, AppError>;
+    /// Recrea la entidad absorbida con sus relaciones y menciones y retira lo que la fusión añadió.
+    async fn undo_entity_merge(&self, id: Uuid) -> Result<EntityMergeRecord, AppError>;
+    async fn list_entity_merges(&self, limit: usize) -> Result<Vec<EntityMergeRecord>, AppError>;
+
     // --- Métodos para razonamiento ---
     async fn get_graph_context_for_reasoning(&self, limit: usize) -> Result<String, AppError>;
     async fn save_inferred_relations(&self, relations: Vec<InferredRelation>) -> Result<(), AppError>;
 }
 
+/// Almacén duradero de trabajos de ingesta (estado + contenido pendiente para poder reanudar).
+#[async_trait]
+pub trait JobRepository: Send + Sync {
+    /// Registra un trabajo nuevo junto con la petición a procesar.
+    async fn create_job(&self, job: &IngestionJob, request: &IngestionRequest) -> Result<(), AppError>;
+    /// Actualiza el estado; al llegar a un estado terminal se descarta el contenido pendiente.
+    async fn update_job(&self, job: &IngestionJob) -> Result<(), AppError>;
+    async fn get_job(&self, id: Uuid) -> Result<Option<IngestionJob>, AppError>;
+    /// Trabajos que quedaron a medias (en cola o en curso) con su petición original.
+    async fn list_resumable_jobs(&self) -> Result<Vec<(IngestionJob, IngestionRequest)>, AppError>;
+}
+
+/// Historial persistente de las conversaciones del chat.
+#[async_trait]
+pub trait ConversationRepository: Send + Sync {
+    /// Más recientes primero.
+    async fn list_conversations(&self, limit: usize) -> Result<Vec<ConversationSummary>, AppError>;
+    async fn get_conversation(&self, id: Uuid) -> Result<Option<Conversation>, AppError>;
+    /// Añade un turno; la conversación se crea con `title` si aún no existe.
+    async fn append_turn(&self, id: Uuid, title: &str, turn: &ConversationTurn) -> Result<(), AppError>;
+    async fn set_conversation_summary(&self, id: Uuid, summary: &str, summarized_turns: usize) -> Result<(), AppError>;
+    async fn rename_conversation(&self, id: Uuid, title: &str) -> Result<Option<ConversationSummary>, AppError>;
+    /// `false` si no existía.
+    async fn delete_conversation(&self, id: Uuid) -> Result<bool, AppError>;
+}
+
+/// Respuesta de chat que llega por fragmentos.
+pub type ChatStream = BoxStream<'static, Result<ChatChunk, AppError>>;
+
 #[async_trait]
 pub trait AIService: Send + Sync {
-    async fn extract_knowledge(&self, text: &str) -> Result<KnowledgeExtraction, AppError>;
+    /// Extrae entidades y relaciones ciñéndose al vocabulario de `ontology`.
+    async fn extract_knowledge(&self, text: &str, ontology: &Ontology) -> Result<KnowledgeExtraction, AppError>;
     async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>, AppError>;
+    /// Embeddings de varios textos, en el mismo orden de entrada. La implementación agrupa
+    /// en tantas peticiones como exija el proveedor.
+    async fn generate_embeddings(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AppError>;
+    /// Número de textos que conviene enviar por petición de embeddings con el proveedor actual.
+    fn embedding_batch_size(&self) -> usize;
     fn update_config(&mut self, config: AIConfig) -> Result<(), AppError>;
     fn get_config(&self) -> AIConfig;
 
     // --- Método para inferencia ---
     async fn generate_inference(&self, prompt: &str) -> Result<InferenceResult, AppError>;
-}
\ No newline at end of file
+    /// Respuesta de texto libre (resúmenes y reescrituras cuyo prompt construye la capa de aplicación).
+    async fn generate_text(&self, prompt: &str) -> Result<String, AppError>;
+    /// Pide al LLM un veredicto sobre si dos entidades descritas en `prompt` son la misma.
+    async fn adjudicate_merge(&self, prompt: &str) -> Result<MergeAdjudication, AppError>;
+    /// Respuesta de chat en streaming, con `preamble` como instrucciones de sistema. Devuelve en
+    /// cuanto el proveedor acepta la petición (los fallos al abrirla se reintentan como cualquier
+    /// otra llamada); un error posterior llega como último elemento del stream.
+    async fn stream_chat(&self, preamble: &str, message: &str) -> Result<ChatStream, AppError>;
+}
+
+/// Segunda etapa de la recuperación: puntúa los fragmentos candidatos frente a la pregunta.
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    /// Puntuación de cada pasaje, en el mismo orden de entrada: mayor es más relevante. La escala
+    /// depende del modelo (probabilidad de 0.0 a 1.0 o logit sin acotar).
+    async fn score(&self, query: &str, passages: &[&str]) -> Result<Vec<f32>, AppError>;
+}
+
+/// Grafo de entidades y comunidades detectadas sobre él.
+#[async_trait]
+pub trait CommunityRepository: Send + Sync {
+    /// Todas las entidades y las relaciones entre entidades distintas.
+    async fn load_entity_graph(&self) -> Result<(Vec<EntityNode>, Vec<EntityLink>), AppError>;
+    /// Sustituye todas las comunidades por `communities`.
+    async fn replace_communities(&self, communities: &[Community]) -> Result<(), AppError>;
+    /// Comunidades de un nivel (o de todos), las más grandes primero.
+    async fn list_communities(&self, level: Option<usize>) -> Result<Vec<Community>, AppError>;
+}
@@ -0,0 +1,421 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use serde_json::{json, Value};
+use crate::domain::{
+    models::{AIProvider, GraphEntity, GraphRelation, KnowledgeExtraction},
+    ontology::Ontology,
+    errors::AppError
+};
+
+// Categoría asignada a entidades sin categoría o creadas desde una relación
+const DEFAULT_CATEGORY: &str = "Concept";
+
+/// Qué hacer con los extremos de una relación que el modelo no declaró como entidad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndeclaredEntities {
+    /// Se crean con la categoría por defecto
+    Create,
+    /// Se descarta la relación
+    Drop,
+}
+
+impl FromStr for UndeclaredEntities {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "create" => Ok(Self::Create),
+            "drop" => Ok(Self::Drop),
+            other => Err(AppError::ConfigError(format!("Unknown undeclared entity policy: {}", other))),
+        }
+    }
+}
+
+/// Opciones de la extracción de conocimiento (ver `from_env`).
+#[derive(Debug, Clone)]
+pub struct ExtractionOptions {
+    /// Modo JSON-schema: `None` lo activa solo en proveedores que lo soportan (OpenAI)
+    pub structured_output: Option<bool>,
+    /// Re-prompts con el error de parseo antes de dar el chunk por fallido
+    pub repair_attempts: u32,
+    pub undeclared_entities: UndeclaredEntities,
+}
+
+impl Default for ExtractionOptions {
+    fn default() -> Self {
+        Self { structured_output: None, repair_attempts: 1, undeclared_entities: UndeclaredEntities::Create }
+    }
+}
+
+impl ExtractionOptions {
+    /// Lee `EXTRACTION_STRUCTURED_OUTPUT` (true/false), `EXTRACTION_REPAIR_ATTEMPTS`
+    /// y `EXTRACTION_UNDECLARED_ENTITIES` (create/drop).
+    pub fn from_env() -> Result<Self, AppError> {
+        let defaults = Self::default();
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+
+        let structured_output = env("EXTRACTION_STRUCTURED_OUTPUT")
+            .map(|v| v.trim().parse::<bool>()
+                .map_err(|_| AppError::ConfigError("EXTRACTION_STRUCTURED_OUTPUT must be true or false".to_string())))
+            .transpose()?;
+        let repair_attempts = env("EXTRACTION_REPAIR_ATTEMPTS")
+            .map(|v| v.trim().parse::<u32>()
+                .map_err(|_| AppError::ConfigError("EXTRACTION_REPAIR_ATTEMPTS must be a number".to_string())))
+            .transpose()?
+            .unwrap_or(defaults.repair_attempts);
+        let undeclared_entities = env("EXTRACTION_UNDECLARED_ENTITIES")
+            .map(|v| v.parse())
+            .transpose()?
+            .unwrap_or(defaults.undeclared_entities);
+
+        Ok(Self { structured_output, repair_attempts, undeclared_entities })
+    }
+
+    pub fn use_structured_output(&self, provider: &AIProvider) -> bool {
+        self.structured_output.unwrap_or(matches!(provider, AIProvider::OpenAI))
+    }
+}
+
+/// Esquema estricto de `KnowledgeExtraction` para el modo structured output. Si la ontología
+/// define categorías o tipos de relación, esos campos se restringen a sus nombres.
+pub fn extraction_schema(ontology: &Ontology) -> Value {
+    let categories = ontology.category_names();
+    let relation_types = ontology.relation_type_names();
+
+    let object = |fields: &[&str]| {
+        let properties: serde_json::Map<String, Value> = fields.iter()
+            .map(|&f| {
+                let allowed = match f {
+                    "category" => &categories,
+                    "relation_type" => &relation_types,
+                    _ => &Vec::new(),
+                };
+                let schema = if f == "aliases" {
+                    json!({ "type": "array", "items": { "type": "string" } })
+                } else if f == "confidence" {
+                    json!({ "type": "number" })
+                } else if allowed.is_empty() {
+                    json!({ "type": "string" })
+                } else {
+                    json!({ "type": "string", "enum": allowed })
+                };
+                (f.to_string(), schema)
+            })
+            .collect();
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": fields,
+            "additionalProperties": false
+        })
+    };
+
+    json!({
+        "type": "object",
+        "properties": {
+            "entities": { "type": "array", "items": object(&["name", "category", "description", "aliases"]) },
+            "relations": { "type": "array", "items": object(&["source", "target", "relation_type", "description", "confidence"]) }
+        },
+        "required": ["entities", "relations"],
+        "additionalProperties": false
+    })
+}
+
+/// Re-prompt que devuelve al modelo su respuesta inválida junto con el error.
+pub fn repair_prompt(raw: &str, error: &str) -> String {
+    format!(
+        "Your previous answer could not be parsed as the requested JSON: {}\n\
+         Previous answer:\n{}\n\n\
+         Return ONLY the corrected JSON object, with no prose and no markdown fences.",
+        error, raw
+    )
+}
+
+/// Devuelve el primer objeto JSON bien balanceado del texto, ignorando prosa y bloques markdown.
+pub fn first_json_object(raw: &str) -> Option<&str> {
+    let bytes = raw.as_bytes();
+    let mut start = 0;
+
+    while let Some(offset) = raw[start..].find('{') {
+        let open = start + offset;
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut closed = false;
+
+        for (i, &b) in bytes.iter().enumerate().skip(open) {
+            if in_string {
+                match b {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b'"' => in_string = true,
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        closed = true;
+                        let candidate = &raw[open..=i];
+                        if serde_json::from_str::<Value>(candidate).is_ok_and(|v| v.is_object()) {
+                            return Some(candidate);
+                        }
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Llave sin cerrar: respuesta truncada, cuyos objetos anidados serían solo fragmentos
+        if !closed {
+            return None;
+        }
+        // Texto entre llaves que no es JSON: probamos con la siguiente
+        start = open + 1;
+    }
+    None
+}
+
+/// Parseo tolerante: primer objeto JSON de la respuesta, aceptando los nombres de campo
+/// alternativos que suelen devolver los modelos (`nodes`, `edges`, `from`, `to`, `type`...).
+pub fn parse_extraction(raw: &str) -> Result<KnowledgeExtraction, AppError> {
+    let json = first_json_object(raw)
+        .ok_or_else(|| AppError::ParseError("no JSON object found in the response".to_string()))?;
+    serde_json::from_str(json).map_err(|e| AppError::ParseError(e.to_string()))
+}
+
+/// Normaliza la extracción: nombres recortados y sin duplicados, relaciones apuntando a
+/// entidades declaradas (según `UndeclaredEntities`) y sin relaciones vacías o repetidas.
+pub fn validate_extraction(raw: KnowledgeExtraction, policy: UndeclaredEntities) -> KnowledgeExtraction {
+    let mut entities: Vec<GraphEntity> = Vec::with_capacity(raw.entities.len());
+    // nombre en minúsculas -> nombre canónico (el primero declarado)
+    let mut declared: HashMap<String, String> = HashMap::new();
+
+    for entity in raw.entities {
+        let name = entity.name.trim();
+        if name.is_empty() { continue; }
+        let description = entity.description.trim();
+        let aliases = entity.aliases.iter()
+            .map(|a| a.trim())
+            .filter(|a| !a.is_empty() && !a.eq_ignore_ascii_case(name));
+
+        // Entidad repetida: se conserva la primera, sumando su descripción y alias
+        if let Some(canonical) = declared.get(&name.to_lowercase()) {
+            if let Some(existing) = entities.iter_mut().find(|e| &e.name == canonical) {
+                if existing.description.is_empty() {
+                    existing.description = description.to_string();
+                } else if !description.is_empty() && !existing.description.contains(description) {
+                    existing.description = format!("{} {}", existing.description, description);
+                }
+                for alias in aliases {
+                    if !existing.aliases.iter().any(|a| a == alias) {
+                        existing.aliases.push(alias.to_string());
+                    }
+                }
+            }
+            continue;
+        }
+
+        let category = match entity.category.trim() {
+            "" => DEFAULT_CATEGORY,
+            category => category,
+        };
+        let mut unique_aliases: Vec<String> = Vec::new();
+        for alias in aliases {
+            if !unique_aliases.iter().any(|a| a == alias) {
+                unique_aliases.push(alias.to_string());
+            }
+        }
+        declared.insert(name.to_lowercase(), name.to_string());
+        entities.push(GraphEntity {
+            name: name.to_string(),
+            category: category.to_string(),
+            description: description.to_string(),
+            aliases: unique_aliases,
+        });
+    }
+
+    let mut relations = Vec::with_capacity(raw.relations.len());
+    let mut seen = HashSet::new();
+    let (mut created, mut dropped) = (0, 0);
+
+    for rel in raw.relations {
+        let relation_type = rel.relation_type.trim();
+        let (source, target) = (rel.source.trim(), rel.target.trim());
+        if relation_type.is_empty() || source.is_empty() || target.is_empty() {
+            dropped += 1;
+            continue;
+        }
+
+        let mut endpoints = Vec::with_capacity(2);
+        for name in [source, target] {
+            match declared.get(&name.to_lowercase()) {
+                Some(canonical) => endpoints.push(canonical.clone()),
+                None if policy == UndeclaredEntities::Create => {
+                    declared.insert(name.to_lowercase(), name.to_string());
+                    entities.push(GraphEntity {
+                        name: name.to_string(),
+                        category: DEFAULT_CATEGORY.to_string(),
+                        description: String::new(),
+                        aliases: Vec::new(),
+                    });
+                    endpoints.push(name.to_string());
+                    created += 1;
+                }
+                None => break,
+            }
+        }
+        let [source, target] = endpoints.as_slice() else {
+            dropped += 1;
+            continue;
+        };
+
+        let description = rel.description.trim();
+        let confidence = rel.confidence.filter(|c| c.is_finite()).map(|c| c.clamp(0.0, 1.0));
+        let key = (source.to_lowercase(), target.to_lowercase(), relation_type.to_lowercase());
+        if seen.insert(key.clone()) {
+            relations.push(GraphRelation {
+                source: source.clone(),
+                target: target.clone(),
+                relation_type: relation_type.to_string(),
+                description: description.to_string(),
+                confidence,
+            });
+        } else if let Some(existing) = relations.iter_mut().find(|r| {
+            (r.source.to_lowercase(), r.target.to_lowercase(), r.relation_type.to_lowercase()) == key
+        }) {
+            // Relación repetida: la confianza más alta y la primera descripción no vacía
+            existing.confidence = match (existing.confidence, confidence) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            };
+            if existing.description.is_empty() {
+                existing.description = description.to_string();
+            }
+        }
+    }
+
+    if created > 0 || dropped > 0 {
+        tracing::debug!("🧹 Extraction validated: {} entities auto-created, {} relations dropped", created, dropped);
+    }
+
+    KnowledgeExtraction { entities, relations }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(name: &str, category: &str) -> GraphEntity {
+        GraphEntity { name: name.to_string(), category: category.to_string(), description: String::new(), aliases: Vec::new() }
+    }
+
+    fn relation(source: &str, target: &str, relation_type: &str, confidence: Option<f32>) -> GraphRelation {
+        GraphRelation {
+            source: source.to_string(),
+            target: target.to_string(),
+            relation_type: relation_type.to_string(),
+            description: String::new(),
+            confidence,
+        }
+    }
+
+    fn names(extraction: &KnowledgeExtraction) -> Vec<&str> {
+        extraction.entities.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn finds_the_json_object_between_prose() {
+        let raw = "Claro, aquí tienes el resultado:\n{\"entities\": [], \"relations\": []}\nEspero que sirva {de ayuda}.";
+        assert_eq!(first_json_object(raw), Some("{\"entities\": [], \"relations\": []}"));
+
+        // Texto entre llaves que no es JSON se salta
+        assert_eq!(first_json_object("{de nada} {\"a\": 1}"), Some("{\"a\": 1}"));
+        assert_eq!(first_json_object("sin json"), None);
+    }
+
+    #[test]
+    fn parses_fenced_json() {
+        let raw = "```json\n{\"entities\": [{\"name\": \"Acme\", \"category\": \"Organization\"}], \"relations\": []}\n```";
+        let extraction = parse_extraction(raw).unwrap();
+        assert_eq!(names(&extraction), vec!["Acme"]);
+        assert_eq!(extraction.entities[0].category, "Organization");
+    }
+
+    #[test]
+    fn braces_and_escaped_quotes_inside_strings_do_not_end_the_object() {
+        let raw = r#"Resultado: {"entities": [{"name": "Llave }", "description": "dice \"hola {\" y se va"}], "relations": []} fin"#;
+        let extraction = parse_extraction(raw).unwrap();
+        assert_eq!(extraction.entities[0].name, "Llave }");
+        assert_eq!(extraction.entities[0].description, "dice \"hola {\" y se va");
+    }
+
+    #[test]
+    fn accepts_alias_keys() {
+        let raw = r#"{"nodes": [{"label": "Alice", "type": "Person"}, {"entity": "Acme"}],
+                      "edges": [{"from": "Alice", "to": "Acme", "type": "WORKS_FOR"}]}"#;
+        let extraction = parse_extraction(raw).unwrap();
+        assert_eq!(names(&extraction), vec!["Alice", "Acme"]);
+        assert_eq!(extraction.entities[0].category, "Person");
+        assert_eq!(extraction.relations.len(), 1);
+        assert_eq!((extraction.relations[0].source.as_str(), extraction.relations[0].target.as_str()), ("Alice", "Acme"));
+        assert_eq!(extraction.relations[0].relation_type, "WORKS_FOR");
+    }
+
+    #[test]
+    fn truncated_json_is_an_error() {
+        let truncated = r#"{"entities": [{"name": "Alice", "category": "Person"}], "relations": [{"source": "Ali"#;
+        assert!(matches!(parse_extraction(truncated), Err(AppError::ParseError(_))));
+        assert!(matches!(parse_extraction("No he encontrado entidades."), Err(AppError::ParseError(_))));
+    }
+
+    #[test]
+    fn duplicate_entities_and_relations_are_merged() {
+        let mut first = entity("Acme", "Organization");
+        first.description = "Fabricante".to_string();
+        let mut second = entity(" acme ", "Company");
+        second.description = "de cohetes".to_string();
+        second.aliases = vec!["ACME Corp".to_string(), "Acme".to_string()];
+
+        let extraction = validate_extraction(KnowledgeExtraction {
+            entities: vec![first, entity("Alice", "Person"), second, entity("  ", "Person")],
+            relations: vec![
+                relation("Alice", "Acme", "WORKS_FOR", Some(0.4)),
+                relation("alice", "ACME", "works_for", Some(0.9)),
+                relation("Alice", "Acme", "", None),
+            ],
+        }, UndeclaredEntities::Create);
+
+        assert_eq!(names(&extraction), vec!["Acme", "Alice"]);
+        let acme = &extraction.entities[0];
+        assert_eq!(acme.category, "Organization");
+        assert_eq!(acme.description, "Fabricante de cohetes");
+        assert_eq!(acme.aliases, vec!["ACME Corp".to_string()]);
+
+        assert_eq!(extraction.relations.len(), 1);
+        assert_eq!(extraction.relations[0].confidence, Some(0.9));
+        assert_eq!((extraction.relations[0].source.as_str(), extraction.relations[0].target.as_str()), ("Alice", "Acme"));
+    }
+
+    #[test]
+    fn undeclared_endpoints_follow_the_policy() {
+        let raw = || KnowledgeExtraction {
+            entities: vec![entity("Alice", "Person")],
+            relations: vec![relation("Alice", "Acme", "WORKS_FOR", Some(1.7))],
+        };
+
+        let created = validate_extraction(raw(), UndeclaredEntities::Create);
+        assert_eq!(names(&created), vec!["Alice", "Acme"]);
+        assert_eq!(created.entities[1].category, DEFAULT_CATEGORY);
+        assert_eq!(created.relations.len(), 1);
+        assert_eq!(created.relations[0].confidence, Some(1.0));
+
+        let dropped = validate_extraction(raw(), UndeclaredEntities::Drop);
+        assert_eq!(names(&dropped), vec!["Alice"]);
+        assert!(dropped.relations.is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use bytes::Bytes;
+use reqwest::multipart::Form;
+use rig::http_client::{self, HttpClientExt, LazyBody, Request, Response, StreamingResponse};
+use rig::wasm_compat::WasmCompatSend;
+
+/// Lo que el proveedor respondió en la última llamada fallida: código HTTP y, si lo indicó,
+/// cuánto esperar antes de reintentar. rig solo propaga el cuerpo del error, así que lo
+/// capturamos en el cliente HTTP.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderSignal {
+    pub status: Option<u16>,
+    pub retry_after: Option<Duration>,
+    /// Fallo de red (sin respuesta HTTP)
+    pub transport_error: bool,
+}
+
+impl ProviderSignal {
+    /// 408, 429, 5xx y errores de red merecen reintento (un 409 es un conflicto de la petición).
+    pub fn is_transient(&self) -> bool {
+        match self.status {
+            Some(status) => matches!(status, 408 | 429) || status >= 500,
+            None => self.transport_error,
+        }
+    }
+}
+
+/// Cliente HTTP para rig que registra la señal del proveedor en cada respuesta no exitosa.
+/// Se crea uno por llamada, de modo que la señal corresponde siempre a esa llamada.
+#[derive(Debug, Clone, Default)]
+pub struct ObservedHttpClient {
+    inner: reqwest::Client,
+    signal: Arc<Mutex<ProviderSignal>>,
+}
+
+impl ObservedHttpClient {
+    pub fn new(inner: reqwest::Client) -> Self {
+        Self { inner, signal: Arc::default() }
+    }
+
+    pub fn signal(&self) -> ProviderSignal {
+        self.signal.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+}
+
+/// `retry-after-ms` (OpenAI), `retry-after` en segundos o como fecha HTTP.
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    let value = header("retry-after")?;
+    if let Ok(secs) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(secs.max(0.0)));
+    }
+    httpdate_delay(value)
+}
+
+/// Interpreta una fecha HTTP (`Wed, 21 Oct 2015 07:28:00 GMT`) como espera desde ahora;
+/// una fecha pasada equivale a no esperar.
+fn httpdate_delay(value: &str) -> Option<Duration> {
+    let target = httpdate::parse_http_date(value).ok()?;
+    Some(target.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+impl HttpClientExt for ObservedHttpClient {
+    fn send<T, U>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = http_client::Result<Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        T: Into<Bytes>,
+        T: WasmCompatSend,
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        let (parts, body) = req.into_parts();
+        let req = self.inner
+            .request(parts.method, parts.uri.to_string())
+            .headers(parts.headers)
+            .body(body.into());
+        let signal = self.signal.clone();
+
+        async move {
+            let response = match req.send().await {
+                Ok(response) => response,
+                Err(e) => {
+                    if let Ok(mut s) = signal.lock() {
+                        *s = ProviderSignal { status: None, retry_after: None, transport_error: true };
+                    }
+                    return Err(http_client::Error::Instance(e.into()));
+                }
+            };
+
+            let status = response.status();
+            if !status.is_success() {
+                if let Ok(mut s) = signal.lock() {
+                    *s = ProviderSignal {
+                        status: Some(status.as_u16()),
+                        retry_after: parse_retry_after(response.headers()),
+                        transport_error: false,
+                    };
+                }
+                let message = response.text().await.unwrap_or_default();
+                return Err(http_client::Error::InvalidStatusCodeWithMessage(status, message));
+            }
+
+            let mut res = Response::builder().status(status);
+            if let Some(headers) = res.headers_mut() {
+                *headers = response.headers().clone();
+            }
+
+            let body: LazyBody<U> = Box::pin(async move {
+                let bytes = response.bytes().await
+                    .map_err(|e| http_client::Error::Instance(e.into()))?;
+                Ok(U::from(bytes))
+            });
+
+            res.body(body).map_err(http_client::Error::Protocol)
+        }
+    }
+
+    fn send_multipart<U>(
+        &self,
+        req: Request<Form>,
+    ) -> impl Future<Output = http_client::Result<Response<LazyBody<U>>>> + WasmCompatSend + 'static
+    where
+        U: From<Bytes>,
+        U: WasmCompatSend + 'static,
+    {
+        self.inner.send_multipart(req)
+    }
+
+    fn send_streaming<T>(
+        &self,
+        req: Request<T>,
+    ) -> impl Future<Output = http_client::Result<StreamingResponse>> + WasmCompatSend
+    where
+        T: Into<Bytes>,
+    {
+        self.inner.send_streaming(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn retry_after(pairs: &[(&'static str, &str)]) -> Option<Duration> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        parse_retry_after(&headers)
+    }
+
+    #[test]
+    fn retry_after_accepts_seconds_and_milliseconds() {
+        assert_eq!(retry_after(&[("retry-after", "7")]), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after(&[("retry-after", " 1.5 ")]), Some(Duration::from_millis(1500)));
+        assert_eq!(retry_after(&[("retry-after-ms", "250")]), Some(Duration::from_millis(250)));
+        // `retry-after-ms` es más preciso y gana
+        assert_eq!(retry_after(&[("retry-after", "7"), ("retry-after-ms", "250")]), Some(Duration::from_millis(250)));
+        assert_eq!(retry_after(&[("retry-after", "-3")]), Some(Duration::ZERO));
+        assert_eq!(retry_after(&[]), None);
+    }
+
+    #[test]
+    fn retry_after_accepts_http_dates() {
+        let future = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+        let wait = retry_after(&[("retry-after", &future)]).unwrap();
+        assert!((118..=120).contains(&wait.as_secs()), "espera {:?}", wait);
+
+        // RFC 7231: fecha pasada = sin espera
+        assert_eq!(retry_after(&[("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")]), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn garbage_retry_after_is_ignored() {
+        assert_eq!(retry_after(&[("retry-after", "pronto")]), None);
+        assert_eq!(retry_after(&[("retry-after", "Wed, 32 Oct 2015 07:28:00 GMT")]), None);
+        assert_eq!(retry_after(&[("retry-after", "21 Oct 2015")]), None);
+        assert_eq!(retry_after(&[("retry-after-ms", "ya")]), None);
+    }
+
+    #[test]
+    fn only_retryable_statuses_are_transient() {
+        let signal = |status: Option<u16>, transport_error: bool| ProviderSignal { status, retry_after: None, transport_error };
+        for status in [408, 429, 500, 502, 503, 529] {
+            assert!(signal(Some(status), false).is_transient(), "{}", status);
+        }
+        for status in [400, 401, 403, 404, 409, 422] {
+            assert!(!signal(Some(status), false).is_transient(), "{}", status);
+        }
+        assert!(signal(None, true).is_transient());
+        assert!(!signal(None, false).is_transient());
+    }
+}
@@ -1,2 +1,5 @@
-pub mod rig_client;
-// pub mod extractors; // Descomentar si creaste este archivo
\ No newline at end of file
+pub mod extractors;
+pub mod http;
+pub mod reranker;
+pub mod resilience;
+pub mod rig_client;
\ No newline at end of file
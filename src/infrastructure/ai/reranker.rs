@@ -0,0 +1,178 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::RwLock;
+use crate::domain::{
+    ports::{AIService, Reranker},
+    errors::AppError
+};
+use crate::infrastructure::ai::extractors;
+
+// Caracteres de cada pasaje que se envían al reranker
+const PASSAGE_MAX_CHARS: usize = 1500;
+const CROSS_ENCODER_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn truncate(passage: &str) -> String {
+    passage.chars().take(PASSAGE_MAX_CHARS).collect()
+}
+
+/// Lee `RERANKER` (`none`, `llm` o `cross_encoder`) y, para `cross_encoder`, `RERANKER_URL`,
+/// `RERANKER_API_KEY` y `RERANKER_MODEL`.
+pub fn reranker_from_env(ai: Arc<RwLock<dyn AIService>>) -> Result<Option<Arc<dyn Reranker>>, AppError> {
+    let env = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+
+    match env("RERANKER").map(|v| v.trim().to_lowercase()).as_deref() {
+        None | Some("none") => Ok(None),
+        Some("llm") => Ok(Some(Arc::new(LlmReranker { ai }))),
+        Some("cross_encoder") => {
+            let url = env("RERANKER_URL")
+                .ok_or_else(|| AppError::ConfigError("RERANKER_URL is required for the cross_encoder reranker".to_string()))?;
+            Ok(Some(Arc::new(CrossEncoderReranker {
+                http: reqwest::Client::new(),
+                url,
+                api_key: env("RERANKER_API_KEY").map(|k| SecretString::new(k.into())),
+                model: env("RERANKER_MODEL"),
+            })))
+        },
+        Some(other) => Err(AppError::ConfigError(format!("Unknown reranker: {}", other))),
+    }
+}
+
+/// Reranking con el LLM del chat: una sola llamada que puntúa todos los pasajes de 0 a 10.
+pub struct LlmReranker {
+    ai: Arc<RwLock<dyn AIService>>,
+}
+
+#[derive(Deserialize)]
+struct LlmScores {
+    scores: Vec<f32>,
+}
+
+#[async_trait]
+impl Reranker for LlmReranker {
+    async fn score(&self, query: &str, passages: &[&str]) -> Result<Vec<f32>, AppError> {
+        let mut prompt = format!(
+            "Puntúa de 0 a 10 cuánto ayuda cada fragmento a responder la pregunta \
+             (10 = la responde directamente, 0 = no tiene relación).\n\
+             Responde SOLO con un objeto JSON {{\"scores\": [..]}} con un número por fragmento, en el mismo orden.\n\n\
+             PREGUNTA: {}\n\n",
+            query
+        );
+        for (i, passage) in passages.iter().enumerate() {
+            prompt.push_str(&format!("FRAGMENTO {}:\n{}\n\n", i + 1, truncate(passage)));
+        }
+
+        let response = self.ai.read().await.generate_text(&prompt).await?;
+        let json = extractors::first_json_object(&response)
+            .ok_or_else(|| AppError::ParseError(format!("No JSON scores in response: {}", response)))?;
+        let parsed: LlmScores = serde_json::from_str(json)
+            .map_err(|e| AppError::ParseError(format!("JSON Error: {}", e)))?;
+        if parsed.scores.len() != passages.len() {
+            return Err(AppError::ParseError(format!(
+                "Expected {} scores, got {}", passages.len(), parsed.scores.len()
+            )));
+        }
+        Ok(parsed.scores.into_iter().map(|s| (s / 10.0).clamp(0.0, 1.0)).collect())
+    }
+}
+
+/// Cross-encoder servido por HTTP con la API `rerank` de Cohere/Jina (también la ofrecen
+/// Infinity, vLLM o llama.cpp): `{model, query, documents}` -> `{results: [{index, relevance_score}]}`.
+pub struct CrossEncoderReranker {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<SecretString>,
+    model: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Deserialize)]
+struct RerankResult {
+    index: usize,
+    #[serde(alias = "score")]
+    relevance_score: f32,
+}
+
+#[async_trait]
+impl Reranker for CrossEncoderReranker {
+    async fn score(&self, query: &str, passages: &[&str]) -> Result<Vec<f32>, AppError> {
+        let documents: Vec<String> = passages.iter().map(|p| truncate(p)).collect();
+        let mut body = json!({ "query": query, "documents": documents, "top_n": passages.len() });
+        if let Some(model) = &self.model {
+            body["model"] = json!(model);
+        }
+
+        let mut request = self.http.post(&self.url).timeout(CROSS_ENCODER_TIMEOUT).json(&body);
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key.expose_secret());
+        }
+        let response = request.send().await
+            .map_err(|e| AppError::AIError(format!("Reranker request failed: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let detail = response.text().await.unwrap_or_default();
+            return Err(AppError::AIError(format!("Reranker returned {}: {}", status, detail)));
+        }
+        let parsed: RerankResponse = response.json().await
+            .map_err(|e| AppError::ParseError(format!("Invalid reranker response: {}", e)))?;
+        Ok(parsed.scores(passages.len()))
+    }
+}
+
+impl RerankResponse {
+    /// Puntuación de cada pasaje en el orden de entrada, tal cual la devuelve el servicio (algunos,
+    /// como llama.cpp, devuelven logits sin acotar). Los pasajes que no devuelva reciben la peor
+    /// puntuación de la respuesta, para que queden al final sin salirse de su escala.
+    fn scores(self, passages: usize) -> Vec<f32> {
+        let worst = self.results.iter().map(|r| r.relevance_score).reduce(f32::min).unwrap_or(0.0);
+        let mut scores = vec![worst; passages];
+        for result in self.results {
+            if let Some(score) = scores.get_mut(result.index) {
+                *score = result.relevance_score;
+            }
+        }
+        scores
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scores(body: &str, passages: usize) -> Vec<f32> {
+        serde_json::from_str::<RerankResponse>(body).expect("respuesta válida").scores(passages)
+    }
+
+    #[test]
+    fn cross_encoder_scores_follow_the_input_order() {
+        // Cohere/Jina devuelven los resultados ordenados por relevancia, no por índice
+        let body = r#"{"results": [{"index": 2, "relevance_score": 0.9}, {"index": 0, "relevance_score": 0.4}, {"index": 1, "relevance_score": 0.1}]}"#;
+        assert_eq!(scores(body, 3), vec![0.4, 0.1, 0.9]);
+    }
+
+    #[test]
+    fn cross_encoder_logits_are_kept_unclamped() {
+        // llama.cpp: logits sin acotar y `score` como nombre alternativo
+        let body = r#"{"results": [{"index": 0, "score": -7.5}, {"index": 1, "score": 3.25}]}"#;
+        assert_eq!(scores(body, 2), vec![-7.5, 3.25]);
+    }
+
+    #[test]
+    fn missing_passages_get_the_worst_score_and_unknown_indexes_are_ignored() {
+        let body = r#"{"results": [{"index": 0, "relevance_score": -1.5}, {"index": 2, "relevance_score": 4.0}, {"index": 9, "relevance_score": 8.0}]}"#;
+        assert_eq!(scores(body, 3), vec![-1.5, -1.5, 4.0]);
+        assert_eq!(scores(r#"{"results": []}"#, 2), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn responses_without_results_are_rejected() {
+        assert!(serde_json::from_str::<RerankResponse>(r#"{"data": []}"#).is_err());
+    }
+}
@@ -0,0 +1,503 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+use crate::domain::{
+    models::{estimate_tokens, AIConfig, KnowledgeExtraction, InferenceResult, MergeAdjudication},
+    ontology::Ontology,
+    ports::{AIService, ChatStream},
+    errors::AppError
+};
+
+// Tokens de salida reservados para las llamadas de chat (extracción / inferencia)
+const COMPLETION_OUTPUT_TOKENS: usize = 1024;
+
+/// Parámetros de la capa de resiliencia (ver `from_env`).
+#[derive(Debug, Clone)]
+pub struct ResiliencePolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Llamadas simultáneas por proveedor
+    pub max_concurrency: usize,
+    /// Presupuesto de tokens por minuto por proveedor (`None` = sin límite)
+    pub tokens_per_minute: Option<usize>,
+    /// Fallos transitorios consecutivos que abren el circuito
+    pub failure_threshold: u32,
+    /// Tiempo que el circuito permanece abierto antes de dejar pasar una prueba
+    pub cooldown: Duration,
+}
+
+impl Default for ResiliencePolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_concurrency: 8,
+            tokens_per_minute: None,
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ResiliencePolicy {
+    /// Lee `AI_MAX_RETRIES`, `AI_RETRY_BASE_MS`, `AI_RETRY_MAX_MS`, `AI_MAX_CONCURRENCY`,
+    /// `AI_TOKENS_PER_MINUTE`, `AI_CIRCUIT_FAILURES` y `AI_CIRCUIT_COOLDOWN_SECS`.
+    pub fn from_env() -> Result<Self, AppError> {
+        let read = |name: &str| -> Result<Option<u64>, AppError> {
+            std::env::var(name).ok()
+                .map(|v| v.trim().parse::<u64>()
+                    .map_err(|_| AppError::ConfigError(format!("{} must be a number", name))))
+                .transpose()
+        };
+
+        let defaults = Self::default();
+        Ok(Self {
+            max_retries: read("AI_MAX_RETRIES")?.map(|v| v as u32).unwrap_or(defaults.max_retries),
+            base_delay: read("AI_RETRY_BASE_MS")?.map(Duration::from_millis).unwrap_or(defaults.base_delay),
+            max_delay: read("AI_RETRY_MAX_MS")?.map(Duration::from_millis).unwrap_or(defaults.max_delay),
+            max_concurrency: read("AI_MAX_CONCURRENCY")?.map(|v| (v as usize).max(1)).unwrap_or(defaults.max_concurrency),
+            tokens_per_minute: read("AI_TOKENS_PER_MINUTE")?.map(|v| v as usize).filter(|&v| v > 0),
+            failure_threshold: read("AI_CIRCUIT_FAILURES")?.map(|v| (v as u32).max(1)).unwrap_or(defaults.failure_threshold),
+            cooldown: read("AI_CIRCUIT_COOLDOWN_SECS")?.map(Duration::from_secs).unwrap_or(defaults.cooldown),
+        })
+    }
+
+    /// Espera antes del reintento `attempt`: la que pidió el proveedor, pero nunca más de
+    /// `max_delay` (un `Retry-After` de horas no debe aparcar un trabajo de ingesta).
+    fn retry_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after.map(|d| d.min(self.max_delay)).unwrap_or_else(|| self.backoff(attempt))
+    }
+
+    /// Espera exponencial con ±25% de variación para no sincronizar reintentos.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let jitter = 0.75 + (nanos % 1000) as f64 / 2000.0;
+        exp.mul_f64(jitter)
+    }
+}
+
+/// Cubo de tokens que se rellena a razón de `capacity` por minuto.
+struct TokenBudget {
+    capacity: f64,
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBudget {
+    fn new(tokens_per_minute: usize) -> Self {
+        let capacity = tokens_per_minute as f64;
+        Self { capacity, available: capacity, refilled_at: Instant::now() }
+    }
+
+    /// Reserva `tokens` o devuelve cuánto falta esperar para tenerlos.
+    fn try_take(&mut self, tokens: usize) -> Result<(), Duration> {
+        let now = Instant::now();
+        let per_sec = self.capacity / 60.0;
+        self.available = (self.available + now.duration_since(self.refilled_at).as_secs_f64() * per_sec).min(self.capacity);
+        self.refilled_at = now;
+
+        // Una petición mayor que el presupuesto completo solo espera a tenerlo lleno
+        let needed = (tokens as f64).min(self.capacity);
+        if self.available >= needed {
+            self.available -= needed;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((needed - self.available) / per_sec))
+        }
+    }
+}
+
+#[derive(Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    /// Semiabierto con la llamada de prueba en curso
+    probing: bool,
+}
+
+/// Llamada de prueba del circuito semiabierto; al soltarse (con cualquier resultado, o si la
+/// llamada se cancela) permite la siguiente prueba.
+struct Probe<'a>(&'a ProviderGate);
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut breaker) = self.0.breaker.lock() {
+            breaker.probing = false;
+        }
+    }
+}
+
+/// Estado compartido por todas las llamadas a un mismo proveedor.
+struct ProviderGate {
+    name: String,
+    permits: Semaphore,
+    budget: Option<Mutex<TokenBudget>>,
+    breaker: Mutex<CircuitBreaker>,
+}
+
+impl ProviderGate {
+    /// `Err(espera)` mientras el circuito esté abierto. Pasado el enfriamiento queda
+    /// semiabierto: deja pasar una única llamada de prueba, cuyo éxito lo cierra y cuyo fallo
+    /// lo vuelve a abrir; mientras tanto las demás fallan sugiriendo esperar `base_delay`.
+    fn check_circuit(&self, policy: &ResiliencePolicy) -> Result<Option<Probe<'_>>, Duration> {
+        let mut breaker = self.breaker.lock().expect("circuit breaker poisoned");
+        let now = Instant::now();
+        match breaker.open_until {
+            None => Ok(None),
+            Some(until) if until > now => Err(until - now),
+            Some(_) if breaker.probing => Err(policy.base_delay),
+            Some(_) => {
+                breaker.probing = true;
+                tracing::info!("🔌 Circuit half-open for provider {}, probing", self.name);
+                Ok(Some(Probe(self)))
+            },
+        }
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock().expect("circuit breaker poisoned");
+        if breaker.open_until.take().is_some() {
+            tracing::info!("🔌 Circuit closed for provider {}", self.name);
+        }
+        breaker.consecutive_failures = 0;
+    }
+
+    fn record_failure(&self, policy: &ResiliencePolicy) {
+        let mut breaker = self.breaker.lock().expect("circuit breaker poisoned");
+        breaker.consecutive_failures += 1;
+        if breaker.consecutive_failures >= policy.failure_threshold {
+            if breaker.open_until.is_none_or(|until| until <= Instant::now()) {
+                tracing::warn!("🔌 Circuit opened for provider {} after {} consecutive failures",
+                    self.name, breaker.consecutive_failures);
+            }
+            breaker.open_until = Some(Instant::now() + policy.cooldown);
+        }
+    }
+
+    async fn reserve_tokens(&self, tokens: usize) {
+        let Some(budget) = &self.budget else { return };
+        loop {
+            let wait = match budget.lock().expect("token budget poisoned").try_take(tokens) {
+                Ok(()) => return,
+                Err(wait) => wait,
+            };
+            tracing::debug!("⏳ Token budget exhausted for {}, waiting {:?}", self.name, wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Decorador del puerto `AIService` que añade reintentos con espera exponencial (respetando
+/// `Retry-After`), límite de concurrencia y presupuesto de tokens por minuto por proveedor,
+/// y un circuit breaker que corta las llamadas mientras el proveedor está caído.
+pub struct ResilientAIService<S> {
+    inner: S,
+    policy: ResiliencePolicy,
+    gates: Mutex<HashMap<String, Arc<ProviderGate>>>,
+}
+
+impl<S: AIService> ResilientAIService<S> {
+    pub fn new(inner: S, policy: ResiliencePolicy) -> Self {
+        Self { inner, policy, gates: Mutex::new(HashMap::new()) }
+    }
+
+    fn gate(&self) -> Arc<ProviderGate> {
+        let name = format!("{:?}", self.inner.get_config().provider);
+        let mut gates = self.gates.lock().expect("provider gates poisoned");
+        gates.entry(name.clone())
+            .or_insert_with(|| Arc::new(ProviderGate {
+                permits: Semaphore::new(self.policy.max_concurrency),
+                budget: self.policy.tokens_per_minute.map(|tpm| Mutex::new(TokenBudget::new(tpm))),
+                breaker: Mutex::new(CircuitBreaker::default()),
+                name,
+            }))
+            .clone()
+    }
+
+    async fn call<T, F, Fut>(&self, operation: &str, tokens: usize, op: F) -> Result<T, AppError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        let gate = self.gate();
+        let mut attempt = 0;
+
+        loop {
+            let result = {
+                // Con el circuito abierto se falla enseguida: reintentar solo alargaría la espera
+                let _probe = gate.check_circuit(&self.policy).map_err(|wait| AppError::AITransientError {
+                    message: format!("circuit open for provider {}", gate.name),
+                    retry_after: Some(wait),
+                })?;
+                let _permit = gate.permits.acquire().await
+                    .map_err(|_| AppError::AIError("Provider limiter closed".to_string()))?;
+                gate.reserve_tokens(tokens).await;
+
+                let result = op().await;
+                match &result {
+                    Ok(_) => gate.record_success(),
+                    Err(AppError::AITransientError { .. }) => gate.record_failure(&self.policy),
+                    // Errores definitivos (credenciales, petición inválida) no dicen nada de la salud del proveedor
+                    Err(_) => {},
+                }
+                result
+            };
+
+            match result {
+                Err(AppError::AITransientError { message, retry_after }) if attempt < self.policy.max_retries => {
+                    let delay = self.policy.retry_delay(attempt, retry_after);
+                    attempt += 1;
+                    tracing::warn!("⏳ {} failed ({}), retry {}/{} in {:?}",
+                        operation, message, attempt, self.policy.max_retries, delay);
+                    tokio::time::sleep(delay).await;
+                },
+                Err(AppError::AITransientError { message, retry_after }) => {
+                    return Err(AppError::AITransientError {
+                        message: format!("{} (gave up after {} retries)", message, attempt),
+                        retry_after,
+                    });
+                },
+                other => return other,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: AIService> AIService for ResilientAIService<S> {
+    async fn extract_knowledge(&self, text: &str, ontology: &Ontology) -> Result<KnowledgeExtraction, AppError> {
+        let tokens = estimate_tokens(text) + COMPLETION_OUTPUT_TOKENS;
+        self.call("Extraction", tokens, || self.inner.extract_knowledge(text, ontology)).await
+    }
+
+    async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>, AppError> {
+        self.call("Embedding", estimate_tokens(text), || self.inner.generate_embedding(text)).await
+    }
+
+    async fn generate_embeddings(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AppError> {
+        let tokens = texts.iter().map(|t| estimate_tokens(t)).sum();
+        self.call("Batch embedding", tokens, || self.inner.generate_embeddings(texts)).await
+    }
+
+    fn embedding_batch_size(&self) -> usize {
+        self.inner.embedding_batch_size()
+    }
+
+    fn update_config(&mut self, config: AIConfig) -> Result<(), AppError> {
+        self.inner.update_config(config)
+    }
+
+    fn get_config(&self) -> AIConfig {
+        self.inner.get_config()
+    }
+
+    async fn generate_inference(&self, prompt: &str) -> Result<InferenceResult, AppError> {
+        let tokens = estimate_tokens(prompt) + COMPLETION_OUTPUT_TOKENS;
+        self.call("Inference", tokens, || self.inner.generate_inference(prompt)).await
+    }
+
+    async fn generate_text(&self, prompt: &str) -> Result<String, AppError> {
+        let tokens = estimate_tokens(prompt) + COMPLETION_OUTPUT_TOKENS;
+        self.call("Text generation", tokens, || self.inner.generate_text(prompt)).await
+    }
+
+    async fn adjudicate_merge(&self, prompt: &str) -> Result<MergeAdjudication, AppError> {
+        let tokens = estimate_tokens(prompt) + COMPLETION_OUTPUT_TOKENS;
+        self.call("Merge adjudication", tokens, || self.inner.adjudicate_merge(prompt)).await
+    }
+
+    /// Solo se protege la apertura del stream: el permiso de concurrencia se suelta al empezar
+    /// a recibir la respuesta.
+    async fn stream_chat(&self, preamble: &str, message: &str) -> Result<ChatStream, AppError> {
+        let tokens = estimate_tokens(preamble) + estimate_tokens(message) + COMPLETION_OUTPUT_TOKENS;
+        self.call("Chat", tokens, || self.inner.stream_chat(preamble, message)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use secrecy::SecretString;
+    use crate::domain::models::AIProvider;
+
+    /// Proveedor caído: cada llamada de texto falla de forma transitoria y se cuenta.
+    #[derive(Default)]
+    struct UnavailableAI {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl AIService for UnavailableAI {
+        async fn extract_knowledge(&self, _text: &str, _ontology: &Ontology) -> Result<KnowledgeExtraction, AppError> {
+            unimplemented!()
+        }
+        async fn generate_embedding(&self, _text: &str) -> Result<Vec<f32>, AppError> {
+            unimplemented!()
+        }
+        async fn generate_embeddings(&self, _texts: &[&str]) -> Result<Vec<Vec<f32>>, AppError> {
+            unimplemented!()
+        }
+        fn embedding_batch_size(&self) -> usize {
+            1
+        }
+        fn update_config(&mut self, _config: AIConfig) -> Result<(), AppError> {
+            Ok(())
+        }
+        fn get_config(&self) -> AIConfig {
+            AIConfig {
+                provider: AIProvider::Ollama,
+                model_name: "test".to_string(),
+                embedding_model: "test".to_string(),
+                api_key: SecretString::new("".into()),
+                embedding_dim: 3,
+                base_url: None,
+            }
+        }
+        async fn generate_inference(&self, _prompt: &str) -> Result<InferenceResult, AppError> {
+            unimplemented!()
+        }
+        async fn generate_text(&self, _prompt: &str) -> Result<String, AppError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(AppError::AITransientError { message: "503".to_string(), retry_after: None })
+        }
+        async fn adjudicate_merge(&self, _prompt: &str) -> Result<MergeAdjudication, AppError> {
+            unimplemented!()
+        }
+        async fn stream_chat(&self, _preamble: &str, _message: &str) -> Result<ChatStream, AppError> {
+            unimplemented!()
+        }
+    }
+
+    fn gate(policy: &ResiliencePolicy) -> ProviderGate {
+        ProviderGate {
+            name: "Test".to_string(),
+            permits: Semaphore::new(policy.max_concurrency),
+            budget: None,
+            breaker: Mutex::new(CircuitBreaker::default()),
+        }
+    }
+
+    /// Da por cumplido el enfriamiento del circuito.
+    fn expire_cooldown(gate: &ProviderGate) {
+        gate.breaker.lock().unwrap().open_until = Some(Instant::now() - Duration::from_millis(1));
+    }
+
+    #[test]
+    fn token_budget_refills_over_time() {
+        let mut budget = TokenBudget::new(600);
+        assert!(budget.try_take(600).is_ok());
+
+        // 10 tokens por segundo: faltan 30 segundos para 300
+        let wait = budget.try_take(300).unwrap_err();
+        assert!((29.0..=30.0).contains(&wait.as_secs_f64()), "espera {:?}", wait);
+
+        budget.refilled_at -= Duration::from_secs(30);
+        assert!(budget.try_take(300).is_ok());
+        assert!(budget.try_take(10).is_err());
+    }
+
+    #[test]
+    fn oversized_requests_only_wait_for_a_full_budget() {
+        let mut budget = TokenBudget::new(60);
+        assert!(budget.try_take(1000).is_ok());
+
+        let wait = budget.try_take(1000).unwrap_err();
+        assert!((59.0..=60.0).contains(&wait.as_secs_f64()), "espera {:?}", wait);
+        budget.refilled_at -= Duration::from_secs(60);
+        assert!(budget.try_take(1000).is_ok());
+    }
+
+    #[test]
+    fn circuit_opens_probes_once_and_closes() {
+        let policy = ResiliencePolicy { failure_threshold: 2, ..ResiliencePolicy::default() };
+        let gate = gate(&policy);
+
+        gate.record_failure(&policy);
+        assert!(gate.check_circuit(&policy).is_ok());
+        gate.record_failure(&policy);
+        let wait = gate.check_circuit(&policy).err().expect("circuito abierto");
+        assert!(wait <= policy.cooldown);
+
+        // Semiabierto: una sola prueba; su fallo lo vuelve a abrir
+        expire_cooldown(&gate);
+        let probe = gate.check_circuit(&policy).expect("llamada de prueba");
+        assert!(probe.is_some());
+        assert_eq!(gate.check_circuit(&policy).err(), Some(policy.base_delay));
+        gate.record_failure(&policy);
+        drop(probe);
+        assert!(gate.check_circuit(&policy).err().unwrap() > policy.base_delay);
+
+        // Una prueba sin resultado (error definitivo o cancelada) deja pasar la siguiente
+        expire_cooldown(&gate);
+        drop(gate.check_circuit(&policy).unwrap());
+        let probe = gate.check_circuit(&policy).unwrap();
+        assert!(probe.is_some());
+
+        // Su éxito lo cierra para todos
+        gate.record_success();
+        drop(probe);
+        assert!(gate.check_circuit(&policy).unwrap().is_none());
+        assert!(gate.check_circuit(&policy).unwrap().is_none());
+        assert_eq!(gate.breaker.lock().unwrap().consecutive_failures, 0);
+    }
+
+    #[test]
+    fn backoff_grows_with_jitter_and_is_capped() {
+        let policy = ResiliencePolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            ..ResiliencePolicy::default()
+        };
+        for attempt in 0..40 {
+            let expected = Duration::from_millis(100).saturating_mul(2u32.saturating_pow(attempt)).min(policy.max_delay);
+            let delay = policy.backoff(attempt);
+            assert!(delay >= expected.mul_f64(0.75) && delay <= expected.mul_f64(1.25),
+                "intento {}: {:?} fuera de ±25% de {:?}", attempt, delay, expected);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_honoured_up_to_max_delay() {
+        let policy = ResiliencePolicy { max_delay: Duration::from_secs(30), ..ResiliencePolicy::default() };
+
+        assert_eq!(policy.retry_delay(0, Some(Duration::from_secs(3))), Duration::from_secs(3));
+        assert_eq!(policy.retry_delay(0, Some(Duration::from_secs(86_400))), Duration::from_secs(30));
+        assert!(policy.retry_delay(0, None) <= policy.base_delay.mul_f64(1.25));
+    }
+
+    #[tokio::test]
+    async fn an_open_circuit_fails_fast_instead_of_retrying() {
+        let policy = ResiliencePolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(60),
+            ..ResiliencePolicy::default()
+        };
+        let service = ResilientAIService::new(UnavailableAI::default(), policy);
+
+        // El segundo fallo abre el circuito: el reintento siguiente ya no llega al proveedor
+        let started = Instant::now();
+        match service.generate_text("hola").await {
+            Err(AppError::AITransientError { message, retry_after }) => {
+                assert!(message.contains("circuit open"), "{}", message);
+                assert!(retry_after.is_some_and(|wait| wait > Duration::from_secs(50)));
+            },
+            other => panic!("se esperaba un fallo transitorio: {:?}", other),
+        }
+        assert_eq!(service.inner.calls.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() < Duration::from_secs(1), "no debe esperar el enfriamiento");
+
+        // Mientras siga abierto, ninguna llamada alcanza al proveedor
+        assert!(service.generate_text("hola").await.is_err());
+        assert_eq!(service.inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,22 +1,58 @@
 use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
 use rig::{
+    agent::MultiTurnStreamItem,
     providers::openai::{self, OpenAIResponsesExt},
     client::{CompletionClient, EmbeddingsClient},
     completion::Prompt,
-    embeddings::EmbeddingsBuilder,
+    embeddings::EmbeddingModel,
+    streaming::{StreamedAssistantContent, StreamingPrompt},
 };
 use secrecy::ExposeSecret;
-use serde_json::from_str;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
-use crate::domain::{models::{AIConfig, KnowledgeExtraction, InferenceResult}, ports::AIService, errors::AppError};
+use crate::domain::{
+    models::{AIConfig, AIProvider, ChatChunk, KnowledgeExtraction, InferenceResult, MergeAdjudication, TokenUsage},
+    ontology::Ontology,
+    ports::{AIService, ChatStream},
+    errors::AppError
+};
+use super::http::ObservedHttpClient;
+use super::extractors::{self, ExtractionOptions};
+
+// Tope de caracteres por petición de embeddings (~100k tokens), por debajo del límite por petición de OpenAI
+const MAX_EMBEDDING_BATCH_CHARS: usize = 400_000;
+
+const EXTRACTION_PREAMBLE: &str = "You are an expert Ontology Engineer. Extract entities and relationships from the text. \
+    Return strictly JSON format matching this structure: \
+    { \"entities\": [{\"name\": \"...\", \"category\": \"...\", \"description\": \"...\", \"aliases\": [\"...\"]}], \
+    \"relations\": [{\"source\": \"...\", \"target\": \"...\", \"relation_type\": \"...\", \"description\": \"...\", \"confidence\": 0.0}] } \
+    \"description\" is one short sentence, in the language of the text, saying what the entity is according to the text. \
+    \"aliases\" lists other names or abbreviations used in the text for the same entity (empty if none). \
+    A relation \"description\" is one short sentence stating the fact as the text supports it; \
+    \"confidence\" is how clearly the text asserts it, from 0.0 (speculative) to 1.0 (explicit). \
+    Every relation source and target must be the name of an entity listed in \"entities\".";
 
 pub struct RigAIService {
     config: AIConfig,
+    extraction: ExtractionOptions,
+    // Pool de conexiones compartido por todas las llamadas
+    http: reqwest::Client,
 }
 
 impl RigAIService {
-    pub fn new(config: AIConfig) -> Self {
-        Self { config }
+    pub fn new(config: AIConfig, extraction: ExtractionOptions) -> Self {
+        Self { config, extraction, http: reqwest::Client::new() }
+    }
+
+    /// Clasifica el error de una llamada: transitorio (reintentable) según lo que respondió el proveedor.
+    fn provider_error(&self, http: &ObservedHttpClient, context: &str, e: impl std::fmt::Display) -> AppError {
+        let signal = http.signal();
+        let message = format!("{} (Provider: {:?}): {}", context, self.config.provider, e);
+        if signal.is_transient() {
+            AppError::AITransientError { message, retry_after: signal.retry_after }
+        } else {
+            AppError::AIError(message)
+        }
     }
 
     fn clean_json_response(&self, raw: &str) -> String {
@@ -27,7 +63,7 @@ impl RigAIService {
             .to_string()
     }
     
-    fn get_client(&self) -> openai::Client {
+    fn get_client(&self, http: &ObservedHttpClient) -> openai::Client<ObservedHttpClient> {
         let base_url = self.config.base_url.as_deref().unwrap_or("https://api.openai.com/v1");
         let api_key = self.config.api_key.expose_secret();
 
@@ -44,10 +80,39 @@ impl RigAIService {
         openai::Client::from_parts(
             base_url.to_string(),
             headers,
-            reqwest::Client::new(),
+            http.clone(),
             OpenAIResponsesExt,
         )
     }
+
+    /// Una llamada de extracción; con `structured` el proveedor restringe la salida al esquema.
+    async fn prompt_extraction(
+        &self,
+        http: &ObservedHttpClient,
+        prompt: &str,
+        ontology: &Ontology,
+        structured: bool
+    ) -> Result<String, AppError> {
+        let client = self.get_client(http);
+        let preamble = match ontology.prompt_guidelines() {
+            Some(guidelines) => format!("{}\n\n{}", EXTRACTION_PREAMBLE, guidelines),
+            None => EXTRACTION_PREAMBLE.to_string(),
+        };
+        let mut builder = client.agent(&self.config.model_name).preamble(&preamble);
+        if structured {
+            builder = builder.additional_params(serde_json::json!({
+                "text": { "format": {
+                    "type": "json_schema",
+                    "name": "knowledge_extraction",
+                    "schema": extractors::extraction_schema(ontology),
+                    "strict": true
+                } }
+            }));
+        }
+
+        builder.build().prompt(prompt).await
+            .map_err(|e| self.provider_error(http, "Extraction failed", e))
+    }
 }
 
 #[async_trait]
@@ -62,51 +127,98 @@ impl AIService for RigAIService {
     }
 
     async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>, AppError> {
-        let client = self.get_client(); 
-        let model = client.embedding_model(&self.config.embedding_model);
-        
-        let embeddings = EmbeddingsBuilder::new(model)
-            .document(text) 
-            .map_err(|e| AppError::AIError(format!("Error adding document: {}", e)))? 
-            .build()
-            .await
-            .map_err(|e| AppError::AIError(format!("Embedding failed (Provider: {:?}): {}", self.config.provider, e)))?;
-
-        let (_, embedding_data) = embeddings.first()
-            .ok_or_else(|| AppError::AIError("No embedding returned".to_string()))?;
-            
-        let first_embedding = embedding_data.first();
-        let embedding_f32: Vec<f32> = first_embedding.vec.iter().map(|&x| x as f32).collect();
-        
-        Ok(embedding_f32)
+        self.generate_embeddings(&[text]).await?
+            .pop()
+            .ok_or_else(|| AppError::AIError("No embedding returned".to_string()))
     }
 
-    async fn extract_knowledge(&self, text: &str) -> Result<KnowledgeExtraction, AppError> {
-        let client = self.get_client(); 
+    async fn generate_embeddings(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, AppError> {
+        let http = ObservedHttpClient::new(self.http.clone());
+        let client = self.get_client(&http); 
+        let model = client.embedding_model(&self.config.embedding_model);
+        let max_docs = self.embedding_batch_size();
 
-        let agent = client.agent(&self.config.model_name)
-            .preamble("You are an expert Ontology Engineer. Extract entities and relationships from the text. \
-                       Return strictly JSON format matching this structure: \
-                       { \"entities\": [{\"name\": \"...\", \"category\": \"...\"}], \"relations\": [{\"source\": \"...\", \"target\": \"...\", \"relation_type\": \"...\"}] }")
-            .build();
+        let mut result = Vec::with_capacity(texts.len());
+        let mut start = 0;
 
-        let response = agent.prompt(text).await
-            .map_err(|e| AppError::AIError(format!("Extraction failed: {}", e)))?;
+        while start < texts.len() {
+            // Lote: hasta `max_docs` textos sin superar el tope de caracteres (al menos uno)
+            let mut end = start;
+            let mut chars = 0;
+            while end < texts.len() && end - start < max_docs {
+                chars += texts[end].len();
+                if end > start && chars > MAX_EMBEDDING_BATCH_CHARS { break; }
+                end += 1;
+            }
 
-        let cleaned_json = self.clean_json_response(&response);
+            let batch: Vec<String> = texts[start..end].iter().map(|t| t.to_string()).collect();
+            let embeddings = model.embed_texts(batch).await
+                .map_err(|e| self.provider_error(&http, "Embedding failed", e))?;
 
-        let extraction: KnowledgeExtraction = from_str(&cleaned_json)
-            .map_err(|e| AppError::ParseError(format!("Failed to parse JSON: {} - Raw: {}", e, cleaned_json)))?;
+            if embeddings.len() != end - start {
+                return Err(AppError::AIError(format!(
+                    "Embedding provider returned {} vectors for {} texts", embeddings.len(), end - start
+                )));
+            }
 
-        Ok(extraction)
+            result.extend(embeddings.into_iter().map(|e| e.vec.into_iter().map(|x| x as f32).collect::<Vec<f32>>()));
+            start = end;
+        }
+
+        Ok(result)
+    }
+
+    fn embedding_batch_size(&self) -> usize {
+        match self.config.provider {
+            // La API de OpenAI admite hasta 2048 entradas por petición
+            AIProvider::OpenAI => 256,
+            // Ollama embebe en local de forma secuencial: lotes pequeños para no bloquear el servidor
+            AIProvider::Ollama => 16,
+            AIProvider::Groq => 64,
+        }
+    }
+
+    async fn extract_knowledge(&self, text: &str, ontology: &Ontology) -> Result<KnowledgeExtraction, AppError> {
+        let http = ObservedHttpClient::new(self.http.clone());
+        let mut structured = self.extraction.use_structured_output(&self.config.provider);
+
+        let mut response = match self.prompt_extraction(&http, text, ontology, structured).await {
+            // Endpoints compatibles que rechazan el esquema (400/422): repetimos en modo texto libre.
+            // Credenciales o modelo inválidos (401/403/404) fallarían igual: se propagan tal cual
+            Err(AppError::AIError(e)) if structured && matches!(http.signal().status, Some(400 | 422)) => {
+                tracing::warn!("⚠️ Structured output rejected, falling back to plain JSON prompt: {}", e);
+                structured = false;
+                self.prompt_extraction(&http, text, ontology, false).await?
+            },
+            other => other?,
+        };
+
+        let mut repairs = 0;
+        loop {
+            match extractors::parse_extraction(&response) {
+                Ok(extraction) => {
+                    return Ok(extractors::validate_extraction(extraction, self.extraction.undeclared_entities));
+                },
+                Err(AppError::ParseError(e)) if repairs < self.extraction.repair_attempts => {
+                    repairs += 1;
+                    tracing::warn!("🔧 Unparseable extraction ({}), repair attempt {}/{}", e, repairs, self.extraction.repair_attempts);
+                    response = self.prompt_extraction(&http, &extractors::repair_prompt(&response, &e), ontology, structured).await?;
+                },
+                Err(AppError::ParseError(e)) => {
+                    return Err(AppError::ParseError(format!("Failed to parse JSON: {} - Raw: {}", e, response)));
+                },
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     async fn generate_inference(&self, prompt: &str) -> Result<InferenceResult, AppError> {
-        let client = self.get_client();
+        let http = ObservedHttpClient::new(self.http.clone());
+        let client = self.get_client(&http);
         let agent = client.agent(&self.config.model_name).build();
         
         let response = agent.prompt(prompt).await
-            .map_err(|e| AppError::AIError(format!("Inference failed: {}", e)))?;
+            .map_err(|e| self.provider_error(&http, "Inference failed", e))?;
             
         let cleaned = self.clean_json_response(&response);
         
@@ -115,4 +227,67 @@ impl AIService for RigAIService {
             
         Ok(result)
     }
+
+    async fn generate_text(&self, prompt: &str) -> Result<String, AppError> {
+        let http = ObservedHttpClient::new(self.http.clone());
+        let client = self.get_client(&http);
+        let agent = client.agent(&self.config.model_name).build();
+
+        let response = agent.prompt(prompt).await
+            .map_err(|e| self.provider_error(&http, "Text generation failed", e))?;
+        Ok(response.trim().to_string())
+    }
+
+    async fn adjudicate_merge(&self, prompt: &str) -> Result<MergeAdjudication, AppError> {
+        let http = ObservedHttpClient::new(self.http.clone());
+        let client = self.get_client(&http);
+        let agent = client.agent(&self.config.model_name).build();
+
+        let response = agent.prompt(prompt).await
+            .map_err(|e| self.provider_error(&http, "Merge adjudication failed", e))?;
+
+        let json = extractors::first_json_object(&response)
+            .ok_or_else(|| AppError::ParseError(format!("No JSON verdict in response: {}", response)))?;
+        serde_json::from_str(json)
+            .map_err(|e| AppError::ParseError(format!("JSON Error: {}", e)))
+    }
+
+    async fn stream_chat(&self, preamble: &str, message: &str) -> Result<ChatStream, AppError> {
+        let http = ObservedHttpClient::new(self.http.clone());
+        let client = self.get_client(&http);
+        let agent = client.agent(&self.config.model_name).preamble(preamble).build();
+
+        let mut chunks = agent.stream_prompt(message.to_string()).await
+            .filter_map(|item| std::future::ready(match item {
+                Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(fragment))) => {
+                    Some(Ok(ChatChunk::Text(fragment.text)))
+                },
+                Ok(MultiTurnStreamItem::FinalResponse(last)) => {
+                    let usage = last.usage();
+                    Some(Ok(ChatChunk::Done(TokenUsage {
+                        input_tokens: usage.input_tokens,
+                        output_tokens: usage.output_tokens,
+                        total_tokens: usage.total_tokens,
+                    })))
+                },
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }))
+            .boxed();
+
+        // La petición sale al leer el primer fragmento: se espera aquí para que un fallo al
+        // conectar (429, 503...) se clasifique y se reintente como en el resto de llamadas
+        let first = match chunks.next().await {
+            Some(Ok(first)) => first,
+            Some(Err(e)) => return Err(self.provider_error(&http, "Chat failed", e)),
+            None => return Ok(stream::empty().boxed()),
+        };
+
+        // A mitad de respuesta ya no se reintenta: el cliente habría recibido texto duplicado
+        let provider = self.config.provider.clone();
+        let rest = chunks.map(move |chunk| chunk.map_err(|e| {
+            AppError::AIError(format!("Chat stream failed (Provider: {:?}): {}", provider, e))
+        }));
+        Ok(stream::once(std::future::ready(Ok(first))).chain(rest).boxed())
+    }
 }
\ No newline at end of file
@@ -1,3 +1,3 @@
 pub mod ai;
 pub mod persistence;
-pub mod parsing;
\ No newline at end of file
+pub mod transmutation;
\ No newline at end of file
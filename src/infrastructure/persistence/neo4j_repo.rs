@@ -1,11 +1,20 @@
 use async_trait::async_trait;
-use neo4rs::{Graph, query};
+use neo4rs::{BoltType, Graph, Query, Txn, query};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 use std::sync::Arc;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use crate::domain::{
-    ports::KGRepository, 
-    models::{KnowledgeExtraction, GraphDataResponse, VisNode, VisEdge, HybridContext, InferredRelation}, 
+    ports::{KGRepository, JobRepository, ConversationRepository, CommunityRepository}, 
+    models::{
+        KnowledgeExtraction, GraphDataResponse, VisNode, VisEdge, HybridContext, RetrievalOptions, RetrievalFilters, InferredRelation,
+        NewDocument, ChunkRecord, DocumentInfo, DocumentChunkInfo, DocumentPurgeResult,
+        IngestionJob, IngestionRequest, EntityProfile, EntityMergeRecord,
+        StaleEntity, EntityEmbedding, RelevantEntity, GraphFact, Conversation, ConversationSummary, ConversationTurn,
+        SourceReference, Community, EntityNode, EntityLink
+    }, 
+    ontology::{Ontology, QuarantinedTriple, sanitize_relation_type},
     errors::AppError
 };
 
@@ -13,12 +22,445 @@ pub struct Neo4jRepo {
     graph: Arc<Graph>,
 }
 
+/// Relación de una entidad vista desde ella (`outgoing` = la entidad es el origen).
+#[derive(Debug, Serialize, Deserialize)]
+struct RelationshipSnapshot {
+    rel_type: String,
+    other: String,
+    outgoing: bool,
+    #[serde(default)]
+    properties: Value,
+}
+
+/// Lo que hace falta para deshacer una fusión: la entidad absorbida tal como era y lo que
+/// la fusión añadió a la conservada.
+#[derive(Debug, Serialize, Deserialize)]
+struct MergeSnapshot {
+    properties: Value,
+    relationships: Vec<RelationshipSnapshot>,
+    mentions: Vec<String>,
+    added_relationships: Vec<RelationshipSnapshot>,
+    added_mentions: Vec<String>,
+    added_aliases: Vec<String>,
+    /// Descripción de la conservada antes de sumarle la de la absorbida
+    #[serde(default)]
+    kept_description: Option<String>,
+    /// Relaciones que la conservada ya tenía y a las que se sumó la evidencia de la absorbida,
+    /// con sus propiedades previas
+    #[serde(default)]
+    updated_relationships: Vec<RelationshipSnapshot>,
+}
+
+/// Tipo de relación leído de la base de datos, escapado para interpolarlo en Cypher.
+fn rel_type_ident(rel_type: &str) -> String {
+    format!("`{}`", rel_type.replace('`', "``"))
+}
+
+/// Etiqueta de un tipo de relación nuevo: la capa de aplicación ya la ha saneado, pero aquí
+/// se vuelve a comprobar porque es lo único que se interpola en el Cypher.
+fn new_rel_type_ident(rel_type: &str) -> Result<String, AppError> {
+    sanitize_relation_type(rel_type)
+        .map(|label| rel_type_ident(&label))
+        .map_err(|e| AppError::ValidationError(format!("Invalid relation type {:?}: {}", rel_type, e)))
+}
+
+/// Mapa de propiedades como parámetro (`null` se trata como mapa vacío).
+fn properties_param(properties: &Value) -> Result<BoltType, AppError> {
+    let properties = if properties.is_object() { properties.clone() } else { Value::Object(Default::default()) };
+    BoltType::try_from(properties).map_err(|e| AppError::DatabaseError(format!("Unsupported property value: {}", e)))
+}
+
+// Términos máximos de una consulta de texto completo
+const FULLTEXT_MAX_TERMS: usize = 32;
+
+// Puntuación BM25 con la que la relevancia de texto completo vale 0.5 (ver `keyword_relevance`)
+const BM25_HALF_RELEVANCE: f32 = 5.0;
+
+/// Relevancia (0.0 - 1.0) de una puntuación BM25. BM25 no está acotado, así que se satura en vez
+/// de dividir por el mejor resultado: una coincidencia débil sigue siendo poco relevante aunque
+/// sea la única, y `min_score` significa lo mismo en todas las preguntas.
+fn keyword_relevance(bm25: f32) -> f32 {
+    let bm25 = bm25.max(0.0);
+    bm25 / (bm25 + BM25_HALF_RELEVANCE)
+}
+
+/// Consulta Lucene a partir del texto del usuario: cada palabra es un término opcional con los
+/// caracteres especiales escapados, de modo que la sintaxis de Lucene no se interpreta.
+fn fulltext_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text.split_whitespace()
+        .take(FULLTEXT_MAX_TERMS)
+        .map(|word| {
+            let mut term = String::with_capacity(word.len());
+            // En minúsculas para que AND/OR/NOT no actúen como operadores
+            for c in word.to_lowercase().chars() {
+                if "+-&|!(){}[]^\"~*?:\\/".contains(c) {
+                    term.push('\\');
+                }
+                term.push(c);
+            }
+            term
+        })
+        .collect();
+    if terms.is_empty() { None } else { Some(terms.join(" OR ")) }
+}
+
+// Entidades nuevas que se alcanzan como mucho en cada nivel (salto) de la expansión del vecindario
+const NEIGHBORHOOD_NODES_PER_LEVEL: usize = 500;
+
+// Con filtros, los índices (que no admiten predicados) devuelven `FILTER_OVERFETCH` veces más
+// candidatos para que queden suficientes tras filtrar
+const FILTER_OVERFETCH: usize = 10;
+
+/// Candidatos a pedir a los índices para conservar `top_k` tras los filtros.
+fn candidate_count(options: &RetrievalOptions) -> i64 {
+    let factor = if options.filters.is_empty() { 1 } else { FILTER_OVERFETCH };
+    (options.top_k * factor) as i64
+}
+
+/// Parámetros de `CHUNK_FILTER`; las listas vacías y las fechas nulas no filtran.
+fn with_filter_params(q: Query, filters: &RetrievalFilters) -> Query {
+    q.param("document_ids", filters.document_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>())
+        .param("categories", filters.categories.clone())
+        .param("uploaded_from", filters.uploaded_from.clone())
+        .param("uploaded_to", filters.uploaded_to.clone())
+}
+
+/// ¿Tienen los metadatos del documento (JSON serializado) todos los pares pedidos? Si el valor
+/// guardado es una lista basta con que contenga el pedido; los escalares se comparan como texto
+/// (`2025` y `"2025"` coinciden).
+fn metadata_matches(raw: Option<&str>, wanted: &serde_json::Map<String, Value>) -> bool {
+    fn scalar_text(value: &Value) -> Option<String> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+    fn same(stored: &Value, wanted: &Value) -> bool {
+        stored == wanted || matches!((scalar_text(stored), scalar_text(wanted)), (Some(a), Some(b)) if a == b)
+    }
+    if wanted.is_empty() {
+        return true;
+    }
+    let Some(Value::Object(metadata)) = raw.and_then(|r| serde_json::from_str(r).ok()) else {
+        return false;
+    };
+    wanted.iter().all(|(key, value)| match metadata.get(key) {
+        Some(Value::Array(items)) if !value.is_array() => items.iter().any(|item| same(item, value)),
+        Some(stored) => same(stored, value),
+        None => false,
+    })
+}
+
 impl Neo4jRepo {
     pub fn new(graph: Arc<Graph>) -> Self {
         Self { graph }
     }
+
+    /// Entidades a `hops` saltos o menos de `seeds`, con su distancia. Se expande en anchura, un
+    /// salto cada vez, así que los vecinos directos siempre entran antes que los lejanos. Los
+    /// caminos solo atraviesan entidades (no los chunks que las mencionan) y no usan relaciones
+    /// inferidas. Cada nivel se corta en `NEIGHBORHOOD_NODES_PER_LEVEL` entidades, las conectadas
+    /// a más entidades del nivel anterior primero, para que una muy conectada no dispare la expansión.
+    async fn reach_entities(&self, seeds: &[String], hops: usize) -> Result<HashMap<String, i64>, AppError> {
+        let mut reached: HashMap<String, i64> = seeds.iter().map(|s| (s.clone(), 0)).collect();
+        let mut frontier: Vec<String> = seeds.to_vec();
+
+        for distance in 1..=hops as i64 {
+            if frontier.is_empty() {
+                break;
+            }
+            let q = query(
+                "MATCH (s:Entity)-[r]-(n:Entity) \
+                 WHERE s.name IN $frontier AND NOT n.name IN $reached AND NOT type(r) STARTS WITH 'INFERRED_' \
+                 WITH n, count(DISTINCT s) as links \
+                 RETURN n.name as name \
+                 ORDER BY links DESC, name \
+                 LIMIT $limit"
+            )
+                .param("frontier", frontier)
+                .param("reached", reached.keys().cloned().collect::<Vec<_>>())
+                .param("limit", NEIGHBORHOOD_NODES_PER_LEVEL as i64);
+
+            let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+            frontier = Vec::new();
+            while let Ok(Some(row)) = stream.next().await {
+                let name: String = row.get("name").unwrap_or_default();
+                reached.insert(name.clone(), distance);
+                frontier.push(name);
+            }
+        }
+        Ok(reached)
+    }
+
+    /// Convierte una fila con alias `d.*` + `chunk_count` en `DocumentInfo`.
+    /// Los metadatos se guardan como JSON serializado porque Neo4j no admite mapas anidados como propiedad.
+    fn row_to_document(row: &neo4rs::Row) -> DocumentInfo {
+        let metadata_raw: String = row.get("metadata").unwrap_or_default();
+        let chunk_count: i64 = row.get("chunk_count").unwrap_or(0);
+        let failed_chunks: i64 = row.get("failed_chunks").unwrap_or(0);
+
+        DocumentInfo {
+            id: row.get("id").unwrap_or_default(),
+            filename: row.get("filename").unwrap_or_default(),
+            mime_type: row.get("mime_type").unwrap_or_default(),
+            content_hash: row.get("content_hash").unwrap_or_default(),
+            uploaded_by: row.get("uploaded_by").unwrap_or_default(),
+            uploaded_at: row.get("uploaded_at").unwrap_or_default(),
+            metadata: serde_json::from_str(&metadata_raw).unwrap_or(serde_json::Value::Null),
+            chunk_count: chunk_count.max(0) as usize,
+            failed_chunks: failed_chunks.max(0) as usize,
+        }
+    }
+
+    /// Fragmento recuperado a partir de las columnas de `HYBRID_CONTEXT_RETURN`, sin puntuar, y la puntuación del índice.
+    fn row_to_hybrid_context(row: &neo4rs::Row) -> (HybridContext, f32) {
+        let page: Option<i64> = row.get("page").unwrap_or(None);
+        let score: f64 = row.get("score").unwrap_or(0.0);
+        let ctx = HybridContext {
+            chunk_id: row.get("id").unwrap_or_else(|_| "unk".to_string()),
+            content: row.get("content").unwrap_or_default(),
+            document_id: row.get("document_id").unwrap_or(None),
+            filename: row.get("filename").unwrap_or(None),
+            page: page.map(|p| p as u32),
+            connected_entities: row.get("entities").unwrap_or_default(),
+            score: 0.0,
+            vector_score: None,
+            keyword_score: None,
+            rerank_score: None,
+            graph_score: None,
+        };
+        (ctx, score as f32)
+    }
+
+    /// Nodo de visualización a partir de las columnas `{prefix}.name`, `.category`, `.description` y `.aliases`.
+    fn row_to_vis_node(row: &neo4rs::Row, prefix: &str) -> VisNode {
+        let name: String = row.get(&format!("{}.name", prefix)).unwrap_or_else(|_| "Unknown".to_string());
+        let description: Option<String> = row.get(&format!("{}.description", prefix)).unwrap_or(None);
+        VisNode {
+            id: name.clone(),
+            label: name,
+            group: row.get(&format!("{}.category", prefix)).unwrap_or_else(|_| "Concept".to_string()),
+            description: description.filter(|d| !d.is_empty()),
+            aliases: row.get::<Option<Vec<String>>>(&format!("{}.aliases", prefix)).unwrap_or(None).unwrap_or_default(),
+        }
+    }
+
+    /// Arista de visualización con la evidencia de la relación (columnas de `RELATION_DETAILS`).
+    fn row_to_vis_edge(row: &neo4rs::Row, from: String, to: String, label: String) -> VisEdge {
+        let mention_count: Option<i64> = row.get("mention_count").unwrap_or(None);
+        let confidence: Option<f64> = row.get("confidence").unwrap_or(None);
+        let description: Option<String> = row.get("description").unwrap_or(None);
+        VisEdge {
+            from,
+            to,
+            label,
+            evidence: row.get::<Option<Vec<String>>>("evidence").unwrap_or(None).unwrap_or_default(),
+            mention_count: mention_count.unwrap_or(0).max(0) as usize,
+            first_seen: row.get("first_seen").unwrap_or(None),
+            last_seen: row.get("last_seen").unwrap_or(None),
+            confidence: confidence.map(|c| c as f32),
+            description: description.filter(|d| !d.is_empty()),
+        }
+    }
+
+    fn row_to_merge(row: &neo4rs::Row) -> EntityMergeRecord {
+        let relations_moved: i64 = row.get("relations_moved").unwrap_or(0);
+        let mentions_moved: i64 = row.get("mentions_moved").unwrap_or(0);
+
+        EntityMergeRecord {
+            id: row.get("id").unwrap_or_default(),
+            kept: row.get("kept").unwrap_or_default(),
+            merged: row.get("merged").unwrap_or_default(),
+            relations_moved: relations_moved.max(0) as usize,
+            mentions_moved: mentions_moved.max(0) as usize,
+            created_at: row.get("created_at").unwrap_or_default(),
+            undone: row.get("undone").unwrap_or(false),
+        }
+    }
+
+    /// Nombres que una fusión convirtió en alias: se redirigen a la entidad conservada para
+    /// que la ingesta posterior no vuelva a crear la entidad absorbida.
+    async fn resolve_aliases_in_txn(txn: &mut Txn, data: &mut KnowledgeExtraction) -> Result<(), AppError> {
+        let names: Vec<String> = data.entities.iter().map(|e| e.name.clone())
+            .chain(data.relations.iter().flat_map(|r| [r.source.clone(), r.target.clone()]))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        let q = query(
+            "UNWIND $names AS n \
+             MATCH (a:Entity) WHERE n IN a.aliases AND NOT EXISTS { MATCH (:Entity {name: n}) } \
+             RETURN n as alias, a.name as canonical"
+        ).param("names", names);
+        let mut stream = txn.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut canonical: HashMap<String, String> = HashMap::new();
+        while let Some(row) = stream.next(txn.handle()).await.map_err(|e| AppError::DatabaseError(e.to_string()))? {
+            if let (Ok(alias), Ok(name)) = (row.get::<String>("alias"), row.get::<String>("canonical")) {
+                canonical.insert(alias, name);
+            }
+        }
+        if canonical.is_empty() {
+            return Ok(());
+        }
+
+        let rename = |name: &mut String| {
+            if let Some(target) = canonical.get(name.as_str()) {
+                *name = target.clone();
+            }
+        };
+        data.entities.iter_mut().for_each(|e| rename(&mut e.name));
+        for rel in &mut data.relations {
+            rename(&mut rel.source);
+            rename(&mut rel.target);
+        }
+        Ok(())
+    }
+
+    /// Ejecuta una consulta dentro de la transacción y devuelve la columna `deleted` de la primera fila.
+    async fn txn_count(txn: &mut Txn, q: neo4rs::Query) -> Result<usize, AppError> {
+        let mut stream = txn.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let deleted: i64 = match stream.next(txn.handle()).await {
+            Ok(Some(row)) => row.get("deleted").unwrap_or(0),
+            Ok(None) => 0,
+            Err(e) => return Err(AppError::DatabaseError(e.to_string())),
+        };
+        Ok(deleted.max(0) as usize)
+    }
+
+    /// Borra los chunks del documento con `order >= from_order` y recolecta la basura del grafo dentro de `txn`.
+    /// Una relación extraída se considera respaldada mientras le quede evidencia (chunks que la afirman);
+    /// las anteriores a la evidencia, mientras algún chunk restante mencione a sus dos extremos.
+    /// Las relaciones inferidas por IA solo caen si desaparece alguna de sus entidades.
+    async fn purge_chunks_in_txn(txn: &mut Txn, id: Uuid, from_order: usize) -> Result<DocumentPurgeResult, AppError> {
+        // 1. Borrar chunks (DETACH elimina también HAS_CHUNK y MENTIONS) y su cuarentena,
+        //    recordando las entidades afectadas
+        let q_chunks = query(
+            "MATCH (d:Document {id: $id})-[h:HAS_CHUNK]->(c:DocumentChunk) \
+             WHERE h.order >= $from_order \
+             OPTIONAL MATCH (c)-[:MENTIONS]->(e:Entity) \
+             OPTIONAL MATCH (c)-[:QUARANTINED]->(q:QuarantinedTriple) \
+             WITH collect(DISTINCT c) as chunks, collect(DISTINCT e.name) as names, collect(DISTINCT q) as quarantined \
+             WITH chunks, names, quarantined, [c IN chunks | c.id] as ids \
+             FOREACH (q IN quarantined | DETACH DELETE q) \
+             FOREACH (c IN chunks | DETACH DELETE c) \
+             RETURN size(chunks) as deleted, names, ids"
+        ).param("id", id.to_string()).param("from_order", from_order as i64);
+
+        let mut stream = txn.execute(q_chunks).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let (chunks_deleted, names, ids): (i64, Vec<String>, Vec<String>) = match stream.next(txn.handle()).await {
+            Ok(Some(row)) => (
+                row.get("deleted").unwrap_or(0),
+                row.get("names").unwrap_or_default(),
+                row.get("ids").unwrap_or_default(),
+            ),
+            Ok(None) => (0, Vec::new(), Vec::new()),
+            Err(e) => return Err(AppError::DatabaseError(e.to_string())),
+        };
+
+        // 2. Retirar los chunks borrados de la evidencia de las relaciones afectadas
+        let q_evidence = query(
+            "MATCH (a:Entity)-[r]->(b:Entity) \
+             WHERE (a.name IN $names OR b.name IN $names) AND r.evidence IS NOT NULL \
+             WITH DISTINCT r, [x IN r.evidence WHERE NOT x IN $ids] as remaining \
+             WHERE size(remaining) < size(r.evidence) \
+             SET r.mention_count = CASE \
+                    WHEN coalesce(r.mention_count, 0) > size(r.evidence) - size(remaining) \
+                    THEN r.mention_count - (size(r.evidence) - size(remaining)) ELSE size(remaining) END, \
+                 r.evidence = remaining"
+        ).param("names", names.clone()).param("ids", ids);
+        txn.run(q_evidence).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        // 3. Relaciones extraídas entre entidades afectadas que ya nada respalda
+        let q_unsupported = query(
+            "MATCH (a:Entity)-[r]->(b:Entity) \
+             WHERE (a.name IN $names OR b.name IN $names) \
+               AND coalesce(r.is_ai_generated, false) = false \
+               AND CASE WHEN r.evidence IS NULL \
+                        THEN NOT EXISTS { MATCH (c:DocumentChunk)-[:MENTIONS]->(a) MATCH (c)-[:MENTIONS]->(b) } \
+                        ELSE size(r.evidence) = 0 END \
+             WITH DISTINCT r DELETE r \
+             RETURN count(*) as deleted"
+        ).param("names", names.clone());
+        let mut relations_deleted = Self::txn_count(txn, q_unsupported).await?;
+
+        // 4. Cualquier relación que toque una entidad huérfana
+        let q_orphan_rels = query(
+            "MATCH (e:Entity)-[r]-(:Entity) \
+             WHERE e.name IN $names AND NOT (e)<-[:MENTIONS]-(:DocumentChunk) \
+             WITH DISTINCT r DELETE r \
+             RETURN count(*) as deleted"
+        ).param("names", names.clone());
+        relations_deleted += Self::txn_count(txn, q_orphan_rels).await?;
+
+        // 5. Entidades huérfanas
+        let q_orphans = query(
+            "MATCH (e:Entity) \
+             WHERE e.name IN $names AND NOT (e)<-[:MENTIONS]-(:DocumentChunk) \
+             DETACH DELETE e \
+             RETURN count(*) as deleted"
+        ).param("names", names);
+        let entities_deleted = Self::txn_count(txn, q_orphans).await?;
+
+        Ok(DocumentPurgeResult {
+            document_id: id.to_string(),
+            chunks_deleted: chunks_deleted.max(0) as usize,
+            relations_deleted,
+            entities_deleted,
+        })
+    }
 }
 
+/// Las fechas de una relación viajan como texto en la instantánea de fusión; al recrearla vuelven a ser `datetime`.
+const RESTORE_TIMESTAMPS: &str = "r.first_seen = datetime(r.first_seen), r.last_seen = datetime(r.last_seen)";
+
+/// Suma a una relación existente `r` la evidencia de otra equivalente (`$properties`, con las
+/// fechas como texto): evidencia sin duplicados, menciones sumadas, primera y última aparición
+/// extremas, la confianza más alta y su descripción si `r` no tenía.
+const COMBINE_EVIDENCE: &str = "r.evidence = reduce(acc = coalesce(r.evidence, []), e IN coalesce($properties.evidence, []) | \
+        CASE WHEN e IN acc THEN acc ELSE acc + e END), \
+     r.mention_count = coalesce(r.mention_count, 0) + coalesce($properties.mention_count, 0), \
+     r.first_seen = CASE WHEN $properties.first_seen IS NOT NULL \
+        AND (r.first_seen IS NULL OR datetime($properties.first_seen) < r.first_seen) \
+        THEN datetime($properties.first_seen) ELSE r.first_seen END, \
+     r.last_seen = CASE WHEN $properties.last_seen IS NOT NULL \
+        AND (r.last_seen IS NULL OR datetime($properties.last_seen) > r.last_seen) \
+        THEN datetime($properties.last_seen) ELSE r.last_seen END, \
+     r.confidence = CASE WHEN $properties.confidence IS NOT NULL \
+        AND (r.confidence IS NULL OR $properties.confidence > r.confidence) \
+        THEN $properties.confidence ELSE r.confidence END, \
+     r.description = CASE WHEN coalesce(r.description, '') = '' THEN $properties.description ELSE r.description END";
+
+/// Propiedades de evidencia de una relación `r`, en el formato que lee `row_to_vis_edge`.
+const RELATION_DETAILS: &str = "r.evidence as evidence, r.mention_count as mention_count, \
+     toString(r.first_seen) as first_seen, toString(r.last_seen) as last_seen, \
+     r.confidence as confidence, r.description as description";
+
+/// Final común de las búsquedas de fragmentos: parte de `chunk` y `score` y devuelve las columnas
+/// que lee `row_to_hybrid_context`. Las entidades son opcionales: un chunk cuya extracción falló o
+/// no dio entidades sigue siendo fuente.
+const HYBRID_CONTEXT_RETURN: &str = "OPTIONAL MATCH (chunk)-[:MENTIONS]->(e:Entity) \
+     OPTIONAL MATCH (d:Document)-[:HAS_CHUNK]->(chunk) \
+     WITH chunk, score, d, collect(DISTINCT e.name) as entities \
+     RETURN chunk.id as id, chunk.content as content, chunk.page as page, \
+            d.id as document_id, d.filename as filename, d.metadata as metadata, entities, score \
+     ORDER BY score DESC";
+
+/// Filtros de `RetrievalFilters` que se evalúan en Cypher sobre `chunk` y `score` (los metadatos
+/// se guardan serializados y se comprueban al leer las filas, con `metadata_matches`).
+const CHUNK_FILTER: &str = "OPTIONAL MATCH (fd:Document)-[:HAS_CHUNK]->(chunk) \
+     WITH chunk, score, fd \
+     WHERE (size($document_ids) = 0 OR fd.id IN $document_ids) \
+       AND (size($categories) = 0 OR EXISTS { MATCH (chunk)-[:MENTIONS]->(fe:Entity) WHERE fe.category IN $categories }) \
+       AND ($uploaded_from IS NULL OR date(fd.uploaded_at) >= date($uploaded_from)) \
+       AND ($uploaded_to IS NULL OR date(fd.uploaded_at) <= date($uploaded_to)) \
+     WITH chunk, score";
+
+const DOCUMENT_RETURN: &str = "RETURN d.id as id, d.filename as filename, d.mime_type as mime_type, \
+     d.content_hash as content_hash, d.uploaded_by as uploaded_by, toString(d.uploaded_at) as uploaded_at, \
+     d.metadata as metadata, coalesce(d.failed_chunks, 0) as failed_chunks, chunk_count";
+
 #[async_trait]
 impl KGRepository for Neo4jRepo {
     async fn create_indexes(&self, dim: usize) -> Result<(), AppError> {
@@ -29,8 +471,42 @@ impl KGRepository for Neo4jRepo {
         );
         self.graph.run(query(&q)).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
         
+        let q = format!(
+            "CREATE VECTOR INDEX entity_embeddings IF NOT EXISTS FOR (e:Entity) ON (e.embedding) \
+             OPTIONS {{indexConfig: {{ `vector.dimensions`: {}, `vector.similarity_function`: 'cosine' }} }}",
+            dim
+        );
+        self.graph.run(query(&q)).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        // Texto completo (BM25) para identificadores y nombres poco frecuentes que se embeben mal
+        self.graph.run(query("CREATE FULLTEXT INDEX chunk_fulltext IF NOT EXISTS FOR (c:DocumentChunk) ON EACH [c.content]")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        self.graph.run(query("CREATE FULLTEXT INDEX entity_fulltext IF NOT EXISTS FOR (e:Entity) ON EACH [e.name]")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
         self.graph.run(query("CREATE CONSTRAINT entity_name IF NOT EXISTS FOR (e:Entity) REQUIRE e.name IS UNIQUE")).await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        self.graph.run(query("CREATE CONSTRAINT document_id IF NOT EXISTS FOR (d:Document) REQUIRE d.id IS UNIQUE")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        self.graph.run(query("CREATE CONSTRAINT ingestion_job_id IF NOT EXISTS FOR (j:IngestionJob) REQUIRE j.id IS UNIQUE")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        self.graph.run(query("CREATE CONSTRAINT conversation_id IF NOT EXISTS FOR (c:Conversation) REQUIRE c.id IS UNIQUE")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        self.graph.run(query("CREATE CONSTRAINT community_id IF NOT EXISTS FOR (c:Community) REQUIRE c.id IS UNIQUE")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        self.graph.run(query("CREATE CONSTRAINT entity_merge_id IF NOT EXISTS FOR (m:EntityMerge) REQUIRE m.id IS UNIQUE")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        // Índices de deduplicación por hash de contenido
+        self.graph.run(query("CREATE INDEX document_hash IF NOT EXISTS FOR (d:Document) ON (d.content_hash)")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        self.graph.run(query("CREATE INDEX chunk_hash IF NOT EXISTS FOR (c:DocumentChunk) ON (c.content_hash)")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
             
         Ok(())
     }
@@ -41,35 +517,153 @@ impl KGRepository for Neo4jRepo {
         Ok(())
     }
 
-    async fn save_chunk(&self, id: Uuid, content: &str, embedding: Vec<f32>) -> Result<(), AppError> {
-        let q = query("CREATE (c:DocumentChunk {id: $id, content: $content, embedding: $embedding})")
-            .param("id", id.to_string())
-            .param("content", content)
-            .param("embedding", embedding);
+    async fn save_document(&self, doc: &NewDocument) -> Result<(), AppError> {
+        let q = query(
+            "MERGE (d:Document {id: $id}) \
+             ON CREATE SET d.uploaded_at = datetime() \
+             ON MATCH SET d.updated_at = datetime() \
+             SET d.filename = $filename, d.mime_type = $mime_type, d.content_hash = $content_hash, \
+                 d.uploaded_by = $uploaded_by, d.metadata = $metadata"
+        )
+            .param("id", doc.id.to_string())
+            .param("filename", doc.filename.as_str())
+            .param("mime_type", doc.mime_type.as_str())
+            .param("content_hash", doc.content_hash.as_str())
+            .param("uploaded_by", doc.uploaded_by.as_str())
+            .param("metadata", doc.metadata.to_string());
+
+        self.graph.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn save_chunk(&self, chunk: &ChunkRecord, embedding: Vec<f32>) -> Result<(), AppError> {
+        let q = query(
+            "MATCH (d:Document {id: $doc_id}) \
+             CREATE (c:DocumentChunk {id: $id, content: $content, embedding: $embedding, page: $page, content_hash: $hash, \
+                                      extraction_failed: $failed}) \
+             CREATE (d)-[:HAS_CHUNK {order: $order}]->(c)"
+        )
+            .param("doc_id", chunk.document_id.to_string())
+            .param("id", chunk.id.to_string())
+            .param("content", chunk.content.as_str())
+            .param("embedding", embedding)
+            .param("failed", chunk.extraction_failed)
+            .param("page", chunk.page.map(|p| p as i64))
+            .param("hash", chunk.content_hash.as_str())
+            .param("order", chunk.order as i64);
         
         self.graph.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
         Ok(())
     }
 
-    async fn save_graph(&self, chunk_id: Uuid, data: KnowledgeExtraction) -> Result<(), AppError> {
+    async fn reuse_chunk_by_hash(&self, chunk: &ChunkRecord) -> Result<bool, AppError> {
+        let q = query(
+            "MATCH (src:DocumentChunk {content_hash: $hash}) \
+             WHERE src.embedding IS NOT NULL AND NOT coalesce(src.extraction_failed, false) \
+             WITH src LIMIT 1 \
+             MATCH (d:Document {id: $doc_id}) \
+             CREATE (c:DocumentChunk {id: $id, content: $content, embedding: src.embedding, page: $page, content_hash: $hash}) \
+             CREATE (d)-[:HAS_CHUNK {order: $order}]->(c) \
+             WITH src, c \
+             OPTIONAL MATCH (src)-[:MENTIONS]->(e:Entity) \
+             FOREACH (x IN CASE WHEN e IS NULL THEN [] ELSE [e] END | MERGE (c)-[:MENTIONS]->(x)) \
+             WITH DISTINCT src, c \
+             OPTIONAL MATCH (src)-[:MENTIONS]->(:Entity)-[r]->(:Entity)<-[:MENTIONS]-(src) \
+             WHERE src.id IN coalesce(r.evidence, []) \
+             WITH c, collect(DISTINCT r) as supported \
+             FOREACH (x IN supported | SET x.evidence = x.evidence + c.id, x.mention_count = coalesce(x.mention_count, 0) + 1, \
+                                          x.last_seen = datetime()) \
+             RETURN count(DISTINCT c) as reused"
+        )
+            .param("hash", chunk.content_hash.as_str())
+            .param("doc_id", chunk.document_id.to_string())
+            .param("id", chunk.id.to_string())
+            .param("content", chunk.content.as_str())
+            .param("page", chunk.page.map(|p| p as i64))
+            .param("order", chunk.order as i64);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let reused: i64 = match stream.next().await {
+            Ok(Some(row)) => row.get("reused").unwrap_or(0),
+            Ok(None) => 0,
+            Err(e) => return Err(AppError::DatabaseError(e.to_string())),
+        };
+        Ok(reused > 0)
+    }
+
+    async fn has_reusable_chunk(&self, content_hash: &str) -> Result<bool, AppError> {
+        let q = query(
+            "MATCH (src:DocumentChunk {content_hash: $hash}) \
+             WHERE src.embedding IS NOT NULL AND NOT coalesce(src.extraction_failed, false) \
+             RETURN count(src) > 0 as reusable"
+        ).param("hash", content_hash);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        match stream.next().await {
+            Ok(Some(row)) => Ok(row.get("reusable").unwrap_or(false)),
+            Ok(None) => Ok(false),
+            Err(e) => Err(AppError::DatabaseError(e.to_string())),
+        }
+    }
+
+    async fn save_graph(&self, chunk_id: Uuid, mut data: KnowledgeExtraction, quarantined: Vec<QuarantinedTriple>) -> Result<usize, AppError> {
         let mut txn = self.graph.start_txn().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut created = 0;
 
+        Self::resolve_aliases_in_txn(&mut txn, &mut data).await?;
+
+        // Las descripciones y alias se acumulan entre chunks; si la descripción crece (o la
+        // entidad es nueva) su embedding queda pendiente de recalcular
         for entity in &data.entities {
-            let q = query("MERGE (e:Entity {name: $name}) ON CREATE SET e.category = $category")
+            let q = query(
+                "OPTIONAL MATCH (x:Entity {name: $name}) \
+                 WITH x IS NULL as is_new \
+                 MERGE (e:Entity {name: $name}) ON CREATE SET e.category = $category \
+                 WITH e, is_new, $description <> '' AND NOT coalesce(e.description, '') CONTAINS $description as grows \
+                 SET e.aliases = coalesce(e.aliases, []) + [a IN $aliases WHERE NOT a IN coalesce(e.aliases, []) AND a <> e.name], \
+                     e.description = CASE \
+                        WHEN NOT grows THEN e.description \
+                        WHEN coalesce(e.description, '') = '' THEN $description \
+                        ELSE e.description + '\\n' + $description END, \
+                     e.embedding_stale = is_new OR grows OR coalesce(e.embedding_stale, false) \
+                 RETURN is_new"
+            )
                 .param("name", entity.name.as_str())
-                .param("category", entity.category.as_str());
-            txn.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+                .param("category", entity.category.as_str())
+                .param("description", entity.description.as_str())
+                .param("aliases", entity.aliases.clone());
+            let mut stream = txn.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+            if let Ok(Some(row)) = stream.next(txn.handle()).await {
+                if row.get::<bool>("is_new").unwrap_or(false) {
+                    created += 1;
+                }
+            }
         }
 
+        // Cada relación acumula su evidencia: chunks que la afirman, número de menciones,
+        // primera/última vez vista, la mayor confianza reportada y sus descripciones
         for rel in data.relations {
             let cypher = format!(
                 "MATCH (a:Entity {{name: $source}}), (b:Entity {{name: $target}}) \
-                 MERGE (a)-[:{}]->(b)", 
-                rel.relation_type.replace(" ", "_").to_uppercase() 
+                 MERGE (a)-[r:{}]->(b) \
+                 ON CREATE SET r.first_seen = datetime(), r.evidence = [], r.mention_count = 0 \
+                 WITH r, $description <> '' AND NOT coalesce(r.description, '') CONTAINS $description as grows \
+                 SET r.evidence = CASE WHEN $cid IN coalesce(r.evidence, []) THEN r.evidence ELSE coalesce(r.evidence, []) + $cid END, \
+                     r.mention_count = coalesce(r.mention_count, 0) + 1, \
+                     r.last_seen = datetime(), \
+                     r.confidence = CASE WHEN r.confidence IS NULL OR $confidence > r.confidence THEN $confidence ELSE r.confidence END, \
+                     r.description = CASE \
+                        WHEN NOT grows THEN r.description \
+                        WHEN coalesce(r.description, '') = '' THEN $description \
+                        ELSE r.description + '\\n' + $description END", 
+                new_rel_type_ident(&rel.relation_type)?
             );
             let q = query(&cypher)
                 .param("source", rel.source.as_str())
-                .param("target", rel.target.as_str());
+                .param("target", rel.target.as_str())
+                .param("cid", chunk_id.to_string())
+                .param("description", rel.description.as_str())
+                .param("confidence", rel.confidence.map(f64::from));
             txn.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
         }
 
@@ -81,16 +675,36 @@ impl KGRepository for Neo4jRepo {
         txn.run(q_link.param("cid", chunk_id.to_string()).param("names", names)).await
             .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
+        // Cuarentena: fuera del grafo de entidades, colgando del chunk para que la purga la recoja
+        for item in quarantined {
+            let q = query(
+                "MATCH (c:DocumentChunk {id: $cid}) \
+                 CREATE (c)-[:QUARANTINED]->(:QuarantinedTriple { \
+                    source: $source, source_category: $source_category, relation_type: $relation_type, \
+                    target: $target, target_category: $target_category, reason: $reason, created_at: datetime() \
+                 })"
+            )
+                .param("cid", chunk_id.to_string())
+                .param("source", item.source)
+                .param("source_category", item.source_category)
+                .param("relation_type", item.relation_type)
+                .param("target", item.target)
+                .param("target_category", item.target_category)
+                .param("reason", item.reason);
+            txn.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        }
+
         txn.commit().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
-        Ok(())
+        Ok(created)
     }
 
     async fn get_full_graph(&self) -> Result<GraphDataResponse, AppError> {
-        let q = query(
+        let q = query(&format!(
             "MATCH (n:Entity)-[r]->(m:Entity) \
-             RETURN n.name, n.category, type(r), m.name, m.category \
-             LIMIT 1000"
-        );
+             RETURN n.name, n.category, n.description, n.aliases, type(r), m.name, m.category, m.description, m.aliases, {} \
+             LIMIT 1000",
+            RELATION_DETAILS
+        ));
         
         let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
@@ -99,62 +713,239 @@ impl KGRepository for Neo4jRepo {
         let mut unique_nodes = HashSet::new(); 
 
         while let Ok(Some(row)) = stream.next().await {
-            let n_name: String = row.get("n.name").unwrap_or_else(|_| "Unknown".to_string());
-            let n_cat: String = row.get("n.category").unwrap_or_else(|_| "Concept".to_string());
+            let n = Self::row_to_vis_node(&row, "n");
+            let m = Self::row_to_vis_node(&row, "m");
             let r_type: String = row.get("type(r)").unwrap_or_else(|_| "RELATED".to_string());
-            let m_name: String = row.get("m.name").unwrap_or_else(|_| "Unknown".to_string());
-            let m_cat: String = row.get("m.category").unwrap_or_else(|_| "Concept".to_string());
+            let (n_name, m_name) = (n.id.clone(), m.id.clone());
 
             if unique_nodes.insert(n_name.clone()) {
-                nodes_vec.push(VisNode { id: n_name.clone(), label: n_name.clone(), group: n_cat });
+                nodes_vec.push(n);
             }
             if unique_nodes.insert(m_name.clone()) {
-                nodes_vec.push(VisNode { id: m_name.clone(), label: m_name.clone(), group: m_cat });
+                nodes_vec.push(m);
             }
 
-            edges_vec.push(VisEdge { from: n_name, to: m_name, label: r_type });
+            edges_vec.push(Self::row_to_vis_edge(&row, n_name, m_name, r_type));
         }
 
         Ok(GraphDataResponse { nodes: nodes_vec, edges: edges_vec })
     }
 
-    async fn find_hybrid_context(&self, embedding: Vec<f32>, limit: usize) -> Result<Vec<HybridContext>, AppError> {
-        let q_str = format!(
-            "CALL db.index.vector.queryNodes('chunk_embeddings', {}, $embedding) \
-             YIELD node as chunk, score \
-             MATCH (chunk)-[:MENTIONS]->(e:Entity) \
-             RETURN chunk.id as id, chunk.content as content, collect(DISTINCT e.name) as entities", 
-            limit
-        );
-
-        let q = query(&q_str).param("embedding", embedding);
+    async fn find_hybrid_context(&self, embedding: Vec<f32>, options: &RetrievalOptions) -> Result<Vec<HybridContext>, AppError> {
+        let candidates = if options.filters.document_ids.is_empty() {
+            "CALL db.index.vector.queryNodes('chunk_embeddings', $candidates, $embedding) \
+             YIELD node as chunk, score"
+        } else {
+            // Acotada a documentos: similitud exacta sobre sus fragmentos (prefiltrado), sin el índice.
+            // Misma escala que el índice: coseno normalizado a 0.0 - 1.0
+            "MATCH (sd:Document)-[:HAS_CHUNK]->(chunk:DocumentChunk) \
+             WHERE sd.id IN $document_ids AND chunk.embedding IS NOT NULL \
+             WITH chunk, vector.similarity.cosine(chunk.embedding, $embedding) as score"
+        };
+        let q = query(&format!(
+            "{} \
+             WHERE score >= $min_score \
+             {} ORDER BY score DESC LIMIT $candidates \
+             {}",
+            candidates, CHUNK_FILTER, HYBRID_CONTEXT_RETURN
+        ))
+        .param("candidates", candidate_count(options))
+        .param("min_score", options.min_score as f64)
+        .param("embedding", embedding);
+        let q = with_filter_params(q, &options.filters);
         let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
         let mut results = Vec::new();
         while let Ok(Some(row)) = stream.next().await {
-            let id: String = row.get("id").unwrap_or_else(|_| "unk".to_string());
-            let content: String = row.get("content").unwrap_or_default();
-            let entities: Vec<String> = row.get("entities").unwrap_or_default();
-
-            results.push(HybridContext {
-                chunk_id: id,
-                content,
-                connected_entities: entities,
-            });
+            let metadata: Option<String> = row.get("metadata").unwrap_or(None);
+            if !metadata_matches(metadata.as_deref(), &options.filters.metadata) {
+                continue;
+            }
+            let (mut ctx, score) = Self::row_to_hybrid_context(&row);
+            ctx.score = score;
+            ctx.vector_score = Some(score);
+            results.push(ctx);
         }
+        results.truncate(options.top_k);
         
         Ok(results)
     }
+
+    async fn find_keyword_context(&self, text: &str, options: &RetrievalOptions) -> Result<Vec<HybridContext>, AppError> {
+        let Some(search) = fulltext_query(text) else {
+            return Ok(Vec::new());
+        };
+        // Un chunk puntúa por su propio texto o por el nombre de una entidad que menciona (lo mejor de ambos)
+        let q = query(&format!(
+            "CALL {{ \
+                 CALL db.index.fulltext.queryNodes('chunk_fulltext', $search, {{limit: $candidates}}) YIELD node, score \
+                 RETURN node as chunk, score \
+                 UNION ALL \
+                 CALL db.index.fulltext.queryNodes('entity_fulltext', $search, {{limit: $candidates}}) YIELD node, score \
+                 MATCH (chunk:DocumentChunk)-[:MENTIONS]->(node) \
+                 RETURN chunk, score \
+             }} \
+             WITH chunk, max(score) as score \
+             {} ORDER BY score DESC LIMIT $candidates \
+             {}",
+            CHUNK_FILTER, HYBRID_CONTEXT_RETURN
+        ))
+        .param("search", search)
+        .param("candidates", candidate_count(options));
+        let q = with_filter_params(q, &options.filters);
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        let mut results = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let metadata: Option<String> = row.get("metadata").unwrap_or(None);
+            if !metadata_matches(metadata.as_deref(), &options.filters.metadata) {
+                continue;
+            }
+            let (mut ctx, score) = Self::row_to_hybrid_context(&row);
+            ctx.score = keyword_relevance(score);
+            if ctx.score < options.min_score {
+                // Ordenados por puntuación: el resto tampoco llega
+                break;
+            }
+            ctx.keyword_score = Some(score);
+            results.push(ctx);
+        }
+        results.truncate(options.top_k);
+        Ok(results)
+    }
     
+    async fn find_similar_entities(&self, embedding: Vec<f32>, limit: usize) -> Result<Vec<RelevantEntity>, AppError> {
+        let q = query(
+            "CALL db.index.vector.queryNodes('entity_embeddings', $limit, $embedding) \
+             YIELD node as e, score \
+             RETURN e.name as name, coalesce(e.category, 'Concept') as category, e.description as description, score \
+             ORDER BY score DESC"
+        ).param("limit", limit as i64).param("embedding", embedding);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut entities = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let description: Option<String> = row.get("description").unwrap_or(None);
+            let score: f64 = row.get("score").unwrap_or(0.0);
+            entities.push(RelevantEntity {
+                name: row.get("name").unwrap_or_default(),
+                category: row.get("category").unwrap_or_default(),
+                description: description.filter(|d| !d.is_empty()),
+                score: score as f32,
+            });
+        }
+        Ok(entities)
+    }
+
+    async fn get_chunks(&self, ids: &[String]) -> Result<Vec<HybridContext>, AppError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let q = query(&format!(
+            "MATCH (chunk:DocumentChunk) WHERE chunk.id IN $ids \
+             WITH chunk, 0.0 as score \
+             {}",
+            HYBRID_CONTEXT_RETURN
+        )).param("ids", ids.to_vec());
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut chunks = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            chunks.push(Self::row_to_hybrid_context(&row).0);
+        }
+        Ok(chunks)
+    }
+
+    async fn find_graph_neighborhood(&self, seeds: &[String], hops: usize, limit: usize, filters: &RetrievalFilters) -> Result<Vec<GraphFact>, AppError> {
+        if seeds.is_empty() || hops == 0 {
+            return Ok(Vec::new());
+        }
+        // Con filtros, cada relación conserva solo la evidencia que los cumple (los metadatos se
+        // comprueban aquí) y se descarta si no le queda ninguna
+        let (scope, candidates) = if filters.is_empty() {
+            ("WITH a, r, b, a_distance, b_distance, [] as scoped_ids, [] as scoped_metadata".to_string(), limit)
+        } else {
+            let scope = format!(
+                "CALL {{ \
+                     WITH r \
+                     UNWIND coalesce(r.evidence, []) as chunk_id \
+                     MATCH (chunk:DocumentChunk {{id: chunk_id}}) \
+                     WITH chunk, 1.0 as score \
+                     {} \
+                     OPTIONAL MATCH (d:Document)-[:HAS_CHUNK]->(chunk) \
+                     RETURN collect(chunk.id) as scoped_ids, collect(coalesce(d.metadata, '')) as scoped_metadata \
+                 }} \
+                 WITH a, r, b, a_distance, b_distance, scoped_ids, scoped_metadata \
+                 WHERE size(scoped_ids) > 0",
+                CHUNK_FILTER
+            );
+            (scope, limit * FILTER_OVERFETCH)
+        };
+        // Relaciones entre las entidades alcanzadas, las más cercanas a las semillas y más mencionadas primero
+        let distances = self.reach_entities(seeds, hops).await?;
+        let names: Vec<String> = distances.keys().cloned().collect();
+        let q = query(&format!(
+            "MATCH (a:Entity)-[r]->(b:Entity) \
+             WHERE a.name IN $names AND b.name IN $names \
+             WITH a, r, b, $distances[a.name] as a_distance, $distances[b.name] as b_distance \
+             {} \
+             RETURN a.name as source, type(r) as relation, b.name as target, r.description as description, \
+                    coalesce(r.mention_count, 1) as mention_count, r.confidence as confidence, \
+                    coalesce(r.evidence, []) as evidence, scoped_ids, scoped_metadata, \
+                    CASE WHEN b_distance < a_distance THEN b_distance ELSE a_distance END as distance \
+             ORDER BY distance, mention_count DESC \
+             LIMIT $limit",
+            scope
+        ))
+        .param("names", names)
+        .param("distances", distances)
+        .param("limit", candidates as i64);
+        let q = with_filter_params(q, filters);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut facts = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let mut evidence: Vec<String> = row.get("evidence").unwrap_or_default();
+            if !filters.is_empty() {
+                let ids: Vec<String> = row.get("scoped_ids").unwrap_or_default();
+                let metadata: Vec<String> = row.get("scoped_metadata").unwrap_or_default();
+                evidence = ids.into_iter()
+                    .zip(metadata)
+                    .filter(|(_, m)| metadata_matches(Some(m), &filters.metadata))
+                    .map(|(id, _)| id)
+                    .collect();
+                if evidence.is_empty() {
+                    continue;
+                }
+            }
+            let description: Option<String> = row.get("description").unwrap_or(None);
+            let confidence: Option<f64> = row.get("confidence").unwrap_or(None);
+            facts.push(GraphFact {
+                source: row.get("source").unwrap_or_default(),
+                relation: row.get("relation").unwrap_or_default(),
+                target: row.get("target").unwrap_or_default(),
+                description: description.filter(|d| !d.is_empty()),
+                mention_count: row.get("mention_count").unwrap_or(1),
+                confidence: confidence.map(|c| c as f32),
+                evidence,
+                score: 0.0,
+            });
+        }
+        facts.truncate(limit);
+        Ok(facts)
+    }
+
     // --- IMPLEMENTACIÓN: VECINDARIO DE CONCEPTO (Deep Dive) ---
 
     async fn get_concept_neighborhood(&self, concept_name: &str) -> Result<GraphDataResponse, AppError> {
         // Busca el nodo central y todas las relaciones (entrantes o salientes) directas
-        let q = query(
-            "MATCH (center:Entity {name: $name})-[r]-(neighbor:Entity)
-             RETURN center.name, center.category, type(r) as rel, startNode(r) = center as is_source, neighbor.name, neighbor.category
-             LIMIT 100"
-        ).param("name", concept_name);
+        let q = query(&format!(
+            "MATCH (center:Entity {{name: $name}})-[r]-(neighbor:Entity)
+             RETURN center.name, center.category, center.description, center.aliases, type(r) as rel,
+                    startNode(r) = center as is_source, neighbor.name, neighbor.category, neighbor.description, neighbor.aliases, {}
+             LIMIT 100",
+            RELATION_DETAILS
+        )).param("name", concept_name);
 
         let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
@@ -167,21 +958,20 @@ impl KGRepository for Neo4jRepo {
         while let Ok(Some(row)) = stream.next().await {
             relations_found = true;
             
-            let c_name: String = row.get("center.name").unwrap_or_default();
-            let c_cat: String = row.get("center.category").unwrap_or_else(|_| "Concept".to_string());
+            let center = Self::row_to_vis_node(&row, "center");
+            let neighbor = Self::row_to_vis_node(&row, "neighbor");
             let rel_type: String = row.get("rel").unwrap_or_default();
             let is_source: bool = row.get("is_source").unwrap_or(true);
-            let n_name: String = row.get("neighbor.name").unwrap_or_default();
-            let n_cat: String = row.get("neighbor.category").unwrap_or_else(|_| "Concept".to_string());
+            let (c_name, n_name) = (center.id.clone(), neighbor.id.clone());
 
             // Añadir/Actualizar nodo central
             if unique_nodes.insert(c_name.clone()) {
-                 nodes_vec.push(VisNode { id: c_name.clone(), label: c_name.clone(), group: c_cat });
+                 nodes_vec.push(center);
             }
 
             // Añadir nodo vecino
             if unique_nodes.insert(n_name.clone()) {
-                nodes_vec.push(VisNode { id: n_name.clone(), label: n_name.clone(), group: n_cat });
+                nodes_vec.push(neighbor);
             }
 
             // Definir dirección
@@ -191,18 +981,17 @@ impl KGRepository for Neo4jRepo {
                 (n_name.clone(), c_name.clone())
             };
 
-            edges_vec.push(VisEdge { from, to, label: rel_type });
+            edges_vec.push(Self::row_to_vis_edge(&row, from, to, rel_type));
         }
         
         // Fallback: Si no hay relaciones, al menos devolvemos el nodo central
         if !relations_found {
-             let q_fallback = query("MATCH (center:Entity {name: $name}) RETURN center.name, center.category")
-                .param("name", concept_name);
+             let q_fallback = query(
+                "MATCH (center:Entity {name: $name}) RETURN center.name, center.category, center.description, center.aliases"
+             ).param("name", concept_name);
              let mut stream_fallback = self.graph.execute(q_fallback).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
              if let Ok(Some(row)) = stream_fallback.next().await {
-                let name: String = row.get("center.name").unwrap_or_default();
-                let cat: String = row.get("center.category").unwrap_or_else(|_| "Concept".to_string());
-                nodes_vec.push(VisNode { id: name.clone(), label: name, group: cat });
+                nodes_vec.push(Self::row_to_vis_node(&row, "center"));
              }
         }
 
@@ -213,54 +1002,1420 @@ impl KGRepository for Neo4jRepo {
         Ok(GraphDataResponse { nodes: nodes_vec, edges: edges_vec })
     }
     
-    // --- MÉTODOS DE RAZONAMIENTO (EXISTENTES) ---
+    // --- DOCUMENTOS Y PROCEDENCIA ---
 
-    async fn get_graph_context_for_reasoning(&self, limit: usize) -> Result<String, AppError> {
-        // Obtenemos las relaciones más "densas" para dar contexto
-        let q = query(
-            "MATCH (n:Entity)-[r]->(m:Entity) 
-             WITH n, r, m, count(n) as degree 
-             ORDER BY degree DESC 
-             LIMIT $limit 
-             RETURN n.name, type(r), m.name"
-        ).param("limit", limit as i64);
+    async fn list_documents(&self) -> Result<Vec<DocumentInfo>, AppError> {
+        let q = query(&format!(
+            "MATCH (d:Document) \
+             OPTIONAL MATCH (d)-[:HAS_CHUNK]->(c:DocumentChunk) \
+             WITH d, count(c) as chunk_count \
+             {} ORDER BY uploaded_at DESC",
+            DOCUMENT_RETURN
+        ));
 
         let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
-        let mut context = String::new();
-
+        let mut documents = Vec::new();
         while let Ok(Some(row)) = stream.next().await {
-            let n: String = row.get("n.name").unwrap_or_default();
-            let r: String = row.get("type(r)").unwrap_or_default();
-            let m: String = row.get("m.name").unwrap_or_default();
-            context.push_str(&format!("({}) -[{}]-> ({})\n", n, r, m));
+            documents.push(Self::row_to_document(&row));
         }
+        Ok(documents)
+    }
 
-        if context.is_empty() {
-            return Ok("El grafo está vacío.".to_string());
+    async fn get_document(&self, id: Uuid) -> Result<Option<DocumentInfo>, AppError> {
+        let q = query(&format!(
+            "MATCH (d:Document {{id: $id}}) \
+             OPTIONAL MATCH (d)-[:HAS_CHUNK]->(c:DocumentChunk) \
+             WITH d, count(c) as chunk_count \
+             {}",
+            DOCUMENT_RETURN
+        )).param("id", id.to_string());
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        match stream.next().await {
+            Ok(Some(row)) => Ok(Some(Self::row_to_document(&row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(AppError::DatabaseError(e.to_string())),
         }
-        Ok(context)
     }
 
-    async fn save_inferred_relations(&self, relations: Vec<InferredRelation>) -> Result<(), AppError> {
-        let mut txn = self.graph.start_txn().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+    async fn find_document_by_hash(&self, content_hash: &str) -> Result<Option<DocumentInfo>, AppError> {
+        let q = query(&format!(
+            "MATCH (d:Document {{content_hash: $hash}}) \
+             OPTIONAL MATCH (d)-[:HAS_CHUNK]->(c:DocumentChunk) \
+             WITH d, count(c) as chunk_count \
+             {} ORDER BY uploaded_at LIMIT 1",
+            DOCUMENT_RETURN
+        )).param("hash", content_hash);
 
-        for rel in relations {
-            let cypher = format!(
-                "MATCH (a:Entity {{name: $source}}), (b:Entity {{name: $target}}) \
-                 MERGE (a)-[r:INFERRED_{}]->(b) \
-                 ON CREATE SET r.reasoning = $reasoning, r.is_ai_generated = true",
-                rel.relation.replace(" ", "_").to_uppercase()
-            );
-            
-            let q = query(&cypher)
-                .param("source", rel.source)
-                .param("target", rel.target)
-                .param("reasoning", rel.reasoning);
-                
-            txn.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        match stream.next().await {
+            Ok(Some(row)) => Ok(Some(Self::row_to_document(&row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(AppError::DatabaseError(e.to_string())),
         }
+    }
+
+    async fn get_document_chunks(&self, id: Uuid) -> Result<Vec<DocumentChunkInfo>, AppError> {
+        let q = query(
+            "MATCH (d:Document {id: $id})-[h:HAS_CHUNK]->(c:DocumentChunk) \
+             OPTIONAL MATCH (c)-[:MENTIONS]->(e:Entity) \
+             RETURN c.id as id, h.order as order, c.page as page, c.content as content, \
+                    collect(DISTINCT e.name) as entities \
+             ORDER BY order"
+        ).param("id", id.to_string());
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut chunks = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let content: String = row.get("content").unwrap_or_default();
+            let order: i64 = row.get("order").unwrap_or(0);
+            let page: Option<i64> = row.get("page").unwrap_or(None);
+
+            chunks.push(DocumentChunkInfo {
+                id: row.get("id").unwrap_or_default(),
+                order: order.max(0) as usize,
+                page: page.map(|p| p as u32),
+                short_content: content.chars().take(200).collect(),
+                entities: row.get("entities").unwrap_or_default(),
+            });
+        }
+        Ok(chunks)
+    }
+
+    async fn purge_document_chunks(&self, id: Uuid, from_order: usize) -> Result<DocumentPurgeResult, AppError> {
+        let mut txn = self.graph.start_txn().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let result = Self::purge_chunks_in_txn(&mut txn, id, from_order).await?;
+        txn.commit().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(result)
+    }
+
+    async fn set_document_failed_chunks(&self, id: Uuid, failed_chunks: usize) -> Result<(), AppError> {
+        let q = query("MATCH (d:Document {id: $id}) SET d.failed_chunks = $failed")
+            .param("id", id.to_string())
+            .param("failed", failed_chunks as i64);
+        self.graph.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))
+    }
+
+    async fn delete_document(&self, id: Uuid) -> Result<DocumentPurgeResult, AppError> {
+        let mut txn = self.graph.start_txn().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let result = Self::purge_chunks_in_txn(&mut txn, id, 0).await?;
+
+        txn.run(query("MATCH (d:Document {id: $id}) DETACH DELETE d").param("id", id.to_string())).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
 
         txn.commit().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(result)
+    }
+
+    // --- MÉTODOS DE RAZONAMIENTO (EXISTENTES) ---
+
+    async fn load_ontology(&self) -> Result<Option<Ontology>, AppError> {
+        let q = query("MATCH (o:Ontology {id: 'active'}) RETURN o.definition as definition");
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        match stream.next().await {
+            Ok(Some(row)) => {
+                let definition: String = row.get("definition").unwrap_or_default();
+                serde_json::from_str(&definition)
+                    .map(Some)
+                    .map_err(|e| AppError::ParseError(format!("Corrupt stored ontology: {}", e)))
+            },
+            Ok(None) => Ok(None),
+            Err(e) => Err(AppError::DatabaseError(e.to_string())),
+        }
+    }
+
+    async fn save_ontology(&self, ontology: &Ontology) -> Result<(), AppError> {
+        let definition = serde_json::to_string(ontology).map_err(|e| AppError::ParseError(e.to_string()))?;
+        let q = query("MERGE (o:Ontology {id: 'active'}) SET o.definition = $definition, o.updated_at = datetime()")
+            .param("definition", definition);
+        self.graph.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn list_quarantined_triples(&self, limit: usize) -> Result<Vec<QuarantinedTriple>, AppError> {
+        let q = query(
+            "MATCH (c:DocumentChunk)-[:QUARANTINED]->(q:QuarantinedTriple) \
+             RETURN q.source as source, q.source_category as source_category, q.relation_type as relation_type, \
+                    q.target as target, q.target_category as target_category, q.reason as reason, c.id as chunk_id \
+             ORDER BY q.created_at DESC LIMIT $limit"
+        ).param("limit", limit as i64);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut triples = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            triples.push(QuarantinedTriple {
+                source: row.get("source").unwrap_or_default(),
+                source_category: row.get("source_category").unwrap_or_default(),
+                relation_type: row.get("relation_type").unwrap_or_default(),
+                target: row.get("target").unwrap_or_default(),
+                target_category: row.get("target_category").unwrap_or_default(),
+                reason: row.get("reason").unwrap_or_default(),
+                chunk_id: row.get("chunk_id").unwrap_or_default(),
+            });
+        }
+        Ok(triples)
+    }
+
+    async fn list_entity_profiles(&self, limit: usize) -> Result<Vec<EntityProfile>, AppError> {
+        let q = query(
+            "MATCH (e:Entity) \
+             WITH e, size([(e)<-[:MENTIONS]-(c:DocumentChunk) | c]) as mentions \
+             ORDER BY mentions DESC LIMIT $limit \
+             RETURN e.name as name, coalesce(e.category, 'Concept') as category, coalesce(e.aliases, []) as aliases, \
+                    e.description as description, [(e)-[]-(o:Entity) | o.name][..8] as neighbors, mentions, \
+                    e.embedding as embedding"
+        ).param("limit", limit as i64);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut profiles = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let mentions: i64 = row.get("mentions").unwrap_or(0);
+            let mut neighbors: Vec<String> = row.get("neighbors").unwrap_or_default();
+            neighbors.dedup();
+            let description: Option<String> = row.get("description").unwrap_or(None);
+            profiles.push(EntityProfile {
+                name: row.get("name").unwrap_or_default(),
+                category: row.get("category").unwrap_or_default(),
+                aliases: row.get("aliases").unwrap_or_default(),
+                description: description.filter(|d| !d.is_empty()),
+                neighbors,
+                mentions: mentions.max(0) as usize,
+                embedding: row.get::<Option<Vec<f32>>>("embedding").unwrap_or(None).filter(|e| !e.is_empty()),
+            });
+        }
+        Ok(profiles)
+    }
+
+    async fn list_stale_entities(&self, limit: usize) -> Result<Vec<StaleEntity>, AppError> {
+        let q = query(
+            "MATCH (e:Entity) WHERE e.embedding IS NULL OR coalesce(e.embedding_stale, false) \
+             RETURN e.name as name, coalesce(e.category, 'Concept') as category, coalesce(e.description, '') as description \
+             LIMIT $limit"
+        ).param("limit", limit as i64);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut entities = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            entities.push(StaleEntity {
+                name: row.get("name").unwrap_or_default(),
+                category: row.get("category").unwrap_or_default(),
+                description: row.get("description").unwrap_or_default(),
+            });
+        }
+        Ok(entities)
+    }
+
+    async fn update_entity_embeddings(&self, updates: Vec<EntityEmbedding>) -> Result<(), AppError> {
+        let mut txn = self.graph.start_txn().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        for update in updates {
+            let q = query(
+                "MATCH (e:Entity {name: $name}) \
+                 SET e.embedding = $embedding, e.description = coalesce($description, e.description), e.embedding_stale = false"
+            )
+                .param("name", update.name)
+                .param("embedding", update.embedding)
+                .param("description", update.description);
+            txn.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        }
+        txn.commit().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn merge_entities(&self, keep: &str, merge: &str) -> Result<EntityMergeRecord, AppError> {
+        if keep == merge {
+            return Err(AppError::ValidationError("Cannot merge an entity into itself".to_string()));
+        }
+        let db = |e: neo4rs::Error| AppError::DatabaseError(e.to_string());
+        let mut txn = self.graph.start_txn().await.map_err(db)?;
+
+        // 1. Estado previo de ambas entidades
+        let q = query(
+            "MATCH (k:Entity {name: $keep}), (m:Entity {name: $merge}) \
+             RETURN properties(m) as properties, coalesce(k.aliases, []) as kept_aliases, coalesce(m.aliases, []) as merged_aliases, \
+                    k.description as kept_description"
+        ).param("keep", keep).param("merge", merge);
+        let mut stream = txn.execute(q).await.map_err(db)?;
+        let (mut properties, kept_aliases, merged_aliases, kept_description): (Value, Vec<String>, Vec<String>, Option<String>) =
+            match stream.next(txn.handle()).await.map_err(db)? {
+                Some(row) => (
+                    row.get("properties").unwrap_or(Value::Null),
+                    row.get("kept_aliases").unwrap_or_default(),
+                    row.get("merged_aliases").unwrap_or_default(),
+                    row.get("kept_description").unwrap_or(None),
+                ),
+                None => return Err(AppError::NotFoundError(format!("Entity '{}' or '{}'", keep, merge))),
+            };
+        // El embedding no se guarda en la instantánea: se recalcula tras deshacer
+        if let Some(map) = properties.as_object_mut() {
+            map.remove("embedding");
+        }
+
+        // 2. Relaciones de la entidad absorbida
+        let q = query(
+            "MATCH (m:Entity {name: $merge})-[r]-(o:Entity) \
+             RETURN type(r) as rel_type, o.name as other, startNode(r) = m as outgoing, \
+                    r {.*, first_seen: toString(r.first_seen), last_seen: toString(r.last_seen)} as properties"
+        ).param("merge", merge);
+        let mut stream = txn.execute(q).await.map_err(db)?;
+        let mut relationships = Vec::new();
+        while let Some(row) = stream.next(txn.handle()).await.map_err(db)? {
+            relationships.push(RelationshipSnapshot {
+                rel_type: row.get("rel_type").unwrap_or_default(),
+                other: row.get("other").unwrap_or_default(),
+                outgoing: row.get("outgoing").unwrap_or(true),
+                properties: row.get("properties").unwrap_or(Value::Null),
+            });
+        }
+
+        // 3. Trasladarlas a la conservada (las que unían ambas entidades desaparecen). Si la
+        //    conservada ya tenía la misma relación, hereda la evidencia: sin ella, purgar los
+        //    documentos de la conservada borraría hechos que los de la absorbida aún respaldan
+        let mut added_relationships = Vec::new();
+        let mut updated_relationships = Vec::new();
+        let mut relations_moved = 0;
+        for rel in &relationships {
+            if rel.other == keep || rel.other == merge {
+                continue;
+            }
+            let (pattern, merge_pattern) = if rel.outgoing {
+                ("(k)-[x:{t}]->(o)", "(k)-[r:{t}]->(o)")
+            } else {
+                ("(o)-[x:{t}]->(k)", "(o)-[r:{t}]->(k)")
+            };
+            let t = rel_type_ident(&rel.rel_type);
+            let cypher = format!(
+                "MATCH (k:Entity {{name: $keep}}), (o:Entity {{name: $other}}) \
+                 OPTIONAL MATCH {} \
+                 WITH k, o, head(collect(x {{.*, first_seen: toString(x.first_seen), last_seen: toString(x.last_seen)}})) as before \
+                 MERGE {} \
+                 ON CREATE SET r = $properties, {} \
+                 ON MATCH SET {} \
+                 RETURN before IS NULL as added, before",
+                pattern.replace("{t}", &t), merge_pattern.replace("{t}", &t), RESTORE_TIMESTAMPS, COMBINE_EVIDENCE
+            );
+            let q = query(&cypher)
+                .param("keep", keep)
+                .param("other", rel.other.as_str())
+                .param("properties", properties_param(&rel.properties)?);
+            let mut stream = txn.execute(q).await.map_err(db)?;
+            if let Some(row) = stream.next(txn.handle()).await.map_err(db)? {
+                relations_moved += 1;
+                if row.get::<bool>("added").unwrap_or(false) {
+                    added_relationships.push(RelationshipSnapshot {
+                        rel_type: rel.rel_type.clone(),
+                        other: rel.other.clone(),
+                        outgoing: rel.outgoing,
+                        properties: Value::Null,
+                    });
+                } else {
+                    updated_relationships.push(RelationshipSnapshot {
+                        rel_type: rel.rel_type.clone(),
+                        other: rel.other.clone(),
+                        outgoing: rel.outgoing,
+                        properties: row.get("before").unwrap_or(Value::Null),
+                    });
+                }
+            }
+        }
+
+        // 4. MENTIONS
+        let q = query(
+            "MATCH (c:DocumentChunk)-[:MENTIONS]->(:Entity {name: $merge}) \
+             MATCH (k:Entity {name: $keep}) \
+             WITH c, k, NOT (c)-[:MENTIONS]->(k) as added \
+             FOREACH (_ IN CASE WHEN added THEN [1] ELSE [] END | MERGE (c)-[:MENTIONS]->(k)) \
+             RETURN collect(c.id) as mentions, collect(CASE WHEN added THEN c.id END) as added_mentions"
+        ).param("merge", merge).param("keep", keep);
+        let mut stream = txn.execute(q).await.map_err(db)?;
+        let (mentions, added_mentions): (Vec<String>, Vec<String>) = match stream.next(txn.handle()).await.map_err(db)? {
+            Some(row) => (row.get("mentions").unwrap_or_default(), row.get("added_mentions").unwrap_or_default()),
+            None => (Vec::new(), Vec::new()),
+        };
+
+        // 5. Alias: el nombre absorbido y sus propios alias
+        let mut added_aliases: Vec<String> = Vec::new();
+        for alias in std::iter::once(merge.to_string()).chain(merged_aliases) {
+            if alias != keep && !kept_aliases.contains(&alias) && !added_aliases.contains(&alias) {
+                added_aliases.push(alias);
+            }
+        }
+        let aliases: Vec<String> = kept_aliases.into_iter().chain(added_aliases.iter().cloned()).collect();
+        txn.run(query(
+            "MATCH (k:Entity {name: $keep}), (m:Entity {name: $merge}) \
+             SET k.aliases = $aliases, \
+                 k.description = CASE \
+                    WHEN coalesce(m.description, '') = '' OR coalesce(k.description, '') CONTAINS m.description THEN k.description \
+                    WHEN coalesce(k.description, '') = '' THEN m.description \
+                    ELSE k.description + '\\n' + m.description END, \
+                 k.embedding_stale = true"
+        ).param("keep", keep).param("merge", merge).param("aliases", aliases)).await.map_err(db)?;
+
+        txn.run(query("MATCH (m:Entity {name: $merge}) DETACH DELETE m").param("merge", merge)).await.map_err(db)?;
+
+        // 6. Registro (con la instantánea para deshacer)
+        let mentions_moved = added_mentions.len();
+        let snapshot = MergeSnapshot {
+            properties, relationships, mentions, added_relationships, added_mentions, added_aliases, kept_description,
+            updated_relationships
+        };
+        let snapshot = serde_json::to_string(&snapshot).map_err(|e| AppError::ParseError(e.to_string()))?;
+        let q = query(
+            "CREATE (x:EntityMerge {id: $id, kept: $keep, merged: $merge, snapshot: $snapshot, \
+                relations_moved: $relations_moved, mentions_moved: $mentions_moved, created_at: datetime(), undone: false}) \
+             RETURN x.id as id, x.kept as kept, x.merged as merged, x.relations_moved as relations_moved, \
+                    x.mentions_moved as mentions_moved, toString(x.created_at) as created_at, x.undone as undone"
+        )
+            .param("id", Uuid::new_v4().to_string())
+            .param("keep", keep)
+            .param("merge", merge)
+            .param("snapshot", snapshot)
+            .param("relations_moved", relations_moved as i64)
+            .param("mentions_moved", mentions_moved as i64);
+        let mut stream = txn.execute(q).await.map_err(db)?;
+        let record = stream.next(txn.handle()).await.map_err(db)?
+            .map(|row| Self::row_to_merge(&row))
+            .ok_or_else(|| AppError::DatabaseError("Merge record was not created".to_string()))?;
+
+        txn.commit().await.map_err(db)?;
+        tracing::info!("🔗 Merged entity '{}' into '{}' ({} relations, {} mentions)", merge, keep, relations_moved, mentions_moved);
+        Ok(record)
+    }
+
+    async fn undo_entity_merge(&self, id: Uuid) -> Result<EntityMergeRecord, AppError> {
+        let db = |e: neo4rs::Error| AppError::DatabaseError(e.to_string());
+        let mut txn = self.graph.start_txn().await.map_err(db)?;
+
+        let q = query(
+            "MATCH (x:EntityMerge {id: $id}) \
+             OPTIONAL MATCH (k:Entity {name: x.kept}) \
+             OPTIONAL MATCH (m:Entity {name: x.merged}) \
+             RETURN x.id as id, x.kept as kept, x.merged as merged, x.relations_moved as relations_moved, \
+                    x.mentions_moved as mentions_moved, toString(x.created_at) as created_at, x.undone as undone, \
+                    x.snapshot as snapshot, k IS NOT NULL as kept_exists, m IS NOT NULL as merged_exists"
+        ).param("id", id.to_string());
+        let mut stream = txn.execute(q).await.map_err(db)?;
+        let row = stream.next(txn.handle()).await.map_err(db)?
+            .ok_or_else(|| AppError::NotFoundError(format!("Entity merge {}", id)))?;
+
+        let mut record = Self::row_to_merge(&row);
+        if record.undone {
+            return Err(AppError::ValidationError(format!("Merge {} was already undone", id)));
+        }
+        if !row.get::<bool>("kept_exists").unwrap_or(false) {
+            return Err(AppError::ValidationError(format!("Entity '{}' no longer exists", record.kept)));
+        }
+        if row.get::<bool>("merged_exists").unwrap_or(false) {
+            return Err(AppError::ValidationError(format!("An entity named '{}' exists again", record.merged)));
+        }
+        let snapshot: MergeSnapshot = serde_json::from_str(&row.get::<String>("snapshot").unwrap_or_default())
+            .map_err(|e| AppError::ParseError(format!("Corrupt merge snapshot: {}", e)))?;
+        let (kept, merged) = (record.kept.as_str(), record.merged.as_str());
+
+        // 1. Retirar de la conservada lo que añadió la fusión
+        for rel in &snapshot.added_relationships {
+            let t = rel_type_ident(&rel.rel_type);
+            let pattern = if rel.outgoing { format!("(k)-[r:{}]->(o)", t) } else { format!("(o)-[r:{}]->(k)", t) };
+            let cypher = format!(
+                "MATCH {} WHERE k.name = $kept AND k:Entity AND o.name = $other AND o:Entity DELETE r",
+                pattern
+            );
+            txn.run(query(&cypher).param("kept", kept).param("other", rel.other.as_str())).await.map_err(db)?;
+        }
+        for rel in &snapshot.updated_relationships {
+            let t = rel_type_ident(&rel.rel_type);
+            let pattern = if rel.outgoing { format!("(k)-[r:{}]->(o)", t) } else { format!("(o)-[r:{}]->(k)", t) };
+            let cypher = format!(
+                "MATCH {} WHERE k.name = $kept AND k:Entity AND o.name = $other AND o:Entity SET r = $properties, {}",
+                pattern, RESTORE_TIMESTAMPS
+            );
+            let q = query(&cypher)
+                .param("kept", kept)
+                .param("other", rel.other.as_str())
+                .param("properties", properties_param(&rel.properties)?);
+            txn.run(q).await.map_err(db)?;
+        }
+        txn.run(query(
+            "MATCH (c:DocumentChunk)-[r:MENTIONS]->(:Entity {name: $kept}) WHERE c.id IN $ids DELETE r"
+        ).param("kept", kept).param("ids", snapshot.added_mentions.clone())).await.map_err(db)?;
+        txn.run(query(
+            "MATCH (k:Entity {name: $kept}) \
+             SET k.aliases = [a IN coalesce(k.aliases, []) WHERE NOT a IN $aliases], \
+                 k.description = $description, k.embedding_stale = true"
+        )
+            .param("kept", kept)
+            .param("aliases", snapshot.added_aliases.clone())
+            .param("description", snapshot.kept_description.clone())).await.map_err(db)?;
+
+        // 2. Recrear la entidad absorbida con sus relaciones y menciones
+        txn.run(query("CREATE (m:Entity) SET m = $properties, m.embedding_stale = true")
+            .param("properties", properties_param(&snapshot.properties)?)).await
+            .map_err(db)?;
+        for rel in &snapshot.relationships {
+            let t = rel_type_ident(&rel.rel_type);
+            let pattern = if rel.outgoing { format!("(m)-[r:{}]->(o)", t) } else { format!("(o)-[r:{}]->(m)", t) };
+            let cypher = format!(
+                "MATCH (m:Entity {{name: $merged}}), (o:Entity {{name: $other}}) MERGE {} SET r = $properties, {}",
+                pattern, RESTORE_TIMESTAMPS
+            );
+            let q = query(&cypher)
+                .param("merged", merged)
+                .param("other", rel.other.as_str())
+                .param("properties", properties_param(&rel.properties)?);
+            txn.run(q).await.map_err(db)?;
+        }
+        txn.run(query(
+            "MATCH (m:Entity {name: $merged}) MATCH (c:DocumentChunk) WHERE c.id IN $ids MERGE (c)-[:MENTIONS]->(m)"
+        ).param("merged", merged).param("ids", snapshot.mentions.clone())).await.map_err(db)?;
+
+        txn.run(query("MATCH (x:EntityMerge {id: $id}) SET x.undone = true, x.undone_at = datetime()")
+            .param("id", id.to_string())).await.map_err(db)?;
+
+        txn.commit().await.map_err(db)?;
+        tracing::info!("↩️ Undid merge of '{}' into '{}'", merged, kept);
+        record.undone = true;
+        Ok(record)
+    }
+
+    async fn list_entity_merges(&self, limit: usize) -> Result<Vec<EntityMergeRecord>, AppError> {
+        let q = query(
+            "MATCH (x:EntityMerge) \
+             RETURN x.id as id, x.kept as kept, x.merged as merged, x.relations_moved as relations_moved, \
+                    x.mentions_moved as mentions_moved, toString(x.created_at) as created_at, x.undone as undone \
+             ORDER BY x.created_at DESC LIMIT $limit"
+        ).param("limit", limit as i64);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut merges = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            merges.push(Self::row_to_merge(&row));
+        }
+        Ok(merges)
+    }
+
+    async fn get_graph_context_for_reasoning(&self, limit: usize) -> Result<String, AppError> {
+        // Obtenemos las relaciones más "densas" para dar contexto
+        let q = query(
+            "MATCH (n:Entity)-[r]->(m:Entity) 
+             WITH n, r, m, count(n) as degree 
+             ORDER BY degree DESC 
+             LIMIT $limit 
+             RETURN n.name, type(r), m.name"
+        ).param("limit", limit as i64);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut context = String::new();
+
+        while let Ok(Some(row)) = stream.next().await {
+            let n: String = row.get("n.name").unwrap_or_default();
+            let r: String = row.get("type(r)").unwrap_or_default();
+            let m: String = row.get("m.name").unwrap_or_default();
+            context.push_str(&format!("({}) -[{}]-> ({})\n", n, r, m));
+        }
+
+        if context.is_empty() {
+            return Ok("El grafo está vacío.".to_string());
+        }
+        Ok(context)
+    }
+
+    async fn save_inferred_relations(&self, relations: Vec<InferredRelation>) -> Result<(), AppError> {
+        let mut txn = self.graph.start_txn().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+
+        for rel in relations {
+            let cypher = format!(
+                "MATCH (a:Entity {{name: $source}}), (b:Entity {{name: $target}}) \
+                 MERGE (a)-[r:{}]->(b) \
+                 ON CREATE SET r.reasoning = $reasoning, r.is_ai_generated = true",
+                rel_type_ident(&format!("INFERRED_{}", sanitize_relation_type(&rel.relation)
+                    .map_err(|e| AppError::ValidationError(format!("Invalid relation type {:?}: {}", rel.relation, e)))?))
+            );
+            
+            let q = query(&cypher)
+                .param("source", rel.source)
+                .param("target", rel.target)
+                .param("reasoning", rel.reasoning);
+                
+            txn.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        }
+
+        txn.commit().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+// --- TRABAJOS DE INGESTA ---
+// El estado completo se guarda como JSON en `state`; `status` se duplica como propiedad
+// para poder filtrar trabajos pendientes, y `payload` guarda la petición hasta que el trabajo termina.
+
+#[async_trait]
+impl JobRepository for Neo4jRepo {
+    async fn create_job(&self, job: &IngestionJob, request: &IngestionRequest) -> Result<(), AppError> {
+        let state = serde_json::to_string(job).map_err(|e| AppError::ParseError(e.to_string()))?;
+        let payload = serde_json::to_string(request).map_err(|e| AppError::ParseError(e.to_string()))?;
+
+        let q = query("CREATE (j:IngestionJob {id: $id, status: $status, state: $state, payload: $payload})")
+            .param("id", job.id.as_str())
+            .param("status", format!("{:?}", job.status))
+            .param("state", state)
+            .param("payload", payload);
+
+        self.graph.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn update_job(&self, job: &IngestionJob) -> Result<(), AppError> {
+        let state = serde_json::to_string(job).map_err(|e| AppError::ParseError(e.to_string()))?;
+
+        let q = query(
+            "MATCH (j:IngestionJob {id: $id}) \
+             SET j.status = $status, j.state = $state \
+             FOREACH (_ IN CASE WHEN $terminal THEN [1] ELSE [] END | REMOVE j.payload)"
+        )
+            .param("id", job.id.as_str())
+            .param("status", format!("{:?}", job.status))
+            .param("state", state)
+            .param("terminal", job.status.is_terminal());
+
+        self.graph.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn get_job(&self, id: Uuid) -> Result<Option<IngestionJob>, AppError> {
+        let q = query("MATCH (j:IngestionJob {id: $id}) RETURN j.state as state")
+            .param("id", id.to_string());
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        match stream.next().await {
+            Ok(Some(row)) => {
+                let state: String = row.get("state").unwrap_or_default();
+                serde_json::from_str(&state)
+                    .map(Some)
+                    .map_err(|e| AppError::ParseError(format!("Corrupt job state: {}", e)))
+            },
+            Ok(None) => Ok(None),
+            Err(e) => Err(AppError::DatabaseError(e.to_string())),
+        }
+    }
+
+    async fn list_resumable_jobs(&self) -> Result<Vec<(IngestionJob, IngestionRequest)>, AppError> {
+        let q = query(
+            "MATCH (j:IngestionJob) \
+             WHERE j.status IN ['Queued', 'Running'] AND j.payload IS NOT NULL \
+             RETURN j.id as id, j.state as state, j.payload as payload"
+        );
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut jobs = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let id: String = row.get("id").unwrap_or_default();
+            let state: String = row.get("state").unwrap_or_default();
+            let payload: String = row.get("payload").unwrap_or_default();
+
+            // Un trabajo ilegible no debe impedir reanudar el resto
+            match (serde_json::from_str(&state), serde_json::from_str(&payload)) {
+                (Ok(job), Ok(request)) => jobs.push((job, request)),
+                _ => tracing::warn!("⚠️ Skipping unreadable ingestion job {}", id),
+            }
+        }
+        Ok(jobs)
+    }
+}
+
+// --- CONVERSACIONES ---
+// Cada turno es un nodo `ConversationTurn` ordenado por `index`; sus fuentes se guardan como JSON.
+
+const CONVERSATION_SUMMARY_RETURN: &str = "RETURN c.id as id, c.title as title, \
+     count { (c)-[:HAS_TURN]->(:ConversationTurn) } as turn_count, \
+     toString(c.created_at) as created_at, toString(c.updated_at) as updated_at";
+
+impl Neo4jRepo {
+    fn row_to_conversation_summary(row: &neo4rs::Row) -> ConversationSummary {
+        let turn_count: i64 = row.get("turn_count").unwrap_or(0);
+        ConversationSummary {
+            id: row.get("id").unwrap_or_default(),
+            title: row.get("title").unwrap_or_default(),
+            turn_count: turn_count.max(0) as usize,
+            created_at: row.get("created_at").unwrap_or_default(),
+            updated_at: row.get("updated_at").unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl ConversationRepository for Neo4jRepo {
+    async fn list_conversations(&self, limit: usize) -> Result<Vec<ConversationSummary>, AppError> {
+        let q = query(&format!(
+            "MATCH (c:Conversation) {} ORDER BY c.updated_at DESC LIMIT $limit",
+            CONVERSATION_SUMMARY_RETURN
+        )).param("limit", limit as i64);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut conversations = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            conversations.push(Self::row_to_conversation_summary(&row));
+        }
+        Ok(conversations)
+    }
+
+    async fn get_conversation(&self, id: Uuid) -> Result<Option<Conversation>, AppError> {
+        let q = query(
+            "MATCH (c:Conversation {id: $id}) \
+             OPTIONAL MATCH (c)-[:HAS_TURN]->(t:ConversationTurn) \
+             WITH c, t ORDER BY t.index \
+             RETURN c.id as id, c.title as title, c.summary as summary, coalesce(c.summarized_turns, 0) as summarized_turns, \
+                    toString(c.created_at) as created_at, toString(c.updated_at) as updated_at, \
+                    collect(t { .question, .standalone_question, .answer, .sources, created_at: toString(t.created_at) }) as turns"
+        ).param("id", id.to_string());
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let row = match stream.next().await {
+            Ok(Some(row)) => row,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(AppError::DatabaseError(e.to_string())),
+        };
+
+        let raw_turns: Vec<Value> = row.get("turns").unwrap_or_default();
+        let turns = raw_turns.into_iter()
+            .map(|t| {
+                let text = |key: &str| t.get(key).and_then(Value::as_str).map(str::to_string);
+                let sources: Vec<SourceReference> = text("sources")
+                    .and_then(|s| serde_json::from_str(&s).ok())
+                    .unwrap_or_default();
+                ConversationTurn {
+                    question: text("question").unwrap_or_default(),
+                    standalone_question: text("standalone_question"),
+                    answer: text("answer").unwrap_or_default(),
+                    sources,
+                    created_at: text("created_at").unwrap_or_default(),
+                }
+            })
+            .collect();
+        let summarized_turns: i64 = row.get("summarized_turns").unwrap_or(0);
+
+        Ok(Some(Conversation {
+            id: row.get("id").unwrap_or_default(),
+            title: row.get("title").unwrap_or_default(),
+            summary: row.get("summary").unwrap_or(None),
+            summarized_turns: summarized_turns.max(0) as usize,
+            turns,
+            created_at: row.get("created_at").unwrap_or_default(),
+            updated_at: row.get("updated_at").unwrap_or_default(),
+        }))
+    }
+
+    async fn append_turn(&self, id: Uuid, title: &str, turn: &ConversationTurn) -> Result<(), AppError> {
+        let sources = serde_json::to_string(&turn.sources).map_err(|e| AppError::ParseError(e.to_string()))?;
+        let q = query(
+            "MERGE (c:Conversation {id: $id}) \
+             ON CREATE SET c.title = $title, c.created_at = datetime(), c.summarized_turns = 0 \
+             SET c.updated_at = datetime() \
+             WITH c, count { (c)-[:HAS_TURN]->(:ConversationTurn) } as index \
+             CREATE (c)-[:HAS_TURN]->(:ConversationTurn { \
+                index: index, question: $question, standalone_question: $standalone_question, \
+                answer: $answer, sources: $sources, created_at: datetime() \
+             })"
+        )
+            .param("id", id.to_string())
+            .param("title", title)
+            .param("question", turn.question.as_str())
+            .param("standalone_question", turn.standalone_question.clone())
+            .param("answer", turn.answer.as_str())
+            .param("sources", sources);
+
+        self.graph.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))
+    }
+
+    async fn set_conversation_summary(&self, id: Uuid, summary: &str, summarized_turns: usize) -> Result<(), AppError> {
+        let q = query("MATCH (c:Conversation {id: $id}) SET c.summary = $summary, c.summarized_turns = $summarized_turns")
+            .param("id", id.to_string())
+            .param("summary", summary)
+            .param("summarized_turns", summarized_turns as i64);
+        self.graph.run(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))
+    }
+
+    async fn rename_conversation(&self, id: Uuid, title: &str) -> Result<Option<ConversationSummary>, AppError> {
+        let q = query(&format!(
+            "MATCH (c:Conversation {{id: $id}}) SET c.title = $title {}",
+            CONVERSATION_SUMMARY_RETURN
+        )).param("id", id.to_string()).param("title", title);
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        match stream.next().await {
+            Ok(Some(row)) => Ok(Some(Self::row_to_conversation_summary(&row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(AppError::DatabaseError(e.to_string())),
+        }
+    }
+
+    async fn delete_conversation(&self, id: Uuid) -> Result<bool, AppError> {
+        let q = query(
+            "MATCH (c:Conversation {id: $id}) \
+             OPTIONAL MATCH (c)-[:HAS_TURN]->(t:ConversationTurn) \
+             WITH c, collect(t) as turns \
+             FOREACH (t IN turns | DETACH DELETE t) \
+             DETACH DELETE c \
+             RETURN count(*) as deleted"
+        ).param("id", id.to_string());
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let deleted: i64 = match stream.next().await {
+            Ok(Some(row)) => row.get("deleted").unwrap_or(0),
+            Ok(None) => 0,
+            Err(e) => return Err(AppError::DatabaseError(e.to_string())),
+        };
+        Ok(deleted > 0)
+    }
+}
+
+#[async_trait]
+impl CommunityRepository for Neo4jRepo {
+    async fn load_entity_graph(&self) -> Result<(Vec<EntityNode>, Vec<EntityLink>), AppError> {
+        let q = query("MATCH (e:Entity) RETURN e.name as name, coalesce(e.category, 'Concept') as category, e.description as description");
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut nodes = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let description: Option<String> = row.get("description").unwrap_or(None);
+            nodes.push(EntityNode {
+                name: row.get("name").unwrap_or_default(),
+                category: row.get("category").unwrap_or_default(),
+                description: description.filter(|d| !d.is_empty()),
+            });
+        }
+
+        let q = query(
+            "MATCH (a:Entity)-[r]->(b:Entity) WHERE a <> b \
+             RETURN a.name as source, b.name as target, type(r) as relation, r.description as description, \
+                    coalesce(r.mention_count, 1) as weight"
+        );
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut links = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let description: Option<String> = row.get("description").unwrap_or(None);
+            let weight: i64 = row.get("weight").unwrap_or(1);
+            links.push(EntityLink {
+                source: row.get("source").unwrap_or_default(),
+                target: row.get("target").unwrap_or_default(),
+                relation: row.get("relation").unwrap_or_default(),
+                description: description.filter(|d| !d.is_empty()),
+                weight: weight.max(1) as f64,
+            });
+        }
+        Ok((nodes, links))
+    }
+
+    async fn replace_communities(&self, communities: &[Community]) -> Result<(), AppError> {
+        let rows = serde_json::to_value(communities)
+            .map_err(|e| AppError::DatabaseError(format!("Could not serialize communities: {}", e)))?;
+        let rows = BoltType::try_from(rows)
+            .map_err(|e| AppError::DatabaseError(format!("Unsupported community value: {}", e)))?;
+
+        // Las comunidades no se enlazan con las entidades (solo guardan sus nombres) para no
+        // aparecer como relaciones en el grafo ni en las purgas de huérfanas
+        let mut txn = self.graph.start_txn().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        txn.run(query("MATCH (c:Community) DETACH DELETE c")).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        txn.run(query(
+            "UNWIND $rows as row \
+             CREATE (:Community {id: row.id, level: row.level, title: row.title, summary: row.summary, \
+                                 size: row.size, members: row.members, parent_id: row.parent_id, created_at: datetime()})"
+        ).param("rows", rows)).await
+            .map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        txn.commit().await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn list_communities(&self, level: Option<usize>) -> Result<Vec<Community>, AppError> {
+        let q = query(
+            "MATCH (c:Community) WHERE $level IS NULL OR c.level = $level \
+             RETURN c.id as id, c.level as level, c.title as title, c.summary as summary, c.size as size, \
+                    c.members as members, c.parent_id as parent_id \
+             ORDER BY c.level, c.size DESC"
+        ).param("level", level.map(|l| l as i64));
+
+        let mut stream = self.graph.execute(q).await.map_err(|e| AppError::DatabaseError(e.to_string()))?;
+        let mut communities = Vec::new();
+        while let Ok(Some(row)) = stream.next().await {
+            let level: i64 = row.get("level").unwrap_or(0);
+            let size: i64 = row.get("size").unwrap_or(0);
+            communities.push(Community {
+                id: row.get("id").unwrap_or_default(),
+                level: level as usize,
+                title: row.get("title").unwrap_or_default(),
+                summary: row.get("summary").unwrap_or_default(),
+                size: size as usize,
+                members: row.get("members").unwrap_or_default(),
+                parent_id: row.get("parent_id").unwrap_or(None),
+            });
+        }
+        Ok(communities)
+    }
+}
+
+/// Regresión de la recuperación: corpus fijo con embeddings construidos a mano y consultas cuyos
+/// fragmentos relevantes se conocen. Necesitan una instancia Neo4j desechable (el índice
+/// `chunk_embeddings` debe tener la dimensión de los fixtures):
+///
+/// `NEO4J_TEST_URI=bolt://localhost:7687 NEO4J_TEST_USER=neo4j NEO4J_TEST_PASS=... cargo test -- --ignored`
+#[cfg(test)]
+mod retrieval_tests {
+    use super::*;
+    use crate::domain::models::{GraphEntity, GraphRelation, RetrievalMode};
+
+    const DIM: usize = 8;
+
+    /// Vector unitario que mezcla los ejes indicados a partes iguales.
+    fn topic(axes: &[usize]) -> Vec<f32> {
+        let weight = 1.0 / (axes.len() as f32).sqrt();
+        let mut v = vec![0.0; DIM];
+        for &axis in axes {
+            v[axis] = weight;
+        }
+        v
+    }
+
+    fn options(mode: RetrievalMode, top_k: usize, min_score: f32) -> RetrievalOptions {
+        RetrievalOptions {
+            mode,
+            top_k,
+            min_score,
+            hops: 0,
+            max_facts: 0,
+            rerank: false,
+            context_tokens: usize::MAX,
+            filters: RetrievalFilters::default(),
+        }
+    }
+
+    /// Conexión a la base de pruebas (`NEO4J_TEST_URI`, `NEO4J_TEST_USER`, `NEO4J_TEST_PASS`).
+    async fn connect() -> Arc<Graph> {
+        let uri = std::env::var("NEO4J_TEST_URI").expect("NEO4J_TEST_URI no definida");
+        let user = std::env::var("NEO4J_TEST_USER").unwrap_or_else(|_| "neo4j".to_string());
+        let pass = std::env::var("NEO4J_TEST_PASS").unwrap_or_default();
+        Arc::new(Graph::new(&uri, &user, &pass).await.expect("Neo4j de pruebas no disponible"))
+    }
+
+    /// Borra al salir de la prueba los documentos y fusiones que ha creado, también si falla
+    /// una aserción. El runtime de la prueba puede estar cerrándose por el pánico, así que la
+    /// limpieza usa su propio hilo, runtime y conexión.
+    #[derive(Default)]
+    struct Cleanup {
+        documents: std::sync::Mutex<Vec<Uuid>>,
+        merges: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl Cleanup {
+        fn document(&self, id: Uuid) {
+            self.documents.lock().unwrap_or_else(|e| e.into_inner()).push(id);
+        }
+
+        /// Registros de fusión cuya entidad conservada es `kept`.
+        fn merge(&self, kept: &str) {
+            self.merges.lock().unwrap_or_else(|e| e.into_inner()).push(kept.to_string());
+        }
+    }
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            let documents = std::mem::take(self.documents.get_mut().unwrap_or_else(|e| e.into_inner()));
+            let merges = std::mem::take(self.merges.get_mut().unwrap_or_else(|e| e.into_inner()));
+            let purge = std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("runtime de limpieza");
+                runtime.block_on(async move {
+                    let repo = Neo4jRepo::new(connect().await);
+                    // Los documentos que la prueba ya purgó fallan o no borran nada: no es un error
+                    for id in documents {
+                        if let Err(e) = repo.delete_document(id).await {
+                            eprintln!("limpieza del documento {}: {}", id, e);
+                        }
+                    }
+                    for kept in merges {
+                        let q = query("MATCH (x:EntityMerge) WHERE x.kept = $kept DELETE x").param("kept", kept);
+                        if let Err(e) = repo.graph.run(q).await {
+                            eprintln!("limpieza de fusiones: {}", e);
+                        }
+                    }
+                });
+            });
+            if purge.join().is_err() {
+                eprintln!("⚠️ la limpieza del fixture falló: pueden quedar datos de prueba en NEO4J_TEST_URI");
+            }
+        }
+    }
+
+    struct Fixture {
+        repo: Neo4jRepo,
+        cleanup: Cleanup,
+        document_id: Uuid,
+        solar: String,
+        wind_failed: String,
+        hydro_empty: String,
+        mixed: String,
+        part_number: String,
+    }
+
+    impl Fixture {
+        fn chunk(&self, order: usize, content: &str, extraction_failed: bool) -> ChunkRecord {
+            ChunkRecord {
+                id: Uuid::new_v4(),
+                document_id: self.document_id,
+                order,
+                page: Some(order as u32 + 1),
+                content: content.to_string(),
+                content_hash: format!("retrieval-fixture-{}-{}", self.document_id, order),
+                extraction_failed,
+            }
+        }
+
+        fn ids(&self) -> [&str; 5] {
+            [&self.solar, &self.wind_failed, &self.hydro_empty, &self.mixed, &self.part_number]
+        }
+
+        /// Resultados de la consulta limitados a los chunks del fixture.
+        async fn search(&self, embedding: Vec<f32>, top_k: usize, min_score: f32) -> Vec<HybridContext> {
+            let options = options(RetrievalMode::Vector, top_k, min_score);
+            self.repo.find_hybrid_context(embedding, &options).await.unwrap()
+                .into_iter()
+                .filter(|ctx| self.ids().contains(&ctx.chunk_id.as_str()))
+                .collect()
+        }
+
+        async fn keyword(&self, text: &str) -> Vec<HybridContext> {
+            let options = options(RetrievalMode::Keyword, 10, 0.0);
+            self.repo.find_keyword_context(text, &options).await.unwrap()
+                .into_iter()
+                .filter(|ctx| self.ids().contains(&ctx.chunk_id.as_str()))
+                .collect()
+        }
+
+        /// Chunks del fixture que devuelven las búsquedas vectorial y de texto completo con los filtros.
+        async fn filtered(&self, embedding: Vec<f32>, text: &str, filters: RetrievalFilters) -> (Vec<String>, Vec<String>) {
+            let options = RetrievalOptions { filters, ..options(RetrievalMode::Vector, 10, 0.0) };
+            let ids = |results: Vec<HybridContext>| results.into_iter()
+                .map(|ctx| ctx.chunk_id)
+                .filter(|id| self.ids().contains(&id.as_str()))
+                .collect::<Vec<_>>();
+            (
+                ids(self.repo.find_hybrid_context(embedding, &options).await.unwrap()),
+                ids(self.repo.find_keyword_context(text, &options).await.unwrap()),
+            )
+        }
+    }
+
+    async fn fixture() -> Fixture {
+        let graph = connect().await;
+        let repo = Neo4jRepo::new(graph.clone());
+        repo.create_indexes(DIM).await.unwrap();
+        graph.run(query("CALL db.awaitIndexes(60)")).await.unwrap();
+
+        let document_id = Uuid::new_v4();
+        let cleanup = Cleanup::default();
+        cleanup.document(document_id);
+        repo.save_document(&NewDocument {
+            id: document_id,
+            filename: "energia.txt".to_string(),
+            mime_type: "text/plain".to_string(),
+            content_hash: format!("retrieval-fixture-{}", document_id),
+            uploaded_by: "tests".to_string(),
+            metadata: serde_json::json!({"year": 2025, "tags": ["energía", "renovables"]}),
+        }).await.unwrap();
+
+        let mut fixture = Fixture {
+            repo,
+            cleanup,
+            document_id,
+            solar: String::new(),
+            wind_failed: String::new(),
+            hydro_empty: String::new(),
+            mixed: String::new(),
+            part_number: String::new(),
+        };
+
+        // Con entidades
+        let solar = fixture.chunk(0, "Los paneles solares convierten la radiación en electricidad.", false);
+        fixture.repo.save_chunk(&solar, topic(&[0])).await.unwrap();
+        fixture.repo.save_graph(solar.id, KnowledgeExtraction {
+            entities: vec![GraphEntity {
+                name: "Panel Solar".to_string(),
+                category: "Technology".to_string(),
+                description: String::new(),
+                aliases: vec![],
+            }],
+            relations: vec![],
+        }, vec![]).await.unwrap();
+
+        // Extracción fallida: sin MENTIONS
+        let wind = fixture.chunk(1, "Los aerogeneradores aprovechan la energía del viento.", true);
+        fixture.repo.save_chunk(&wind, topic(&[1])).await.unwrap();
+
+        // Extracción correcta pero sin entidades
+        let hydro = fixture.chunk(2, "Las presas generan energía hidroeléctrica.", false);
+        fixture.repo.save_chunk(&hydro, topic(&[2])).await.unwrap();
+
+        let mixed = fixture.chunk(3, "Parques híbridos combinan placas solares y turbinas eólicas.", true);
+        fixture.repo.save_chunk(&mixed, topic(&[0, 1])).await.unwrap();
+
+        // Identificador que un embedding no distingue
+        let part = fixture.chunk(4, "El inversor de referencia XR-2041 alimenta el parque.", true);
+        fixture.repo.save_chunk(&part, topic(&[3])).await.unwrap();
+
+        fixture.solar = solar.id.to_string();
+        fixture.wind_failed = wind.id.to_string();
+        fixture.hydro_empty = hydro.id.to_string();
+        fixture.mixed = mixed.id.to_string();
+        fixture.part_number = part.id.to_string();
+        fixture
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn known_relevant_chunks_are_returned() {
+        let f = fixture().await;
+
+        // Chunk sin entidades por extracción fallida
+        let wind = f.search(topic(&[1]), 10, 0.0).await;
+        assert_eq!(wind.first().map(|c| c.chunk_id.as_str()), Some(f.wind_failed.as_str()));
+        assert!(wind[0].connected_entities.is_empty());
+
+        // Chunk sin entidades con extracción correcta
+        let hydro = f.search(topic(&[2]), 10, 0.0).await;
+        assert_eq!(hydro.first().map(|c| c.chunk_id.as_str()), Some(f.hydro_empty.as_str()));
+
+        // Chunk con entidades: se devuelven junto al fragmento
+        let solar = f.search(topic(&[0]), 10, 0.0).await;
+        assert_eq!(solar.first().map(|c| c.chunk_id.as_str()), Some(f.solar.as_str()));
+        assert_eq!(solar[0].connected_entities, vec!["Panel Solar".to_string()]);
+        assert_eq!(solar[0].filename.as_deref(), Some("energia.txt"));
+        assert_eq!(solar[0].page, Some(1));
+        assert_eq!(solar[1].chunk_id, f.mixed);
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn scores_are_real_and_ordered() {
+        let f = fixture().await;
+
+        let results = f.search(topic(&[1]), 10, 0.0).await;
+        assert_eq!(results.len(), 5);
+        assert!(results.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(results.iter().all(|c| (0.0..=1.0).contains(&c.score)));
+        // Coseno normalizado: idéntico -> 1.0, a 45º -> ~0.85, ortogonal -> 0.5
+        assert!(results[0].score > 0.99);
+        assert!((results[1].score - 0.853).abs() < 0.01);
+        assert!((results[2].score - 0.5).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn min_score_and_top_k_are_applied() {
+        let f = fixture().await;
+
+        let strict = f.search(topic(&[1]), 10, 0.9).await;
+        assert_eq!(strict.iter().map(|c| c.chunk_id.as_str()).collect::<Vec<_>>(), vec![f.wind_failed.as_str()]);
+
+        let related = f.search(topic(&[1]), 10, 0.8).await;
+        assert_eq!(related.len(), 2);
+        assert_eq!(related[1].chunk_id, f.mixed);
+
+        let options = options(RetrievalMode::Vector, 1, 0.0);
+        assert_eq!(f.repo.find_hybrid_context(topic(&[2]), &options).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn keyword_search_finds_identifiers_and_entity_names() {
+        let f = fixture().await;
+
+        // La sintaxis de Lucene del texto del usuario no rompe la consulta
+        let part = f.keyword("¿Qué alimenta XR-2041?").await;
+        assert_eq!(part.first().map(|c| c.chunk_id.as_str()), Some(f.part_number.as_str()));
+        assert!(part[0].keyword_score.is_some() && part[0].vector_score.is_none());
+        // La relevancia sale del BM25 del propio fragmento, no de compararlo con el mejor
+        assert_eq!(part[0].score, keyword_relevance(part[0].keyword_score.unwrap()));
+        assert!(part[0].score < 1.0);
+
+        // Por el nombre de una entidad mencionada
+        let solar = f.keyword("Panel Solar").await;
+        assert!(solar.iter().any(|c| c.chunk_id == f.solar));
+
+        assert!(f.keyword("   ").await.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn filters_scope_vector_and_keyword_results() {
+        let f = fixture().await;
+        let all = f.filtered(topic(&[0]), "solares", RetrievalFilters::default()).await;
+        assert!(all.0.contains(&f.solar) && all.0.contains(&f.mixed));
+
+        // Por documento: prefiltrado exacto, con la misma escala de puntuación que el índice
+        let scoped = RetrievalFilters { document_ids: vec![f.document_id], ..Default::default() };
+        let options = RetrievalOptions { filters: scoped.clone(), ..options(RetrievalMode::Vector, 10, 0.0) };
+        let results = f.repo.find_hybrid_context(topic(&[1]), &options).await.unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].chunk_id, f.wind_failed);
+        assert!(results[0].score > 0.99 && (results[2].score - 0.5).abs() < 0.01);
+        let other = RetrievalFilters { document_ids: vec![Uuid::new_v4()], ..Default::default() };
+        assert_eq!(f.filtered(topic(&[0]), "solares", other).await, (vec![], vec![]));
+
+        // Por categoría de las entidades mencionadas
+        let technology = RetrievalFilters { categories: vec!["Technology".to_string()], ..Default::default() };
+        assert_eq!(f.filtered(topic(&[0]), "solares", technology).await, (vec![f.solar.clone()], vec![f.solar.clone()]));
+
+        // Por metadatos: igualdad, pertenencia a una lista y escalares comparados como texto
+        let tagged = |metadata: serde_json::Value| RetrievalFilters {
+            metadata: metadata.as_object().cloned().unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(f.filtered(topic(&[0]), "solares", tagged(serde_json::json!({"tags": "energía"}))).await, all);
+        assert_eq!(f.filtered(topic(&[0]), "solares", tagged(serde_json::json!({"year": "2025"}))).await, all);
+        assert_eq!(f.filtered(topic(&[0]), "solares", tagged(serde_json::json!({"year": 2024}))).await, (vec![], vec![]));
+
+        // Por fecha de subida
+        let since = RetrievalFilters { uploaded_from: Some("2000-01-01".to_string()), ..Default::default() };
+        assert_eq!(f.filtered(topic(&[0]), "solares", since).await, all);
+        let until = RetrievalFilters { uploaded_to: Some("2000-01-01".to_string()), ..Default::default() };
+        assert_eq!(f.filtered(topic(&[0]), "solares", until).await, (vec![], vec![]));
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn merged_relations_keep_the_evidence_of_both_entities() {
+        let f = fixture().await;
+        let suffix = Uuid::new_v4().simple().to_string();
+        let (person, keep, merge) = (format!("Alice {}", suffix), format!("Acme {}", suffix), format!("ACME Corp {}", suffix));
+
+        // Cada documento afirma la misma relación con uno de los dos nombres de la empresa
+        let mut documents = Vec::new();
+        let mut chunks = Vec::new();
+        for (n, company) in [&keep, &merge].into_iter().enumerate() {
+            let document_id = Uuid::new_v4();
+            f.cleanup.document(document_id);
+            f.repo.save_document(&NewDocument {
+                id: document_id,
+                filename: format!("contrato-{}.txt", n),
+                mime_type: "text/plain".to_string(),
+                content_hash: format!("merge-fixture-{}", document_id),
+                uploaded_by: "tests".to_string(),
+                metadata: serde_json::json!({}),
+            }).await.unwrap();
+            let chunk = ChunkRecord {
+                id: Uuid::new_v4(),
+                document_id,
+                order: 0,
+                page: None,
+                content: format!("{} trabaja para {}.", person, company),
+                content_hash: format!("merge-fixture-{}-0", document_id),
+                extraction_failed: false,
+            };
+            f.repo.save_chunk(&chunk, topic(&[5])).await.unwrap();
+            let entity = |name: &str| GraphEntity {
+                name: name.to_string(),
+                category: "Organization".to_string(),
+                description: String::new(),
+                aliases: vec![],
+            };
+            f.repo.save_graph(chunk.id, KnowledgeExtraction {
+                entities: vec![entity(&person), entity(company)],
+                relations: vec![GraphRelation {
+                    source: person.clone(),
+                    target: company.clone(),
+                    relation_type: "WORKS_FOR".to_string(),
+                    description: String::new(),
+                    confidence: Some(0.5 + n as f32 * 0.4),
+                }],
+            }, vec![]).await.unwrap();
+            documents.push(document_id);
+            chunks.push(chunk.id.to_string());
+        }
+
+        let relation = || async {
+            let q = query(
+                "MATCH (:Entity {name: $person})-[r:WORKS_FOR]->(:Entity {name: $keep}) \
+                 RETURN r.evidence as evidence, r.mention_count as mention_count, r.confidence as confidence"
+            ).param("person", person.as_str()).param("keep", keep.as_str());
+            let mut stream = f.repo.graph.execute(q).await.unwrap();
+            stream.next().await.unwrap().map(|row| {
+                let mut evidence: Vec<String> = row.get("evidence").unwrap_or_default();
+                evidence.sort();
+                (evidence, row.get::<i64>("mention_count").unwrap_or(0), row.get::<f64>("confidence").unwrap_or(0.0))
+            })
+        };
+        let mut both = chunks.clone();
+        both.sort();
+
+        // La fusión suma la evidencia y deshacerla la devuelve a su estado previo
+        f.cleanup.merge(&keep);
+        let record = f.repo.merge_entities(&keep, &merge).await.unwrap();
+        let (evidence, mentions, confidence) = relation().await.expect("relación conservada");
+        assert_eq!((evidence, mentions), (both.clone(), 2));
+        assert!((confidence - 0.9).abs() < 1e-6);
+        f.repo.undo_entity_merge(Uuid::parse_str(&record.id).unwrap()).await.unwrap();
+        let (evidence, mentions, confidence) = relation().await.unwrap();
+        assert_eq!((evidence, mentions), (vec![chunks[0].clone()], 1));
+        assert!((confidence - 0.5).abs() < 1e-6);
+
+        // Purgar el documento de la conservada no borra lo que afirma el de la absorbida
+        f.repo.merge_entities(&keep, &merge).await.unwrap();
+        f.repo.delete_document(documents[0]).await.unwrap();
+        let (evidence, mentions, _) = relation().await.expect("la relación sigue respaldada");
+        assert_eq!((evidence, mentions), (vec![chunks[1].clone()], 1));
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn filters_scope_graph_expansion() {
+        let f = fixture().await;
+        let suffix = Uuid::new_v4().simple().to_string();
+        let (inverter, turbine) = (format!("Inversor {}", suffix), format!("Turbina {}", suffix));
+        let entity = |name: &str| GraphEntity {
+            name: name.to_string(),
+            category: "Technology".to_string(),
+            description: String::new(),
+            aliases: vec![],
+        };
+        let relation = |target: &str, relation_type: &str| GraphRelation {
+            source: "Panel Solar".to_string(),
+            target: target.to_string(),
+            relation_type: relation_type.to_string(),
+            description: String::new(),
+            confidence: None,
+        };
+
+        // Un hecho respaldado por el documento del fixture y otro por un documento distinto
+        let inside = f.chunk(5, &format!("Los paneles solares alimentan el {}.", inverter), false);
+        f.repo.save_chunk(&inside, topic(&[0])).await.unwrap();
+        f.repo.save_graph(inside.id, KnowledgeExtraction {
+            entities: vec![entity("Panel Solar"), entity(&inverter)],
+            relations: vec![relation(&inverter, "POWERS")],
+        }, vec![]).await.unwrap();
+
+        let other_document = Uuid::new_v4();
+        f.cleanup.document(other_document);
+        f.repo.save_document(&NewDocument {
+            id: other_document,
+            filename: "competencia.txt".to_string(),
+            mime_type: "text/plain".to_string(),
+            content_hash: format!("scope-fixture-{}", other_document),
+            uploaded_by: "tests".to_string(),
+            metadata: serde_json::json!({"year": 2024}),
+        }).await.unwrap();
+        let outside = ChunkRecord {
+            id: Uuid::new_v4(),
+            document_id: other_document,
+            order: 0,
+            page: None,
+            content: format!("Los paneles solares compiten con la {}.", turbine),
+            content_hash: format!("scope-fixture-{}-0", other_document),
+            extraction_failed: false,
+        };
+        f.repo.save_chunk(&outside, topic(&[1])).await.unwrap();
+        f.repo.save_graph(outside.id, KnowledgeExtraction {
+            entities: vec![entity("Panel Solar"), entity(&turbine)],
+            relations: vec![relation(&turbine, "COMPETES_WITH")],
+        }, vec![]).await.unwrap();
+
+        let facts = |filters: RetrievalFilters| {
+            let repo = &f.repo;
+            let suffix = suffix.clone();
+            async move {
+                let mut facts: Vec<(String, Vec<String>)> = repo
+                    .find_graph_neighborhood(&["Panel Solar".to_string()], 1, 100, &filters).await.unwrap()
+                    .into_iter()
+                    .filter(|fact| fact.target.ends_with(&suffix))
+                    .map(|fact| (fact.target, fact.evidence))
+                    .collect();
+                facts.sort();
+                facts
+            }
+        };
+        let inverter_fact = (inverter.clone(), vec![inside.id.to_string()]);
+        let turbine_fact = (turbine.clone(), vec![outside.id.to_string()]);
+
+        assert_eq!(facts(RetrievalFilters::default()).await, vec![inverter_fact.clone(), turbine_fact.clone()]);
+
+        // Ningún hecho respaldado solo fuera del ámbito vuelve con los filtros
+        let scoped = RetrievalFilters { document_ids: vec![f.document_id], ..Default::default() };
+        assert_eq!(facts(scoped).await, vec![inverter_fact.clone()]);
+        let other = RetrievalFilters { document_ids: vec![other_document], ..Default::default() };
+        assert_eq!(facts(other).await, vec![turbine_fact]);
+        let mut metadata = serde_json::Map::new();
+        metadata.insert("year".to_string(), serde_json::json!(2025));
+        assert_eq!(facts(RetrievalFilters { metadata, ..Default::default() }).await, vec![inverter_fact]);
+        let until = RetrievalFilters { uploaded_to: Some("2000-01-01".to_string()), ..Default::default() };
+        assert!(facts(until).await.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn direct_neighbours_of_a_hub_are_never_cut() {
+        let f = fixture().await;
+        let suffix = Uuid::new_v4().simple().to_string();
+        let name = |label: &str| format!("{} {}", label, suffix);
+        let entity = |name: &str| GraphEntity {
+            name: name.to_string(),
+            category: "Concept".to_string(),
+            description: String::new(),
+            aliases: vec![],
+        };
+        let relation = |source: &str, target: &str| GraphRelation {
+            source: source.to_string(),
+            target: target.to_string(),
+            relation_type: "RELATED_TO".to_string(),
+            description: String::new(),
+            confidence: None,
+        };
+
+        // La semilla tiene un vecino directo y otro con más ramas a dos saltos que el corte por nivel
+        let (seed, direct, hub) = (name("Semilla"), name("Directo"), name("Hub"));
+        let leaves: Vec<String> = (0..NEIGHBORHOOD_NODES_PER_LEVEL + 100).map(|i| name(&format!("Hoja {}", i))).collect();
+        let mut entities = vec![entity(&seed), entity(&direct), entity(&hub)];
+        entities.extend(leaves.iter().map(|leaf| entity(leaf)));
+        let mut relations = vec![relation(&seed, &direct), relation(&seed, &hub)];
+        relations.extend(leaves.iter().map(|leaf| relation(&hub, leaf)));
+
+        let chunk = f.chunk(5, "Texto con una entidad muy conectada.", false);
+        f.repo.save_chunk(&chunk, topic(&[4])).await.unwrap();
+        f.repo.save_graph(chunk.id, KnowledgeExtraction { entities, relations }, vec![]).await.unwrap();
+
+        let facts = f.repo.find_graph_neighborhood(std::slice::from_ref(&seed), 2, 10_000, &RetrievalFilters::default()).await.unwrap();
+        let pairs: Vec<(&str, &str)> = facts.iter().map(|fact| (fact.source.as_str(), fact.target.as_str())).collect();
+
+        // Primero los hechos de la semilla, después como mucho un nivel completo de hojas
+        let mut first = pairs[..2].to_vec();
+        first.sort();
+        let mut expected = vec![(seed.as_str(), direct.as_str()), (seed.as_str(), hub.as_str())];
+        expected.sort();
+        assert_eq!(first, expected);
+        assert_eq!(pairs.len(), 2 + NEIGHBORHOOD_NODES_PER_LEVEL, "el segundo nivel se corta en el límite");
+    }
+
+    #[test]
+    fn keyword_relevance_is_bounded_and_does_not_depend_on_other_hits() {
+        assert_eq!(keyword_relevance(0.0), 0.0);
+        assert_eq!(keyword_relevance(-1.0), 0.0, "BM25 nunca es negativo, pero por si acaso");
+        assert!((keyword_relevance(BM25_HALF_RELEVANCE) - 0.5).abs() < 1e-6);
+        assert!(keyword_relevance(0.5) < 0.1, "una coincidencia débil no parece relevante");
+        assert!(keyword_relevance(1000.0) < 1.0);
+        assert!(keyword_relevance(2.0) < keyword_relevance(3.0), "conserva el orden de BM25");
+    }
+
+    #[tokio::test]
+    #[ignore = "necesita una instancia Neo4j desechable (NEO4J_TEST_URI, ver docker-compose.test.yml)"]
+    async fn min_score_applies_to_keyword_results() {
+        let f = fixture().await;
+        let search = |min_score: f32| {
+            let options = options(RetrievalMode::Keyword, 10, min_score);
+            let repo = &f.repo;
+            async move { repo.find_keyword_context("XR-2041 inversor", &options).await.unwrap() }
+        };
+
+        let all = search(0.0).await;
+        let best = all.iter().find(|c| c.chunk_id == f.part_number).expect("el identificador se encuentra");
+        assert!(all.iter().all(|c| c.score == keyword_relevance(c.keyword_score.unwrap())));
+
+        // Justo por encima de su relevancia desaparece; justo por debajo se conserva
+        assert!(search(best.score + 0.01).await.iter().all(|c| c.chunk_id != f.part_number));
+        assert!(search(best.score - 0.01).await.iter().any(|c| c.chunk_id == f.part_number));
+        assert!(search(1.0).await.is_empty(), "BM25 saturado nunca llega a 1");
+    }
+}
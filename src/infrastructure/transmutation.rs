@@ -1,11 +1,18 @@
 use std::io::{Cursor, Read};
 use std::path::Path;
-use anyhow::{Context, Result, anyhow};
-use calamine::{Reader, Xlsx, open_workbook_from_rs};
+use calamine::{Reader, open_workbook_auto_from_rs};
 use lopdf::Document;
 use xml::reader::{EventReader, XmlEvent};
+use crate::domain::{errors::AppError, models::PAGE_BREAK};
+
+// Firmas binarias (magic bytes) de los formatos contenedores
+const PDF_MAGIC: &[u8] = b"%PDF";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const OLE_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
 
 /// Enumeración de tipos de documentos soportados
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum SupportedFormat {
     PDF,
     DOCX,
@@ -32,6 +39,58 @@ impl SupportedFormat {
             _ => None,
         }
     }
+
+    /// Detecta el formato a partir de los primeros bytes del contenido.
+    /// Solo reconoce formatos con firma inequívoca; el resto se resuelve por extensión.
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        if data.starts_with(PDF_MAGIC) {
+            return Some(Self::PDF);
+        }
+        if data.starts_with(OLE_MAGIC) {
+            // Contenedor OLE2: solo los libros Excel 97-2003 (.xls) tienen un flujo `Workbook`
+            // (`Book` en BIFF5); .doc, .ppt y similares no se soportan
+            let spreadsheet = ["Workbook", "Book"].iter().any(|stream| ole_has_stream(data, stream));
+            return spreadsheet.then_some(Self::XLSX);
+        }
+        if data.starts_with(ZIP_MAGIC) {
+            // DOCX y XLSX son ZIP: distinguimos por la estructura interna
+            let archive = zip::ZipArchive::new(Cursor::new(data)).ok()?;
+            let mut names = archive.file_names();
+            return names.find_map(|name| {
+                if name.starts_with("word/") {
+                    Some(Self::DOCX)
+                } else if name.starts_with("xl/") {
+                    Some(Self::XLSX)
+                } else {
+                    None
+                }
+            });
+        }
+
+        let head = String::from_utf8_lossy(&data[..data.len().min(512)]).to_lowercase();
+        let head = head.trim_start_matches('\u{feff}').trim_start();
+        if head.starts_with("<!doctype html") || head.starts_with("<html") {
+            return Some(Self::HTML);
+        }
+        None
+    }
+
+    /// Resuelve el formato combinando firma binaria y extensión.
+    /// La firma manda sobre la extensión para formatos binarios (ej: un PDF subido como `.txt`).
+    /// Un contenedor OLE2 que no es un libro Excel nunca se resuelve por extensión.
+    pub fn detect(filename: &str, data: &[u8]) -> Option<Self> {
+        if data.starts_with(OLE_MAGIC) {
+            return Self::sniff(data);
+        }
+        Self::sniff(data).or_else(|| Self::from_filename(filename))
+    }
+}
+
+/// ¿Hay en el directorio OLE2 una entrada con este nombre? Los nombres se guardan en UTF-16LE
+/// terminados en cero.
+fn ole_has_stream(data: &[u8], name: &str) -> bool {
+    let entry: Vec<u8> = name.encode_utf16().chain([0]).flat_map(u16::to_le_bytes).collect();
+    data.windows(entry.len()).any(|window| window == entry.as_slice())
 }
 
 /// Servicio principal de Transmutación
@@ -39,9 +98,17 @@ pub struct DocumentTransmuter;
 
 impl DocumentTransmuter {
     /// Transmuta (convierte) bytes crudos a texto limpio normalizado
-    pub fn transmute(filename: &str, data: &[u8]) -> Result<String> {
-        let format = SupportedFormat::from_filename(filename)
-            .ok_or_else(|| anyhow!("Formato no soportado: {}", filename))?;
+    pub fn transmute(filename: &str, data: &[u8]) -> Result<String, AppError> {
+        let format = SupportedFormat::detect(filename, data).ok_or_else(|| {
+            if data.starts_with(OLE_MAGIC) {
+                AppError::ValidationError(format!(
+                    "Unsupported file format: {} is a legacy Office document (.doc/.ppt); convert it to DOCX or PDF",
+                    filename
+                ))
+            } else {
+                AppError::ValidationError(format!("Unsupported file format: {}", filename))
+            }
+        })?;
 
         match format {
             SupportedFormat::PDF => Self::parse_pdf(data),
@@ -49,66 +116,157 @@ impl DocumentTransmuter {
             SupportedFormat::XLSX => Self::parse_xlsx(data),
             SupportedFormat::CSV => Self::parse_csv(data),
             SupportedFormat::HTML => Self::parse_html(data),
-            SupportedFormat::PlainText => {
-                String::from_utf8(data.to_vec()).map_err(|e| anyhow!("Error UTF-8: {}", e))
+            SupportedFormat::PlainText => Self::parse_utf8(data),
+        }
+    }
+
+    /// Tipo MIME a registrar en el nodo `Document`
+    pub fn mime_type(filename: &str, data: &[u8]) -> &'static str {
+        match SupportedFormat::detect(filename, data) {
+            Some(SupportedFormat::PDF) => "application/pdf",
+            Some(SupportedFormat::DOCX) => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            Some(SupportedFormat::XLSX) if data.starts_with(OLE_MAGIC) => "application/vnd.ms-excel",
+            Some(SupportedFormat::XLSX) => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Some(SupportedFormat::CSV) => "text/csv",
+            Some(SupportedFormat::HTML) => "text/html",
+            Some(SupportedFormat::PlainText) => match Path::new(filename)
+                .extension()
+                .and_then(|s| s.to_str())
+                .map(|s| s.to_lowercase())
+                .as_deref()
+            {
+                Some("md") => "text/markdown",
+                Some("json") => "application/json",
+                Some("xml") => "application/xml",
+                _ => "text/plain",
             },
+            None => "application/octet-stream",
         }
     }
 
-    fn parse_pdf(data: &[u8]) -> Result<String> {
+    fn parse_utf8(data: &[u8]) -> Result<String, AppError> {
+        String::from_utf8(data.to_vec())
+            .map_err(|e| AppError::ParseError(format!("Invalid UTF-8: {}", e)))
+    }
+
+    fn parse_pdf(data: &[u8]) -> Result<String, AppError> {
         // Cargar desde memoria usando lopdf
         let doc = Document::load_mem(data)
-            .map_err(|e| anyhow!("Fallo al cargar PDF: {}", e))?;
-        
+            .map_err(|e| AppError::ParseError(format!("Failed to load PDF: {}", e)))?;
+
         let mut text = String::new();
         // Iteramos páginas
         for page_num in doc.get_pages().keys() {
             // lopdf devuelve Result<String>, manejamos el error con ok() para seguir si falla una pag
-            if let Ok(content) = doc.extract_text(&[*page_num]) {
-                text.push_str(&content);
-                text.push_str("\n\n");
+            // Si una página falla se deja vacía para no desplazar la numeración
+            let content = doc.extract_text(&[*page_num]).unwrap_or_default();
+            if !text.is_empty() {
+                text.push(PAGE_BREAK);
             }
+            text.push_str(&content);
+            text.push_str("\n\n");
         }
-        
-        if text.trim().is_empty() {
-             return Err(anyhow!("El PDF parece vacío o contiene imágenes escaneadas no procesables."));
+
+        if text.replace(PAGE_BREAK, "").trim().is_empty() {
+            return Err(AppError::ParseError("PDF appears to be empty or scanned images".to_string()));
         }
 
         Ok(text)
     }
 
-    fn parse_docx(data: &[u8]) -> Result<String> {
+    fn parse_docx(data: &[u8]) -> Result<String, AppError> {
         let cursor = Cursor::new(data);
         let mut archive = zip::ZipArchive::new(cursor)
-            .context("No es un archivo ZIP/DOCX válido")?;
+            .map_err(|e| AppError::ParseError(format!("Failed to read DOCX zip: {}", e)))?;
 
         // El contenido principal en DOCX suele estar en word/document.xml
         let mut xml_file = archive.by_name("word/document.xml")
-            .context("DOCX corrupto: falta document.xml")?;
+            .map_err(|_| AppError::ParseError("Invalid DOCX: missing document.xml".to_string()))?;
 
         let mut xml_content = String::new();
-        xml_file.read_to_string(&mut xml_content)?;
+        xml_file.read_to_string(&mut xml_content)
+            .map_err(|e| AppError::ParseError(format!("Failed to read XML: {}", e)))?;
 
         let parser = EventReader::from_str(&xml_content);
         let mut text = String::new();
-        
-        // Extracción simple de XML (texto dentro de etiquetas)
+        let mut paragraph = String::new();
+        let mut heading: Option<usize> = None;
+        let mut table_depth = 0;
+
+        // Párrafos separados por línea en blanco; los estilos de título se convierten en
+        // encabezados Markdown y las tablas en filas `celda | celda` (ver chunking por párrafos)
         for e in parser {
-            if let Ok(XmlEvent::Characters(s)) = e {
-                text.push_str(&s);
-                text.push(' ');
+            match e {
+                Ok(XmlEvent::StartElement { name, attributes, .. }) => match name.local_name.as_str() {
+                    "pStyle" => {
+                        heading = attributes.iter()
+                            .find(|a| a.name.local_name == "val")
+                            .and_then(|a| Self::docx_heading_level(&a.value));
+                    },
+                    "tbl" => table_depth += 1,
+                    "tab" => paragraph.push('\t'),
+                    "br" => paragraph.push('\n'),
+                    _ => {}
+                },
+                Ok(XmlEvent::Characters(s)) => paragraph.push_str(&s),
+                Ok(XmlEvent::EndElement { name }) => match name.local_name.as_str() {
+                    "p" => {
+                        let content = paragraph.trim();
+                        if table_depth > 0 {
+                            // Dentro de una celda: varios párrafos forman una sola línea
+                            if !content.is_empty() {
+                                text.push_str(content);
+                                text.push(' ');
+                            }
+                        } else if !content.is_empty() {
+                            if let Some(level) = heading {
+                                text.push_str(&"#".repeat(level));
+                                text.push(' ');
+                            }
+                            text.push_str(content);
+                            text.push_str("\n\n");
+                        }
+                        paragraph.clear();
+                        heading = None;
+                    },
+                    "tc" => text.push_str("| "),
+                    "tr" => text.push('\n'),
+                    "tbl" => {
+                        table_depth -= 1;
+                        text.push('\n');
+                    },
+                    _ => {}
+                },
+                Err(e) => return Err(AppError::ParseError(format!("XML Error: {}", e))),
+                _ => {}
             }
         }
         Ok(text)
     }
 
-    fn parse_xlsx(data: &[u8]) -> Result<String> {
+    /// Nivel de encabezado de un estilo de párrafo DOCX (`Heading2`, `Title`, `Titulo1`...).
+    fn docx_heading_level(style: &str) -> Option<usize> {
+        let lower = style.to_lowercase();
+        if lower == "title" || lower == "titulo" || lower == "ttulo" {
+            return Some(1);
+        }
+        let digits = lower.trim_start_matches(|c: char| !c.is_ascii_digit());
+        let prefix = &lower[..lower.len() - digits.len()];
+        let is_heading = ["heading", "titulo", "ttulo", "título", "berschrift", "titre"].contains(&prefix);
+        match digits.parse::<usize>() {
+            Ok(level) if is_heading => Some(level.clamp(1, 6)),
+            _ => None,
+        }
+    }
+
+    fn parse_xlsx(data: &[u8]) -> Result<String, AppError> {
+        // open_workbook_auto_from_rs cubre tanto .xlsx como el formato binario .xls
         let cursor = Cursor::new(data);
-        let mut workbook: Xlsx<_> = open_workbook_from_rs(cursor)
-            .map_err(|e| anyhow!("Error abriendo Excel: {}", e))?;
+        let mut workbook = open_workbook_auto_from_rs(cursor)
+            .map_err(|e| AppError::ParseError(format!("Failed to open spreadsheet: {}", e)))?;
 
         let mut text = String::new();
-        
+
         // Iteramos todas las hojas
         for sheet_name in workbook.sheet_names().to_vec() {
             if let Ok(range) = workbook.worksheet_range(&sheet_name) {
@@ -126,10 +284,10 @@ impl DocumentTransmuter {
         Ok(text)
     }
 
-    fn parse_csv(data: &[u8]) -> Result<String> {
+    fn parse_csv(data: &[u8]) -> Result<String, AppError> {
         let mut rdr = csv::Reader::from_reader(Cursor::new(data));
         let mut text = String::new();
-        
+
         // Intentamos obtener headers
         if let Ok(headers) = rdr.headers() {
             let header_line: Vec<String> = headers.iter().map(|s| s.to_string()).collect();
@@ -137,24 +295,82 @@ impl DocumentTransmuter {
             text.push_str("\n---\n");
         }
 
-        for result in rdr.records() {
-            if let Ok(record) = result {
-                let row: Vec<String> = record.iter().map(|s| s.to_string()).collect();
-                text.push_str(&row.join(" | "));
-                text.push('\n');
-            }
+        for record in rdr.records().flatten() {
+            let row: Vec<String> = record.iter().map(|s| s.to_string()).collect();
+            text.push_str(&row.join(" | "));
+            text.push('\n');
         }
         Ok(text)
     }
 
-    fn parse_html(data: &[u8]) -> Result<String> {
+    fn parse_html(data: &[u8]) -> Result<String, AppError> {
         let html_string = String::from_utf8(data.to_vec())
-            .context("El HTML no es UTF-8 válido")?;
-        
+            .map_err(|e| AppError::ParseError(format!("HTML is not valid UTF-8: {}", e)))?;
+
         // Manejo del Result devuelto por html2text v0.16.4+
-        let text = html2text::from_read(html_string.as_bytes(), 80)
-            .map_err(|e| anyhow!("Error procesando HTML: {}", e))?;
-            
-        Ok(text)
+        html2text::from_read(html_string.as_bytes(), 80)
+            .map_err(|e| AppError::ParseError(format!("Failed to process HTML: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_with(entry: &str) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file(entry, zip::write::FileOptions::default()).unwrap();
+        writer.write_all(b"<xml/>").unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Cabecera OLE2 seguida de una entrada de directorio con el nombre del flujo.
+    fn ole_with(stream: &str) -> Vec<u8> {
+        let mut data = OLE_MAGIC.to_vec();
+        data.resize(512, 0);
+        data.extend(stream.encode_utf16().chain([0]).flat_map(u16::to_le_bytes));
+        data.resize(1024, 0);
+        data
+    }
+
+    #[test]
+    fn signatures_win_over_extensions() {
+        assert_eq!(SupportedFormat::detect("informe.txt", b"%PDF-1.7 ..."), Some(SupportedFormat::PDF));
+        assert_eq!(SupportedFormat::detect("informe", &zip_with("word/document.xml")), Some(SupportedFormat::DOCX));
+        assert_eq!(SupportedFormat::detect("datos.bin", &zip_with("xl/workbook.xml")), Some(SupportedFormat::XLSX));
+        assert_eq!(SupportedFormat::detect("web.txt", b"\xEF\xBB\xBF  <!DOCTYPE html><html></html>"), Some(SupportedFormat::HTML));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn without_signature_the_extension_decides() {
+        assert_eq!(SupportedFormat::detect("notas.MD", b"# Notas"), Some(SupportedFormat::PlainText));
+        assert_eq!(SupportedFormat::detect("tabla.csv", b"a,b\n1,2"), Some(SupportedFormat::CSV));
+        assert_eq!(SupportedFormat::detect("imagen.png", b"\x89PNG"), None);
+        assert_eq!(SupportedFormat::detect("sin_extension", b"texto"), None);
+        // Un ZIP que no es DOCX ni XLSX no tiene firma reconocible
+        assert_eq!(SupportedFormat::sniff(&zip_with("otro/fichero.txt")), None);
+    }
+
+    #[test]
+    fn only_excel_ole_containers_are_spreadsheets() {
+        assert_eq!(SupportedFormat::detect("libro.xls", &ole_with("Workbook")), Some(SupportedFormat::XLSX));
+        assert_eq!(SupportedFormat::detect("libro.xls", &ole_with("Book")), Some(SupportedFormat::XLSX));
+        assert_eq!(DocumentTransmuter::mime_type("libro.xls", &ole_with("Workbook")), "application/vnd.ms-excel");
+
+        // .doc y .ppt son OLE2 sin libro: ni siquiera con extensión .xls se envían a calamine
+        for (filename, stream) in [("carta.doc", "WordDocument"), ("charla.ppt", "PowerPoint Document"), ("carta.xls", "WordDocument")] {
+            assert_eq!(SupportedFormat::detect(filename, &ole_with(stream)), None, "{}", filename);
+            match DocumentTransmuter::transmute(filename, &ole_with(stream)) {
+                Err(AppError::ValidationError(message)) => assert!(message.contains("legacy Office"), "{}", message),
+                other => panic!("{} debería rechazarse: {:?}", filename, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn plain_text_is_returned_as_is() {
+        assert_eq!(DocumentTransmuter::transmute("a.txt", "Año 2025".as_bytes()).unwrap(), "Año 2025");
+        assert!(matches!(DocumentTransmuter::transmute("a.txt", b"\xFF\xFE"), Err(AppError::ParseError(_))));
+    }
+}
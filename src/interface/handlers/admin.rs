@@ -1,8 +1,8 @@
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use crate::domain::{ports::{KGRepository, AIService}, errors::AppError};
-use crate::application::dtos::AdminConfigPayload;
+use crate::domain::{ports::{KGRepository, AIService}, ontology::Ontology, errors::AppError};
+use crate::application::{dtos::AdminConfigPayload, jobs::IngestionJobManager, conversation::ConversationService, retrieval::RetrievalService, communities::CommunityService};
 use tera::Tera;
 
 // Estado compartido (ver main.rs)
@@ -10,6 +10,13 @@ pub struct AppState {
     pub repo: Arc<dyn KGRepository>,
     pub ai_service: Arc<RwLock<dyn AIService>>, // RwLock para poder actualizar config
     pub tera: Tera, // <-- NUEVO CAMPO
+    pub jobs: Arc<IngestionJobManager>,
+    /// Compartida con el gestor de trabajos; cada trabajo toma una copia al arrancar
+    pub ontology: Arc<RwLock<Ontology>>,
+    pub conversations: Arc<ConversationService>,
+    /// Búsqueda, reranking y expansión por el grafo del chat
+    pub retrieval: Arc<RetrievalService>,
+    pub communities: Arc<CommunityService>,
 }
 
 #[utoipa::path(
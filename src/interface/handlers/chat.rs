@@ -1,16 +1,22 @@
 // FILE: src/interface/handlers/chat.rs
 
-use axum::{Json, extract::State};
-use std::sync::Arc;
-use rig::{
-    completion::Prompt, 
-    providers::openai::{self, OpenAIResponsesExt},
-    client::CompletionClient 
+use axum::{
+    Json,
+    extract::State,
+    response::sse::{Event, KeepAlive, KeepAliveStream, Sse},
 };
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
-use secrecy::ExposeSecret; 
+use futures::stream::{self, BoxStream, StreamExt};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use crate::application::conversation::ConversationContext;
 use crate::domain::{
-    models::{ChatRequest, ChatResponse, SourceReference}, 
+    models::{
+        ChatChunk, ChatRequest, ChatResponse, ChatStreamEvent, ConversationTurn, GraphFact, RelevantEntity, RetrievalMode,
+        SourceReference, TokenUsage
+    },
+    ports::ChatStream,
     errors::AppError
 };
 use super::admin::AppState;
@@ -29,16 +35,177 @@ pub async fn chat_handler(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<ChatRequest>,
 ) -> Result<Json<ChatResponse>, AppError> {
-    
-    // 1. Obtener lock de lectura del servicio IA
-    let ai_guard = state.ai_service.read().await;
+    let prepared = prepare_chat(&state, &payload).await?;
+
+    // 6-7. Generación de respuesta (a través del servicio de IA: reintentos, presupuesto y circuit breaker)
+    let answer = state.ai_service.read().await.stream_chat(&prepared.system_prompt, &payload.message).await?;
+    let answer = collect_answer(answer).await?;
+
+    // 8. Guardar el turno y retorno estructurado
+    state.conversations.record_turn(&prepared.conversation, prepared.turn(&payload.message, answer.clone())).await?;
+
+    Ok(Json(ChatResponse {
+        conversation_id: prepared.conversation.id,
+        response: answer,
+        sources: prepared.sources,
+        entities: prepared.entities,
+        facts: prepared.facts,
+    }))
+}
+
+type ChatEventStream = BoxStream<'static, Result<Event, Infallible>>;
+
+#[utoipa::path(
+    post,
+    path = "/api/chat/stream",
+    request_body = ChatRequest,
+    responses(
+        (status = 200, description = "Server-Sent Events: `Sources` with the retrieved sources, one `Token` per \
+                                      text fragment as the LLM writes it, and a final `Usage` (or `Error`)",
+         body = ChatStreamEvent, content_type = "text/event-stream"),
+        (status = 500, description = "Error interno")
+    ),
+    tag = "chat"
+)]
+pub async fn chat_stream_handler(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ChatRequest>,
+) -> Result<Sse<KeepAliveStream<ChatEventStream>>, AppError> {
+    // La recuperación y la apertura de la respuesta fallan antes de abrir el stream, con su código HTTP
+    let prepared = prepare_chat(&state, &payload).await?;
+    let answer = state.ai_service.read().await.stream_chat(&prepared.system_prompt, &payload.message).await?;
+
+    let sources = chat_event(&ChatStreamEvent::Sources {
+        conversation_id: prepared.conversation.id,
+        sources: prepared.sources.clone(),
+        entities: prepared.entities.clone(),
+        facts: prepared.facts.clone(),
+    });
+
+    // La respuesta se lee en su propia tarea: si el cliente se desconecta antes del final,
+    // se termina de leer igualmente y el turno se guarda
+    let (events, received) = mpsc::channel(CHAT_EVENT_BUFFER);
+    let question = payload.message;
+    tokio::spawn(async move {
+        let Some((text, usage)) = relay_answer(answer, &events).await else { return };
+        if let Err(e) = state.conversations.record_turn(&prepared.conversation, prepared.turn(&question, text)).await {
+            tracing::error!("❌ Could not save conversation turn: {}", e);
+        }
+        let _ = events.send(ChatStreamEvent::Usage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            total_tokens: usage.total_tokens,
+        }).await;
+    });
+
+    let tokens = ReceiverStream::new(received).map(|event| chat_event(&event));
+    let stream = stream::once(async move { sources }).chain(tokens).boxed();
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// Caracteres de la vista previa de cada fuente
+const SNIPPET_CHARS: usize = 150;
+// Eventos pendientes de enviar a un cliente lento antes de frenar la lectura de la respuesta
+const CHAT_EVENT_BUFFER: usize = 64;
 
-    // 2. Generar Embedding de la pregunta del usuario
-    let embedding = ai_guard.generate_embedding(&payload.message).await?;
+/// Respuesta completa de un stream de chat.
+async fn collect_answer(mut answer: ChatStream) -> Result<String, AppError> {
+    let mut text = String::new();
+    while let Some(chunk) = answer.next().await {
+        if let ChatChunk::Text(fragment) = chunk? {
+            text.push_str(&fragment);
+        }
+    }
+    Ok(text)
+}
+
+/// Reenvía al cliente un `Token` por fragmento y devuelve la respuesta completa con su consumo.
+/// Si el cliente ya no escucha se sigue leyendo hasta el final. Ante un fallo del LLM (o un stream
+/// que termina sin respuesta final) envía `Error` y devuelve `None`: no hay turno que guardar.
+async fn relay_answer(mut answer: ChatStream, events: &mpsc::Sender<ChatStreamEvent>) -> Option<(String, TokenUsage)> {
+    let mut text = String::new();
+    while let Some(chunk) = answer.next().await {
+        match chunk {
+            Ok(ChatChunk::Text(fragment)) => {
+                text.push_str(&fragment);
+                let _ = events.send(ChatStreamEvent::Token { text: fragment }).await;
+            },
+            Ok(ChatChunk::Done(usage)) => return Some((text, usage)),
+            Err(e) => {
+                tracing::error!("❌ Chat stream failed: {}", e);
+                let _ = events.send(ChatStreamEvent::Error { message: format!("Error generando respuesta LLM: {}", e) }).await;
+                return None;
+            },
+        }
+    }
+
+    tracing::error!("❌ Chat stream ended without a final response");
+    let _ = events.send(ChatStreamEvent::Error { message: "Respuesta LLM incompleta".to_string() }).await;
+    None
+}
+
+fn chat_event(event: &ChatStreamEvent) -> Result<Event, Infallible> {
+    let value = serde_json::to_value(event).unwrap_or_default();
+    let name = value.get("type").and_then(|t| t.as_str()).unwrap_or("message").to_string();
+    Ok(Event::default().event(name).json_data(&value).unwrap_or_default())
+}
+
+/// Contexto recuperado y prompt listos para enviar al LLM.
+struct PreparedChat {
+    system_prompt: String,
+    sources: Vec<SourceReference>,
+    entities: Vec<RelevantEntity>,
+    facts: Vec<GraphFact>,
+    conversation: ConversationContext,
+}
+
+impl PreparedChat {
+    fn turn(&self, question: &str, answer: String) -> ConversationTurn {
+        ConversationTurn {
+            question: question.to_string(),
+            standalone_question: self.conversation.standalone_question.clone(),
+            answer,
+            sources: self.sources.clone(),
+            created_at: String::new(),
+        }
+    }
+}
+
+/// Pasos 0-5 comunes a la respuesta completa y a la respuesta en streaming.
+async fn prepare_chat(state: &AppState, request: &ChatRequest) -> Result<PreparedChat, AppError> {
+    let retrieval = state.retrieval.resolve(request)?;
+
+    // 0. Historial de la conversación y pregunta reescrita sin referencias a él
+    //    (antes de tomar el lock: el servicio de conversaciones también usa la IA)
+    let conversation = state.conversations.open(request.conversation_id, &request.message).await?;
+
+    // Modo global: la respuesta sale de los resúmenes de comunidades (map-reduce), no de fragmentos
+    if retrieval.mode == RetrievalMode::Global {
+        return prepare_global_chat(state, request, conversation).await;
+    }
+
+    // 1-2. Generar Embedding de la pregunta del usuario (el lock se suelta antes de la recuperación,
+    //      que también puede usar la IA para el reranking)
+    let search_query = conversation.search_query(&request.message);
+    let embedding = state.ai_service.read().await.generate_embedding(search_query).await?;
     
     // 3. Recuperación Híbrida en Neo4j (Vector Search + Graph Traversals)
-    // Traemos los top 5 fragmentos más relevantes
-    let hybrid_contexts = state.repo.find_hybrid_context(embedding, 5).await?;
+    // Los top_k fragmentos más relevantes por embeddings, texto completo o ambos (según el modo)
+    // (reordenados por el reranker si está configurado y recortados al presupuesto de tokens)
+    let mut hybrid_contexts = state.retrieval.retrieve(embedding.clone(), search_query, &retrieval).await?;
+
+    // Entidades cuyo perfil (nombre, categoría y descripción) se parece a la pregunta
+    let mut entities = state.repo.find_similar_entities(embedding, 5).await?;
+    // Con filtros, solo las que aparecen en las fuentes: las demás pueden venir de fuera del ámbito pedido
+    if !retrieval.filters.is_empty() {
+        entities.retain(|e| hybrid_contexts.iter().any(|ctx| ctx.connected_entities.contains(&e.name)));
+    }
+
+    // Vecindario a `hops` saltos de esas entidades, ordenado por personalized PageRank; los
+    // fragmentos que respaldan sus hechos se añaden como fuentes tras los recuperados
+    let expansion = state.retrieval.expand_graph(&hybrid_contexts, &entities, &retrieval).await?;
+    let facts = expansion.facts;
+    hybrid_contexts.extend(expansion.chunks);
     
     // 4. Construir Contexto Estructurado para el Prompt y para la Respuesta API
     let mut context_text = String::new();
@@ -50,29 +217,67 @@ pub async fn chat_handler(
         // Limpieza básica de espacios para ahorrar tokens y mejorar legibilidad
         let clean_content = ctx.content.replace("\n", " ").trim().to_string();
         let entity_list = ctx.connected_entities.join(", ");
+        let origin = match (&ctx.filename, ctx.page) {
+            (Some(file), Some(page)) => format!("{} (pág. {})", file, page),
+            (Some(file), None) => file.clone(),
+            _ => "Desconocido".to_string(),
+        };
         
         // Texto que leerá el LLM
         context_text.push_str(&format!(
-            "FUENTE [{}]:\n- Contenido: {}\n- Conceptos Relacionados: [{}]\n\n", 
-            idx, clean_content, entity_list
+            "FUENTE [{}]:\n- Documento: {}\n- Contenido: {}\n- Conceptos Relacionados: [{}]\n\n", 
+            idx, origin, clean_content, entity_list
         ));
 
         // Metadatos estructurados para el Frontend (Interactividad)
         sources_output.push(SourceReference {
             index: idx,
             chunk_id: ctx.chunk_id.clone(),
+            document_id: ctx.document_id.clone(),
+            filename: ctx.filename.clone(),
+            page: ctx.page,
             // Creamos un snippet corto para previsualización
-            short_content: if clean_content.len() > 150 {
-                format!("{}...", &clean_content[..150])
-            } else {
-                clean_content.clone()
-            },
-            // Simulación de relevancia (en un sistema real vendría del score vectorial)
-            relevance: 1.0 - (i as f32 * 0.1), 
+            short_content: snippet(&clean_content),
+            relevance: ctx.score,
+            vector_score: ctx.vector_score,
+            keyword_score: ctx.keyword_score,
+            rerank_score: ctx.rerank_score,
+            graph_score: ctx.graph_score,
             concepts: ctx.connected_entities.clone(),
         });
     }
 
+    let mut entities_text = String::new();
+    for entity in &entities {
+        entities_text.push_str(&format!(
+            "- {} ({}): {}\n",
+            entity.name, entity.category, entity.description.as_deref().unwrap_or("sin descripción")
+        ));
+    }
+    if entities_text.is_empty() {
+        entities_text.push_str("(ninguna)\n");
+    }
+
+    // Hechos como triples, con las fuentes recuperadas que los respaldan
+    let mut facts_text = String::new();
+    for fact in &facts {
+        let cited: Vec<String> = sources_output.iter()
+            .filter(|s| fact.evidence.contains(&s.chunk_id))
+            .map(|s| format!("[{}]", s.index))
+            .collect();
+        facts_text.push_str(&format!("- ({}) -[{}]-> ({})", fact.source, fact.relation, fact.target));
+        if let Some(description) = &fact.description {
+            facts_text.push_str(&format!(": {}", description));
+        }
+        if !cited.is_empty() {
+            facts_text.push_str(&format!(" {}", cited.join("")));
+        }
+        facts_text.push('\n');
+    }
+    if facts_text.is_empty() {
+        facts_text.push_str("(ninguno)\n");
+    }
+
     // 5. Construcción del System Prompt
     // Es CRÍTICO instruir al modelo sobre cómo citar.
     let system_prompt = format!(
@@ -86,47 +291,174 @@ pub async fn chat_handler(
         4. Si combinas información de varias fuentes, usa [1][3].
         5. Usa formato Markdown para estructurar la respuesta (negritas, listas, encabezados).
         6. Si el contexto es insuficiente, dilo claramente.
+        7. Usa el HISTORIAL solo para entender a qué se refiere la pregunta; tus afirmaciones deben apoyarse en las FUENTES.
+        8. Los HECHOS DEL GRAFO sirven para conectar conceptos entre fuentes; cítalos con las fuentes [n] que los acompañan.
         
+        HISTORIAL DE LA CONVERSACIÓN:
+        {}
+        
+        ENTIDADES RELEVANTES (definiciones de apoyo; cita siempre las FUENTES, no esta lista):
+        {}
+        HECHOS DEL GRAFO (relaciones entre entidades cercanas a las fuentes):
+        {}
         CONTEXTO RECUPERADO:
         {}
         "#, 
+        if conversation.history.is_empty() { "(conversación nueva)" } else { &conversation.history },
+        entities_text,
+        facts_text,
         context_text
     );
 
-    // 6. Configuración dinámica del cliente LLM (Rig + Reqwest)
-    let config = ai_guard.get_config(); 
-    let base_url = config.base_url.as_deref().unwrap_or("https://api.openai.com/v1");
-    let api_key = config.api_key.expose_secret();
+    Ok(PreparedChat { system_prompt, sources: sources_output, entities, facts, conversation })
+}
 
-    // Construcción manual del cliente HTTP para asegurar headers correctos
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    
-    if !api_key.is_empty() {
-        if let Ok(mut val) = HeaderValue::from_str(&format!("Bearer {}", api_key)) {
-            val.set_sensitive(true);
-            headers.insert(AUTHORIZATION, val);
-        }
+/// Vista previa de una fuente: los primeros `SNIPPET_CHARS` caracteres (no bytes: el texto
+/// suele llevar tildes y eñes).
+fn snippet(content: &str) -> String {
+    let mut chars = content.chars();
+    let preview: String = chars.by_ref().take(SNIPPET_CHARS).collect();
+    if chars.next().is_some() {
+        format!("{}...", preview)
+    } else {
+        preview
     }
+}
+
+/// Variante de los pasos 1-5 para preguntas sobre el corpus completo: los puntos clave salen
+/// de los resúmenes de comunidades del nivel pedido, sin fragmentos que citar.
+async fn prepare_global_chat(
+    state: &AppState,
+    request: &ChatRequest,
+    conversation: ConversationContext,
+) -> Result<PreparedChat, AppError> {
+    let search_query = conversation.search_query(&request.message);
+    let global = state.communities.global_context(search_query, request.community_level).await?;
+    tracing::info!("🏘️ Global answer from {} communities at level {}", global.communities, global.level);
 
-    let client = openai::Client::from_parts(
-        base_url.to_string(),
-        headers,
-        reqwest::Client::new(),
-        OpenAIResponsesExt,
+    let system_prompt = format!(
+        r#"Eres 'La Muralla', un asistente de inteligencia cognitiva avanzado que responde basándose en un Grafo de Conocimiento.
+        
+        INSTRUCCIONES PRINCIPALES:
+        1. Responde a la pregunta del usuario basándote EXCLUSIVAMENTE en los PUNTOS CLAVE proporcionados abajo.
+        2. Los puntos resumen comunidades de conceptos de todo el corpus; sintetízalos en una visión de conjunto.
+        3. NO utilices conocimiento externo si no está respaldado por los puntos.
+        4. No uses citas [n]: en este modo no hay fuentes individuales.
+        5. Usa formato Markdown para estructurar la respuesta (negritas, listas, encabezados).
+        6. Si los puntos son insuficientes, dilo claramente.
+        7. Usa el HISTORIAL solo para entender a qué se refiere la pregunta.
+        
+        HISTORIAL DE LA CONVERSACIÓN:
+        {}
+        
+        PUNTOS CLAVE (de {} comunidades, nivel {}; los más relevantes primero):
+        {}
+        "#,
+        if conversation.history.is_empty() { "(conversación nueva)" } else { &conversation.history },
+        global.communities,
+        global.level,
+        global.points
     );
 
-    // 7. Generación de respuesta
-    let agent = client.agent(&config.model_name)
-        .preamble(&system_prompt)
-        .build();
+    Ok(PreparedChat {
+        system_prompt,
+        sources: Vec::new(),
+        entities: Vec::new(),
+        facts: Vec::new(),
+        conversation,
+    })
+}
 
-    let answer = agent.prompt(&payload.message).await
-        .map_err(|e| AppError::AIError(format!("Error generando respuesta LLM: {}", e)))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // 8. Retorno estructurado
-    Ok(Json(ChatResponse {
-        response: answer,
-        sources: sources_output,
-    }))
-}
\ No newline at end of file
+    fn answer(chunks: Vec<Result<ChatChunk, AppError>>) -> ChatStream {
+        stream::iter(chunks).boxed()
+    }
+
+    fn usage() -> TokenUsage {
+        TokenUsage { input_tokens: 10, output_tokens: 3, total_tokens: 13 }
+    }
+
+    /// Eventos ya enviados por `relay_answer` (el emisor debe estar cerrado).
+    async fn drain(mut received: mpsc::Receiver<ChatStreamEvent>) -> Vec<ChatStreamEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = received.recv().await {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn tokens_are_relayed_and_the_full_answer_is_returned() {
+        let (events, received) = mpsc::channel(CHAT_EVENT_BUFFER);
+        let chunks = vec![Ok(ChatChunk::Text("Hola".to_string())), Ok(ChatChunk::Text(" mundo".to_string())), Ok(ChatChunk::Done(usage()))];
+
+        let result = relay_answer(answer(chunks), &events).await;
+        drop(events);
+
+        assert_eq!(result, Some(("Hola mundo".to_string(), usage())));
+        let tokens: Vec<String> = drain(received).await.into_iter()
+            .map(|event| match event {
+                ChatStreamEvent::Token { text } => text,
+                other => panic!("solo se esperaban tokens: {:?}", other),
+            })
+            .collect();
+        assert_eq!(tokens, vec!["Hola", " mundo"]);
+    }
+
+    #[tokio::test]
+    async fn a_disconnected_client_does_not_lose_the_answer() {
+        let (events, received) = mpsc::channel(1);
+        drop(received);
+        let chunks = vec![Ok(ChatChunk::Text("a".to_string())), Ok(ChatChunk::Text("b".to_string())), Ok(ChatChunk::Done(usage()))];
+
+        let result = relay_answer(answer(chunks), &events).await;
+        assert_eq!(result, Some(("ab".to_string(), usage())), "la respuesta se lee hasta el final para guardar el turno");
+    }
+
+    #[tokio::test]
+    async fn a_failed_stream_reports_the_error_and_saves_nothing() {
+        let (events, received) = mpsc::channel(CHAT_EVENT_BUFFER);
+        let chunks = vec![Ok(ChatChunk::Text("a medias".to_string())), Err(AppError::AIError("conexión cortada".to_string()))];
+
+        assert!(relay_answer(answer(chunks), &events).await.is_none());
+        drop(events);
+
+        let events = drain(received).await;
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[1], ChatStreamEvent::Error { message } if message.contains("conexión cortada")), "{:?}", events[1]);
+    }
+
+    #[tokio::test]
+    async fn a_stream_without_final_response_is_an_error() {
+        let (events, received) = mpsc::channel(CHAT_EVENT_BUFFER);
+
+        assert!(relay_answer(answer(vec![Ok(ChatChunk::Text("a".to_string()))]), &events).await.is_none());
+        drop(events);
+        assert!(matches!(drain(received).await.last(), Some(ChatStreamEvent::Error { .. })));
+    }
+
+    #[tokio::test]
+    async fn the_blocking_answer_collects_every_fragment() {
+        let chunks = vec![Ok(ChatChunk::Text("Ho".to_string())), Ok(ChatChunk::Text("la".to_string())), Ok(ChatChunk::Done(usage()))];
+        assert_eq!(collect_answer(answer(chunks)).await.unwrap(), "Hola");
+
+        let failed = vec![Ok(ChatChunk::Text("Ho".to_string())), Err(AppError::AIError("caído".to_string()))];
+        assert!(matches!(collect_answer(answer(failed)).await, Err(AppError::AIError(_))));
+    }
+
+    #[test]
+    fn snippets_cut_on_characters_not_bytes() {
+        // Un carácter de dos bytes justo en la posición 150 hacía entrar en pánico al corte por bytes
+        let text = format!("{}ñandú y energía solar", "a".repeat(149));
+        let preview = snippet(&text);
+        assert_eq!(preview.chars().count(), SNIPPET_CHARS + 3);
+        assert!(preview.ends_with("añ..."), "{}", preview);
+
+        let exact = "é".repeat(SNIPPET_CHARS);
+        assert_eq!(snippet(&exact), exact, "sin recortar no se añaden puntos suspensivos");
+        assert_eq!(snippet("corto"), "corto");
+    }
+}
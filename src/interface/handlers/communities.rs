@@ -0,0 +1,56 @@
+use axum::{Json, extract::{State, Query}, http::StatusCode};
+use std::sync::Arc;
+use crate::domain::{
+    models::{Community, CommunityIndexStatus},
+    errors::AppError
+};
+use crate::application::dtos::CommunityQuery;
+use super::admin::AppState;
+
+#[utoipa::path(
+    get,
+    path = "/api/communities",
+    params(CommunityQuery),
+    responses(
+        (status = 200, description = "Entity communities with their summaries, by level and largest first", body = Vec<Community>),
+        (status = 500, description = "Database error")
+    ),
+    tag = "communities"
+)]
+pub async fn list_communities(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CommunityQuery>,
+) -> Result<Json<Vec<Community>>, AppError> {
+    Ok(Json(state.communities.list(params.level).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/communities/status",
+    responses(
+        (status = 200, description = "Whether a rebuild is running and the outcome of the last one", body = CommunityIndexStatus)
+    ),
+    tag = "communities"
+)]
+pub async fn community_status(
+    State(state): State<Arc<AppState>>,
+) -> Json<CommunityIndexStatus> {
+    Json(state.communities.status())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/communities/rebuild",
+    responses(
+        (status = 202, description = "Community detection and summarization started in the background", body = CommunityIndexStatus),
+        (status = 400, description = "A rebuild is already running")
+    ),
+    tag = "communities"
+)]
+pub async fn rebuild_communities(
+    State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<CommunityIndexStatus>), AppError> {
+    let status = state.communities.start_rebuild()?;
+    tracing::info!("🏘️ Community rebuild started");
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
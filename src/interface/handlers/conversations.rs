@@ -0,0 +1,90 @@
+use axum::{Json, extract::{State, Path}, http::StatusCode};
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::domain::{
+    models::{Conversation, ConversationSummary, RenameConversationRequest},
+    errors::AppError
+};
+use super::admin::AppState;
+
+// Conversaciones devueltas por el listado
+const CONVERSATION_LIST_LIMIT: usize = 100;
+
+#[utoipa::path(
+    get,
+    path = "/api/conversations",
+    responses(
+        (status = 200, description = "Conversations, most recently updated first", body = Vec<ConversationSummary>),
+        (status = 500, description = "Database error")
+    ),
+    tag = "conversations"
+)]
+pub async fn list_conversations(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ConversationSummary>>, AppError> {
+    Ok(Json(state.conversations.list(CONVERSATION_LIST_LIMIT).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/conversations/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Conversation ID")
+    ),
+    responses(
+        (status = 200, description = "Conversation with all its turns and their sources", body = Conversation),
+        (status = 404, description = "Conversation not found"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "conversations"
+)]
+pub async fn get_conversation(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Conversation>, AppError> {
+    Ok(Json(state.conversations.get(id).await?))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/conversations/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Conversation ID")
+    ),
+    request_body = RenameConversationRequest,
+    responses(
+        (status = 200, description = "Conversation renamed", body = ConversationSummary),
+        (status = 400, description = "Empty title"),
+        (status = 404, description = "Conversation not found"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "conversations"
+)]
+pub async fn rename_conversation(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(request): Json<RenameConversationRequest>,
+) -> Result<Json<ConversationSummary>, AppError> {
+    Ok(Json(state.conversations.rename(id, &request.title).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/conversations/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Conversation ID")
+    ),
+    responses(
+        (status = 204, description = "Conversation and its turns deleted"),
+        (status = 404, description = "Conversation not found"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "conversations"
+)]
+pub async fn delete_conversation(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    state.conversations.delete(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
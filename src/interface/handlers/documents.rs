@@ -0,0 +1,74 @@
+use axum::{Json, extract::{State, Path}};
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::domain::{models::{DocumentInfo, DocumentDetail, DocumentPurgeResult}, errors::AppError};
+use super::admin::AppState;
+
+#[utoipa::path(
+    get,
+    path = "/api/documents",
+    responses(
+        (status = 200, description = "List of ingested source documents", body = Vec<DocumentInfo>),
+        (status = 500, description = "Database error")
+    ),
+    tag = "documents"
+)]
+pub async fn list_documents(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<DocumentInfo>>, AppError> {
+    let documents = state.repo.list_documents().await?;
+    Ok(Json(documents))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/documents/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Document ID")
+    ),
+    responses(
+        (status = 200, description = "Document provenance with its chunks and mentioned entities", body = DocumentDetail),
+        (status = 404, description = "Document not found"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "documents"
+)]
+pub async fn get_document(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DocumentDetail>, AppError> {
+    let document = state.repo.get_document(id).await?
+        .ok_or_else(|| AppError::NotFoundError(format!("Document {}", id)))?;
+
+    // Inspección: chunks en orden con las entidades que mencionan
+    let chunks = state.repo.get_document_chunks(id).await?;
+
+    Ok(Json(DocumentDetail { document, chunks }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/documents/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Document ID")
+    ),
+    responses(
+        (status = 200, description = "Document removed; orphaned entities and relations garbage-collected", body = DocumentPurgeResult),
+        (status = 404, description = "Document not found"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "documents"
+)]
+pub async fn delete_document(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<DocumentPurgeResult>, AppError> {
+    if state.repo.get_document(id).await?.is_none() {
+        return Err(AppError::NotFoundError(format!("Document {}", id)));
+    }
+
+    let result = state.repo.delete_document(id).await?;
+    tracing::info!("🗑️ Document {} deleted: {:?}", id, result);
+
+    Ok(Json(result))
+}
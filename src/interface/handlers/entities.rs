@@ -0,0 +1,86 @@
+use axum::{Json, extract::{State, Path}};
+use std::sync::Arc;
+use uuid::Uuid;
+use crate::application::resolution::EntityResolutionService;
+use crate::domain::{
+    models::{ResolutionRequest, MergeCandidate, MergeEntitiesRequest, EntityMergeRecord},
+    errors::AppError
+};
+use super::admin::AppState;
+
+// Historial de fusiones devuelto por el listado
+const MERGE_HISTORY_LIMIT: usize = 100;
+
+#[utoipa::path(
+    post,
+    path = "/api/entities/resolve",
+    request_body = ResolutionRequest,
+    responses(
+        (status = 200, description = "Likely duplicate entities, best matches first", body = Vec<MergeCandidate>),
+        (status = 500, description = "Database or AI provider error")
+    ),
+    tag = "entities"
+)]
+pub async fn find_merge_candidates(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ResolutionRequest>,
+) -> Result<Json<Vec<MergeCandidate>>, AppError> {
+    let service = EntityResolutionService::new(state.repo.clone(), state.ai_service.clone());
+    Ok(Json(service.find_candidates(&request).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/entities/merge",
+    request_body = MergeEntitiesRequest,
+    responses(
+        (status = 200, description = "Entity merged; relations and mentions re-pointed", body = EntityMergeRecord),
+        (status = 400, description = "Both names refer to the same entity"),
+        (status = 404, description = "Entity not found"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "entities"
+)]
+pub async fn merge_entities(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<MergeEntitiesRequest>,
+) -> Result<Json<EntityMergeRecord>, AppError> {
+    let record = state.repo.merge_entities(request.keep.trim(), request.merge.trim()).await?;
+    Ok(Json(record))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/entities/merges",
+    responses(
+        (status = 200, description = "Most recent entity merges", body = Vec<EntityMergeRecord>),
+        (status = 500, description = "Database error")
+    ),
+    tag = "entities"
+)]
+pub async fn list_merges(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<EntityMergeRecord>>, AppError> {
+    Ok(Json(state.repo.list_entity_merges(MERGE_HISTORY_LIMIT).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/entities/merges/{id}/undo",
+    params(
+        ("id" = Uuid, Path, description = "Entity merge ID")
+    ),
+    responses(
+        (status = 200, description = "Merged entity restored with its relations and mentions", body = EntityMergeRecord),
+        (status = 400, description = "Merge already undone or entities changed since"),
+        (status = 404, description = "Merge not found"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "entities"
+)]
+pub async fn undo_merge(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<EntityMergeRecord>, AppError> {
+    Ok(Json(state.repo.undo_entity_merge(id).await?))
+}
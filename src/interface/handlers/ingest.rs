@@ -1,14 +1,13 @@
 use axum::{
-    extract::{State, Multipart},
-    response::IntoResponse,
-    body::{Body, Bytes}, 
+    Json,
+    extract::{State, Multipart, Path},
+    http::StatusCode,
 };
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::ReceiverStream;
-use tokio_stream::StreamExt;
-use crate::application::ingestion::IngestionService;
-use crate::infrastructure::parsing::parse_text_from_bytes; // E0432 CORREGIDO
+use uuid::Uuid;
+use crate::application::dtos::IngestionResponse;
+use crate::domain::{models::{IngestionRequest, IngestionJob, ChunkingOptions}, errors::AppError};
+use crate::infrastructure::transmutation::DocumentTransmuter;
 use super::admin::AppState;
 
 #[utoipa::path(
@@ -16,91 +15,159 @@ use super::admin::AppState;
     path = "/api/ingest",
     request_body(
         content_type = "multipart/form-data", 
-        description = "Sube un archivo (PDF/DOCX/TXT) en el campo 'file' o texto plano en 'content'",
+        description = "Sube un archivo (PDF/DOCX/XLSX/CSV/HTML/TXT) en el campo 'file' o texto plano en 'content'. \
+                       Opcionales: 'metadata' (objeto JSON), 'uploaded_by', 'force' (reprocesar contenido ya ingerido) y \
+                       troceado: 'chunk_strategy' (auto|sliding|sentence|paragraph|tokens|table), 'chunk_size' y 'chunk_overlap' \
+                       (caracteres, o tokens con 'tokens'); lo no indicado se toma de la configuración.",
     ),
     responses(
-        (status = 200, description = "Stream de texto con el progreso del proceso"),
+        (status = 202, description = "Trabajo de ingesta encolado; consultar su estado en /api/jobs/{id}", body = IngestionResponse),
+        (status = 400, description = "Formulario inválido o formato no soportado"),
         (status = 500, description = "Error interno del servidor")
     ),
     tag = "ingestion" // Añadimos el tag para utoipa
 )]
 pub async fn ingest_document(
     State(state): State<Arc<AppState>>,
-    mut multipart: Multipart,
-) -> impl IntoResponse {
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<IngestionResponse>), AppError> {
 
-    // Creamos un canal para streaming de logs
-    let (tx, rx) = mpsc::channel::<String>(10);
-    let tx_inner = tx.clone();
+    // 1. Leer archivo del Multipart
+    let request = read_ingestion_form(multipart).await?;
 
-    // Lanzamos el proceso en background
-    tokio::spawn(async move {
-        // 1. Leer archivo del Multipart
-        let mut content = String::new();
-        // Variable renombrada a 'file_label' y usada para logging, eliminando la advertencia.
-        let mut file_label = String::from("Text Input"); 
+    // 2. Lanzar el trabajo en segundo plano
+    let job = state.jobs.submit(request, None).await?;
 
-        while let Ok(Some(field)) = multipart.next_field().await {
-            if let Some(name) = field.name() {
-                if name == "file" {
-                    // 1. Obtener nombre y notificar
-                    file_label = field.file_name().unwrap_or("file").to_string();
-                    let _ = tx_inner.send(format!("📂 Leyendo archivo: {}...", file_label)).await;
-                    
-                    // 2. Obtener bytes del archivo
-                    let bytes_result = field.bytes().await;
+    Ok((StatusCode::ACCEPTED, Json(job_response(&job))))
+}
 
-                    match bytes_result {
-                        Ok(bytes) => {
-                             let _ = tx_inner.send("📄 Parseando contenido...".to_string()).await;
-                             match parse_text_from_bytes(&file_label, &bytes) {
-                                Ok(text) => content = text,
-                                Err(e) => {
-                                    let _ = tx_inner.send(format!("❌ Error parseando: {}", e)).await;
-                                    return;
-                                }
-                             }
-                        },
-                        Err(e) => {
-                            // Si falla la subida (ej. límite de tamaño excedido, parseo multipart inválido)
-                            let _ = tx_inner.send(format!("❌ Error subida: Error parsing `multipart/form-data` request: {}", e)).await;
-                            return;
-                        }
+#[utoipa::path(
+    put,
+    path = "/api/documents/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Document ID")
+    ),
+    request_body(
+        content_type = "multipart/form-data",
+        description = "Nuevo contenido del documento: archivo en 'file' o texto en 'content'. \
+                       'metadata' y 'uploaded_by' se conservan si no se envían; 'force' reprocesa aunque no haya cambios. \
+                       Admite los mismos campos de troceado que /api/ingest.",
+    ),
+    responses(
+        (status = 202, description = "Trabajo de reingesta encolado", body = IngestionResponse),
+        (status = 404, description = "Document not found"),
+        (status = 500, description = "Error interno del servidor")
+    ),
+    tag = "documents"
+)]
+pub async fn reingest_document(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    multipart: Multipart,
+) -> Result<(StatusCode, Json<IngestionResponse>), AppError> {
+
+    // Comprobamos la existencia antes de encolar para poder responder 404
+    if state.repo.get_document(id).await?.is_none() {
+        return Err(AppError::NotFoundError(format!("Document {}", id)));
+    }
+
+    let request = read_ingestion_form(multipart).await?;
+    let job = state.jobs.submit(request, Some(id)).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(job_response(&job))))
+}
+
+fn job_response(job: &IngestionJob) -> IngestionResponse {
+    IngestionResponse {
+        id: job.id.clone(),
+        status: format!("{:?}", job.status),
+        document_id: job.document_id.clone(),
+    }
+}
+
+/// Lee el formulario multipart común a la ingesta y la reingesta.
+async fn read_ingestion_form(mut multipart: Multipart) -> Result<IngestionRequest, AppError> {
+    let mut content = String::new();
+    let mut filename: Option<String> = None;
+    let mut mime_type: Option<String> = None;
+    let mut uploaded_by: Option<String> = None;
+    let mut metadata = serde_json::Value::Null;
+    let mut force = false;
+    let mut chunking = ChunkingOptions::default();
+
+    loop {
+        // Si falla la subida (ej. límite de tamaño excedido, parseo multipart inválido)
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return Err(AppError::ValidationError(format!("Error parsing `multipart/form-data` request: {}", e))),
+        };
+
+        let Some(name) = field.name().map(|n| n.to_string()) else { continue };
+
+        match name.as_str() {
+            "file" => {
+                let file_label = field.file_name().unwrap_or("file").to_string();
+                let bytes = field.bytes().await
+                    .map_err(|e| AppError::ValidationError(format!("Error reading upload '{}': {}", file_label, e)))?;
+
+                tracing::info!("📂 Parseando archivo: {}", file_label);
+                content = DocumentTransmuter::transmute(&file_label, &bytes)?;
+                mime_type = Some(DocumentTransmuter::mime_type(&file_label, &bytes).to_string());
+                filename = Some(file_label);
+            },
+            "content" => {
+                if let Ok(text) = field.text().await {
+                    if !text.is_empty() {
+                        content = text;
+                        filename = None;
+                        mime_type = None;
                     }
-                } else if name == "content" {
-                     if let Ok(text) = field.text().await {
-                        if !text.is_empty() {
-                            content = text;
-                            file_label = "Texto Plano".to_string(); // Actualizamos la etiqueta para el log
-                            let _ = tx_inner.send("📝 Recibido texto directo...".to_string()).await;
+                }
+            },
+            "metadata" => {
+                if let Ok(raw) = field.text().await {
+                    metadata = serde_json::from_str(&raw)
+                        .map_err(|e| AppError::ValidationError(format!("'metadata' is not valid JSON: {}", e)))?;
+                }
+            },
+            "force" => {
+                if let Ok(flag) = field.text().await {
+                    force = matches!(flag.trim().to_lowercase().as_str(), "true" | "1" | "on");
+                }
+            },
+            "chunk_strategy" => {
+                if let Ok(raw) = field.text().await {
+                    if !raw.trim().is_empty() {
+                        chunking.strategy = Some(raw.parse().map_err(AppError::ValidationError)?);
+                    }
+                }
+            },
+            "chunk_size" | "chunk_overlap" => {
+                if let Ok(raw) = field.text().await {
+                    if !raw.trim().is_empty() {
+                        let value = raw.trim().parse::<usize>()
+                            .map_err(|_| AppError::ValidationError(format!("'{}' must be a positive integer", name)))?;
+                        if name == "chunk_size" {
+                            chunking.chunk_size = Some(value);
+                        } else {
+                            chunking.chunk_overlap = Some(value);
                         }
-                     }
+                    }
+                }
+            },
+            "uploaded_by" => {
+                if let Ok(user) = field.text().await {
+                    uploaded_by = Some(user).filter(|u| !u.trim().is_empty());
                 }
-            }
-        }
-        
-        if content.trim().len() < 5 {
-            let _ = tx_inner.send("❌ Error: Contenido vacío o muy corto.".to_string()).await;
-            return;
-        }
-
-        // 2. Iniciar Servicio
-        let service = IngestionService::new(state.repo.clone(), state.ai_service.clone());
-
-        match service.ingest_with_progress(content, tx_inner.clone()).await {
-            Ok(_) => {
-                let _ = tx_inner.send("DONE".to_string()).await;
             },
-            Err(e) => {
-                let _ = tx_inner.send(format!("❌ Error Crítico: {}", e)).await;
-            }
+            _ => {}
         }
-    });
-
-    // Convertimos el Receiver en un Stream compatible con Axum Body
-    let stream = ReceiverStream::new(rx).map(|msg| {
-        Ok::<_, std::io::Error>(Bytes::from(format!("{}\n", msg))) 
-    });
+    }
+    
+    if content.trim().len() < 5 {
+        return Err(AppError::ValidationError("Content is empty or too short".to_string()));
+    }
 
-    Body::from_stream(stream)
-}
\ No newline at end of file
+    Ok(IngestionRequest { content, metadata, filename, mime_type, uploaded_by, force, chunking })
+}
@@ -0,0 +1,148 @@
+use axum::{
+    Json,
+    extract::{State, Path},
+    response::sse::{Event, KeepAlive, KeepAliveStream, Sse},
+};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+use crate::domain::{
+    models::{IngestionJob, JobStatus, IngestionEvent, IngestionProgress},
+    errors::AppError
+};
+use super::admin::AppState;
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Ingestion job ID")
+    ),
+    responses(
+        (status = 200, description = "Structured status of the ingestion job", body = IngestionJob),
+        (status = 404, description = "Job not found"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "ingestion"
+)]
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<IngestionJob>, AppError> {
+    let job = state.jobs.get(id).await?
+        .ok_or_else(|| AppError::NotFoundError(format!("Job {}", id)))?;
+    Ok(Json(job))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/jobs/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Ingestion job ID")
+    ),
+    responses(
+        (status = 200, description = "Cancellation requested; the job stops after the chunk in progress", body = IngestionJob),
+        (status = 400, description = "Job is not running"),
+        (status = 404, description = "Job not found")
+    ),
+    tag = "ingestion"
+)]
+pub async fn cancel_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<IngestionJob>, AppError> {
+    let job = state.jobs.cancel(id).await?;
+    Ok(Json(job))
+}
+
+type EventStream = BoxStream<'static, Result<Event, Infallible>>;
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{id}/events",
+    params(
+        ("id" = Uuid, Path, description = "Ingestion job ID")
+    ),
+    responses(
+        (status = 200, description = "Server-Sent Events: an initial `status` event with the job state, \
+                                      then one JSON event per progress step (event name = `type`) until \
+                                      `Completed`, `Cancelled` or `Fatal`", body = IngestionProgress,
+         content_type = "text/event-stream"),
+        (status = 404, description = "Job not found")
+    ),
+    tag = "ingestion"
+)]
+pub async fn job_events(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Sse<KeepAliveStream<EventStream>>, AppError> {
+
+    let stream = match state.jobs.live(id) {
+        Some(handle) => {
+            // Suscribirse antes de leer el estado: ningún evento queda entre ambos
+            let (snapshot, rx) = handle.subscribe().await;
+            let initial = status_event(&snapshot);
+
+            match terminal_event(&snapshot) {
+                // Terminó justo antes de suscribirnos
+                Some(last) => stream::iter(vec![initial, last]).boxed(),
+                None => {
+                    let updates = stream::unfold(Some(rx), |rx| async move {
+                        let mut rx = rx?;
+                        loop {
+                            match rx.recv().await {
+                                Ok(progress) => {
+                                    let next = (!progress.event.is_terminal()).then_some(rx);
+                                    return Some((progress_event(&progress), next));
+                                },
+                                // Un cliente lento pierde eventos intermedios, no el stream
+                                Err(RecvError::Lagged(_)) => continue,
+                                Err(RecvError::Closed) => return None,
+                            }
+                        }
+                    });
+                    stream::once(async move { initial }).chain(updates).boxed()
+                }
+            }
+        },
+        None => {
+            // Trabajo que no corre en este proceso: estado guardado y, si acabó, su evento final
+            let job = state.jobs.get(id).await?
+                .ok_or_else(|| AppError::NotFoundError(format!("Job {}", id)))?;
+            let mut events = vec![status_event(&job)];
+            events.extend(terminal_event(&job));
+            stream::iter(events).boxed()
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+fn status_event(job: &IngestionJob) -> Result<Event, Infallible> {
+    Ok(Event::default().event("status").json_data(job).unwrap_or_default())
+}
+
+fn progress_event(progress: &IngestionProgress) -> Result<Event, Infallible> {
+    let value = serde_json::to_value(progress).unwrap_or_default();
+    let name = value.get("type").and_then(|t| t.as_str()).unwrap_or("message").to_string();
+    Ok(Event::default().event(name).json_data(&value).unwrap_or_default())
+}
+
+/// Reconstruye el evento final de un trabajo ya terminado a partir de su estado.
+fn terminal_event(job: &IngestionJob) -> Option<Result<Event, Infallible>> {
+    let event = match job.status {
+        JobStatus::Completed => IngestionEvent::Completed {
+            document_id: job.document_id.clone(),
+            chunks_failed: job.chunks_failed,
+        },
+        JobStatus::Cancelled => IngestionEvent::Cancelled,
+        JobStatus::Failed => IngestionEvent::Fatal {
+            error: job.errors.last().cloned().unwrap_or_else(|| "Unknown error".to_string()),
+        },
+        JobStatus::Queued | JobStatus::Running => return None,
+    };
+    let message = event.message();
+    Some(progress_event(&IngestionProgress { job_id: job.id.clone(), event, message }))
+}
@@ -3,4 +3,10 @@ pub mod ingest;
 pub mod graph;
 pub mod ui;
 pub mod chat;
+pub mod communities;
+pub mod conversations;
+pub mod documents;
+pub mod entities;
+pub mod jobs;
+pub mod ontology;
 pub mod reasoning; // <-- NUEVO
\ No newline at end of file
@@ -0,0 +1,66 @@
+use axum::{Json, extract::{State, Query}};
+use std::sync::Arc;
+use crate::domain::{ontology::{Ontology, QuarantinedTriple}, errors::AppError};
+use crate::application::dtos::QuarantineQuery;
+use super::admin::AppState;
+
+const DEFAULT_QUARANTINE_LIMIT: usize = 100;
+const MAX_QUARANTINE_LIMIT: usize = 1000;
+
+#[utoipa::path(
+    get,
+    path = "/api/ontology",
+    responses(
+        (status = 200, description = "Active ontology (empty = unconstrained extraction)", body = Ontology)
+    ),
+    tag = "ontology"
+)]
+pub async fn get_ontology(
+    State(state): State<Arc<AppState>>,
+) -> Json<Ontology> {
+    Json(state.ontology.read().await.clone())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/ontology",
+    request_body = Ontology,
+    responses(
+        (status = 200, description = "Ontology replaced; applies to ingestion jobs started from now on", body = Ontology),
+        (status = 400, description = "Invalid ontology (duplicate names, unknown domain/range categories)"),
+        (status = 500, description = "Database error")
+    ),
+    tag = "ontology"
+)]
+pub async fn put_ontology(
+    State(state): State<Arc<AppState>>,
+    Json(ontology): Json<Ontology>,
+) -> Result<Json<Ontology>, AppError> {
+    ontology.validate()?;
+
+    // Persistida para que sobreviva a reinicios (prioridad sobre ONTOLOGY_PATH)
+    state.repo.save_ontology(&ontology).await?;
+    *state.ontology.write().await = ontology.clone();
+
+    tracing::info!("📚 Ontology updated ({} categories, {} relation types)",
+        ontology.categories.len(), ontology.relation_types.len());
+    Ok(Json(ontology))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/ontology/quarantine",
+    params(QuarantineQuery),
+    responses(
+        (status = 200, description = "Extracted entities/relations rejected by the ontology, newest first", body = Vec<QuarantinedTriple>),
+        (status = 500, description = "Database error")
+    ),
+    tag = "ontology"
+)]
+pub async fn list_quarantine(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<QuarantineQuery>,
+) -> Result<Json<Vec<QuarantinedTriple>>, AppError> {
+    let limit = params.limit.unwrap_or(DEFAULT_QUARANTINE_LIMIT).clamp(1, MAX_QUARANTINE_LIMIT);
+    Ok(Json(state.repo.list_quarantined_triples(limit).await?))
+}
@@ -1,7 +1,7 @@
 use axum::{Json, extract::State};
 use std::sync::Arc;
 use crate::application::reasoning::ReasoningService;
-use crate::domain::models::InferredRelation;
+use crate::domain::models::ReasoningResult;
 use crate::domain::errors::AppError;
 use super::admin::AppState;
 
@@ -9,15 +9,15 @@ use super::admin::AppState;
     post,
     path = "/api/reasoning/run",
     responses(
-        (status = 200, description = "Knowledge consolidated", body = Vec<InferredRelation>)
+        (status = 200, description = "Knowledge consolidated; relations with unsafe types are reported as rejected", body = ReasoningResult)
     )
 )]
 pub async fn run_reasoning(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<InferredRelation>>, AppError> {
+) -> Result<Json<ReasoningResult>, AppError> {
     
     let service = ReasoningService::new(state.repo.clone(), state.ai_service.clone());
-    let new_relations = service.infer_new_knowledge().await?;
+    let result = service.infer_new_knowledge().await?;
     
-    Ok(Json(new_relations))
+    Ok(Json(result))
 }
\ No newline at end of file
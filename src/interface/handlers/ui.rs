@@ -77,7 +77,7 @@ pub async fn render_dashboard_guarded(
     State(state): State<Arc<AppState>>
 ) -> impl IntoResponse {
     // 1. Ejecutar el guard de autenticación
-    if let Err(_) = auth_guard(headers).await {
+    if auth_guard(headers).await.is_err() {
         return Redirect::to("/").into_response();
     }
     
@@ -20,11 +20,22 @@ use secrecy::SecretString;
 use tera::Tera;
 
 use crate::domain::models::*;
+use crate::domain::ontology::{Ontology, CategoryDefinition, RelationTypeDefinition, OffOntologyPolicy, QuarantinedTriple};
 use crate::domain::ports::KGRepository; 
+use crate::domain::errors::AppError;
 
 use crate::infrastructure::ai::rig_client::RigAIService;
+use crate::infrastructure::ai::resilience::{ResilientAIService, ResiliencePolicy};
+use crate::infrastructure::ai::extractors::ExtractionOptions;
+use crate::infrastructure::ai::reranker::reranker_from_env;
 use crate::infrastructure::persistence::neo4j_repo::Neo4jRepo;
-use crate::interface::handlers::{admin::{self, AppState}, ingest, graph, ui, chat, reasoning}; 
+use crate::interface::handlers::{admin::{self, AppState}, ingest, graph, ui, chat, conversations, communities, reasoning, documents, entities, jobs, ontology}; 
+use crate::application::jobs::IngestionJobManager;
+use crate::application::conversation::ConversationService;
+use crate::application::communities::CommunityService;
+use crate::application::retrieval::{RetrievalService, RetrievalSettings};
+use crate::application::ingestion::IngestionLimits;
+use crate::application::chunking;
 use crate::application::dtos::*;
 
 // Documentación OpenAPI (Swagger)
@@ -33,26 +44,59 @@ use crate::application::dtos::*;
     paths(
         interface::handlers::admin::update_config,
         interface::handlers::ingest::ingest_document,
+        interface::handlers::jobs::get_job,
+        interface::handlers::jobs::cancel_job,
+        interface::handlers::jobs::job_events,
+        interface::handlers::documents::list_documents,
+        interface::handlers::documents::get_document,
+        interface::handlers::documents::delete_document,
+        interface::handlers::ingest::reingest_document,
+        interface::handlers::ontology::get_ontology,
+        interface::handlers::ontology::put_ontology,
+        interface::handlers::ontology::list_quarantine,
+        interface::handlers::entities::find_merge_candidates,
+        interface::handlers::entities::merge_entities,
+        interface::handlers::entities::list_merges,
+        interface::handlers::entities::undo_merge,
         interface::handlers::graph::get_graph,
         interface::handlers::graph::get_concept_neighborhood,
         interface::handlers::chat::chat_handler,
+        interface::handlers::chat::chat_stream_handler,
+        interface::handlers::conversations::list_conversations,
+        interface::handlers::conversations::get_conversation,
+        interface::handlers::conversations::rename_conversation,
+        interface::handlers::conversations::delete_conversation,
+        interface::handlers::communities::list_communities,
+        interface::handlers::communities::community_status,
+        interface::handlers::communities::rebuild_communities,
         interface::handlers::reasoning::run_reasoning
     ),
     components(
         schemas(
             AIConfig, AIProvider, 
-            IngestionRequest, IngestionResponse, 
+            IngestionRequest, IngestionResponse, ChunkingOptions, ChunkingStrategy, 
+            IngestionJob, JobStatus, IngestionEvent, IngestionProgress, ChunkFailure, ChunkStage,
+            DocumentInfo, DocumentChunkInfo, DocumentDetail, DocumentPurgeResult,
             AdminConfigPayload,
             VisNode, VisEdge, GraphDataResponse,
-            ChatRequest, ChatResponse, 
-            InferredRelation 
+            ChatRequest, RetrievalMode, RetrievalFilters, ChatResponse, SourceReference, RelevantEntity, GraphFact, ChatStreamEvent,
+            Conversation, ConversationTurn, ConversationSummary, RenameConversationRequest,
+            Community, CommunityIndexStatus,
+            InferredRelation, RejectedRelation, ReasoningResult,
+            Ontology, CategoryDefinition, RelationTypeDefinition, OffOntologyPolicy, QuarantinedTriple,
+            ResolutionRequest, MatchReason, MergeAdjudication, MergeCandidate, MergeEntitiesRequest, EntityMergeRecord
         )
     ),
     tags(
         (name = "admin", description = "Administration endpoints"),
         (name = "ingestion", description = "Data ingestion endpoints"),
+        (name = "documents", description = "Source documents and provenance"),
+        (name = "ontology", description = "Allowed entity categories and relation types"),
+        (name = "entities", description = "Entity resolution and merging"),
         (name = "visualization", description = "Graph visual exploration"),
         (name = "chat", description = "Semantic GraphRAG Chat"),
+        (name = "conversations", description = "Persisted multi-turn chat history"),
+        (name = "communities", description = "Entity communities and their summaries for corpus-wide questions"),
         (name = "reasoning", description = "AI Graph Enrichment")
     )
 )]
@@ -75,7 +119,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let embedding_dim = std::env::var("AI_EMBEDDING_DIM")
         .unwrap_or_else(|_| "1536".to_string())
         .parse::<usize>()
-        .expect("AI_EMBEDDING_DIM must be a number");
+        .map_err(|_| AppError::ConfigError("AI_EMBEDDING_DIM must be a number".to_string()))?;
     let base_url = std::env::var("AI_BASE_URL").ok();
 
     let provider = match provider_str.to_lowercase().as_str() {
@@ -107,7 +151,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         tracing::warn!("⚠️ Could not ensure indexes: {}", e);
     }
 
-    let ai_service = Arc::new(RwLock::new(RigAIService::new(initial_config)));
+    // Reintentos, límites por proveedor y circuit breaker alrededor del cliente rig
+    let ai_service = Arc::new(RwLock::new(ResilientAIService::new(
+        RigAIService::new(initial_config, ExtractionOptions::from_env()?),
+        ResiliencePolicy::from_env()?,
+    )));
+
+    // Trabajos de ingesta: se reanudan los que quedaron a medias antes del reinicio
+    let limits = IngestionLimits::from_env()?;
+    let chunking = chunking::options_from_env()?;
+    let ontology = Arc::new(RwLock::new(application::ontology::load_initial(repo.as_ref()).await?));
+    let jobs = Arc::new(IngestionJobManager::new(
+        repo.clone(), ai_service.clone(), repo.clone(), limits, chunking, ontology.clone()
+    ));
+    match jobs.resume_pending().await {
+        Ok(0) => {},
+        Ok(n) => tracing::info!("🔁 Resumed {} pending ingestion jobs", n),
+        Err(e) => tracing::warn!("⚠️ Could not resume pending ingestion jobs: {}", e),
+    }
 
     let tera = match Tera::new("templates/**/*.html") {
         Ok(t) => t,
@@ -117,10 +178,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    // Recuperación del chat, con el reranker opcional (`RERANKER`)
+    let retrieval = Arc::new(RetrievalService::new(
+        repo.clone(), reranker_from_env(ai_service.clone())?, RetrievalSettings::from_env()?
+    ));
+
+    // Comunidades de entidades para el modo de chat global
+    let communities = Arc::new(CommunityService::new(
+        repo.clone(), ai_service.clone(), CommunityService::default_level_from_env()?
+    ));
+
+    let conversations = Arc::new(ConversationService::new(
+        repo.clone(), ai_service.clone(), ConversationService::history_tokens_from_env()?
+    ));
+
     let app_state = Arc::new(AppState {
         repo,
         ai_service,
         tera, 
+        jobs,
+        ontology,
+        conversations,
+        retrieval,
+        communities,
     });
 
     let app = Router::new()
@@ -134,9 +214,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         // Endpoints API
         .route("/api/admin/config", post(admin::update_config))
         .route("/api/ingest", post(ingest::ingest_document))
+        .route("/api/jobs/{id}", get(jobs::get_job).delete(jobs::cancel_job))
+        .route("/api/jobs/{id}/events", get(jobs::job_events))
+        .route("/api/documents", get(documents::list_documents))
+        .route(
+            "/api/documents/{id}",
+            get(documents::get_document)
+                .put(ingest::reingest_document)
+                .delete(documents::delete_document),
+        )
+        .route("/api/ontology", get(ontology::get_ontology).put(ontology::put_ontology))
+        .route("/api/ontology/quarantine", get(ontology::list_quarantine))
+        .route("/api/entities/resolve", post(entities::find_merge_candidates))
+        .route("/api/entities/merge", post(entities::merge_entities))
+        .route("/api/entities/merges", get(entities::list_merges))
+        .route("/api/entities/merges/{id}/undo", post(entities::undo_merge))
         .route("/api/graph", get(graph::get_graph))
         .route("/api/graph/concept/{name}", get(graph::get_concept_neighborhood)) 
         .route("/api/chat", post(chat::chat_handler))
+        .route("/api/chat/stream", post(chat::chat_stream_handler))
+        .route("/api/conversations", get(conversations::list_conversations))
+        .route(
+            "/api/conversations/{id}",
+            get(conversations::get_conversation)
+                .patch(conversations::rename_conversation)
+                .delete(conversations::delete_conversation),
+        )
+        .route("/api/communities", get(communities::list_communities))
+        .route("/api/communities/status", get(communities::community_status))
+        .route("/api/communities/rebuild", post(communities::rebuild_communities))
         .route("/api/reasoning/run", post(reasoning::run_reasoning))
         
         // UI